@@ -0,0 +1,101 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+fn main() {
+    // libnl is found and loaded when the program starts instead
+    if cfg!(feature = "runtime-libnl") {
+        return;
+    }
+
+    let libnl = std::env::var("DL_SHELL_LIBNL").unwrap();
+
+    println!("cargo:rustc-link-search=native={libnl}/lib");
+    println!("cargo:rustc-link-lib=static=nl-3");
+    println!("cargo:rustc-link-lib=static=nl-route-3");
+
+    #[cfg(feature = "bindgen")]
+    generate_bindings(&libnl);
+}
+
+/// Generates declarations for every function declared in src/ffi.rs from
+/// the libnl headers, so that they can't disagree with the library that is
+/// linked in. The types they refer to still come from src/ffi.rs
+#[cfg(feature = "bindgen")]
+fn generate_bindings(libnl: &str) {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+
+    let ffi = std::fs::read_to_string("src/ffi.rs").unwrap();
+    let functions = ffi
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("pub fn "))
+        .filter_map(|l| l.split('(').next())
+        .filter(|f| f.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+
+    let headers = [
+        "netlink/netlink.h",
+        "netlink/addr.h",
+        "netlink/attr.h",
+        "netlink/cache.h",
+        "netlink/errno.h",
+        "netlink/handlers.h",
+        "netlink/msg.h",
+        "netlink/object.h",
+        "netlink/socket.h",
+        "netlink/fib_lookup/lookup.h",
+        "netlink/route/act/mirred.h",
+        "netlink/route/action.h",
+        "netlink/route/addr.h",
+        "netlink/route/class.h",
+        "netlink/route/classifier.h",
+        "netlink/route/cls/basic.h",
+        "netlink/route/link.h",
+        "netlink/route/link/ip6tnl.h",
+        "netlink/route/link/macvlan.h",
+        "netlink/route/link/sit.h",
+        "netlink/route/link/veth.h",
+        "netlink/route/link/vlan.h",
+        "netlink/route/neighbour.h",
+        "netlink/route/qdisc.h",
+        "netlink/route/qdisc/tbf.h",
+        "netlink/route/route.h",
+        "netlink/route/tc.h",
+    ];
+
+    let mut builder = bindgen::builder()
+        .header_contents(
+            "libnl.h",
+            &headers
+                .iter()
+                .map(|h| format!("#include <{h}>\n"))
+                .collect::<String>(),
+        )
+        .clang_arg(format!("-I{libnl}/include/libnl3"))
+        .rust_edition(bindgen::RustEdition::Edition2024)
+        .ctypes_prefix("libc")
+        .allowlist_recursively(false)
+        .generate_comments(false)
+        .layout_tests(false);
+
+    for function in functions {
+        builder = builder.allowlist_function(function);
+    }
+
+    let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    builder
+        .generate()
+        .expect("could not generate bindings from the libnl headers")
+        .write_to_file(out.join("libnl.rs"))
+        .unwrap();
+}
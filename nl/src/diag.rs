@@ -0,0 +1,446 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Querying the TCP and UDP sockets of the current network namespace
+//! through sock_diag, the way `ss` does. A [`Request`] picks the sockets,
+//! which come back as [`InetSocket`]s, and can be closed with
+//! [`Socket::destroy_socket`]
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use libc::{AF_INET, AF_INET6, c_int, c_void};
+
+use super::{error, ffi::*, netlink::Socket};
+
+/// The transport protocols whose sockets can be listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    fn number(self) -> u8 {
+        match self {
+            Protocol::Tcp => libc::IPPROTO_TCP as u8,
+            Protocol::Udp => libc::IPPROTO_UDP as u8,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+/// Identifies a single socket to the kernel, as needed to look it up or
+/// destroy it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketId {
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    /// The interface the socket is bound to, or 0
+    pub ifindex: u32,
+    /// The kernel's handle for the socket, which stays the same for as
+    /// long as it exists
+    pub cookie: u64,
+}
+
+impl SocketId {
+    fn to_raw(self) -> inet_diag_sockid {
+        let octets = |addr: IpAddr| {
+            let mut octets = [0; 16];
+            match addr {
+                IpAddr::V4(v4) => octets[..4].copy_from_slice(&v4.octets()),
+                IpAddr::V6(v6) => octets = v6.octets(),
+            }
+            octets
+        };
+
+        inet_diag_sockid {
+            idiag_sport: self.local.port().to_be_bytes(),
+            idiag_dport: self.remote.port().to_be_bytes(),
+            idiag_src: octets(self.local.ip()),
+            idiag_dst: octets(self.remote.ip()),
+            idiag_if: self.ifindex,
+            idiag_cookie: [self.cookie as u32, (self.cookie >> 32) as u32],
+        }
+    }
+
+    fn from_raw(family: c_int, raw: &inet_diag_sockid) -> Option<Self> {
+        let addr = |octets: [u8; 16]| -> Option<IpAddr> {
+            match family {
+                AF_INET => Some(IpAddr::from(Ipv4Addr::new(
+                    octets[0], octets[1], octets[2], octets[3],
+                ))),
+                AF_INET6 => Some(IpAddr::from(Ipv6Addr::from(octets))),
+                _ => None,
+            }
+        };
+
+        Some(Self {
+            local: SocketAddr::new(addr(raw.idiag_src)?, u16::from_be_bytes(raw.idiag_sport)),
+            remote: SocketAddr::new(addr(raw.idiag_dst)?, u16::from_be_bytes(raw.idiag_dport)),
+            ifindex: raw.idiag_if,
+            cookie: u64::from(raw.idiag_cookie[0]) | (u64::from(raw.idiag_cookie[1]) << 32),
+        })
+    }
+}
+
+/// Which sockets to ask the kernel about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request {
+    pub protocol: Protocol,
+    /// `AF_INET` or `AF_INET6`, or `None` for both
+    pub family: Option<c_int>,
+    /// A bit for each state to include, `1 << TCP_ESTABLISHED` and so on
+    pub states: u32,
+    /// A single socket to look up instead of dumping every one that matches
+    pub id: Option<SocketId>,
+}
+
+impl Request {
+    /// Asks for every socket of the protocol, in any state
+    pub fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            family: None,
+            states: !0,
+            id: None,
+        }
+    }
+
+    /// Only asks for sockets in one of the states given
+    pub fn with_states(self, states: &[u8]) -> Self {
+        Self {
+            states: states.iter().fold(0, |mask, state| mask | 1 << state),
+            ..self
+        }
+    }
+
+    /// Only asks for the socket given
+    pub fn with_id(self, id: SocketId) -> Self {
+        Self {
+            family: Some(match id.local {
+                SocketAddr::V4(_) => AF_INET,
+                SocketAddr::V6(_) => AF_INET6,
+            }),
+            id: Some(id),
+            ..self
+        }
+    }
+
+    /// The families the request is sent for, one message each
+    fn families(&self) -> Vec<c_int> {
+        match self.family {
+            Some(family) => vec![family],
+            None => vec![AF_INET, AF_INET6],
+        }
+    }
+
+    /// Encodes the request for the family given, as the payload of a
+    /// `SOCK_DIAG_BY_FAMILY` message
+    pub fn encode(&self, family: c_int) -> Vec<u8> {
+        let req = inet_diag_req_v2 {
+            sdiag_family: family as u8,
+            sdiag_protocol: self.protocol.number(),
+            idiag_ext: 1 << (INET_DIAG_INFO - 1),
+            pad: 0,
+            idiag_states: self.states,
+            id: self.id.map(SocketId::to_raw).unwrap_or(inet_diag_sockid {
+                idiag_sport: [0; 2],
+                idiag_dport: [0; 2],
+                idiag_src: [0; 16],
+                idiag_dst: [0; 16],
+                idiag_if: 0,
+                idiag_cookie: [!0; 2], // INET_DIAG_NOCOOKIE
+            }),
+        };
+
+        unsafe {
+            std::slice::from_raw_parts(
+                &req as *const inet_diag_req_v2 as *const u8,
+                size_of::<inet_diag_req_v2>(),
+            )
+        }
+        .to_vec()
+    }
+}
+
+/// A socket as described by the kernel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InetSocket {
+    pub protocol: Protocol,
+    /// One of the `TCP_*` states of linux/tcp_states.h, which UDP sockets
+    /// use too
+    pub state: u8,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub ifindex: u32,
+    pub cookie: u64,
+    pub uid: u32,
+    pub inode: u32,
+    /// Bytes waiting to be read by the program
+    pub recv_queue: u32,
+    /// Bytes waiting to be sent, or acknowledged for TCP
+    pub send_queue: u32,
+    /// Bytes sent and acknowledged by the peer, which only TCP keeps count of
+    pub bytes_acked: Option<u64>,
+    /// Bytes received, which only TCP keeps count of
+    pub bytes_received: Option<u64>,
+    /// How long ago data was last sent, for TCP
+    pub last_data_sent: Option<Duration>,
+    /// How long ago data was last received, for TCP
+    pub last_data_received: Option<Duration>,
+}
+
+impl InetSocket {
+    pub const TCP_ESTABLISHED: u8 = 1;
+    pub const TCP_SYN_SENT: u8 = 2;
+    pub const TCP_TIME_WAIT: u8 = 6;
+    pub const TCP_CLOSE: u8 = 7;
+    pub const TCP_CLOSE_WAIT: u8 = 8;
+    pub const TCP_LISTEN: u8 = 10;
+
+    /// The name `ss` shows for the state of the socket
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            1 => "ESTAB",
+            2 => "SYN-SENT",
+            3 => "SYN-RECV",
+            4 => "FIN-WAIT-1",
+            5 => "FIN-WAIT-2",
+            6 => "TIME-WAIT",
+            7 if self.protocol == Protocol::Udp => "UNCONN",
+            7 => "CLOSE",
+            8 => "CLOSE-WAIT",
+            9 => "LAST-ACK",
+            10 => "LISTEN",
+            11 => "CLOSING",
+            12 => "NEW-SYN-RECV",
+            _ => "UNKNOWN",
+        }
+    }
+
+    /// What identifies the socket to the kernel
+    pub fn id(&self) -> SocketId {
+        SocketId {
+            local: self.local,
+            remote: self.remote,
+            ifindex: self.ifindex,
+            cookie: self.cookie,
+        }
+    }
+
+    /// How long the socket has neither sent nor received any data, for TCP
+    pub fn idle(&self) -> Option<Duration> {
+        Some(self.last_data_sent?.min(self.last_data_received?))
+    }
+
+    /// Parses the payload of a `SOCK_DIAG_BY_FAMILY` message describing a
+    /// socket of the protocol given
+    pub fn decode(protocol: Protocol, payload: &[u8]) -> Option<Self> {
+        if payload.len() < size_of::<inet_diag_msg>() {
+            return None;
+        }
+        let msg = unsafe { std::ptr::read_unaligned(payload.as_ptr() as *const inet_diag_msg) };
+        let id = SocketId::from_raw(c_int::from(msg.idiag_family), &msg.id)?;
+
+        let mut socket = InetSocket {
+            protocol,
+            state: msg.idiag_state,
+            local: id.local,
+            remote: id.remote,
+            ifindex: id.ifindex,
+            cookie: id.cookie,
+            uid: msg.idiag_uid,
+            inode: msg.idiag_inode,
+            recv_queue: msg.idiag_rqueue,
+            send_queue: msg.idiag_wqueue,
+            bytes_acked: None,
+            bytes_received: None,
+            last_data_sent: None,
+            last_data_received: None,
+        };
+
+        // struct tcp_info, which has grown over time; the byte counters
+        // are missing before Linux 4.2
+        for (kind, data) in attributes(&payload[size_of::<inet_diag_msg>()..]) {
+            if kind != INET_DIAG_INFO {
+                continue;
+            }
+
+            let millis = |at: usize| {
+                Some(Duration::from_millis(u64::from(u32::from_ne_bytes(
+                    data.get(at..at + 4)?.try_into().ok()?,
+                ))))
+            };
+            let counter =
+                |at: usize| Some(u64::from_ne_bytes(data.get(at..at + 8)?.try_into().ok()?));
+
+            socket.last_data_sent = millis(44);
+            socket.last_data_received = millis(52);
+            socket.bytes_acked = counter(120);
+            socket.bytes_received = counter(128);
+        }
+
+        Some(socket)
+    }
+}
+
+/// Splits the attributes following the header of a message into their
+/// types and payloads
+fn attributes(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let len = usize::from(u16::from_ne_bytes(data.get(..2)?.try_into().ok()?));
+        let kind = u16::from_ne_bytes(data.get(2..4)?.try_into().ok()?);
+        let payload = data.get(4..len)?;
+
+        data = data.get(len.next_multiple_of(4)..).unwrap_or_default();
+        Some((
+            kind & 0x3fff, /* ~(NLA_F_NESTED | NLA_F_NET_BYTEORDER) */
+            payload,
+        ))
+    })
+}
+
+/// What [`collect_sockets_cb`] needs to know and fill in
+struct Collection {
+    protocol: Protocol,
+    sockets: Vec<InetSocket>,
+}
+
+/// Gathers the sockets described by each message of a reply
+extern "C" fn collect_sockets_cb(msg: *mut nl_msg, arg: *mut c_void) -> c_int {
+    unsafe {
+        let collection = &mut *(arg as *mut Collection);
+        let hdr = nlmsg_hdr(msg);
+
+        if c_int::from((*hdr).nlmsg_type) == SOCK_DIAG_BY_FAMILY {
+            let len = ((*hdr).nlmsg_len as usize).saturating_sub(size_of::<libc::nlmsghdr>());
+            let payload = std::slice::from_raw_parts(
+                (hdr as *const u8).add(size_of::<libc::nlmsghdr>()),
+                len,
+            );
+            collection
+                .sockets
+                .extend(InetSocket::decode(collection.protocol, payload));
+        }
+    }
+
+    NL_OK
+}
+
+impl Socket {
+    /// Asks for the sockets matching the request, in the network namespace
+    /// the socket was opened in. Requires a socket from
+    /// [`Socket::new_sock_diag`]
+    pub fn query_sockets(&self, request: &Request) -> error::Result<Vec<InetSocket>> {
+        let mut collection = Collection {
+            protocol: request.protocol,
+            sockets: Vec::new(),
+        };
+        let flags = if request.id.is_some() { 0 } else { NLM_F_DUMP };
+
+        for family in request.families() {
+            let mut payload = request.encode(family);
+
+            // A dump which is retried starts over from the first socket
+            let start = collection.sockets.len();
+            let ret = self.retrying(|| unsafe {
+                collection.sockets.truncate(start);
+
+                let ret = nl_send_simple(
+                    self.sock,
+                    SOCK_DIAG_BY_FAMILY,
+                    flags,
+                    payload.as_mut_ptr() as *mut c_void,
+                    payload.len(),
+                );
+                if ret < 0 {
+                    return ret;
+                }
+
+                let sock_cb = nl_socket_get_cb(self.sock);
+                let cb = nl_cb_clone(sock_cb);
+                nl_cb_put(sock_cb);
+                if cb.is_null() {
+                    return -5 /* NLE_NOMEM */;
+                }
+
+                nl_cb_set(
+                    cb,
+                    NL_CB_VALID,
+                    NL_CB_CUSTOM,
+                    collect_sockets_cb,
+                    &mut collection as *mut Collection as *mut c_void,
+                );
+                let mut ret = nl_recvmsgs(self.sock, cb);
+                nl_cb_put(cb);
+
+                // Unlike a dump, a lookup is acknowledged after its reply
+                if ret >= 0 && flags == 0 {
+                    ret = nl_wait_for_ack(self.sock);
+                }
+                ret
+            });
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+        }
+
+        Ok(collection.sockets)
+    }
+
+    /// Closes a socket as if the connection had been reset, waking up
+    /// whatever is waiting on it with `ECONNABORTED`. The kernel has to be
+    /// built with `CONFIG_INET_DIAG_DESTROY`, and fails with
+    /// [`error::Error::NLE_OPNOTSUPP`] otherwise
+    pub fn destroy_socket(&self, protocol: Protocol, id: SocketId) -> error::Result<()> {
+        let request = Request::new(protocol).with_id(id);
+        let mut payload = request.encode(request.families()[0]);
+
+        let ret = self.retrying(|| unsafe {
+            match nl_send_simple(
+                self.sock,
+                SOCK_DESTROY,
+                0,
+                payload.as_mut_ptr() as *mut c_void,
+                payload.len(),
+            ) {
+                ret if ret < 0 => ret,
+                _ => nl_wait_for_ack(self.sock),
+            }
+        });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// Lists the IPv4 and IPv6 sockets of a protocol in every state, see
+    /// [`Socket::query_sockets`]
+    pub fn get_inet_sockets(&self, protocol: Protocol) -> error::Result<Vec<InetSocket>> {
+        self.query_sockets(&Request::new(protocol))
+    }
+}
@@ -26,13 +26,48 @@ pub struct Error {
 }
 
 impl Error {
+    /// A system call was interrupted by a signal
+    pub const NLE_INTR: c_int = 2;
+    /// The operation would block, or a socket timeout expired
+    pub const NLE_AGAIN: c_int = 4;
+    /// The kernel doesn't support the operation
+    pub const NLE_OPNOTSUPP: c_int = 10;
+    /// The object asked for doesn't exist
+    pub const NLE_OBJ_NOTFOUND: c_int = 12;
+    /// The device or resource is busy
+    pub const NLE_BUSY: c_int = 25;
+    /// The kernel's tables changed while they were being dumped
+    pub const NLE_DUMP_INTR: c_int = 33;
+    /// Not a libnl code: the kernel didn't answer within the timeout set
+    /// with [`crate::netlink::Socket::set_timeout`]
+    pub const NLE_TIMEOUT: c_int = 0x1000;
+
     pub(crate) fn new(error_code: c_int) -> Self {
         Error { error_code }
     }
+
+    /// The libnl error code, e.g. [`Error::NLE_BUSY`]
+    pub fn code(&self) -> c_int {
+        self.error_code.abs()
+    }
+
+    /// Whether the same request is likely to succeed if it is sent again
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.code(), Self::NLE_BUSY | Self::NLE_DUMP_INTR)
+    }
+
+    /// Whether the kernel didn't answer in time
+    pub fn is_timeout(&self) -> bool {
+        self.code() == Self::NLE_TIMEOUT
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_timeout() {
+            return write!(f, "netlink request timed out");
+        }
+
         let error_msg_utf8 = unsafe {
             let error_msg = nl_geterror(self.error_code);
             let error_msg_ptr = CStr::from_ptr(error_msg);
@@ -0,0 +1,500 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+use libc::{c_char, c_int, c_uint, c_void};
+
+macro_rules! nl_obj {
+    ($name:ident) => {
+        #[repr(C)]
+        #[allow(non_camel_case_types)]
+        pub struct $name {
+            _data: [u8; 0],
+            _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+        }
+    };
+}
+
+nl_obj!(nl_sock);
+nl_obj!(nl_cache);
+nl_obj!(nl_addr);
+nl_obj!(nl_object);
+nl_obj!(nl_list_head);
+nl_obj!(rtnl_addr);
+nl_obj!(rtnl_link);
+nl_obj!(rtnl_neigh);
+nl_obj!(rtnl_route);
+nl_obj!(rtnl_nexthop);
+nl_obj!(flnl_request);
+nl_obj!(flnl_result);
+nl_obj!(nl_msg);
+nl_obj!(nl_cb);
+nl_obj!(rtnl_qdisc);
+nl_obj!(rtnl_tc);
+nl_obj!(rtnl_cls);
+nl_obj!(rtnl_act);
+
+pub const NL_OK: c_int = 0;
+pub const NL_CB_VALID: c_int = 0;
+pub const NL_CB_DEFAULT: c_int = 0;
+pub const NL_CB_DEBUG: c_int = 2;
+pub const NL_CB_CUSTOM: c_int = 3;
+
+pub const SOL_NETLINK: c_int = 270;
+pub const NETLINK_GET_STRICT_CHK: c_int = 12;
+pub const NETLINK_FIB_LOOKUP: c_int = 10;
+pub const NETLINK_SOCK_DIAG: c_int = 4;
+
+pub const RTM_NEWROUTE: c_int = 24;
+pub const RTM_DELROUTE: c_int = 25;
+pub const RTM_GETROUTE: c_int = 26;
+pub const RTM_NEWNEIGH: c_int = 28;
+pub const RTM_DELNEIGH: c_int = 29;
+pub const RTNLGRP_NEIGH: c_int = 3;
+pub const RTM_NEWRULE: c_int = 32;
+pub const RTM_DELRULE: c_int = 33;
+pub const RTM_NEWNEXTHOP: c_int = 104;
+pub const RTM_DELNEXTHOP: c_int = 105;
+pub const RTA_DST: c_int = 1;
+pub const RTA_TABLE: c_int = 15;
+pub const RTA_NH_ID: c_int = 30;
+pub const NHA_ID: c_int = 1;
+pub const NHA_GROUP: c_int = 2;
+pub const NHA_OIF: c_int = 5;
+pub const NHA_GATEWAY: c_int = 6;
+pub const FRA_PRIORITY: c_int = 6;
+pub const FRA_FWMARK: c_int = 10;
+pub const FRA_TABLE: c_int = 15;
+pub const FRA_FWMASK: c_int = 16;
+pub const FR_ACT_TO_TBL: u8 = 1;
+pub const NLM_F_EXCL: c_int = 0x200;
+pub const NLM_F_CREATE: c_int = 0x400;
+pub const RTPROT_BOOT: u8 = 3;
+pub const NTF_PROXY: c_uint = 0x08;
+pub const NUD_PERMANENT: c_int = 0x80;
+pub const IFA_F_NODAD: c_uint = 0x02;
+
+pub const TC_H_ROOT: u32 = 0xFFFFFFFF;
+pub const TC_H_CLSACT: u32 = 0xFFFFFFF1;
+pub const TC_H_CLSACT_HANDLE: u32 = 0xFFFF0000;
+pub const TC_H_MIN_INGRESS: u32 = 0xFFF2;
+pub const TC_H_MIN_EGRESS: u32 = 0xFFF3;
+pub const TCA_EGRESS_MIRROR: c_int = 2;
+pub const TC_ACT_PIPE: c_int = 3;
+pub const ETH_P_ALL: u16 = 0x0003;
+
+pub const RTNL_TC_PACKETS: c_uint = 0;
+pub const RTNL_TC_BYTES: c_uint = 1;
+pub const RTNL_TC_QLEN: c_uint = 4;
+pub const RTNL_TC_BACKLOG: c_uint = 5;
+pub const RTNL_TC_DROPS: c_uint = 6;
+pub const RTNL_TC_REQUEUES: c_uint = 7;
+pub const RTNL_TC_OVERLIMITS: c_uint = 8;
+
+/// Header of rtnetlink route messages, from linux/rtnetlink.h
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct rtmsg {
+    pub rtm_family: u8,
+    pub rtm_dst_len: u8,
+    pub rtm_src_len: u8,
+    pub rtm_tos: u8,
+    pub rtm_table: u8,
+    pub rtm_protocol: u8,
+    pub rtm_scope: u8,
+    pub rtm_type: u8,
+    pub rtm_flags: c_uint,
+}
+
+/// Header of nexthop object messages, from linux/nexthop.h
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct nhmsg {
+    pub nh_family: u8,
+    pub nh_scope: u8,
+    pub nh_protocol: u8,
+    pub resvd: u8,
+    pub nh_flags: c_uint,
+}
+
+pub const SOCK_DIAG_BY_FAMILY: c_int = 20;
+pub const SOCK_DESTROY: c_int = 21;
+pub const NLM_F_DUMP: c_int = 0x300;
+pub const INET_DIAG_INFO: u16 = 2;
+
+/// Identifies a socket in sock_diag messages, from linux/inet_diag.h.
+/// Ports and addresses are in network byte order
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+pub struct inet_diag_sockid {
+    pub idiag_sport: [u8; 2],
+    pub idiag_dport: [u8; 2],
+    pub idiag_src: [u8; 16],
+    pub idiag_dst: [u8; 16],
+    pub idiag_if: u32,
+    pub idiag_cookie: [u32; 2],
+}
+
+/// A request for the sockets of a family and protocol, from linux/inet_diag.h
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct inet_diag_req_v2 {
+    pub sdiag_family: u8,
+    pub sdiag_protocol: u8,
+    pub idiag_ext: u8,
+    pub pad: u8,
+    pub idiag_states: u32,
+    pub id: inet_diag_sockid,
+}
+
+/// Header of the messages describing a socket, from linux/inet_diag.h
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+pub struct inet_diag_msg {
+    pub idiag_family: u8,
+    pub idiag_state: u8,
+    pub idiag_timer: u8,
+    pub idiag_retrans: u8,
+    pub id: inet_diag_sockid,
+    pub idiag_expires: u32,
+    pub idiag_rqueue: u32,
+    pub idiag_wqueue: u32,
+    pub idiag_uid: u32,
+    pub idiag_inode: u32,
+}
+
+/// A member of a nexthop group, from linux/nexthop.h
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct nexthop_grp {
+    pub id: u32,
+    pub weight: u8,
+    pub resvd1: u8,
+    pub resvd2: u16,
+}
+
+/// Header of policy routing rule messages, from linux/fib_rules.h
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct fib_rule_hdr {
+    pub family: u8,
+    pub dst_len: u8,
+    pub src_len: u8,
+    pub tos: u8,
+    pub table: u8,
+    pub res1: u8,
+    pub res2: u8,
+    pub action: u8,
+    pub flags: u32,
+}
+
+// Names the generated declarations use for types which aren't generated
+#[cfg(feature = "bindgen")]
+pub use libc::{in6_addr, nlmsghdr, pid_t};
+#[cfg(feature = "bindgen")]
+#[allow(non_camel_case_types)]
+pub type nl_cb_kind = c_int;
+#[cfg(feature = "bindgen")]
+#[allow(non_camel_case_types)]
+pub type nl_cb_type = c_int;
+#[cfg(feature = "bindgen")]
+#[allow(non_camel_case_types)]
+pub type rtnl_tc_stat = c_uint;
+#[cfg(feature = "bindgen")]
+#[allow(non_camel_case_types)]
+pub type nl_recvmsg_msg_cb_t = extern "C" fn(*mut nl_msg, *mut c_void) -> c_int;
+
+#[cfg(feature = "bindgen")]
+include!(concat!(env!("OUT_DIR"), "/libnl.rs"));
+
+/// Declares the functions used from libnl: as plain externs when libnl is
+/// linked in, or as wrappers around pointers looked up by [`crate::runtime`]
+/// when it is loaded as the program starts
+macro_rules! libnl {
+    ($(pub fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*) => {
+        #[cfg(not(any(feature = "bindgen", feature = "runtime-libnl")))]
+        unsafe extern "C" {
+            $(pub fn $name($($arg: $ty),*) $(-> $ret)?;)*
+        }
+
+        /// Every function of libnl, as found in the loaded libraries
+        #[cfg(feature = "runtime-libnl")]
+        #[allow(dead_code)]
+        pub(crate) struct Symbols {
+            $($name: unsafe extern "C" fn($($ty),*) $(-> $ret)?,)*
+        }
+
+        #[cfg(feature = "runtime-libnl")]
+        impl Symbols {
+            pub(crate) fn find(libs: &[libloading::Library]) -> Result<Self, libloading::Error> {
+                Ok(Self {
+                    $($name: crate::runtime::find(libs, concat!(stringify!($name), "\0"))?,)*
+                })
+            }
+        }
+
+        $(
+            #[cfg(feature = "runtime-libnl")]
+            #[allow(dead_code)]
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                unsafe { (crate::runtime::symbols().$name)($($arg),*) }
+            }
+        )*
+    };
+}
+
+// from libnl and libnl-route. With the bindgen feature, these only serve as
+// the list of functions to generate declarations for
+libnl! {
+    pub fn nl_socket_alloc() -> *mut nl_sock;
+    pub fn nl_socket_free(sock: *mut nl_sock);
+    pub fn nl_socket_get_local_port(sock: *const nl_sock) -> u32;
+    pub fn nl_connect(sock: *mut nl_sock, protocol: c_int) -> c_int;
+    pub fn nl_close(sock: *mut nl_sock);
+    pub fn nl_geterror(error: c_int) -> *const c_char;
+    pub fn nl_send_auto(sock: *mut nl_sock, msg: *mut nl_msg) -> c_int;
+    pub fn nl_recvmsgs(sock: *mut nl_sock, cb: *mut nl_cb) -> c_int;
+    pub fn nl_wait_for_ack(sock: *mut nl_sock) -> c_int;
+    pub fn nl_send_simple(
+        sock: *mut nl_sock,
+        msg_type: c_int,
+        flags: c_int,
+        buf: *mut c_void,
+        size: usize,
+    ) -> c_int;
+    pub fn nl_socket_get_cb(sock: *const nl_sock) -> *mut nl_cb;
+    pub fn nl_socket_get_fd(sock: *const nl_sock) -> c_int;
+    pub fn nl_socket_set_buffer_size(sock: *mut nl_sock, rxbuf: c_int, txbuf: c_int) -> c_int;
+    pub fn nl_socket_enable_msg_peek(sock: *mut nl_sock);
+    pub fn nl_socket_disable_msg_peek(sock: *mut nl_sock);
+    pub fn nl_syserr2nlerr(error: c_int) -> c_int;
+    pub fn nl_socket_add_membership(sock: *mut nl_sock, group: c_int) -> c_int;
+    pub fn nl_socket_disable_seq_check(sock: *mut nl_sock);
+
+    pub fn nl_socket_set_cb(sock: *mut nl_sock, cb: *mut nl_cb);
+
+    pub fn nl_cb_alloc(kind: c_int) -> *mut nl_cb;
+    pub fn nl_cb_clone(cb: *mut nl_cb) -> *mut nl_cb;
+    pub fn nl_cb_set(
+        cb: *mut nl_cb,
+        cb_type: c_int,
+        kind: c_int,
+        func: extern "C" fn(*mut nl_msg, *mut c_void) -> c_int,
+        arg: *mut c_void,
+    ) -> c_int;
+    pub fn nl_cb_put(cb: *mut nl_cb);
+
+    pub fn nlmsg_alloc_simple(nlmsgtype: c_int, flags: c_int) -> *mut nl_msg;
+    pub fn nlmsg_append(
+        msg: *mut nl_msg,
+        data: *mut c_void,
+        len: libc::size_t,
+        pad: c_int,
+    ) -> c_int;
+    pub fn nlmsg_hdr(msg: *mut nl_msg) -> *mut libc::nlmsghdr;
+    pub fn nlmsg_free(msg: *mut nl_msg);
+    pub fn nla_put(msg: *mut nl_msg, attrtype: c_int, datalen: c_int, data: *const c_void)
+    -> c_int;
+
+    pub fn nl_object_put(obj: *mut nl_object);
+
+    pub fn nl_addr_get_len(addr: *mut nl_addr) -> c_uint;
+    pub fn nl_addr_get_binary_addr(addr: *mut nl_addr) -> *mut c_void;
+    pub fn nl_addr_build(family: c_int, buf: *const c_void, size: libc::size_t) -> *mut nl_addr;
+    pub fn nl_addr_put(addr: *mut nl_addr);
+    pub fn nl_addr_get_family(addr: *mut nl_addr) -> c_int;
+    pub fn nl_addr_get_prefixlen(addr: *mut nl_addr) -> c_uint;
+    pub fn nl_addr_set_prefixlen(addr: *mut nl_addr, cidr: c_int);
+
+    pub fn nl_cache_foreach(
+        cache: *mut nl_cache,
+        cb: extern "C" fn(*mut nl_object, *mut c_void),
+        arg: *mut c_void,
+    );
+    pub fn nl_cache_put(cache: *mut nl_cache);
+    pub fn nl_cache_subset(orig: *mut nl_cache, filter: *mut nl_object) -> *mut nl_cache;
+    pub fn nl_cache_nitems(cache: *mut nl_cache) -> c_int;
+    pub fn nl_cache_get_first(cache: *mut nl_cache) -> *mut nl_object;
+    pub fn nl_cache_get_next(obj: *mut nl_object) -> *mut nl_object;
+
+    pub fn rtnl_addr_alloc_cache(sock: *mut nl_sock, result: *mut *mut nl_cache) -> c_int;
+    pub fn rtnl_addr_alloc() -> *mut rtnl_addr;
+    pub fn rtnl_addr_get_ifindex(addr: *mut rtnl_addr) -> c_int;
+    pub fn rtnl_addr_set_ifindex(addr: *mut rtnl_addr, index: c_int);
+    pub fn rtnl_addr_set_prefixlen(addr: *mut rtnl_addr, cidr: c_int);
+    pub fn rtnl_addr_get_prefixlen(addr: *mut rtnl_addr) -> c_int;
+    pub fn rtnl_addr_get_family(addr: *mut rtnl_addr) -> c_int;
+    pub fn rtnl_addr_get_local(addr: *mut rtnl_addr) -> *mut nl_addr;
+    pub fn rtnl_addr_set_local(addr: *mut rtnl_addr, local: *mut nl_addr) -> c_int;
+    pub fn rtnl_addr_set_broadcast(addr: *mut rtnl_addr, broadcast: *mut nl_addr) -> c_int;
+    pub fn rtnl_addr_set_peer(addr: *mut rtnl_addr, peer: *mut nl_addr) -> c_int;
+    pub fn rtnl_addr_set_label(addr: *mut rtnl_addr, label: *const c_char) -> c_int;
+    pub fn rtnl_addr_get_label(addr: *mut rtnl_addr) -> *mut c_char;
+    pub fn rtnl_addr_set_flags(addr: *mut rtnl_addr, flags: c_uint);
+    pub fn rtnl_addr_add(sock: *mut nl_sock, addr: *mut rtnl_addr, flags: c_int) -> c_int;
+
+    pub fn rtnl_neigh_alloc_cache(sock: *mut nl_sock, result: *mut *mut nl_cache) -> c_int;
+    pub fn rtnl_neigh_get(
+        cache: *mut nl_cache,
+        ifindex: c_int,
+        dst: *mut nl_addr,
+    ) -> *mut rtnl_neigh;
+    pub fn rtnl_neigh_get_dst(neigh: *mut rtnl_neigh) -> *mut nl_addr;
+    pub fn rtnl_neigh_get_lladdr(neigh: *mut rtnl_neigh) -> *mut nl_addr;
+    pub fn rtnl_neigh_get_ifindex(neigh: *mut rtnl_neigh) -> c_int;
+    pub fn rtnl_neigh_get_state(neigh: *mut rtnl_neigh) -> c_int;
+    pub fn rtnl_neigh_state2str(state: c_int, buf: *mut c_char, len: usize) -> *mut c_char;
+    pub fn rtnl_neigh_parse(nlh: *mut libc::nlmsghdr, result: *mut *mut rtnl_neigh) -> c_int;
+    pub fn rtnl_neigh_alloc() -> *mut rtnl_neigh;
+    pub fn rtnl_neigh_put(neigh: *mut rtnl_neigh);
+    pub fn rtnl_neigh_set_ifindex(neigh: *mut rtnl_neigh, ifindex: c_int);
+    pub fn rtnl_neigh_set_dst(neigh: *mut rtnl_neigh, addr: *mut nl_addr) -> c_int;
+    pub fn rtnl_neigh_set_state(neigh: *mut rtnl_neigh, state: c_int);
+    pub fn rtnl_neigh_set_flags(neigh: *mut rtnl_neigh, flags: c_uint);
+    pub fn rtnl_neigh_add(sock: *mut nl_sock, neigh: *mut rtnl_neigh, flags: c_int) -> c_int;
+    pub fn rtnl_neigh_delete(sock: *mut nl_sock, neigh: *mut rtnl_neigh, flags: c_int) -> c_int;
+
+    pub fn rtnl_link_alloc() -> *mut rtnl_link;
+    pub fn rtnl_link_put(link: *mut rtnl_link);
+    pub fn rtnl_link_veth_alloc() -> *mut rtnl_link;
+    pub fn rtnl_link_macvlan_alloc() -> *mut rtnl_link;
+    pub fn rtnl_link_sit_alloc() -> *mut rtnl_link;
+    pub fn rtnl_link_sit_set_local(link: *mut rtnl_link, addr: u32) -> c_int;
+    pub fn rtnl_link_sit_get_local(link: *mut rtnl_link) -> u32;
+    pub fn rtnl_link_sit_set_remote(link: *mut rtnl_link, addr: u32) -> c_int;
+    pub fn rtnl_link_sit_get_remote(link: *mut rtnl_link) -> u32;
+    pub fn rtnl_link_sit_set_ttl(link: *mut rtnl_link, ttl: u8) -> c_int;
+    pub fn rtnl_link_ip6_tnl_alloc() -> *mut rtnl_link;
+    pub fn rtnl_link_ip6_tnl_set_local(link: *mut rtnl_link, addr: *mut libc::in6_addr) -> c_int;
+    pub fn rtnl_link_ip6_tnl_get_local(link: *mut rtnl_link, addr: *mut libc::in6_addr) -> c_int;
+    pub fn rtnl_link_ip6_tnl_set_remote(link: *mut rtnl_link, addr: *mut libc::in6_addr) -> c_int;
+    pub fn rtnl_link_ip6_tnl_get_remote(link: *mut rtnl_link, addr: *mut libc::in6_addr) -> c_int;
+    pub fn rtnl_link_ip6_tnl_set_ttl(link: *mut rtnl_link, ttl: u8) -> c_int;
+    pub fn rtnl_link_ip6_tnl_set_proto(link: *mut rtnl_link, proto: u8) -> c_int;
+    pub fn rtnl_link_get(cache: *mut nl_cache, index: c_int) -> *mut rtnl_link;
+    pub fn rtnl_link_alloc_cache(
+        sock: *mut nl_sock,
+        family: c_int,
+        result: *mut *mut nl_cache,
+    ) -> c_int;
+    pub fn rtnl_link_get_addr(link: *mut rtnl_link) -> *mut nl_addr;
+    pub fn rtnl_link_get_name(link: *mut rtnl_link) -> *const c_char;
+    pub fn rtnl_link_get_ifindex(link: *mut rtnl_link) -> c_int;
+    pub fn rtnl_link_get_type(link: *mut rtnl_link) -> *const c_char;
+    pub fn rtnl_link_set_type(link: *mut rtnl_link, kind: *const c_char) -> c_int;
+    pub fn rtnl_link_get_flags(link: *mut rtnl_link) -> c_uint;
+    pub fn rtnl_link_set_flags(link: *mut rtnl_link, flags: c_uint);
+    pub fn rtnl_link_unset_flags(link: *mut rtnl_link, flags: c_uint);
+    pub fn rtnl_link_get_mtu(link: *mut rtnl_link) -> c_uint;
+    pub fn rtnl_link_set_mtu(link: *mut rtnl_link, mtu: c_uint);
+    pub fn rtnl_link_set_num_tx_queues(link: *mut rtnl_link, nqueues: u32);
+    pub fn rtnl_link_set_num_rx_queues(link: *mut rtnl_link, nqueues: u32);
+    pub fn rtnl_link_set_txqlen(link: *mut rtnl_link, txqlen: c_uint);
+
+    pub fn flnl_request_alloc() -> *mut flnl_request;
+    pub fn flnl_request_set_addr(req: *mut flnl_request, addr: *mut nl_addr) -> c_int;
+    pub fn flnl_request_set_fwmark(req: *mut flnl_request, fwmark: u64);
+    pub fn flnl_result_alloc_cache() -> *mut nl_cache;
+    pub fn flnl_lookup(sock: *mut nl_sock, req: *mut flnl_request, cache: *mut nl_cache) -> c_int;
+    pub fn flnl_result_get_table_id(res: *mut flnl_result) -> c_int;
+    pub fn flnl_result_get_prefixlen(res: *mut flnl_result) -> c_int;
+    pub fn flnl_result_get_nexthop_sel(res: *mut flnl_result) -> c_int;
+    pub fn flnl_result_get_type(res: *mut flnl_result) -> c_int;
+    pub fn flnl_result_get_scope(res: *mut flnl_result) -> c_int;
+    pub fn flnl_result_get_error(res: *mut flnl_result) -> c_int;
+
+    pub fn rtnl_qdisc_alloc() -> *mut rtnl_qdisc;
+    pub fn rtnl_qdisc_put(qdisc: *mut rtnl_qdisc);
+    pub fn rtnl_qdisc_add(sock: *mut nl_sock, qdisc: *mut rtnl_qdisc, flags: c_int) -> c_int;
+    pub fn rtnl_qdisc_tbf_set_rate(qdisc: *mut rtnl_qdisc, rate: c_int, bucket: c_int, cell: c_int);
+    pub fn rtnl_qdisc_tbf_set_limit_by_latency(qdisc: *mut rtnl_qdisc, latency: c_int) -> c_int;
+    pub fn rtnl_tc_set_ifindex(tc: *mut rtnl_tc, ifindex: c_int);
+    pub fn rtnl_tc_set_parent(tc: *mut rtnl_tc, parent: u32);
+    pub fn rtnl_tc_set_kind(tc: *mut rtnl_tc, kind: *const c_char) -> c_int;
+    pub fn rtnl_tc_set_handle(tc: *mut rtnl_tc, handle: u32);
+    pub fn rtnl_cls_alloc() -> *mut rtnl_cls;
+    pub fn rtnl_cls_put(cls: *mut rtnl_cls);
+    pub fn rtnl_cls_add(sock: *mut nl_sock, cls: *mut rtnl_cls, flags: c_int) -> c_int;
+    pub fn rtnl_cls_set_protocol(cls: *mut rtnl_cls, protocol: u16);
+    pub fn rtnl_basic_add_action(cls: *mut rtnl_cls, act: *mut rtnl_act) -> c_int;
+    pub fn rtnl_act_alloc() -> *mut rtnl_act;
+    pub fn rtnl_act_put(act: *mut rtnl_act);
+    pub fn rtnl_mirred_set_action(act: *mut rtnl_act, action: c_int) -> c_int;
+    pub fn rtnl_mirred_set_ifindex(act: *mut rtnl_act, ifindex: u32);
+    pub fn rtnl_mirred_set_policy(act: *mut rtnl_act, policy: c_int) -> c_int;
+    pub fn rtnl_qdisc_alloc_cache(sock: *mut nl_sock, result: *mut *mut nl_cache) -> c_int;
+    pub fn rtnl_class_alloc_cache(
+        sock: *mut nl_sock,
+        ifindex: c_int,
+        result: *mut *mut nl_cache,
+    ) -> c_int;
+    pub fn rtnl_tc_get_ifindex(tc: *mut rtnl_tc) -> c_int;
+    pub fn rtnl_tc_get_handle(tc: *mut rtnl_tc) -> u32;
+    pub fn rtnl_tc_get_parent(tc: *mut rtnl_tc) -> u32;
+    pub fn rtnl_tc_get_kind(tc: *mut rtnl_tc) -> *mut c_char;
+    pub fn rtnl_tc_get_stat(tc: *mut rtnl_tc, id: c_uint) -> u64;
+    pub fn rtnl_link_set_ns_pid(link: *mut rtnl_link, pid: libc::pid_t);
+    pub fn rtnl_link_set_name(link: *mut rtnl_link, name: *const c_char);
+    pub fn rtnl_link_change(
+        sock: *mut nl_sock,
+        link: *mut rtnl_link,
+        changes: *mut rtnl_link,
+        flags: c_int,
+    ) -> c_int;
+    pub fn rtnl_link_add(sock: *mut nl_sock, link: *mut rtnl_link, flags: c_int) -> c_int;
+    pub fn rtnl_link_delete(sock: *mut nl_sock, link: *const rtnl_link) -> c_int;
+    pub fn rtnl_link_veth_get_peer(link: *mut rtnl_link) -> *mut rtnl_link;
+    pub fn rtnl_link_get_link(link: *mut rtnl_link) -> c_int;
+    pub fn rtnl_link_set_link(link: *mut rtnl_link, ifindex: c_int);
+    pub fn rtnl_link_set_addr(link: *mut rtnl_link, addr: *mut nl_addr);
+    pub fn rtnl_link_get_master(link: *mut rtnl_link) -> c_int;
+    pub fn rtnl_link_set_master(link: *mut rtnl_link, ifindex: c_int);
+    pub fn rtnl_link_enslave_ifindex(sock: *mut nl_sock, master: c_int, slave: c_int) -> c_int;
+    pub fn rtnl_link_release_ifindex(sock: *mut nl_sock, slave: c_int) -> c_int;
+    pub fn rtnl_link_vlan_get_id(link: *mut rtnl_link) -> c_int;
+
+    pub fn rtnl_route_alloc() -> *mut rtnl_route;
+    pub fn rtnl_route_alloc_cache(
+        sock: *mut nl_sock,
+        family: c_int,
+        flags: c_int,
+        result: *mut *mut nl_cache,
+    ) -> c_int;
+    pub fn rtnl_route_get_src(route: *mut rtnl_route) -> *mut nl_addr;
+    pub fn rtnl_route_get_dst(route: *mut rtnl_route) -> *mut nl_addr;
+    pub fn rtnl_route_set_dst(route: *mut rtnl_route, addr: *mut nl_addr) -> c_int;
+    pub fn rtnl_route_get_table(route: *mut rtnl_route) -> u32;
+    pub fn rtnl_route_set_table(route: *mut rtnl_route, table: u32);
+    pub fn rtnl_route_get_type(route: *mut rtnl_route) -> u8;
+    pub fn rtnl_route_get_iif(route: *mut rtnl_route) -> c_int;
+    pub fn rtnl_route_get_pref_src(route: *mut rtnl_route) -> *mut nl_addr;
+    pub fn rtnl_route_get_priority(route: *mut rtnl_route) -> u32;
+    pub fn rtnl_route_parse(nlh: *mut libc::nlmsghdr, result: *mut *mut rtnl_route) -> c_int;
+    pub fn rtnl_route_put(route: *mut rtnl_route);
+    pub fn rtnl_route_add_nexthop(route: *mut rtnl_route, hop: *mut rtnl_nexthop);
+    pub fn rtnl_route_get_nnexthops(route: *mut rtnl_route) -> c_int;
+    pub fn rtnl_route_nexthop_n(route: *mut rtnl_route, ind: c_int) -> *mut rtnl_nexthop;
+    pub fn rtnl_route_add(sock: *mut nl_sock, route: *mut rtnl_route, flags: c_int) -> c_int;
+
+    pub fn rtnl_route_nh_alloc() -> *mut rtnl_nexthop;
+    pub fn rtnl_route_nh_get_gateway(hop: *mut rtnl_nexthop) -> *mut nl_addr;
+    pub fn rtnl_route_nh_set_gateway(hop: *mut rtnl_nexthop, addr: *mut nl_addr);
+    pub fn rtnl_route_nh_get_ifindex(hop: *mut rtnl_nexthop) -> c_int;
+    pub fn rtnl_route_nh_set_ifindex(hop: *mut rtnl_nexthop, index: c_int);
+    pub fn rtnl_route_nh_set_flags(hop: *mut rtnl_nexthop, flags: c_uint);
+    pub fn rtnl_route_nh_unset_flags(hop: *mut rtnl_nexthop, flags: c_uint);
+    pub fn rtnl_route_nh_get_flags(hop: *mut rtnl_nexthop) -> c_uint;
+}
@@ -13,8 +13,23 @@
 // You should have received a copy of the GNU General Public License
 // along with this program; if not, see <https://www.gnu.org/licenses/>.
 
+//! Safe wrappers around the parts of libnl-3 and libnl-route-3 used by
+//! download-shell: sockets, caches, links, addresses, routes and neighbors,
+//! along with TUN and TAP devices and the listing of sockets.
+//!
+//! libnl is linked statically from the directory named by the
+//! `DL_SHELL_LIBNL` environment variable at build time, or with the
+//! `runtime-libnl` feature, loaded from the system when first used
+
+#[cfg(all(feature = "bindgen", feature = "runtime-libnl"))]
+compile_error!("the bindgen and runtime-libnl features can't be combined");
+
 mod ffi;
 
+pub mod diag;
 pub mod error;
 pub mod netlink;
 pub mod route;
+#[cfg(feature = "runtime-libnl")]
+pub mod runtime;
+pub mod tun;
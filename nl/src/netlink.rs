@@ -0,0 +1,1021 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    net::Ipv4Addr,
+    ops::Deref,
+    ptr,
+    time::{Duration, Instant},
+};
+
+use libc::{AF_INET, AF_INET6, AF_UNSPEC, c_int, c_void};
+
+use super::{
+    error,
+    ffi::*,
+    route::{
+        Addr, FibResult, FwmarkRule, Link, Neigh, Nexthop, NexthopObject, Route, RouteLookup,
+        RtAddr, TcStats,
+    },
+};
+
+/// A netlink socket used to communicate with the kernel
+///
+/// Sockets can be moved to another thread, but not shared between threads:
+/// libnl does no locking of its own, and a request and its reply have to be
+/// paired up on the same socket. To use one socket from several threads,
+/// wrap it in a [`std::sync::Mutex`]; otherwise give each thread a socket of
+/// its own
+pub struct Socket {
+    pub(crate) sock: *mut nl_sock,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+}
+
+/// How often requests that fail for transient reasons, such as a dump being
+/// interrupted by a change to the table, are sent again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times a request is sent in total before giving up
+    pub attempts: u32,
+    /// How long to wait before the first retry, doubling for every retry
+    /// after that
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Send every request only once
+    pub const NEVER: Self = Self {
+        attempts: 1,
+        backoff: Duration::ZERO,
+    };
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+// SAFETY: nl_sock holds no thread local state, and Socket is the only owner of
+// the pointer, so handing the socket over to another thread is sound. Socket
+// is deliberately not Sync, as concurrent calls would interleave messages
+unsafe impl Send for Socket {}
+
+impl Socket {
+    /// Establish a new connection with the Linux kernel
+    pub fn new() -> error::Result<Self> {
+        Self::connect(0 /* NETLINK_ROUTE */)
+    }
+
+    /// Establish a connection for looking up routes in the kernel's FIB
+    /// with [`Socket::fib_lookup`]. It speaks a different protocol, and so
+    /// can't be used for anything else
+    pub fn new_fib_lookup() -> error::Result<Self> {
+        Self::connect(NETLINK_FIB_LOOKUP)
+    }
+
+    /// Establish a connection for listing sockets with
+    /// [`Socket::get_inet_sockets`]. Like [`Socket::new_fib_lookup`], it
+    /// can't be used for anything else
+    pub fn new_sock_diag() -> error::Result<Self> {
+        Self::connect(NETLINK_SOCK_DIAG)
+    }
+
+    fn connect(protocol: c_int) -> error::Result<Self> {
+        unsafe {
+            let sock = Socket {
+                sock: nl_socket_alloc(),
+                retry: RetryPolicy::default(),
+                timeout: None,
+            };
+
+            let ret = nl_connect(sock.sock, protocol);
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(sock)
+        }
+    }
+
+    /// Changes how requests that fail for transient reasons are retried
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Limits how long a request waits for the kernel, including the time
+    /// spent on retries, after which it fails with [`error::Error::is_timeout`].
+    /// `None` waits forever, which is the default. A socket which timed out
+    /// may still receive the late answer, and should be discarded
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> error::Result<()> {
+        let tv = timeout.map_or(
+            libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            |t| libc::timeval {
+                tv_sec: t.as_secs() as libc::time_t,
+                // A zero timeval means no timeout at all
+                tv_usec: (t.subsec_micros() as libc::suseconds_t).max(t.is_zero() as _),
+            },
+        );
+
+        for opt in [libc::SO_RCVTIMEO, libc::SO_SNDTIMEO] {
+            let ret = unsafe {
+                libc::setsockopt(
+                    nl_socket_get_fd(self.sock),
+                    libc::SOL_SOCKET,
+                    opt,
+                    &tv as *const libc::timeval as *const c_void,
+                    std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+                )
+            };
+
+            if ret < 0 {
+                let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+                return Err(error::Error::new(unsafe { nl_syserr2nlerr(errno) }));
+            }
+        }
+
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    /// Calls `f` until it returns a libnl result that isn't a transient
+    /// failure, or the retry policy gives up, returning the last result
+    pub(crate) fn retrying(&self, mut f: impl FnMut() -> c_int) -> c_int {
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        let mut backoff = self.retry.backoff;
+
+        for _ in 1..self.retry.attempts {
+            let ret = self.interruptible(deadline, &mut f);
+            if ret >= 0 || !error::Error::new(ret).is_retryable() {
+                return ret;
+            }
+
+            if deadline.is_some_and(|d| Instant::now() + backoff >= d) {
+                return -error::Error::NLE_TIMEOUT;
+            }
+
+            std::thread::sleep(backoff);
+            backoff = backoff.saturating_mul(2);
+        }
+
+        self.interruptible(deadline, f)
+    }
+
+    /// Calls `f` again for as long as it is interrupted by signals, and
+    /// reports a socket timeout expiring as [`error::Error::NLE_TIMEOUT`]
+    pub(crate) fn interruptible(
+        &self,
+        deadline: Option<Instant>,
+        mut f: impl FnMut() -> c_int,
+    ) -> c_int {
+        loop {
+            let ret = f();
+
+            match -ret {
+                error::Error::NLE_INTR if deadline.is_none_or(|d| Instant::now() < d) => continue,
+                error::Error::NLE_INTR | error::Error::NLE_AGAIN if self.timeout.is_some() => {
+                    return -error::Error::NLE_TIMEOUT;
+                }
+                _ => return ret,
+            }
+        }
+    }
+
+    pub fn get_links(&self) -> error::Result<Cache<Link>> {
+        unsafe {
+            let mut link_cache = ptr::null_mut::<nl_cache>();
+
+            let ret = self.retrying(|| {
+                rtnl_link_alloc_cache(self.sock, AF_UNSPEC, &mut link_cache as *mut _)
+            });
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(Cache {
+                cache: link_cache,
+                dt: PhantomData,
+            })
+        }
+    }
+
+    pub fn get_neigh(&self) -> error::Result<Cache<Neigh>> {
+        unsafe {
+            let mut neigh_cache = ptr::null_mut::<nl_cache>();
+
+            let ret =
+                self.retrying(|| rtnl_neigh_alloc_cache(self.sock, &mut neigh_cache as *mut _));
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(Cache {
+                cache: neigh_cache,
+                dt: PhantomData,
+            })
+        }
+    }
+
+    /// Loads the IPv4 routes in the routing table specified, e.g.
+    /// [`Route::RT_TABLE_MAIN`], or every table with [`Route::RT_TABLE_UNSPEC`]
+    pub fn get_routes(&self, table: u32) -> error::Result<Cache<Route>> {
+        self.get_family_routes(AF_INET, table)
+    }
+
+    /// Loads the IPv6 routes in the routing table specified, like
+    /// [`Socket::get_routes`]
+    pub fn get_routes6(&self, table: u32) -> error::Result<Cache<Route>> {
+        self.get_family_routes(AF_INET6, table)
+    }
+
+    fn get_family_routes(&self, family: c_int, table: u32) -> error::Result<Cache<Route>> {
+        unsafe {
+            let mut route_cache = ptr::null_mut::<nl_cache>();
+
+            let ret = self.retrying(|| {
+                rtnl_route_alloc_cache(self.sock, family, 0, &mut route_cache as *mut _)
+            });
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            if table != Route::RT_TABLE_UNSPEC {
+                let filter = rtnl_route_alloc();
+                if filter.is_null() {
+                    nl_cache_put(route_cache);
+                    return Err(error::Error::new(5 /* NLE_NOMEM */));
+                }
+                rtnl_route_set_table(filter, table);
+
+                let subset = nl_cache_subset(route_cache, filter as *mut nl_object);
+                rtnl_route_put(filter);
+                nl_cache_put(route_cache);
+
+                if subset.is_null() {
+                    return Err(error::Error::new(5 /* NLE_NOMEM */));
+                }
+                route_cache = subset;
+            }
+
+            Ok(Cache {
+                cache: route_cache,
+                dt: PhantomData,
+            })
+        }
+    }
+
+    pub fn get_addrs(&self) -> error::Result<Cache<RtAddr>> {
+        unsafe {
+            let mut addr_cache = ptr::null_mut::<nl_cache>();
+
+            let ret = self.retrying(|| rtnl_addr_alloc_cache(self.sock, &mut addr_cache as *mut _));
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(Cache {
+                cache: addr_cache,
+                dt: PhantomData,
+            })
+        }
+    }
+
+    /// Reads the counters of every qdisc attached to the link specified
+    pub fn get_qdisc_stats(&self, ifindex: c_int) -> error::Result<Vec<TcStats>> {
+        unsafe {
+            let mut qdisc_cache = ptr::null_mut::<nl_cache>();
+
+            let ret =
+                self.retrying(|| rtnl_qdisc_alloc_cache(self.sock, &mut qdisc_cache as *mut _));
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(tc_stats(qdisc_cache, ifindex))
+        }
+    }
+
+    /// Reads the counters of every traffic class of the link specified, as
+    /// created by classful qdiscs such as htb
+    pub fn get_class_stats(&self, ifindex: c_int) -> error::Result<Vec<TcStats>> {
+        unsafe {
+            let mut class_cache = ptr::null_mut::<nl_cache>();
+
+            let ret = self.retrying(|| {
+                rtnl_class_alloc_cache(self.sock, ifindex, &mut class_cache as *mut _)
+            });
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(tc_stats(class_cache, ifindex))
+        }
+    }
+}
+
+/// Copies the counters of the qdiscs or classes of a link out of a cache,
+/// releasing the cache
+unsafe fn tc_stats(cache: *mut nl_cache, ifindex: c_int) -> Vec<TcStats> {
+    unsafe {
+        let mut stats = Vec::new();
+
+        let mut obj = nl_cache_get_first(cache);
+        while !obj.is_null() {
+            let tc = obj as *mut rtnl_tc;
+            if rtnl_tc_get_ifindex(tc) == ifindex {
+                stats.push(TcStats::read(tc));
+            }
+            obj = nl_cache_get_next(obj);
+        }
+
+        nl_cache_put(cache);
+        stats
+    }
+}
+
+/// Captures the first route in a netlink response for [`Socket::lookup_route`]
+extern "C" fn parse_route_cb(msg: *mut nl_msg, arg: *mut c_void) -> c_int {
+    unsafe {
+        let route = arg as *mut *mut rtnl_route;
+        if (*route).is_null() {
+            rtnl_route_parse(nlmsg_hdr(msg), route);
+        }
+    }
+
+    NL_OK
+}
+
+impl Socket {
+    /// Sets the size of the kernel's receive and send buffers for this
+    /// socket. A value of 0 keeps libnl's default of 32KiB. Large dumps on
+    /// hosts with many routes or neighbors can overrun the default
+    pub fn set_buffer_size(&self, rx: c_int, tx: c_int) -> error::Result<()> {
+        let ret = unsafe { nl_socket_set_buffer_size(self.sock, rx, tx) };
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// Enables or disables NETLINK_GET_STRICT_CHK, which makes the kernel
+    /// validate requests strictly and honor filters in dump requests. Only
+    /// supported from Linux 4.20
+    pub fn set_strict_check(&self, enable: bool) -> error::Result<()> {
+        let value: c_int = enable.into();
+
+        let ret = unsafe {
+            libc::setsockopt(
+                nl_socket_get_fd(self.sock),
+                SOL_NETLINK,
+                NETLINK_GET_STRICT_CHK,
+                &value as *const c_int as *const c_void,
+                std::mem::size_of::<c_int>() as libc::socklen_t,
+            )
+        };
+
+        if ret < 0 {
+            let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+            return Err(error::Error::new(unsafe { nl_syserr2nlerr(errno) }));
+        }
+
+        Ok(())
+    }
+
+    /// Enables or disables peeking at messages before reading them, so that
+    /// libnl can size its receive buffer to fit messages of any length
+    pub fn set_msg_peek(&self, enable: bool) {
+        unsafe {
+            if enable {
+                nl_socket_enable_msg_peek(self.sock);
+            } else {
+                nl_socket_disable_msg_peek(self.sock);
+            }
+        }
+    }
+
+    /// Enables or disables libnl's debug callbacks, which dump every message
+    /// sent and received, including error replies, to stderr
+    pub fn set_debug(&self, enable: bool) -> error::Result<()> {
+        unsafe {
+            let cb = nl_cb_alloc(if enable { NL_CB_DEBUG } else { NL_CB_DEFAULT });
+            if cb.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+
+            // The socket takes its own reference
+            nl_socket_set_cb(self.sock, cb);
+            nl_cb_put(cb);
+        }
+
+        Ok(())
+    }
+}
+
+impl Socket {
+    /// Asks the kernel to resolve the route it would use to reach the
+    /// destination, taking policy routing, metrics and preferred sources into
+    /// account instead of guessing from a route dump
+    pub fn lookup_route(&self, dst: Ipv4Addr) -> error::Result<RouteLookup> {
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+
+        unsafe {
+            let msg = nlmsg_alloc_simple(RTM_GETROUTE, 0);
+            if msg.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+
+            let hdr = rtmsg {
+                rtm_family: AF_INET as u8,
+                rtm_dst_len: 32,
+                rtm_src_len: 0,
+                rtm_tos: 0,
+                rtm_table: 0,
+                rtm_protocol: 0,
+                rtm_scope: 0,
+                rtm_type: 0,
+                rtm_flags: 0,
+            };
+            let octets = dst.octets();
+
+            let mut ret = nlmsg_append(
+                msg,
+                &hdr as *const rtmsg as *mut c_void,
+                std::mem::size_of::<rtmsg>(),
+                4, /* NLMSG_ALIGNTO */
+            );
+            if ret >= 0 {
+                ret = nla_put(msg, RTA_DST, 4, octets.as_ptr() as *const c_void);
+            }
+            if ret >= 0 {
+                ret = self.interruptible(deadline, || nl_send_auto(self.sock, msg));
+            }
+            nlmsg_free(msg);
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            let mut route = ptr::null_mut::<rtnl_route>();
+
+            let sock_cb = nl_socket_get_cb(self.sock);
+            let cb = nl_cb_clone(sock_cb);
+            nl_cb_put(sock_cb);
+            if cb.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+
+            nl_cb_set(
+                cb,
+                NL_CB_VALID,
+                NL_CB_CUSTOM,
+                parse_route_cb,
+                &mut route as *mut *mut rtnl_route as *mut c_void,
+            );
+            let mut ret = self.interruptible(deadline, || nl_recvmsgs(self.sock, cb));
+            nl_cb_put(cb);
+
+            // The reply is followed by an acknowledgement, which has to be
+            // consumed so the next request doesn't mistake it for its own
+            if ret >= 0 {
+                ret = self.interruptible(deadline, || nl_wait_for_ack(self.sock));
+            }
+
+            if route.is_null() {
+                return Err(error::Error::new(if ret < 0 {
+                    ret
+                } else {
+                    12 /* NLE_OBJ_NOTFOUND */
+                }));
+            }
+
+            let lookup = Route::from(route as *mut nl_object).lookup_info();
+            rtnl_route_put(route);
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(lookup)
+        }
+    }
+}
+
+impl Socket {
+    /// Sends a request made up of a fixed header and attributes, and waits
+    /// for the kernel to acknowledge it
+    fn request<H>(
+        &self,
+        msg_type: c_int,
+        flags: c_int,
+        hdr: &H,
+        attrs: &[(c_int, &[u8])],
+    ) -> error::Result<()> {
+        let ret = self.retrying(|| unsafe {
+            let msg = nlmsg_alloc_simple(msg_type, flags);
+            if msg.is_null() {
+                return -5 /* NLE_NOMEM */;
+            }
+
+            let mut ret = nlmsg_append(
+                msg,
+                hdr as *const H as *mut c_void,
+                std::mem::size_of::<H>(),
+                4, /* NLMSG_ALIGNTO */
+            );
+            for (attr, data) in attrs {
+                if ret >= 0 {
+                    ret = nla_put(
+                        msg,
+                        *attr,
+                        data.len() as c_int,
+                        data.as_ptr() as *const c_void,
+                    );
+                }
+            }
+            if ret >= 0 {
+                ret = nl_send_auto(self.sock, msg);
+            }
+            nlmsg_free(msg);
+
+            if ret < 0 {
+                return ret;
+            }
+            nl_wait_for_ack(self.sock)
+        });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// Creates a nexthop object. The kernel has to be 5.3 or newer
+    pub fn add_nexthop(&self, nexthop: &NexthopObject) -> error::Result<()> {
+        if nexthop.id() == 0 {
+            return Err(error::Error::new(7 /* NLE_INVAL */));
+        }
+        let id = nexthop.id().to_ne_bytes();
+
+        match nexthop {
+            NexthopObject::Hop {
+                ifindex,
+                gateway,
+                onlink,
+                ..
+            } => {
+                let hdr = nhmsg {
+                    nh_family: AF_INET as u8,
+                    nh_scope: 0,
+                    nh_protocol: RTPROT_BOOT,
+                    resvd: 0,
+                    nh_flags: if *onlink { Nexthop::RTNH_F_ONLINK } else { 0 },
+                };
+                let oif = (*ifindex as u32).to_ne_bytes();
+                let gateway = gateway.map(|g| g.octets());
+
+                let mut attrs = vec![(NHA_ID, &id[..]), (NHA_OIF, &oif[..])];
+                if let Some(gateway) = &gateway {
+                    attrs.push((NHA_GATEWAY, &gateway[..]));
+                }
+
+                self.request(RTM_NEWNEXTHOP, NLM_F_CREATE | NLM_F_EXCL, &hdr, &attrs)
+            }
+            NexthopObject::Group { members, .. } => {
+                if members.is_empty() || members.iter().any(|(id, weight)| *id == 0 || *weight == 0)
+                {
+                    return Err(error::Error::new(7 /* NLE_INVAL */));
+                }
+
+                let hdr = nhmsg {
+                    nh_family: AF_UNSPEC as u8,
+                    nh_scope: 0,
+                    nh_protocol: RTPROT_BOOT,
+                    resvd: 0,
+                    nh_flags: 0,
+                };
+                // The kernel counts weights from 0
+                let group = members
+                    .iter()
+                    .map(|(id, weight)| nexthop_grp {
+                        id: *id,
+                        weight: weight - 1,
+                        resvd1: 0,
+                        resvd2: 0,
+                    })
+                    .collect::<Vec<_>>();
+                let group = unsafe {
+                    std::slice::from_raw_parts(
+                        group.as_ptr() as *const u8,
+                        std::mem::size_of_val(&group[..]),
+                    )
+                };
+
+                self.request(
+                    RTM_NEWNEXTHOP,
+                    NLM_F_CREATE | NLM_F_EXCL,
+                    &hdr,
+                    &[(NHA_ID, &id), (NHA_GROUP, group)],
+                )
+            }
+        }
+    }
+
+    /// Deletes a nexthop object, along with every route using it. Groups
+    /// have to be deleted before their members
+    pub fn delete_nexthop(&self, id: u32) -> error::Result<()> {
+        let hdr = nhmsg {
+            nh_family: AF_UNSPEC as u8,
+            nh_scope: 0,
+            nh_protocol: 0,
+            resvd: 0,
+            nh_flags: 0,
+        };
+
+        self.request(RTM_DELNEXTHOP, 0, &hdr, &[(NHA_ID, &id.to_ne_bytes())])
+    }
+
+    /// Adds an IPv4 route to a table which sends traffic to a nexthop
+    /// object or group
+    pub fn add_route_via_nexthop(
+        &self,
+        dst: Ipv4Addr,
+        prefixlen: u8,
+        table: u32,
+        nexthop: u32,
+    ) -> error::Result<()> {
+        let hdr = route_header(prefixlen, table);
+        let octets = dst.octets();
+        let table = table.to_ne_bytes();
+        let nexthop = nexthop.to_ne_bytes();
+
+        let mut attrs = vec![(RTA_TABLE, &table[..]), (RTA_NH_ID, &nexthop[..])];
+        if prefixlen > 0 {
+            attrs.push((RTA_DST, &octets[..]));
+        }
+
+        self.request(RTM_NEWROUTE, NLM_F_CREATE | NLM_F_EXCL, &hdr, &attrs)
+    }
+
+    /// Deletes the IPv4 route to the destination from a table, whatever
+    /// its next hops are
+    pub fn delete_route(&self, dst: Ipv4Addr, prefixlen: u8, table: u32) -> error::Result<()> {
+        let mut hdr = route_header(prefixlen, table);
+        hdr.rtm_protocol = 0;
+        hdr.rtm_type = 0;
+        let octets = dst.octets();
+        let table = table.to_ne_bytes();
+
+        let mut attrs = vec![(RTA_TABLE, &table[..])];
+        if prefixlen > 0 {
+            attrs.push((RTA_DST, &octets[..]));
+        }
+
+        self.request(RTM_DELROUTE, 0, &hdr, &attrs)
+    }
+
+    /// Adds a policy routing rule for a firewall mark
+    pub fn add_fwmark_rule(&self, rule: &FwmarkRule) -> error::Result<()> {
+        self.fwmark_rule(RTM_NEWRULE, NLM_F_CREATE | NLM_F_EXCL, rule)
+    }
+
+    /// Deletes a policy routing rule added by [`Socket::add_fwmark_rule`]
+    pub fn delete_fwmark_rule(&self, rule: &FwmarkRule) -> error::Result<()> {
+        self.fwmark_rule(RTM_DELRULE, 0, rule)
+    }
+
+    fn fwmark_rule(&self, msg_type: c_int, flags: c_int, rule: &FwmarkRule) -> error::Result<()> {
+        let hdr = fib_rule_hdr {
+            family: AF_INET as u8,
+            dst_len: 0,
+            src_len: 0,
+            tos: 0,
+            table: if rule.table < 256 {
+                rule.table as u8
+            } else {
+                0
+            },
+            res1: 0,
+            res2: 0,
+            action: FR_ACT_TO_TBL,
+            flags: 0,
+        };
+
+        self.request(
+            msg_type,
+            flags,
+            &hdr,
+            &[
+                (FRA_FWMARK, &rule.fwmark.to_ne_bytes()),
+                (FRA_FWMASK, &rule.mask.to_ne_bytes()),
+                (FRA_TABLE, &rule.table.to_ne_bytes()),
+                (FRA_PRIORITY, &rule.priority.to_ne_bytes()),
+            ],
+        )
+    }
+}
+
+/// The header of a unicast route in a table, which the RTA_TABLE attribute
+/// overrides for table ids that don't fit in it
+fn route_header(prefixlen: u8, table: u32) -> rtmsg {
+    rtmsg {
+        rtm_family: AF_INET as u8,
+        rtm_dst_len: prefixlen,
+        rtm_src_len: 0,
+        rtm_tos: 0,
+        rtm_table: if table < 256 { table as u8 } else { 0 },
+        rtm_protocol: RTPROT_BOOT,
+        rtm_scope: 0,
+        rtm_type: Route::RTN_UNICAST,
+        rtm_flags: 0,
+    }
+}
+
+impl Socket {
+    /// Asks the FIB which route it would use to reach the destination, for
+    /// traffic carrying the firewall mark given. This skips building and
+    /// parsing a whole route message, but only identifies the route; see
+    /// [`FibResult::resolve`]. The socket has to come from
+    /// [`Socket::new_fib_lookup`]
+    pub fn fib_lookup(&self, dst: Ipv4Addr, fwmark: Option<u32>) -> error::Result<FibResult> {
+        unsafe {
+            let req = flnl_request_alloc();
+            if req.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+
+            let addr = Addr::from(dst);
+            let mut ret = flnl_request_set_addr(req, addr.addr);
+            if let Some(fwmark) = fwmark {
+                flnl_request_set_fwmark(req, fwmark as u64);
+            }
+
+            let results = flnl_result_alloc_cache();
+            if results.is_null() {
+                nl_object_put(req as *mut nl_object);
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+
+            if ret >= 0 {
+                ret = self.retrying(|| flnl_lookup(self.sock, req, results));
+            }
+            nl_object_put(req as *mut nl_object);
+
+            let res = nl_cache_get_first(results) as *mut flnl_result;
+            if ret >= 0 && res.is_null() {
+                ret = -12; /* NLE_OBJ_NOTFOUND */
+            }
+
+            // The kernel reports a failed lookup, such as an unreachable
+            // destination, as a negative errno inside of the result
+            if ret >= 0 {
+                let errno = flnl_result_get_error(res);
+                if errno < 0 {
+                    ret = -nl_syserr2nlerr(-errno);
+                }
+            }
+
+            let result = (ret >= 0).then(|| FibResult {
+                table: flnl_result_get_table_id(res) as u32,
+                prefixlen: flnl_result_get_prefixlen(res) as u8,
+                nexthop: flnl_result_get_nexthop_sel(res),
+                rtype: flnl_result_get_type(res) as u8,
+                scope: flnl_result_get_scope(res) as u8,
+            });
+            nl_cache_put(results);
+
+            result.ok_or(error::Error::new(ret))
+        }
+    }
+}
+
+/// What happened to a neighbour table entry, see
+/// [`Socket::recv_neigh_events`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighChange {
+    /// The entry was added, or its address or state changed
+    Updated,
+    /// The entry was removed
+    Removed,
+}
+
+/// Hands each neighbour notification to the closure behind `arg`, for
+/// [`Socket::recv_neigh_events`]
+extern "C" fn neigh_event_cb(msg: *mut nl_msg, arg: *mut c_void) -> c_int {
+    unsafe {
+        let f = &mut *(arg as *mut &mut dyn FnMut(NeighChange, &Neigh));
+        let hdr = nlmsg_hdr(msg);
+
+        let change = match (*hdr).nlmsg_type as c_int {
+            RTM_NEWNEIGH => NeighChange::Updated,
+            RTM_DELNEIGH => NeighChange::Removed,
+            _ => return NL_OK,
+        };
+
+        let mut neigh = ptr::null_mut::<rtnl_neigh>();
+        if rtnl_neigh_parse(hdr, &mut neigh) >= 0 && !neigh.is_null() {
+            f(change, &Neigh::from(neigh as *mut nl_object));
+            nl_object_put(neigh as *mut nl_object);
+        }
+    }
+
+    NL_OK
+}
+
+impl Socket {
+    /// Joins the group the kernel announces changes to the neighbour table
+    /// on, such as a gateway moving to another MAC address after a VRRP
+    /// failover. Notifications don't answer any request, so sequence
+    /// checking is turned off and the socket should not be used for
+    /// anything else afterwards
+    pub fn subscribe_neigh(&self) -> error::Result<()> {
+        unsafe {
+            let ret = nl_socket_add_membership(self.sock, RTNLGRP_NEIGH);
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            nl_socket_disable_seq_check(self.sock);
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the next batch of notifications on a socket set up with
+    /// [`Socket::subscribe_neigh`], calling `f` for each of them. With a
+    /// timeout set, this gives up once no notification arrived in that long,
+    /// and the socket remains usable
+    pub fn recv_neigh_events(&self, mut f: impl FnMut(NeighChange, &Neigh)) -> error::Result<()> {
+        let mut f: &mut dyn FnMut(NeighChange, &Neigh) = &mut f;
+
+        unsafe {
+            let sock_cb = nl_socket_get_cb(self.sock);
+            let cb = nl_cb_clone(sock_cb);
+            nl_cb_put(sock_cb);
+            if cb.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+
+            nl_cb_set(
+                cb,
+                NL_CB_VALID,
+                NL_CB_CUSTOM,
+                neigh_event_cb,
+                &mut f as *mut &mut dyn FnMut(NeighChange, &Neigh) as *mut c_void,
+            );
+            let ret = self.interruptible(None, || nl_recvmsgs(self.sock, cb));
+            nl_cb_put(cb);
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe {
+            nl_close(self.sock);
+        }
+    }
+}
+
+/// Tries to get a link by the specified ifindex. The link holds a reference
+/// of its own, so it can outlive the cache
+pub fn get_link_by_index(cache: &Cache<Link>, index: i32) -> Option<Link> {
+    unsafe {
+        let link = rtnl_link_get(cache.cache, index);
+
+        if link.is_null() {
+            return None;
+        }
+
+        Some(Link { link })
+    }
+}
+
+/// Represents the nl_cache in the libnl library, which is itself a general
+/// collection of nl_objects
+///
+/// Like [`Socket`], a cache can be moved between threads but not shared. The
+/// objects yielded by [`Cache::iter`] borrow the cache, so it cannot be moved
+/// or freed while any of them is alive
+pub struct Cache<T>
+where
+    T: From<*mut nl_object>,
+{
+    pub(crate) cache: *mut nl_cache,
+    dt: PhantomData<T>,
+}
+
+// SAFETY: the reference counts of an nl_cache and its objects are not atomic,
+// but Cache is the only owner of its reference, and the CacheItems pointing
+// into it hold a borrow of it, so none can be left behind on another thread
+unsafe impl<T: From<*mut nl_object>> Send for Cache<T> {}
+
+impl<T: From<*mut nl_object>> Cache<T> {
+    pub fn iter(&self) -> CacheIter<'_, T> {
+        let cache_size = unsafe { nl_cache_nitems(self.cache) } as usize;
+
+        CacheIter {
+            obj: unsafe { nl_cache_get_first(self.cache) },
+            cache_size,
+            index: 0,
+            item_type: PhantomData {},
+        }
+    }
+}
+
+impl<T: From<*mut nl_object>> Drop for Cache<T> {
+    fn drop(&mut self) {
+        unsafe {
+            nl_cache_put(self.cache);
+        }
+    }
+}
+
+/// Iterates over caches and provides an easy way to work with them
+pub struct CacheIter<'a, T> {
+    obj: *mut nl_object,
+    cache_size: usize,
+    index: usize,
+    item_type: PhantomData<&'a T>,
+}
+
+/// An object of a [`Cache`], which does not hold a reference of its own and
+/// is therefore only valid as long as the cache is borrowed. The object is
+/// never dropped, as that would release the reference of the cache
+pub struct CacheItem<'a, T> {
+    item: ManuallyDrop<T>,
+    cache: PhantomData<&'a T>,
+}
+
+impl<T> Deref for CacheItem<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.item
+    }
+}
+
+impl<'a, T: From<*mut nl_object>> Iterator for CacheIter<'a, T> {
+    type Item = CacheItem<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.index >= self.cache_size {
+                return None;
+            }
+
+            self.index += 1;
+
+            let obj = self.obj;
+            self.obj = unsafe { nl_cache_get_next(obj) };
+
+            if obj.is_null() {
+                continue;
+            }
+
+            break Some(CacheItem {
+                item: ManuallyDrop::new(T::from(obj)),
+                cache: PhantomData,
+            });
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.cache_size, Some(self.cache_size))
+    }
+}
@@ -0,0 +1,1900 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    ffi::{CStr, CString},
+    fmt::{Debug, Display},
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use libc::{AF_INET, AF_INET6, AF_LLC, c_char, c_int, c_uint};
+
+use super::{
+    error,
+    netlink::{self, Cache, CacheItem},
+};
+
+use super::ffi::*;
+
+/// Represents an address assigned to a link
+pub struct RtAddr {
+    addr: *mut rtnl_addr,
+}
+
+impl RtAddr {
+    pub fn new() -> Option<Self> {
+        let addr = unsafe { rtnl_addr_alloc() };
+
+        if addr.is_null() {
+            return None;
+        }
+
+        Some(Self { addr })
+    }
+
+    pub fn local(&self) -> Option<Addr> {
+        unsafe {
+            let addr = rtnl_addr_get_local(self.addr);
+
+            if addr.is_null() {
+                return None;
+            }
+
+            Some(Addr { addr })
+        }
+    }
+
+    pub fn set_local(&self, addr: Addr) -> error::Result<()> {
+        let res = unsafe { rtnl_addr_set_local(self.addr, addr.addr) };
+
+        if res < 0 {
+            return Err(error::Error::new(res));
+        }
+
+        Ok(())
+    }
+
+    pub fn set_broadcast(&self, addr: Addr) -> error::Result<()> {
+        let res = unsafe { rtnl_addr_set_broadcast(self.addr, addr.addr) };
+
+        if res < 0 {
+            return Err(error::Error::new(res));
+        }
+
+        Ok(())
+    }
+
+    /// Sets the address of the other end of a point to point link
+    pub fn set_peer(&self, addr: Addr) -> error::Result<()> {
+        let res = unsafe { rtnl_addr_set_peer(self.addr, addr.addr) };
+
+        if res < 0 {
+            return Err(error::Error::new(res));
+        }
+
+        Ok(())
+    }
+
+    /// The label of the address, e.g. `eth0:dlsh`. The kernel gives IPv4
+    /// addresses added without one the name of their link
+    pub fn label(&self) -> Option<String> {
+        unsafe {
+            let label = rtnl_addr_get_label(self.addr);
+            if label.is_null() {
+                return None;
+            }
+            Some(CStr::from_ptr(label).to_string_lossy().into_owned())
+        }
+    }
+
+    /// Labels the address, which shows up as an alias like `eth0:dlsh` in
+    /// ifconfig. Labels are at most 15 bytes long, and by convention start
+    /// with the name of the link and a colon
+    pub fn set_label(&self, label: &str) -> error::Result<()> {
+        let label = CString::new(label).map_err(|_| error::Error::new(7 /* NLE_INVAL */))?;
+        let res = unsafe { rtnl_addr_set_label(self.addr, label.as_ptr()) };
+
+        if res < 0 {
+            return Err(error::Error::new(res));
+        }
+
+        Ok(())
+    }
+
+    pub fn ifindex(&self) -> i32 {
+        unsafe { rtnl_addr_get_ifindex(self.addr) }
+    }
+
+    pub fn set_ifindex(&self, ifindex: c_int) {
+        unsafe { rtnl_addr_set_ifindex(self.addr, ifindex) };
+    }
+
+    pub fn set_prefixlen(&self, prefixlen: c_int) {
+        unsafe { rtnl_addr_set_prefixlen(self.addr, prefixlen) };
+    }
+
+    /// Skips duplicate address detection, so that an IPv6 address can be
+    /// used right away instead of staying tentative for a second or two
+    pub fn set_nodad(&self) {
+        unsafe { rtnl_addr_set_flags(self.addr, IFA_F_NODAD) };
+    }
+
+    pub fn prefixlen(&self) -> c_int {
+        unsafe { rtnl_addr_get_prefixlen(self.addr) }
+    }
+
+    pub fn family(&self) -> i32 {
+        unsafe { rtnl_addr_get_family(self.addr) }
+    }
+
+    pub fn add(&self, sock: &netlink::Socket, flags: c_int) -> error::Result<()> {
+        let ret = sock.retrying(|| unsafe { rtnl_addr_add(sock.sock, self.addr, flags) });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for RtAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.local() {
+            Some(local) => write!(f, "{local}")?,
+            None => write!(f, "none")?,
+        }
+
+        write!(f, " dev {}", self.ifindex())
+    }
+}
+
+impl Debug for RtAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RtAddr")
+            .field("local", &self.local().map(|a| a.to_string()))
+            .field("prefixlen", &self.prefixlen())
+            .field("ifindex", &self.ifindex())
+            .field("label", &self.label())
+            .finish()
+    }
+}
+
+impl From<*mut nl_object> for RtAddr {
+    fn from(value: *mut nl_object) -> Self {
+        RtAddr {
+            addr: value as *mut _,
+        }
+    }
+}
+
+/// Represents a network link, which can represent a network device
+///
+/// A link holds a reference of its own and releases it when dropped, except
+/// for the ones yielded by [`super::netlink::Cache::iter`], which borrow the
+/// reference of the cache instead
+pub struct Link {
+    pub(crate) link: *mut rtnl_link,
+}
+
+impl Link {
+    pub const IFF_UP: c_uint = 1 << 0;
+
+    /// Creates a new, empty link object that can be used to issue changes
+    pub fn new() -> Self {
+        Self {
+            link: unsafe { rtnl_link_alloc() },
+        }
+    }
+
+    /// Create a new empty link that is optimized for virtual ethernet pairing
+    pub fn new_veth() -> Self {
+        Self {
+            link: unsafe { rtnl_link_veth_alloc() },
+        }
+    }
+
+    /// Create a new empty macvlan link, which has to be put on top of a
+    /// parent with [`Link::set_link`]
+    pub fn new_macvlan() -> Self {
+        Self {
+            link: unsafe { rtnl_link_macvlan_alloc() },
+        }
+    }
+
+    /// Create a new empty sit link, which carries IPv6 in IPv4 (6in4), as
+    /// offered by tunnel brokers. See [`Link::set_sit_endpoints`]
+    pub fn new_sit() -> Self {
+        Self {
+            link: unsafe { rtnl_link_sit_alloc() },
+        }
+    }
+
+    /// Create a new empty ip6tnl link, which carries IPv4 or IPv6 in IPv6.
+    /// See [`Link::set_ip6tnl_endpoints`]
+    pub fn new_ip6tnl() -> Self {
+        Self {
+            link: unsafe { rtnl_link_ip6_tnl_alloc() },
+        }
+    }
+
+    /// Create a new empty dummy link, which drops whatever is sent through
+    /// it but holds addresses and routes like any other link
+    pub fn new_dummy() -> error::Result<Self> {
+        let link = Self::new();
+        link.set_type("dummy")?;
+        Ok(link)
+    }
+
+    /// Apply differences found in the other link object
+    pub fn change(&self, socket: &super::netlink::Socket, other: &Link) -> error::Result<()> {
+        let ret = socket.retrying(|| unsafe {
+            rtnl_link_change(
+                socket.sock,
+                self.link,
+                other.link,
+                0x100, /* NLM_F_REPLACE */
+            )
+        });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the network link name, e.g. eth0
+    pub fn name(&self) -> String {
+        unsafe {
+            let name = rtnl_link_get_name(self.link);
+            if name.is_null() {
+                return "".to_string();
+            }
+            let name_rs = CStr::from_ptr(name);
+            std::str::from_utf8(name_rs.to_bytes()).unwrap().to_owned()
+        }
+    }
+
+    /// Provides the hardware address of the link, if it is an ethernet-like
+    /// link which has one
+    pub fn addr(&self) -> Option<MacAddr> {
+        let addr = Addr {
+            addr: unsafe { rtnl_link_get_addr(self.link) },
+        };
+
+        addr.atype().and_then(|_| MacAddr::try_from(&addr).ok())
+    }
+
+    /// Returns the MTU of the link
+    pub fn mtu(&self) -> u32 {
+        unsafe { rtnl_link_get_mtu(self.link) }
+    }
+
+    /// Sets the MTU to request when the link is added or changed
+    pub fn set_mtu(&self, mtu: u32) {
+        unsafe { rtnl_link_set_mtu(self.link, mtu) }
+    }
+
+    /// Sets the length of the transmit queue
+    pub fn set_txqlen(&self, txqlen: u32) {
+        unsafe { rtnl_link_set_txqlen(self.link, txqlen) }
+    }
+
+    /// Sets the number of transmit and receive queues a new link is
+    /// created with
+    pub fn set_num_queues(&self, tx: u32, rx: u32) {
+        unsafe {
+            rtnl_link_set_num_tx_queues(self.link, tx);
+            rtnl_link_set_num_rx_queues(self.link, rx);
+        }
+    }
+
+    /// Determines the type of link. Ethernet devices are "veth or eth"
+    pub fn ltype(&self) -> Option<String> {
+        unsafe {
+            let ltype = rtnl_link_get_type(self.link);
+            if ltype.is_null() {
+                return None;
+            }
+            let ltype_rs = CStr::from_ptr(ltype);
+            Some(std::str::from_utf8(ltype_rs.to_bytes()).ok()?.to_owned())
+        }
+    }
+
+    /// Sets the kind of link to create, e.g. "dummy" or "bridge"
+    pub fn set_type(&self, kind: &str) -> error::Result<()> {
+        let kind = CString::new(kind).map_err(|_| error::Error::new(7 /* NLE_INVAL */))?;
+        let ret = unsafe { rtnl_link_set_type(self.link, kind.as_ptr()) };
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// Determines the index of the interface in the kernel table
+    pub fn ifindex(&self) -> c_int {
+        unsafe { rtnl_link_get_ifindex(self.link) }
+    }
+
+    /// Tries to get the neighbor for this link, which can provide the destination address and the
+    /// link layer address (lladdr)
+    pub fn get_neigh(&self, neigh_table: &Cache<Neigh>, addr: &Addr) -> Option<MacAddr> {
+        unsafe {
+            let neigh = rtnl_neigh_get(neigh_table.cache, self.ifindex(), addr.addr);
+
+            if neigh.is_null() {
+                return None;
+            }
+
+            Neigh { neigh }.lladdr()
+        }
+    }
+
+    /// Set the name of an interface. Names containing NUL bytes are ignored
+    pub fn set_name(&self, name: &str) {
+        let Ok(name) = CString::new(name) else {
+            return;
+        };
+
+        unsafe {
+            rtnl_link_set_name(self.link, name.as_ptr());
+        }
+    }
+
+    /// Set the namespace file descriptor for an interface
+    pub fn set_ns_pid(&self, pid: libc::pid_t) {
+        unsafe {
+            rtnl_link_set_ns_pid(self.link, pid);
+        }
+    }
+
+    /// Add the link to the running environment
+    pub fn add(&self, socket: &super::netlink::Socket, flags: c_int) -> error::Result<()> {
+        let ret = socket.retrying(|| unsafe { rtnl_link_add(socket.sock, self.link, flags) });
+
+        if ret < 0 {
+            Err(error::Error::new(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Deletes the active link
+    pub fn delete(self, socket: &super::netlink::Socket) -> error::Result<()> {
+        let ret = socket.retrying(|| unsafe { rtnl_link_delete(socket.sock, self.link) });
+
+        if ret < 0 {
+            Err(error::Error::new(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the flags on a link
+    pub fn get_flags(&self) -> c_uint {
+        unsafe { rtnl_link_get_flags(self.link) }
+    }
+
+    /// Set flags to ON for a link
+    pub fn set_flags(&self, flags: c_uint) {
+        unsafe { rtnl_link_set_flags(self.link, flags) }
+    }
+
+    /// Toggle flags OFF for a link
+    pub fn unset_flags(&self, flags: c_uint) {
+        unsafe { rtnl_link_unset_flags(self.link, flags) }
+    }
+
+    /// If this is a veth link, return the peer
+    pub fn get_peer(&self) -> Option<Self> {
+        let link = unsafe { rtnl_link_veth_get_peer(self.link) };
+
+        if link.is_null() {
+            return None;
+        }
+
+        Some(Self { link })
+    }
+
+    /// The index of the link this one sits on top of (IFLA_LINK). For a veth
+    /// this is the peer, for a VLAN it is the parent device
+    pub fn link_index(&self) -> Option<c_int> {
+        match unsafe { rtnl_link_get_link(self.link) } {
+            0 => None,
+            index => Some(index),
+        }
+    }
+
+    /// Sets the link a new link sits on top of, such as the parent of a
+    /// macvlan
+    pub fn set_link(&self, ifindex: c_int) {
+        unsafe { rtnl_link_set_link(self.link, ifindex) }
+    }
+
+    /// Sets the hardware address of the link
+    pub fn set_addr(&self, addr: &Addr) {
+        unsafe { rtnl_link_set_addr(self.link, addr.addr) }
+    }
+
+    /// The index of the bridge or bond this link is enslaved to
+    pub fn master(&self) -> Option<c_int> {
+        match unsafe { rtnl_link_get_master(self.link) } {
+            0 => None,
+            index => Some(index),
+        }
+    }
+
+    /// Sets the addresses the packets of a sit link are sent between, with
+    /// an unspecified local address letting routing pick one. The TTL is
+    /// set too, as the inherited default prevents path MTU discovery
+    pub fn set_sit_endpoints(
+        &self,
+        local: Ipv4Addr,
+        remote: Ipv4Addr,
+        ttl: u8,
+    ) -> error::Result<()> {
+        let ret = unsafe {
+            match rtnl_link_sit_set_local(self.link, u32::from(local).to_be()) {
+                0 => match rtnl_link_sit_set_remote(self.link, u32::from(remote).to_be()) {
+                    0 => rtnl_link_sit_set_ttl(self.link, ttl),
+                    ret => ret,
+                },
+                ret => ret,
+            }
+        };
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// The local and remote addresses of a sit link
+    pub fn sit_endpoints(&self) -> Option<(Ipv4Addr, Ipv4Addr)> {
+        if self.ltype().as_deref() != Some("sit") {
+            return None;
+        }
+
+        unsafe {
+            Some((
+                Ipv4Addr::from(u32::from_be(rtnl_link_sit_get_local(self.link))),
+                Ipv4Addr::from(u32::from_be(rtnl_link_sit_get_remote(self.link))),
+            ))
+        }
+    }
+
+    /// Sets the addresses the packets of an ip6tnl link are sent between,
+    /// the protocol carried, `IPPROTO_IPIP` or `IPPROTO_IPV6`, and the hop
+    /// limit
+    pub fn set_ip6tnl_endpoints(
+        &self,
+        local: Ipv6Addr,
+        remote: Ipv6Addr,
+        proto: u8,
+        ttl: u8,
+    ) -> error::Result<()> {
+        let mut local = libc::in6_addr {
+            s6_addr: local.octets(),
+        };
+        let mut remote = libc::in6_addr {
+            s6_addr: remote.octets(),
+        };
+
+        let ret = unsafe {
+            match rtnl_link_ip6_tnl_set_local(self.link, &mut local) {
+                0 => match rtnl_link_ip6_tnl_set_remote(self.link, &mut remote) {
+                    0 => match rtnl_link_ip6_tnl_set_proto(self.link, proto) {
+                        0 => rtnl_link_ip6_tnl_set_ttl(self.link, ttl),
+                        ret => ret,
+                    },
+                    ret => ret,
+                },
+                ret => ret,
+            }
+        };
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// The local and remote addresses of an ip6tnl link
+    pub fn ip6tnl_endpoints(&self) -> Option<(Ipv6Addr, Ipv6Addr)> {
+        if self.ltype().as_deref() != Some("ip6tnl") {
+            return None;
+        }
+
+        let mut local = libc::in6_addr { s6_addr: [0; 16] };
+        let mut remote = libc::in6_addr { s6_addr: [0; 16] };
+        unsafe {
+            if rtnl_link_ip6_tnl_get_local(self.link, &mut local) < 0
+                || rtnl_link_ip6_tnl_get_remote(self.link, &mut remote) < 0
+            {
+                return None;
+            }
+        }
+
+        Some((
+            Ipv6Addr::from(local.s6_addr),
+            Ipv6Addr::from(remote.s6_addr),
+        ))
+    }
+
+    /// Sets the bridge, bond or VRF to enslave the link to when it is added
+    /// or changed, or releases it from its master with an index of 0
+    pub fn set_master(&self, ifindex: c_int) {
+        unsafe { rtnl_link_set_master(self.link, ifindex) }
+    }
+
+    /// Enslaves this existing link to a bridge, bond or VRF
+    pub fn enslave(&self, socket: &netlink::Socket, master: &Link) -> error::Result<()> {
+        let ret = socket.retrying(|| unsafe {
+            rtnl_link_enslave_ifindex(socket.sock, master.ifindex(), self.ifindex())
+        });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// Releases this existing link from its master
+    pub fn release(&self, socket: &netlink::Socket) -> error::Result<()> {
+        let ret =
+            socket.retrying(|| unsafe { rtnl_link_release_ifindex(socket.sock, self.ifindex()) });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// If this is a VLAN link, return the VLAN id
+    pub fn vlan_id(&self) -> Option<u16> {
+        if self.ltype().as_deref() != Some("vlan") {
+            return None;
+        }
+
+        let id = unsafe { rtnl_link_vlan_get_id(self.link) };
+        u16::try_from(id).ok().filter(|id| *id != 0)
+    }
+
+    /// Whether this link is a bridge
+    pub fn is_bridge(&self) -> bool {
+        self.ltype().as_deref() == Some("bridge")
+    }
+
+    /// If this is a tun or tap device, return its IFF_* flags. libnl does
+    /// not parse tun link info, so they are read from sysfs instead
+    pub fn tun_flags(&self) -> Option<u32> {
+        let flags =
+            std::fs::read_to_string(format!("/sys/class/net/{}/tun_flags", self.name())).ok()?;
+
+        u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).ok()
+    }
+}
+
+impl Debug for Link {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Link")
+            .field("name", &self.name())
+            .field("ifindex", &self.ifindex())
+            .finish()
+    }
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        unsafe { rtnl_link_put(self.link) }
+    }
+}
+
+/// Takes over a reference to the object, or borrows one when wrapped in a
+/// [`CacheItem`](super::netlink::CacheItem)
+impl From<*mut nl_object> for Link {
+    fn from(value: *mut nl_object) -> Self {
+        Self {
+            link: value as *mut _,
+        }
+    }
+}
+
+/// Everything needed to send frames towards an IP address by hand
+#[derive(Debug, Clone)]
+pub struct RouteInfo {
+    /// Name of the link the traffic leaves through
+    pub link_name: String,
+    /// Index of the link the traffic leaves through
+    pub ifindex: i32,
+    /// Address of the link used as the source of the traffic
+    pub src_ip: Ipv4Addr,
+    /// MAC address of the link the traffic leaves through
+    pub src_mac: MacAddr,
+    /// Address of the next hop, which is the gateway if there is one
+    pub next_hop: Ipv4Addr,
+    /// MAC address of the next hop, or the broadcast address if unknown
+    pub gw_mac: MacAddr,
+    /// Length of the subnet the source address lives in
+    pub prefixlen: u8,
+}
+
+/// Logs every link along with its addresses and neighbors
+pub fn dump_links(addrs: &Cache<RtAddr>, neighs: &Cache<Neigh>, links: &Cache<Link>) {
+    for link in links.iter() {
+        log::debug!(
+            "Link {}: {:?} ({})",
+            link.name(),
+            link.addr(),
+            link.ifindex()
+        );
+
+        for addr in addrs.iter().filter(|addr| addr.ifindex() == link.ifindex()) {
+            if let Some(a) = addr.local() {
+                log::debug!("\taddress {:?}", a)
+            }
+        }
+
+        for neigh in neighs
+            .iter()
+            .filter(|neigh| neigh.ifindex() == link.ifindex())
+        {
+            log::debug!("\tneighbor {:?}, {:?}", neigh.dst(), neigh.lladdr());
+        }
+    }
+}
+
+/// Determines the link, addresses and next hop used to reach the address
+/// specified. With debug logging, the links known to the host are dumped
+/// along the way
+pub fn get_macs_and_src_for_ip(
+    sock: &netlink::Socket,
+    addrs: &Cache<RtAddr>,
+    neighs: &Cache<Neigh>,
+    links: &Cache<Link>,
+    addr: Ipv4Addr,
+) -> Option<RouteInfo> {
+    let route = sock.lookup_route(addr).ok()?;
+
+    log::debug!("Route to {addr} leaves through link {}", route.ifindex);
+    if log::log_enabled!(log::Level::Debug) {
+        dump_links(addrs, neighs, links);
+    }
+
+    RouteInfo::from_lookup(&route, addrs, neighs, links, addr)
+}
+
+impl RouteInfo {
+    /// Fills in the addresses needed to reach `addr` along a route which
+    /// was already resolved, by [`netlink::Socket::lookup_route`] or
+    /// [`FibResult::resolve`]
+    pub fn from_lookup(
+        route: &RouteLookup,
+        addrs: &Cache<RtAddr>,
+        neighs: &Cache<Neigh>,
+        links: &Cache<Link>,
+        addr: Ipv4Addr,
+    ) -> Option<Self> {
+        let link_ind = route.ifindex;
+        let link = netlink::get_link_by_index(links, link_ind)?;
+
+        // Packets are addressed to the gateway if there is one, otherwise
+        // directly to the destination
+        let next_hop = route.gateway.unwrap_or(addr);
+        let neigh = link
+            .get_neigh(neighs, &Addr::from(next_hop))
+            .unwrap_or(MacAddr::BROADCAST);
+
+        let srcip = match route.pref_src {
+            Some(pref_src) => addrs.iter().find(|a| {
+                a.ifindex() == link_ind
+                    && a.local()
+                        .and_then(|l| Ipv4Addr::try_from(&l).ok())
+                        .is_some_and(|l| l == pref_src)
+            })?,
+            None => source_addr(addrs, link_ind, next_hop)?,
+        };
+
+        Some(Self {
+            link_name: link.name(),
+            ifindex: link_ind,
+            src_ip: (&srcip.local()?).try_into().ok()?,
+            src_mac: link.addr()?,
+            next_hop,
+            gw_mac: neigh,
+            prefixlen: srcip.prefixlen() as u8,
+        })
+    }
+
+    /// Follows a change to the neighbour table received through
+    /// [`netlink::Socket::recv_neigh_events`], so that a long running user
+    /// doesn't keep sending to a stale MAC address. Returns whether
+    /// [`RouteInfo::gw_mac`] changed
+    pub fn apply_neigh(&mut self, change: netlink::NeighChange, neigh: &Neigh) -> bool {
+        let dst = neigh.dst();
+        if neigh.ifindex() != self.ifindex
+            || dst.atype().is_none()
+            || Ipv4Addr::try_from(&dst).ok() != Some(self.next_hop)
+        {
+            return false;
+        }
+
+        let gw_mac = match change {
+            netlink::NeighChange::Updated => match neigh.lladdr() {
+                Some(mac) => mac,
+                None => return false,
+            },
+            // Without an entry, frames have to be broadcast until the next
+            // hop is resolved again
+            netlink::NeighChange::Removed => MacAddr::BROADCAST,
+        };
+
+        let changed = gw_mac != self.gw_mac;
+        self.gw_mac = gw_mac;
+        changed
+    }
+}
+
+/// Gets the neighbor record for the source IP specified, or get the default address
+pub fn get_neigh_for_addr(
+    routes: &Cache<Route>,
+    neighs: &Cache<Neigh>,
+    links: &Cache<Link>,
+    addr: &Addr,
+) -> Option<(Ipv4Addr, Link, MacAddr)> {
+    for link in links.iter() {
+        let Some(neigh) = link.get_neigh(&neighs, addr) else {
+            continue;
+        };
+        // Hand out a link with a reference of its own, not one of the cache
+        let link = netlink::get_link_by_index(links, link.ifindex())?;
+        return Some((addr.try_into().ok()?, link, neigh));
+    }
+
+    // No good neighbors were found above, try to use the default address
+    if let Some(def_neigh) = get_default_route(routes) {
+        log::debug!("Found default route, trying to get link for it");
+        if let Some((laddr, link, neigh)) = neighs
+            .iter()
+            .filter_map(|n| {
+                let Some(link) = netlink::get_link_by_index(links, n.ifindex()) else {
+                    return None;
+                };
+
+                let Some(first_hop) = def_neigh.hop_iter().next() else {
+                    return None;
+                };
+
+                if n.ifindex() != first_hop.ifindex() {
+                    return None;
+                }
+
+                Some(((&first_hop.gateway()?).try_into().ok()?, link, n.lladdr()?))
+            })
+            .next()
+        {
+            return Some((laddr, link, neigh));
+        }
+    }
+
+    None
+}
+
+/// Given the routes cache, returns the default route among them
+pub fn get_default_route(routes: &Cache<Route>) -> Option<CacheItem<'_, Route>> {
+    routes
+        .iter()
+        .find(|r| r.dst().map(|a| a.cidrlen()).unwrap_or(33) == 0)
+}
+
+/// A struct representing the neighbor of a link
+pub struct Neigh {
+    neigh: *mut rtnl_neigh,
+}
+
+impl Neigh {
+    /// Pull up the destination address for this neighbor record
+    pub fn dst(&self) -> Addr {
+        unsafe {
+            let addr = rtnl_neigh_get_dst(self.neigh);
+            Addr { addr }
+        }
+    }
+
+    /// The hardware address of the neighbor, unless it hasn't been resolved
+    pub fn lladdr(&self) -> Option<MacAddr> {
+        let addr = Addr {
+            addr: unsafe { rtnl_neigh_get_lladdr(self.neigh) },
+        };
+
+        addr.atype().and_then(|_| MacAddr::try_from(&addr).ok())
+    }
+
+    pub fn ifindex(&self) -> i32 {
+        unsafe { rtnl_neigh_get_ifindex(self.neigh) }
+    }
+
+    /// The NUD_* state of the entry, e.g. whether it is reachable or stale
+    pub fn state(&self) -> c_int {
+        unsafe { rtnl_neigh_get_state(self.neigh) }
+    }
+
+    /// The state of the entry as named by libnl, e.g. `reachable`
+    pub fn state_name(&self) -> String {
+        let mut buf = [0 as c_char; 64];
+
+        unsafe {
+            rtnl_neigh_state2str(self.state(), buf.as_mut_ptr(), buf.len());
+            CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+        }
+    }
+}
+
+impl Display for Neigh {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} dev {}", self.dst(), self.ifindex())?;
+
+        if let Some(lladdr) = self.lladdr() {
+            write!(f, " lladdr {lladdr}")?;
+        }
+
+        write!(f, " {}", self.state_name())
+    }
+}
+
+impl Debug for Neigh {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Neigh")
+            .field("dst", &self.dst().to_string())
+            .field("lladdr", &self.lladdr())
+            .field("state", &self.state_name())
+            .field("ifindex", &self.ifindex())
+            .finish()
+    }
+}
+
+impl From<*mut nl_object> for Neigh {
+    fn from(value: *mut nl_object) -> Self {
+        Self {
+            neigh: value as *mut _,
+        }
+    }
+}
+
+/// An entry making the host answer neighbour solicitations for an address
+/// on a link, as with `ip -6 neigh add proxy ADDR dev LINK`. The kernel
+/// only looks at these once `proxy_ndp` is turned on for the link
+pub struct NeighProxy {
+    neigh: *mut rtnl_neigh,
+}
+
+impl NeighProxy {
+    pub fn new(ifindex: c_int, dst: &Addr) -> error::Result<Self> {
+        unsafe {
+            let neigh = rtnl_neigh_alloc();
+            if neigh.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+            let proxy = NeighProxy { neigh };
+
+            rtnl_neigh_set_ifindex(neigh, ifindex);
+            let ret = rtnl_neigh_set_dst(neigh, dst.addr);
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+            rtnl_neigh_set_state(neigh, NUD_PERMANENT);
+            rtnl_neigh_set_flags(neigh, NTF_PROXY);
+
+            Ok(proxy)
+        }
+    }
+
+    /// Adds the entry to the neighbour table
+    pub fn add(&self, sock: &netlink::Socket) -> error::Result<()> {
+        let ret = sock.retrying(|| unsafe {
+            rtnl_neigh_add(
+                sock.sock,
+                self.neigh,
+                0x400 | 0x200, /* NLM_F_CREATE | NLM_F_EXCL */
+            )
+        });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+
+    /// Removes the entry from the neighbour table
+    pub fn delete(&self, sock: &netlink::Socket) -> error::Result<()> {
+        let ret = sock.retrying(|| unsafe { rtnl_neigh_delete(sock.sock, self.neigh, 0) });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for NeighProxy {
+    fn drop(&mut self) {
+        unsafe { rtnl_neigh_put(self.neigh) }
+    }
+}
+
+/// Represents "an address"
+/// IPv4? IPv6? MAC? Whatever the "any" or "lo" devices use? Yes!
+pub struct Addr {
+    pub(crate) addr: *mut nl_addr,
+}
+
+impl Addr {
+    /// Builds an address of the family specified out of its raw bytes. The
+    /// prefix length starts out covering the entire address
+    pub fn new(family: c_int, bytes: &[u8]) -> Option<Self> {
+        let addr = unsafe { nl_addr_build(family, bytes.as_ptr() as *const _, bytes.len()) };
+
+        if addr.is_null() {
+            return None;
+        }
+
+        Some(Addr { addr })
+    }
+
+    /// Builds a link layer address out of a MAC address
+    pub fn from_mac(mac: [u8; 6]) -> Self {
+        Self::from(MacAddr(mac))
+    }
+
+    /// Returns the number of bytes that are in the address
+    pub fn len(&self) -> u32 {
+        unsafe { nl_addr_get_len(self.addr) }
+    }
+
+    /// Returns whether the address has no bytes at all
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the address, which can be interpreted based on the results of [`Addr::atype`]
+    pub fn hw_address(&self) -> Vec<u8> {
+        unsafe {
+            let hw_address_ptr = nl_addr_get_binary_addr(self.addr) as *const u8;
+            let hw_address_slice = std::slice::from_raw_parts(hw_address_ptr, self.len() as usize);
+
+            hw_address_slice.to_vec()
+        }
+    }
+
+    // Determines the type of data in [`Addr::hw_address`]
+    pub fn atype(&self) -> Option<c_int> {
+        if self.addr.is_null() {
+            None
+        } else {
+            Some(unsafe { nl_addr_get_family(self.addr) })
+        }
+    }
+
+    /// Returns the length of the subnet mask applying to this address
+    pub fn cidrlen(&self) -> c_uint {
+        unsafe { nl_addr_get_prefixlen(self.addr) }
+    }
+
+    pub fn set_cidrlen(&self, cidr: c_int) {
+        unsafe { nl_addr_set_prefixlen(self.addr, cidr) };
+    }
+
+    /// Sets the length of the subnet mask, for use when building addresses
+    pub fn with_cidrlen(self, cidr: c_int) -> Self {
+        self.set_cidrlen(cidr);
+        self
+    }
+}
+
+impl Debug for Addr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let res = match self.atype() {
+            Some(AF_INET) => {
+                let octets = self.hw_address();
+                f.debug_struct("Addr")
+                    .field(
+                        "addr",
+                        &format!(
+                            "{}.{}.{}.{}/{}",
+                            octets[0],
+                            octets[1],
+                            octets[2],
+                            octets[3],
+                            self.cidrlen()
+                        ),
+                    )
+                    .finish()
+            }
+            Some(AF_INET6) => {
+                let addr: Result<Ipv6Addr, _> = self.try_into();
+                f.debug_struct("Addr")
+                    .field(
+                        "addr",
+                        &format!(
+                            "{}/{}",
+                            addr.map(|a| a.to_string()).unwrap_or_default(),
+                            self.cidrlen()
+                        ),
+                    )
+                    .finish()
+            }
+            Some(AF_LLC) => match MacAddr::try_from(self) {
+                Ok(mac) => f.debug_struct("Addr").field("addr", &mac).finish(),
+                Err(_) => f
+                    .debug_struct("Addr")
+                    .field("addr", &self.hw_address())
+                    .finish(),
+            },
+            None => f
+                .debug_struct("Addr")
+                .field("addr", &"unknown")
+                .field("atype", &"unknown")
+                .finish(),
+            _ => f
+                .debug_struct("Addr")
+                .field("addr", &self.hw_address())
+                .field("atype", &self.atype())
+                .finish(),
+        };
+        res
+    }
+}
+
+impl Display for Addr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.atype() {
+            Some(AF_INET) | Some(AF_INET6) => {
+                let ip = Ipv4Addr::try_from(self)
+                    .map(std::net::IpAddr::from)
+                    .or_else(|_| Ipv6Addr::try_from(self).map(std::net::IpAddr::from));
+
+                match ip {
+                    Ok(ip) if self.cidrlen() as usize == self.len() as usize * 8 => {
+                        write!(f, "{ip}")
+                    }
+                    Ok(ip) => write!(f, "{ip}/{}", self.cidrlen()),
+                    Err(_) => write!(f, "invalid"),
+                }
+            }
+            Some(AF_LLC) => match MacAddr::try_from(self) {
+                Ok(mac) => write!(f, "{mac}"),
+                Err(_) => write!(f, "{:02x?}", self.hw_address()),
+            },
+            _ => write!(f, "none"),
+        }
+    }
+}
+
+impl From<Ipv4Addr> for Addr {
+    fn from(value: Ipv4Addr) -> Self {
+        Self::new(AF_INET, &value.octets()).expect("could not allocate IPv4 address")
+    }
+}
+
+impl From<Ipv6Addr> for Addr {
+    fn from(value: Ipv6Addr) -> Self {
+        Self::new(AF_INET6, &value.octets()).expect("could not allocate IPv6 address")
+    }
+}
+
+impl TryFrom<&Addr> for Ipv4Addr {
+    type Error = error::Error;
+
+    fn try_from(value: &Addr) -> Result<Self, Self::Error> {
+        if value.len() != 4 {
+            return Err(error::Error::new(15 /* NL_AF_MISMATCH */));
+        }
+
+        let addr = value.hw_address();
+        Ok(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]))
+    }
+}
+
+impl TryFrom<&Addr> for Ipv6Addr {
+    type Error = error::Error;
+
+    fn try_from(value: &Addr) -> Result<Self, Self::Error> {
+        let addr: [u8; 16] = value
+            .hw_address()
+            .try_into()
+            .map_err(|_| error::Error::new(15 /* NL_AF_MISMATCH */))?;
+
+        Ok(Ipv6Addr::from(addr))
+    }
+}
+
+/// The hardware address of an ethernet-like link or neighbor
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: Self = Self([0xFF; 6]);
+
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    /// Whether the address was made up rather than assigned by the vendor,
+    /// as done for virtual links and randomised Wi-Fi addresses
+    pub fn is_locally_administered(&self) -> bool {
+        self.0[0] & 0x02 != 0
+    }
+}
+
+impl Display for MacAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl Debug for MacAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl FromStr for MacAddr {
+    type Err = error::Error;
+
+    /// Parses six pairs of hex digits separated by colons or dashes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let octets = s
+            .split([':', '-'])
+            .map(|o| match o.len() {
+                1 | 2 if o.bytes().all(|b| b.is_ascii_hexdigit()) => u8::from_str_radix(o, 16).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .and_then(|o| <[u8; 6]>::try_from(o).ok())
+            .ok_or(error::Error::new(7 /* NLE_INVAL */))?;
+
+        Ok(Self(octets))
+    }
+}
+
+impl From<[u8; 6]> for MacAddr {
+    fn from(value: [u8; 6]) -> Self {
+        Self(value)
+    }
+}
+
+impl From<MacAddr> for [u8; 6] {
+    fn from(value: MacAddr) -> Self {
+        value.0
+    }
+}
+
+impl From<MacAddr> for Addr {
+    fn from(value: MacAddr) -> Self {
+        Self::new(AF_LLC, &value.0).expect("could not allocate MAC address")
+    }
+}
+
+impl TryFrom<&Addr> for MacAddr {
+    type Error = error::Error;
+
+    fn try_from(value: &Addr) -> Result<Self, Self::Error> {
+        let octets: [u8; 6] = value
+            .hw_address()
+            .try_into()
+            .map_err(|_| error::Error::new(15 /* NL_AF_MISMATCH */))?;
+
+        Ok(Self(octets))
+    }
+}
+
+/// Represents a route in the kernel routing table
+pub struct Route {
+    route: *mut rtnl_route,
+}
+
+impl Route {
+    pub const RT_TABLE_UNSPEC: u32 = 0;
+    pub const RT_TABLE_DEFAULT: u32 = 253;
+    pub const RT_TABLE_MAIN: u32 = 254;
+    pub const RT_TABLE_LOCAL: u32 = 255;
+
+    pub const RTN_UNICAST: u8 = 1;
+    pub const RTN_LOCAL: u8 = 2;
+    pub const RTN_BROADCAST: u8 = 3;
+
+    /// Allocates a new route to modify
+    pub fn new() -> Option<Self> {
+        let route = unsafe { rtnl_route_alloc() };
+
+        if route.is_null() {
+            None
+        } else {
+            Some(Route { route })
+        }
+    }
+
+    /// Represents the destination of the route
+    pub fn src(&self) -> Option<Addr> {
+        unsafe {
+            let addr = rtnl_route_get_src(self.route);
+
+            if addr.is_null() {
+                return None;
+            }
+
+            Some(Addr { addr })
+        }
+    }
+
+    /// Represents the destination of the route
+    pub fn dst(&self) -> Option<Addr> {
+        unsafe {
+            let addr = rtnl_route_get_dst(self.route);
+
+            if addr.is_null() {
+                return None;
+            }
+
+            Some(Addr { addr })
+        }
+    }
+
+    /// The routing table the route belongs to
+    pub fn table(&self) -> u32 {
+        unsafe { rtnl_route_get_table(self.route) }
+    }
+
+    /// Sets the routing table to add the route to
+    pub fn set_table(&self, table: u32) {
+        unsafe { rtnl_route_set_table(self.route, table) };
+    }
+
+    /// The type of the route, e.g. [`Route::RTN_LOCAL`] for addresses owned
+    /// by the host
+    pub fn rtype(&self) -> u8 {
+        unsafe { rtnl_route_get_type(self.route) }
+    }
+
+    /// Represents the source address preferred when sending along this route
+    pub fn pref_src(&self) -> Option<Addr> {
+        unsafe {
+            let addr = rtnl_route_get_pref_src(self.route);
+
+            if addr.is_null() {
+                return None;
+            }
+
+            Some(Addr { addr })
+        }
+    }
+
+    /// The metric of the route; lower is preferred
+    pub fn priority(&self) -> u32 {
+        unsafe { rtnl_route_get_priority(self.route) }
+    }
+
+    /// Collects the parts of a resolved route that callers care about
+    pub(crate) fn lookup_info(&self) -> RouteLookup {
+        let hop = self.hop_iter().next();
+
+        RouteLookup {
+            ifindex: hop.as_ref().map(|h| h.ifindex()).unwrap_or(0),
+            gateway: hop
+                .and_then(|h| h.gateway())
+                .and_then(|g| (&g).try_into().ok()),
+            pref_src: self.pref_src().and_then(|a| (&a).try_into().ok()),
+        }
+    }
+
+    pub fn set_dst(&self, addr: Addr) {
+        unsafe {
+            rtnl_route_set_dst(self.route, addr.addr);
+        }
+    }
+
+    /// Adds a new next hop or link
+    pub fn add_nexthop(&self, nh: &Nexthop) {
+        unsafe { rtnl_route_add_nexthop(self.route, nh.nexthop) };
+    }
+
+    /// Talks to the kernel and adds the route to the routing table
+    pub fn add(&self, socket: &netlink::Socket, flags: c_int) -> error::Result<()> {
+        let ret = socket.retrying(|| unsafe { rtnl_route_add(socket.sock, self.route, flags) });
+
+        if ret < 0 {
+            Err(error::Error::new(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the amount of hops are in this route
+    pub fn nexthop_len(&self) -> c_int {
+        unsafe { rtnl_route_get_nnexthops(self.route) }
+    }
+
+    /// Gets the hop at the index specify
+    pub fn nexthop(&self, ind: i32) -> Option<Nexthop> {
+        unsafe {
+            let nexthop = rtnl_route_nexthop_n(self.route, ind);
+            if nexthop.is_null() {
+                return None;
+            }
+            Some(Nexthop { nexthop })
+        }
+    }
+
+    /// Returns an iterator representing all the hops for this route
+    pub fn hop_iter(&self) -> NexthopIter<'_> {
+        NexthopIter {
+            route: &self,
+            index: 0,
+        }
+    }
+}
+
+impl Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.dst() {
+            Some(dst) if dst.cidrlen() > 0 => write!(f, "{dst}")?,
+            _ => write!(f, "default")?,
+        }
+
+        for hop in self.hop_iter() {
+            if let Some(gateway) = hop.gateway() {
+                write!(f, " via {gateway}")?;
+            }
+            write!(f, " dev {}", hop.ifindex())?;
+            if hop.is_onlink() {
+                write!(f, " onlink")?;
+            }
+        }
+
+        if self.priority() != 0 {
+            write!(f, " metric {}", self.priority())?;
+        }
+        if self.table() != Self::RT_TABLE_MAIN {
+            write!(f, " table {}", self.table())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let info = self.lookup_info();
+
+        f.debug_struct("Route")
+            .field("dst", &self.dst().map(|a| a.to_string()))
+            .field("gateway", &info.gateway)
+            .field("oif", &info.ifindex)
+            .field("metric", &self.priority())
+            .field("table", &self.table())
+            .finish()
+    }
+}
+
+impl From<*mut nl_object> for Route {
+    fn from(value: *mut nl_object) -> Self {
+        Route {
+            route: value as *mut _,
+        }
+    }
+}
+
+/// The route the kernel resolved for a destination, see
+/// [`netlink::Socket::lookup_route`]
+#[derive(Debug, Clone)]
+pub struct RouteLookup {
+    /// The interface packets would be sent out of
+    pub ifindex: i32,
+    /// The next hop, if the destination isn't directly connected
+    pub gateway: Option<Ipv4Addr>,
+    /// The source address the kernel would pick
+    pub pref_src: Option<Ipv4Addr>,
+}
+
+/// The route the FIB matched for a destination, see
+/// [`netlink::Socket::fib_lookup`]. Only the route is identified, not where
+/// it leads
+#[derive(Debug, Clone, Copy)]
+pub struct FibResult {
+    /// The routing table the route is in
+    pub table: u32,
+    /// The prefix length of the route's destination
+    pub prefixlen: u8,
+    /// The index of the next hop chosen, for multipath routes
+    pub nexthop: c_int,
+    /// The type of the route, e.g. [`Route::RTN_UNICAST`]
+    pub rtype: u8,
+    /// The scope of the route
+    pub scope: u8,
+}
+
+impl FibResult {
+    /// Finds the route in a cache of routes, such as one loaded with
+    /// [`Route::RT_TABLE_UNSPEC`], and where it sends traffic for `dst`
+    pub fn resolve(&self, routes: &Cache<Route>, dst: Ipv4Addr) -> Option<RouteLookup> {
+        let mask = u32::MAX
+            .checked_shl(32 - self.prefixlen as u32)
+            .unwrap_or(0);
+
+        let route = routes.iter().find(|r| {
+            r.table() == self.table
+                && r.rtype() == self.rtype
+                && r.dst().is_some_and(|d| {
+                    d.cidrlen() == self.prefixlen as c_uint
+                        && Ipv4Addr::try_from(&d)
+                            .is_ok_and(|d| u32::from(d) & mask == u32::from(dst) & mask)
+                })
+        })?;
+
+        let hop = route.nexthop(self.nexthop).or(route.hop_iter().next());
+
+        Some(RouteLookup {
+            ifindex: hop.as_ref().map(|h| h.ifindex()).unwrap_or(0),
+            gateway: hop
+                .and_then(|h| h.gateway())
+                .and_then(|g| (&g).try_into().ok()),
+            pref_src: route.pref_src().and_then(|a| (&a).try_into().ok()),
+        })
+    }
+}
+
+/// Represents the hops of a network route
+pub struct Nexthop {
+    nexthop: *mut rtnl_nexthop,
+}
+
+impl Nexthop {
+    /// The gateway is on the link even though no prefix of the link covers it
+    pub const RTNH_F_ONLINK: c_uint = 4;
+
+    /// Allocates a new next hop, unless out of memory
+    pub fn new() -> Option<Self> {
+        let nexthop = unsafe { rtnl_route_nh_alloc() };
+
+        if nexthop.is_null() {
+            return None;
+        }
+
+        Some(Self { nexthop })
+    }
+
+    /// Returns the gateway used for this network hop
+    pub fn gateway(&self) -> Option<Addr> {
+        unsafe {
+            let addr = rtnl_route_nh_get_gateway(self.nexthop);
+
+            if addr.is_null() {
+                return None;
+            }
+
+            Some(Addr { addr })
+        }
+    }
+
+    /// Sets the gateway to use
+    pub fn set_gateway(&self, addr: Addr) {
+        unsafe {
+            rtnl_route_nh_set_gateway(self.nexthop, addr.addr);
+        }
+    }
+
+    /// Returns the interface index for this network hop
+    pub fn ifindex(&self) -> i32 {
+        unsafe { rtnl_route_nh_get_ifindex(self.nexthop) }
+    }
+
+    /// Sets the interface index that represents the device this route is on
+    pub fn set_ifindex(&self, index: c_int) {
+        unsafe { rtnl_route_nh_set_ifindex(self.nexthop, index) };
+    }
+
+    /// Returns the RTNH_F_* flags of this hop
+    pub fn flags(&self) -> c_uint {
+        unsafe { rtnl_route_nh_get_flags(self.nexthop) }
+    }
+
+    /// Sets RTNH_F_* flags, leaving the others as they are
+    pub fn set_flags(&self, flags: c_uint) {
+        unsafe { rtnl_route_nh_set_flags(self.nexthop, flags) };
+    }
+
+    /// Clears RTNH_F_* flags, leaving the others as they are
+    pub fn unset_flags(&self, flags: c_uint) {
+        unsafe { rtnl_route_nh_unset_flags(self.nexthop, flags) };
+    }
+
+    /// Whether the gateway is taken to be directly reachable on the link
+    pub fn is_onlink(&self) -> bool {
+        self.flags() & Self::RTNH_F_ONLINK != 0
+    }
+
+    /// Lets the gateway be outside of every prefix configured on the link,
+    /// as with `ip route add ... via GW dev DEV onlink`
+    pub fn set_onlink(&self, onlink: bool) {
+        if onlink {
+            self.set_flags(Self::RTNH_F_ONLINK);
+        } else {
+            self.unset_flags(Self::RTNH_F_ONLINK);
+        }
+    }
+}
+
+/// An iterator for working with route hops
+pub struct NexthopIter<'a> {
+    route: &'a Route,
+    index: i32,
+}
+
+impl Iterator for NexthopIter<'_> {
+    type Item = Nexthop;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.route.nexthop(self.index);
+
+        if next.is_none() {
+            return None;
+        }
+
+        self.index += 1;
+
+        next
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (
+            self.route.nexthop_len() as usize,
+            Some(self.route.nexthop_len() as usize),
+        )
+    }
+}
+
+/// A standalone nexthop object (Linux 5.3), which routes refer to by its
+/// id instead of carrying next hops of their own, as with `ip nexthop`.
+/// Ids are chosen by the caller and have to be nonzero
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NexthopObject {
+    /// A gateway reached through a link, or the link itself when there is
+    /// no gateway
+    Hop {
+        id: u32,
+        ifindex: c_int,
+        gateway: Option<Ipv4Addr>,
+        onlink: bool,
+    },
+    /// Spreads traffic over other nexthop objects, given by id along with
+    /// their weight from 1 to 255
+    Group { id: u32, members: Vec<(u32, u8)> },
+}
+
+impl NexthopObject {
+    pub fn id(&self) -> u32 {
+        match self {
+            NexthopObject::Hop { id, .. } | NexthopObject::Group { id, .. } => *id,
+        }
+    }
+}
+
+/// A policy routing rule sending traffic which carries a firewall mark to
+/// a routing table, as with `ip rule add fwmark MARK/MASK table TABLE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FwmarkRule {
+    pub fwmark: u32,
+    pub mask: u32,
+    pub table: u32,
+    pub priority: u32,
+}
+
+/// A queueing discipline attached to a link
+pub struct Qdisc {
+    qdisc: *mut rtnl_qdisc,
+}
+
+impl Qdisc {
+    /// Allocates a new root qdisc of the kind given, e.g. "fq_codel", for
+    /// the link with the index specified
+    pub fn new_root(ifindex: c_int, kind: &str) -> error::Result<Self> {
+        let kind = CString::new(kind).map_err(|_| error::Error::new(7 /* NLE_INVAL */))?;
+
+        unsafe {
+            let qdisc = rtnl_qdisc_alloc();
+            if qdisc.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+            let qdisc = Qdisc { qdisc };
+
+            let tc = qdisc.qdisc as *mut rtnl_tc;
+            rtnl_tc_set_ifindex(tc, ifindex);
+            rtnl_tc_set_parent(tc, TC_H_ROOT);
+
+            let ret = rtnl_tc_set_kind(tc, kind.as_ptr());
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(qdisc)
+        }
+    }
+
+    /// Allocates the clsact qdisc of the link with the index specified, which
+    /// has no queue of its own but holds the filters run on the packets
+    /// entering and leaving the link. See [`Filter::new_mirror`]
+    pub fn new_clsact(ifindex: c_int) -> error::Result<Self> {
+        let qdisc = Self::new_root(ifindex, "clsact")?;
+
+        unsafe {
+            let tc = qdisc.qdisc as *mut rtnl_tc;
+            rtnl_tc_set_parent(tc, TC_H_CLSACT);
+            rtnl_tc_set_handle(tc, TC_H_CLSACT_HANDLE);
+        }
+
+        Ok(qdisc)
+    }
+
+    /// Allocates a token bucket filter for the link with the index
+    /// specified, letting `rate` bytes a second through with bursts of up to
+    /// `bucket` bytes, and queueing packets for at most `latency`
+    /// microseconds before dropping them
+    pub fn new_tbf(ifindex: c_int, rate: u32, bucket: u32, latency: u32) -> error::Result<Self> {
+        let int =
+            |n: u32| c_int::try_from(n).map_err(|_| error::Error::new(34 /* NLE_RANGE */));
+        let (rate, bucket, latency) = (int(rate)?, int(bucket)?, int(latency)?);
+
+        let qdisc = Self::new_root(ifindex, "tbf")?;
+
+        unsafe {
+            // The limit is worked out from the rate, which has to be set first
+            rtnl_qdisc_tbf_set_rate(qdisc.qdisc, rate, bucket, 0);
+            let ret = rtnl_qdisc_tbf_set_limit_by_latency(qdisc.qdisc, latency);
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+        }
+
+        Ok(qdisc)
+    }
+
+    /// Attaches the qdisc to its link, replacing the one already there
+    pub fn replace(&self, sock: &netlink::Socket) -> error::Result<()> {
+        let ret = sock.retrying(|| unsafe {
+            rtnl_qdisc_add(
+                sock.sock,
+                self.qdisc,
+                0x400 | 0x100, /* NLM_F_CREATE | NLM_F_REPLACE */
+            )
+        });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Qdisc {
+    fn drop(&mut self) {
+        unsafe { rtnl_qdisc_put(self.qdisc) }
+    }
+}
+
+/// Which packets of a link a filter under its clsact qdisc sees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Ingress,
+    Egress,
+}
+
+/// A classifier attached to a qdisc, acting on the packets it matches
+pub struct Filter {
+    cls: *mut rtnl_cls,
+}
+
+impl Filter {
+    /// Allocates a filter for the clsact qdisc of the link with the index
+    /// specified, copying every packet seen in the direction given out of
+    /// the link `target`, as with
+    /// `tc filter add dev LINK ingress matchall action mirred egress mirror dev TARGET`
+    pub fn new_mirror(ifindex: c_int, direction: Direction, target: c_int) -> error::Result<Self> {
+        let parent = TC_H_CLSACT_HANDLE
+            | match direction {
+                Direction::Ingress => TC_H_MIN_INGRESS,
+                Direction::Egress => TC_H_MIN_EGRESS,
+            };
+
+        unsafe {
+            let cls = rtnl_cls_alloc();
+            if cls.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+            let filter = Filter { cls };
+
+            // Without any ematches, the basic classifier matches everything
+            let tc = cls as *mut rtnl_tc;
+            rtnl_tc_set_ifindex(tc, ifindex);
+            rtnl_tc_set_parent(tc, parent);
+            let ret = rtnl_tc_set_kind(tc, c"basic".as_ptr());
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+            rtnl_cls_set_protocol(cls, ETH_P_ALL);
+
+            let act = rtnl_act_alloc();
+            if act.is_null() {
+                return Err(error::Error::new(5 /* NLE_NOMEM */));
+            }
+            let ret = match rtnl_tc_set_kind(act as *mut rtnl_tc, c"mirred".as_ptr()) {
+                0 => match rtnl_mirred_set_action(act, TCA_EGRESS_MIRROR) {
+                    0 => match rtnl_mirred_set_policy(act, TC_ACT_PIPE) {
+                        0 => {
+                            rtnl_mirred_set_ifindex(act, target as u32);
+                            rtnl_basic_add_action(cls, act)
+                        }
+                        ret => ret,
+                    },
+                    ret => ret,
+                },
+                ret => ret,
+            };
+            // The classifier holds a reference of its own
+            rtnl_act_put(act);
+
+            if ret < 0 {
+                return Err(error::Error::new(ret));
+            }
+
+            Ok(filter)
+        }
+    }
+
+    /// Attaches the filter to its qdisc
+    pub fn add(&self, sock: &netlink::Socket) -> error::Result<()> {
+        let ret = sock.retrying(|| unsafe {
+            rtnl_cls_add(
+                sock.sock,
+                self.cls,
+                0x400 | 0x200, /* NLM_F_CREATE | NLM_F_EXCL */
+            )
+        });
+
+        if ret < 0 {
+            return Err(error::Error::new(ret));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Filter {
+    fn drop(&mut self) {
+        unsafe { rtnl_cls_put(self.cls) }
+    }
+}
+
+/// The counters of a qdisc or traffic class, as shown by `tc -s`. A
+/// shaper is the bottleneck of a link when its backlog stays above zero and
+/// its overlimits keep growing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcStats {
+    pub ifindex: c_int,
+    pub handle: u32,
+    pub parent: u32,
+    pub kind: Option<String>,
+    pub packets: u64,
+    pub bytes: u64,
+    /// Packets waiting in the queue
+    pub qlen: u64,
+    /// Bytes waiting in the queue
+    pub backlog: u64,
+    pub drops: u64,
+    pub requeues: u64,
+    /// Times a packet was held back to keep to the configured rate
+    pub overlimits: u64,
+}
+
+impl TcStats {
+    /// Reads the counters of a qdisc or class out of a cache
+    pub(crate) unsafe fn read(tc: *mut rtnl_tc) -> Self {
+        unsafe {
+            let kind = rtnl_tc_get_kind(tc);
+
+            Self {
+                ifindex: rtnl_tc_get_ifindex(tc),
+                handle: rtnl_tc_get_handle(tc),
+                parent: rtnl_tc_get_parent(tc),
+                kind: (!kind.is_null())
+                    .then(|| CStr::from_ptr(kind).to_string_lossy().into_owned()),
+                packets: rtnl_tc_get_stat(tc, RTNL_TC_PACKETS),
+                bytes: rtnl_tc_get_stat(tc, RTNL_TC_BYTES),
+                qlen: rtnl_tc_get_stat(tc, RTNL_TC_QLEN),
+                backlog: rtnl_tc_get_stat(tc, RTNL_TC_BACKLOG),
+                drops: rtnl_tc_get_stat(tc, RTNL_TC_DROPS),
+                requeues: rtnl_tc_get_stat(tc, RTNL_TC_REQUEUES),
+                overlimits: rtnl_tc_get_stat(tc, RTNL_TC_OVERLIMITS),
+            }
+        }
+    }
+
+    /// Whether these are the counters of the root qdisc of the link
+    pub fn is_root(&self) -> bool {
+        self.parent == TC_H_ROOT
+    }
+}
+
+/// Picks an IPv4 address of the link to send from when the route has no
+/// preferred source, favouring one on the same subnet as the next hop
+fn source_addr(
+    addrs: &Cache<RtAddr>,
+    ifindex: c_int,
+    next_hop: Ipv4Addr,
+) -> Option<CacheItem<'_, RtAddr>> {
+    let on_link = || {
+        addrs
+            .iter()
+            .filter(move |a| a.ifindex() == ifindex && a.family() == AF_INET)
+    };
+
+    on_link()
+        .find(|a| {
+            let mask = u32::MAX.checked_shl(32 - a.prefixlen() as u32).unwrap_or(0);
+            a.local()
+                .and_then(|l| Ipv4Addr::try_from(&l).ok())
+                .is_some_and(|l| u32::from(l) & mask == u32::from(next_hop) & mask)
+        })
+        .or_else(|| on_link().next())
+}
+
+/// Determines the source IP address to use in order to make a network
+/// request: the route's preferred source if it has one, otherwise an
+/// address of the link the route leaves through
+pub fn get_srcip_for_dstip(sock: &netlink::Socket, ip: Ipv4Addr) -> Option<Ipv4Addr> {
+    let route = sock.lookup_route(ip).ok()?;
+    if let Some(pref_src) = route.pref_src {
+        return Some(pref_src);
+    }
+
+    let addrs = sock.get_addrs().ok()?;
+    let addr = source_addr(&addrs, route.ifindex, route.gateway.unwrap_or(ip))?;
+    Ipv4Addr::try_from(&addr.local()?).ok()
+}
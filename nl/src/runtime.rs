@@ -0,0 +1,94 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Finding libnl on the system as the program runs, for binaries built with
+//! the `runtime-libnl` feature. Call [`load`] before anything else in this
+//! crate to get an error instead of a panic when libnl isn't installed
+
+use std::{fmt::Display, sync::OnceLock};
+
+use libloading::Library;
+
+use super::ffi::Symbols;
+
+/// The names libnl-3 and libnl-route-3 are looked for under, in order
+const LIBRARIES: [&[&str]; 2] = [
+    &["libnl-3.so.200", "libnl-3.so"],
+    &["libnl-route-3.so.200", "libnl-route-3.so"],
+];
+
+static LIBNL: OnceLock<Result<(Vec<Library>, Symbols), LoadError>> = OnceLock::new();
+
+/// libnl, or one of the functions used from it, couldn't be found
+#[derive(Debug, Clone)]
+pub struct LoadError(String);
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "libnl not found: {}", self.0)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Loads libnl, if it hasn't been loaded already
+pub fn load() -> Result<(), LoadError> {
+    LIBNL
+        .get_or_init(open)
+        .as_ref()
+        .map(|_| ())
+        .map_err(Clone::clone)
+}
+
+fn open() -> Result<(Vec<Library>, Symbols), LoadError> {
+    let libs = LIBRARIES
+        .iter()
+        .map(|names| {
+            let mut last = None;
+            for name in *names {
+                match unsafe { Library::new(name) } {
+                    Ok(lib) => return Ok(lib),
+                    Err(e) => last = Some(e),
+                }
+            }
+            Err(LoadError(last.map(|e| e.to_string()).unwrap_or_default()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let symbols = Symbols::find(&libs).map_err(|e| LoadError(e.to_string()))?;
+
+    Ok((libs, symbols))
+}
+
+/// The functions of the loaded libnl. Panics if it can't be loaded
+pub(crate) fn symbols() -> &'static Symbols {
+    match LIBNL.get_or_init(open) {
+        Ok((_, symbols)) => symbols,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Looks a function up in each of the libraries in turn
+pub(crate) fn find<T: Copy>(libs: &[Library], name: &str) -> Result<T, libloading::Error> {
+    let mut last = None;
+    for lib in libs {
+        match unsafe { lib.get::<T>(name.as_bytes()) } {
+            Ok(symbol) => return Ok(*symbol),
+            Err(e) => last = Some(e),
+        }
+    }
+
+    Err(last.expect("no libraries to look in"))
+}
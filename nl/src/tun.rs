@@ -0,0 +1,152 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! TUN and TAP devices, which hand the packets routed to them to a file
+//! descriptor rather than to a driver. They are created through
+//! `/dev/net/tun` rather than netlink, but otherwise behave like any link
+
+use std::{
+    ffi::CStr,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+};
+
+use libc::{c_int, c_ulong};
+
+use super::{error, ffi::nl_syserr2nlerr, netlink::Socket, route::Link};
+
+const TUNSETIFF: c_ulong = 0x400454ca;
+const TUNSETPERSIST: c_ulong = 0x400454cb;
+const TUNSETOWNER: c_ulong = 0x400454cc;
+
+const IFF_TUN: libc::c_short = 0x0001;
+const IFF_TAP: libc::c_short = 0x0002;
+const IFF_NO_PI: libc::c_short = 0x1000;
+
+/// What the file descriptor of a device reads and writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// IP packets
+    Tun,
+    /// Ethernet frames
+    Tap,
+}
+
+/// A TUN or TAP device along with the file descriptor its packets go
+/// through. The device goes away with the descriptor unless it is made
+/// persistent
+#[derive(Debug)]
+pub struct Tun {
+    fd: OwnedFd,
+    name: String,
+}
+
+/// The libnl error for the errno of the last failed system call
+fn last_error() -> error::Error {
+    let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+    error::Error::new(unsafe { nl_syserr2nlerr(errno) })
+}
+
+impl Tun {
+    /// Creates a device with the name given, or attaches to the persistent
+    /// device of that name. With an empty name the kernel picks one, such as
+    /// `tun0`. Packets carry no extra header
+    pub fn create(name: &str, kind: Kind) -> error::Result<Self> {
+        let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+        if name.len() >= ifr.ifr_name.len() || name.contains('\0') {
+            return Err(error::Error::new(7 /* NLE_INVAL */));
+        }
+        for (dst, src) in ifr.ifr_name.iter_mut().zip(name.bytes()) {
+            *dst = src as libc::c_char;
+        }
+        ifr.ifr_ifru.ifru_flags = IFF_NO_PI
+            | match kind {
+                Kind::Tun => IFF_TUN,
+                Kind::Tap => IFF_TAP,
+            };
+
+        unsafe {
+            let fd = libc::open(c"/dev/net/tun".as_ptr(), libc::O_RDWR | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(last_error());
+            }
+            let fd = OwnedFd::from_raw_fd(fd);
+
+            if libc::ioctl(fd.as_raw_fd(), TUNSETIFF, &mut ifr) < 0 {
+                return Err(last_error());
+            }
+
+            let name = CStr::from_ptr(ifr.ifr_name.as_ptr())
+                .to_string_lossy()
+                .into_owned();
+            Ok(Self { fd, name })
+        }
+    }
+
+    /// The name of the device, which the kernel may have picked
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Keeps the device around after the descriptor is closed, so that
+    /// another process can attach to it by name
+    pub fn set_persistent(&self, persistent: bool) -> error::Result<()> {
+        self.ioctl(TUNSETPERSIST, persistent as c_int)
+    }
+
+    /// Lets the user given attach to the device without CAP_NET_ADMIN
+    pub fn set_owner(&self, uid: libc::uid_t) -> error::Result<()> {
+        self.ioctl(TUNSETOWNER, uid as c_int)
+    }
+
+    fn ioctl(&self, request: c_ulong, arg: c_int) -> error::Result<()> {
+        if unsafe { libc::ioctl(self.fd.as_raw_fd(), request, arg as c_ulong) } < 0 {
+            return Err(last_error());
+        }
+
+        Ok(())
+    }
+
+    /// Moves the device into the network namespace of the process given.
+    /// The descriptor keeps working, so the packets of a namespace can be
+    /// handled from outside of it
+    pub fn set_netns(&self, socket: &Socket, pid: libc::pid_t) -> error::Result<()> {
+        let links = socket.get_links()?;
+        let link = links
+            .iter()
+            .find(|l| l.name() == self.name)
+            .ok_or(error::Error::new(12 /* NLE_OBJ_NOTFOUND */))?;
+
+        let changes = Link::new();
+        changes.set_ns_pid(pid);
+        link.change(socket, &changes)
+    }
+
+    /// Gives up the device, keeping only the descriptor
+    pub fn into_fd(self) -> OwnedFd {
+        self.fd
+    }
+}
+
+impl AsFd for Tun {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for Tun {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
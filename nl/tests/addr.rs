@@ -0,0 +1,107 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use download_shell_nl::route::{Addr, MacAddr, RtAddr};
+
+#[test]
+fn ipv4_round_trip() {
+    let ip = Ipv4Addr::new(192, 168, 1, 20);
+    let addr = Addr::from(ip);
+
+    assert_eq!(addr.atype(), Some(libc::AF_INET));
+    assert_eq!(addr.len(), 4);
+    assert_eq!(addr.cidrlen(), 32);
+    assert_eq!(Ipv4Addr::try_from(&addr).unwrap(), ip);
+}
+
+#[test]
+fn ipv6_round_trip() {
+    let ip = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1);
+    let addr = Addr::from(ip);
+
+    assert_eq!(addr.atype(), Some(libc::AF_INET6));
+    assert_eq!(addr.cidrlen(), 128);
+    assert_eq!(Ipv6Addr::try_from(&addr).unwrap(), ip);
+    assert!(Ipv4Addr::try_from(&addr).is_err());
+}
+
+#[test]
+fn mac_address() {
+    let mac = [0x02, 0x00, 0x5e, 0x10, 0x20, 0x30];
+    let addr = Addr::from_mac(mac);
+
+    assert_eq!(addr.atype(), Some(libc::AF_LLC));
+    assert_eq!(addr.hw_address(), mac);
+    assert_eq!(MacAddr::try_from(&addr).unwrap(), MacAddr(mac));
+}
+
+#[test]
+fn mac_address_parsing() {
+    let mac: MacAddr = "02:00:5E:10:20:30".parse().unwrap();
+
+    assert_eq!(mac, MacAddr([0x02, 0x00, 0x5e, 0x10, 0x20, 0x30]));
+    assert_eq!(mac.to_string(), "02:00:5e:10:20:30");
+    assert_eq!("02-00-5e-10-20-30".parse::<MacAddr>().unwrap(), mac);
+    assert!(mac.is_locally_administered());
+    assert!(!mac.is_multicast());
+
+    assert!("02:00:5e:10:20".parse::<MacAddr>().is_err());
+    assert!("02:00:5e:10:20:30:40".parse::<MacAddr>().is_err());
+    assert!("02:00:5e:10:20:3g".parse::<MacAddr>().is_err());
+    assert!(MacAddr::try_from(&Addr::from(Ipv4Addr::LOCALHOST)).is_err());
+}
+
+#[test]
+fn prefix_length() {
+    let addr = Addr::from(Ipv4Addr::new(10, 0, 0, 0)).with_cidrlen(8);
+    assert_eq!(addr.cidrlen(), 8);
+
+    addr.set_cidrlen(0);
+    assert_eq!(addr.cidrlen(), 0);
+}
+
+#[test]
+fn display() {
+    assert_eq!(
+        Addr::from(Ipv4Addr::new(10, 1, 2, 3)).to_string(),
+        "10.1.2.3"
+    );
+    assert_eq!(
+        Addr::from(Ipv4Addr::new(10, 0, 0, 0))
+            .with_cidrlen(8)
+            .to_string(),
+        "10.0.0.0/8"
+    );
+    assert_eq!(Addr::from(Ipv6Addr::LOCALHOST).to_string(), "::1");
+    assert_eq!(
+        Addr::from_mac([0x02, 0x00, 0x5e, 0x10, 0x20, 0x30]).to_string(),
+        "02:00:5e:10:20:30"
+    );
+}
+
+#[test]
+fn address_labels() {
+    let addr = RtAddr::new().unwrap();
+    assert_eq!(addr.label(), None);
+
+    addr.set_label("eth0:dlsh").unwrap();
+    assert_eq!(addr.label().as_deref(), Some("eth0:dlsh"));
+
+    assert_eq!(addr.set_label("eth0:0123456789ab").unwrap_err().code(), 8);
+    assert_eq!(addr.set_label("eth0:\0").unwrap_err().code(), 7);
+    assert_eq!(addr.label().as_deref(), Some("eth0:dlsh"));
+}
@@ -0,0 +1,152 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
+    time::Duration,
+};
+
+use download_shell_nl::{
+    diag::{InetSocket, Protocol, Request},
+    netlink::Socket,
+};
+
+/// A `SOCK_DIAG_BY_FAMILY` message for an established IPv4 connection from
+/// 10.0.0.2:8080 to 1.1.1.1:443, followed by a tcp_info
+fn tcp_message() -> Vec<u8> {
+    let mut msg = vec![libc::AF_INET as u8, InetSocket::TCP_ESTABLISHED, 0, 0];
+    msg.extend(8080u16.to_be_bytes());
+    msg.extend(443u16.to_be_bytes());
+    msg.extend([10, 0, 0, 2].iter().chain(&[0; 12]));
+    msg.extend([1, 1, 1, 1].iter().chain(&[0; 12]));
+    msg.extend(0u32.to_ne_bytes()); // interface
+    msg.extend(5u32.to_ne_bytes()); // cookie
+    msg.extend(0u32.to_ne_bytes());
+    for field in [0u32, 0, 0, 1000, 77] {
+        // expires, rqueue, wqueue, uid, inode
+        msg.extend(field.to_ne_bytes());
+    }
+
+    let mut info = vec![0; 136];
+    info[44..48].copy_from_slice(&1500u32.to_ne_bytes());
+    info[52..56].copy_from_slice(&250u32.to_ne_bytes());
+    info[120..128].copy_from_slice(&1000u64.to_ne_bytes());
+    info[128..136].copy_from_slice(&2000u64.to_ne_bytes());
+    msg.extend((4 + info.len() as u16).to_ne_bytes());
+    msg.extend(2u16.to_ne_bytes()); // INET_DIAG_INFO
+    msg.extend(info);
+
+    msg
+}
+
+#[test]
+fn messages_are_decoded() {
+    let socket = InetSocket::decode(Protocol::Tcp, &tcp_message()).unwrap();
+
+    assert_eq!(socket.state_name(), "ESTAB");
+    assert_eq!(socket.local, "10.0.0.2:8080".parse::<SocketAddr>().unwrap());
+    assert_eq!(socket.remote, "1.1.1.1:443".parse::<SocketAddr>().unwrap());
+    assert_eq!(socket.cookie, 5);
+    assert_eq!((socket.uid, socket.inode), (1000, 77));
+    assert_eq!(socket.bytes_acked, Some(1000));
+    assert_eq!(socket.bytes_received, Some(2000));
+    assert_eq!(socket.idle(), Some(Duration::from_millis(250)));
+}
+
+#[test]
+fn truncated_messages_are_ignored() {
+    let msg = tcp_message();
+
+    assert!(InetSocket::decode(Protocol::Tcp, &msg[..40]).is_none());
+
+    // Without a whole tcp_info, only what is there is read
+    let socket = InetSocket::decode(Protocol::Tcp, &msg[..72 + 4 + 100]).unwrap();
+    assert_eq!(socket.bytes_acked, None);
+}
+
+#[test]
+fn requests_carry_the_socket_asked_for() {
+    let socket = InetSocket::decode(Protocol::Tcp, &tcp_message()).unwrap();
+    let request = Request::new(Protocol::Tcp)
+        .with_states(&[InetSocket::TCP_ESTABLISHED])
+        .with_id(socket.id());
+
+    assert_eq!(request.family, Some(libc::AF_INET));
+    assert_eq!(request.states, 1 << InetSocket::TCP_ESTABLISHED);
+
+    let encoded = request.encode(libc::AF_INET);
+    assert_eq!(encoded.len(), 56);
+    assert_eq!(
+        &encoded[..2],
+        &[libc::AF_INET as u8, libc::IPPROTO_TCP as u8]
+    );
+    assert_eq!(&encoded[8..12], &[0x1f, 0x90, 0x01, 0xbb]);
+    assert_eq!(&encoded[12..16], &[10, 0, 0, 2]);
+    assert_eq!(&encoded[48..52], &5u32.to_ne_bytes());
+}
+
+#[test]
+fn tcp_connections_are_listed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+    let sock = Socket::new_sock_diag().unwrap();
+    let sockets = sock.get_inet_sockets(Protocol::Tcp).unwrap();
+
+    let listening = sockets
+        .iter()
+        .find(|s| s.local == listener.local_addr().unwrap())
+        .unwrap();
+    assert_eq!(listening.state, InetSocket::TCP_LISTEN);
+
+    let connected = sockets
+        .iter()
+        .find(|s| s.local == client.local_addr().unwrap())
+        .unwrap();
+    assert_eq!(connected.state_name(), "ESTAB");
+    assert_eq!(connected.remote, listener.local_addr().unwrap());
+}
+
+#[test]
+fn unconnected_udp_sockets_are_listed() {
+    let udp = UdpSocket::bind("[::1]:0").unwrap();
+
+    let sock = Socket::new_sock_diag().unwrap();
+    let sockets = sock.get_inet_sockets(Protocol::Udp).unwrap();
+
+    let bound = sockets
+        .iter()
+        .find(|s| s.local == udp.local_addr().unwrap())
+        .unwrap();
+    assert_eq!(bound.state_name(), "UNCONN");
+    assert_eq!(bound.bytes_received, None);
+}
+
+#[test]
+fn dumps_can_be_limited_to_states() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+    let sock = Socket::new_sock_diag().unwrap();
+    let request = Request::new(Protocol::Tcp).with_states(&[InetSocket::TCP_LISTEN]);
+    let sockets = sock.query_sockets(&request).unwrap();
+
+    assert!(sockets.iter().all(|s| s.state == InetSocket::TCP_LISTEN));
+    assert!(
+        sockets
+            .iter()
+            .any(|s| s.local == listener.local_addr().unwrap())
+    );
+}
@@ -0,0 +1,245 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Read only queries, which any user is allowed to make
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use download_shell_nl::{
+    netlink::{RetryPolicy, Socket},
+    route::{
+        Addr, Direction, Filter, Link, NeighProxy, Nexthop, NexthopObject, Qdisc, Route,
+        get_srcip_for_dstip,
+    },
+};
+
+#[test]
+fn loopback_is_listed() {
+    let sock = Socket::new().unwrap();
+    let links = sock.get_links().unwrap();
+
+    let lo = links.iter().find(|l| l.name() == "lo").unwrap();
+    assert!(lo.ifindex() > 0);
+}
+
+#[test]
+fn dumps_succeed_without_retries() {
+    let mut sock = Socket::new().unwrap();
+    sock.set_retry_policy(RetryPolicy::NEVER);
+
+    assert!(sock.get_links().unwrap().iter().any(|l| l.name() == "lo"));
+}
+
+#[test]
+fn dumps_succeed_with_a_timeout() {
+    let mut sock = Socket::new().unwrap();
+    sock.set_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    assert!(sock.get_links().unwrap().iter().any(|l| l.name() == "lo"));
+}
+
+#[test]
+fn local_table_only_holds_local_routes() {
+    let sock = Socket::new().unwrap();
+    let routes = sock.get_routes(Route::RT_TABLE_LOCAL).unwrap();
+
+    assert!(routes.iter().all(|r| r.table() == Route::RT_TABLE_LOCAL));
+    assert!(routes.iter().any(|r| r.rtype() == Route::RTN_LOCAL));
+}
+
+#[test]
+fn ipv6_routes_are_loaded_on_their_own() {
+    let sock = Socket::new().unwrap();
+    let routes = sock.get_routes6(Route::RT_TABLE_LOCAL).unwrap();
+
+    assert!(
+        routes
+            .iter()
+            .filter_map(|r| r.dst())
+            .all(|dst| dst.atype() == Some(libc::AF_INET6))
+    );
+}
+
+#[test]
+fn routes_display_like_ip_route() {
+    let sock = Socket::new().unwrap();
+    let routes = sock.get_routes(Route::RT_TABLE_LOCAL).unwrap();
+
+    let lo = routes
+        .iter()
+        .find(|r| r.dst().is_some_and(|d| d.to_string() == "127.0.0.1"))
+        .unwrap();
+    assert!(lo.to_string().starts_with("127.0.0.1 dev "));
+    assert!(lo.to_string().ends_with(" table 255"));
+}
+
+#[test]
+fn masters_are_set_and_cleared() {
+    let link = Link::new();
+    assert_eq!(link.master(), None);
+
+    link.set_master(7);
+    assert_eq!(link.master(), Some(7));
+
+    link.set_master(0);
+    assert_eq!(link.master(), None);
+}
+
+#[test]
+fn dummy_links_have_their_kind() {
+    let link = Link::new_dummy().unwrap();
+    assert_eq!(link.ltype().as_deref(), Some("dummy"));
+
+    assert_eq!(link.set_type("du\0mmy").unwrap_err().code(), 7);
+}
+
+#[test]
+fn tunnel_links_keep_their_endpoints() {
+    let sit = Link::new_sit();
+    let (local, remote) = (Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(198, 51, 100, 1));
+    sit.set_sit_endpoints(local, remote, 64).unwrap();
+    assert_eq!(sit.sit_endpoints(), Some((local, remote)));
+    assert_eq!(sit.ip6tnl_endpoints(), None);
+
+    let ip6tnl = Link::new_ip6tnl();
+    let (local, remote) = (
+        "2001:db8::1".parse().unwrap(),
+        "2001:db8::2".parse().unwrap(),
+    );
+    ip6tnl
+        .set_ip6tnl_endpoints(local, remote, 4 /* IPPROTO_IPIP */, 64)
+        .unwrap();
+    assert_eq!(ip6tnl.ip6tnl_endpoints(), Some((local, remote)));
+
+    // The settings of one kind of link can't be applied to another
+    let sit_remote = Ipv4Addr::new(198, 51, 100, 1);
+    assert!(
+        ip6tnl
+            .set_sit_endpoints(Ipv4Addr::UNSPECIFIED, sit_remote, 64)
+            .is_err()
+    );
+}
+
+#[test]
+fn onlink_hops_keep_their_other_flags() {
+    let hop = Nexthop::new().unwrap();
+    hop.set_ifindex(1);
+    hop.set_gateway(Addr::from(Ipv4Addr::new(203, 0, 113, 1)));
+    assert!(!hop.is_onlink());
+
+    hop.set_flags(1 /* RTNH_F_DEAD */);
+    hop.set_onlink(true);
+    assert!(hop.is_onlink());
+    assert_eq!(hop.flags(), 1 | Nexthop::RTNH_F_ONLINK);
+
+    let route = Route::new().unwrap();
+    route.add_nexthop(&hop);
+    assert_eq!(route.to_string(), "default via 203.0.113.1 dev 1 onlink");
+
+    hop.set_onlink(false);
+    assert_eq!(hop.flags(), 1);
+}
+
+#[test]
+fn invalid_nexthop_objects_are_not_sent() {
+    let sock = Socket::new().unwrap();
+
+    let hop = NexthopObject::Hop {
+        id: 0,
+        ifindex: 1,
+        gateway: None,
+        onlink: false,
+    };
+    assert_eq!(sock.add_nexthop(&hop).unwrap_err().code(), 7);
+
+    let empty = NexthopObject::Group {
+        id: 1,
+        members: vec![],
+    };
+    assert_eq!(sock.add_nexthop(&empty).unwrap_err().code(), 7);
+
+    let weightless = NexthopObject::Group {
+        id: 1,
+        members: vec![(2, 0)],
+    };
+    assert_eq!(sock.add_nexthop(&weightless).unwrap_err().code(), 7);
+}
+
+#[test]
+fn qdisc_stats_only_cover_the_link_asked_for() {
+    let sock = Socket::new().unwrap();
+    let links = sock.get_links().unwrap();
+    let lo = links.iter().find(|l| l.name() == "lo").unwrap();
+
+    let stats = sock.get_qdisc_stats(lo.ifindex()).unwrap();
+    assert!(stats.iter().all(|s| s.ifindex == lo.ifindex()));
+    assert!(sock.get_class_stats(lo.ifindex()).unwrap().is_empty());
+}
+
+#[test]
+fn mirror_filters_can_be_built() {
+    assert!(Qdisc::new_clsact(1).is_ok());
+    for direction in [Direction::Ingress, Direction::Egress] {
+        assert!(Filter::new_mirror(1, direction, 2).is_ok());
+    }
+}
+
+#[test]
+fn token_bucket_filters_can_be_built() {
+    assert!(Qdisc::new_tbf(1, 625_000, 65_536, 50_000).is_ok());
+    assert!(Qdisc::new_tbf(1, u32::MAX, 65_536, 50_000).is_err());
+}
+
+#[test]
+fn neighbour_proxies_can_be_built() {
+    let addr = Addr::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x50));
+    assert!(NeighProxy::new(1, &addr).is_ok());
+}
+
+#[test]
+fn fib_lookup_resolves_loopback() {
+    let fib = Socket::new_fib_lookup().unwrap();
+    let result = fib.fib_lookup(Ipv4Addr::LOCALHOST, None).unwrap();
+
+    assert_eq!(result.table, Route::RT_TABLE_LOCAL);
+    assert_eq!(result.rtype, Route::RTN_LOCAL);
+
+    let sock = Socket::new().unwrap();
+    let routes = sock.get_routes(Route::RT_TABLE_UNSPEC).unwrap();
+    let lookup = result.resolve(&routes, Ipv4Addr::LOCALHOST).unwrap();
+
+    let links = sock.get_links().unwrap();
+    let lo = links.iter().find(|l| l.name() == "lo").unwrap();
+    assert_eq!(lookup.ifindex, lo.ifindex());
+}
+
+#[test]
+fn neighbour_notifications_can_be_subscribed_to() {
+    let sock = Socket::new().unwrap();
+    sock.subscribe_neigh().unwrap();
+}
+
+#[test]
+fn source_for_loopback_is_loopback() {
+    let sock = Socket::new().unwrap();
+
+    assert_eq!(
+        get_srcip_for_dstip(&sock, Ipv4Addr::LOCALHOST),
+        Some(Ipv4Addr::LOCALHOST)
+    );
+}
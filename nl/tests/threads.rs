@@ -0,0 +1,75 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use download_shell_nl::{
+    netlink::{Cache, Socket},
+    route::Link,
+};
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn sockets_and_caches_are_send() {
+    assert_send::<Socket>();
+    assert_send::<Cache<Link>>();
+}
+
+#[test]
+fn socket_moved_to_another_thread() {
+    let sock = Socket::new().unwrap();
+
+    let count = thread::spawn(move || sock.get_links().unwrap().iter().count())
+        .join()
+        .unwrap();
+
+    assert!(count > 0);
+}
+
+#[test]
+fn cache_moved_to_another_thread() {
+    let links = Socket::new().unwrap().get_links().unwrap();
+
+    let has_lo = thread::spawn(move || links.iter().any(|l| l.name() == "lo"))
+        .join()
+        .unwrap();
+
+    assert!(has_lo);
+}
+
+#[test]
+fn socket_shared_behind_a_mutex() {
+    let sock = Arc::new(Mutex::new(Socket::new().unwrap()));
+
+    let handles = (0..4)
+        .map(|_| {
+            let sock = Arc::clone(&sock);
+            thread::spawn(move || {
+                let sock = sock.lock().unwrap();
+                for _ in 0..16 {
+                    assert!(sock.get_links().unwrap().iter().count() > 0);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
@@ -13,11 +13,16 @@
 // You should have received a copy of the GNU General Public License
 // along with this program; if not, see <https://www.gnu.org/licenses/>.
 
-fn main() {
-    println!(
-        "cargo:rustc-link-search=native={}/lib",
-        std::env::var("DL_SHELL_LIBNL").unwrap()
-    );
-    println!("cargo:rustc-link-lib=static=nl-3");
-    println!("cargo:rustc-link-lib=static=nl-route-3");
+//! Creating devices takes CAP_NET_ADMIN, so only the checks made before
+//! asking the kernel are covered here
+
+use download_shell_nl::tun::{Kind, Tun};
+
+#[test]
+fn names_which_dont_fit_are_rejected() {
+    let err = Tun::create("dlsh-much-too-long", Kind::Tun).unwrap_err();
+    assert_eq!(err.code(), 7);
+
+    let err = Tun::create("dlsh\0tap", Kind::Tap).unwrap_err();
+    assert_eq!(err.code(), 7);
 }
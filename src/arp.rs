@@ -0,0 +1,225 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Sending ARP probes and listening for the answers, to find out which
+//! addresses on the LAN are in use
+
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    io,
+    net::Ipv4Addr,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    time::{Duration, Instant},
+};
+
+use nl::route::MacAddr;
+
+use crate::packet::PacketSocket;
+
+const ETH_P_ARP: u16 = libc::ETH_P_ARP as u16;
+const ETH_P_IP: u16 = libc::ETH_P_IP as u16;
+const ARPHRD_ETHER: u16 = 1;
+const ARPOP_REQUEST: u16 = 1;
+
+/// An ethernet header followed by an ARP packet for IPv4
+const FRAME_LEN: usize = 42;
+
+/// How long to wait after each probe sent while scanning, which keeps the
+/// scan from flooding the LAN and the receive queue from overflowing
+const PACING: Duration = Duration::from_millis(2);
+
+/// The fields of an ARP packet for IPv4 over ethernet
+#[derive(Debug, Clone, Copy)]
+pub struct Packet {
+    pub op: u16,
+    pub sender_mac: MacAddr,
+    pub sender_ip: Ipv4Addr,
+    pub target_ip: Ipv4Addr,
+}
+
+impl Packet {
+    /// Parses an ethernet frame, returning `None` if it isn't ARP for IPv4
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        if frame.len() < FRAME_LEN
+            || u16::from_be_bytes([frame[12], frame[13]]) != ETH_P_ARP
+            || u16::from_be_bytes([frame[14], frame[15]]) != ARPHRD_ETHER
+            || u16::from_be_bytes([frame[16], frame[17]]) != ETH_P_IP
+            || frame[18] != 6
+            || frame[19] != 4
+        {
+            return None;
+        }
+
+        let mac = |at: usize| MacAddr(frame[at..at + 6].try_into().unwrap());
+        let ip = |at: usize| Ipv4Addr::new(frame[at], frame[at + 1], frame[at + 2], frame[at + 3]);
+
+        Some(Self {
+            op: u16::from_be_bytes([frame[20], frame[21]]),
+            sender_mac: mac(22),
+            sender_ip: ip(28),
+            target_ip: ip(38),
+        })
+    }
+
+    /// Whether this is an RFC 5227 probe, which asks about an address
+    /// without claiming one
+    pub fn is_probe(&self) -> bool {
+        self.op == ARPOP_REQUEST && self.sender_ip.is_unspecified()
+    }
+}
+
+/// Looks up the index of an interface
+pub fn ifindex(ifname: &str) -> io::Result<i32> {
+    let name = CString::new(ifname)?;
+    let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if ifindex == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(ifindex as i32)
+}
+
+/// Looks up the hardware address of an interface
+pub fn hwaddr(ifname: &str) -> io::Result<MacAddr> {
+    if ifname.len() >= libc::IFNAMSIZ {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let sock = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(ifname.bytes()) {
+        *dst = src as libc::c_char;
+    }
+
+    let ret = unsafe { libc::ioctl(sock.as_raw_fd(), libc::SIOCGIFHWADDR, &mut ifr) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let data = unsafe { ifr.ifr_ifru.ifru_hwaddr.sa_data };
+    Ok(MacAddr(std::array::from_fn(|i| data[i] as u8)))
+}
+
+/// Sends ARP probes out of an interface and collects the answers
+pub struct Prober {
+    sock: PacketSocket,
+    mac: MacAddr,
+}
+
+impl Prober {
+    /// Opens a raw socket on the interface with the name given
+    pub fn open(ifname: &str) -> io::Result<Self> {
+        Ok(Self {
+            sock: PacketSocket::open(ifindex(ifname)?, ETH_P_ARP)?,
+            mac: hwaddr(ifname)?,
+        })
+    }
+
+    /// Broadcasts an RFC 5227 probe for the address. The sender address is
+    /// left empty, so that no neighbour caches are updated by it
+    pub fn probe(&self, ip: Ipv4Addr) -> io::Result<()> {
+        let mut frame = [0u8; FRAME_LEN];
+        frame[0..6].copy_from_slice(&[0xff; 6]);
+        frame[6..12].copy_from_slice(&self.mac.0);
+        frame[12..14].copy_from_slice(&ETH_P_ARP.to_be_bytes());
+        frame[14..16].copy_from_slice(&ARPHRD_ETHER.to_be_bytes());
+        frame[16..18].copy_from_slice(&ETH_P_IP.to_be_bytes());
+        frame[18] = 6;
+        frame[19] = 4;
+        frame[20..22].copy_from_slice(&ARPOP_REQUEST.to_be_bytes());
+        frame[22..28].copy_from_slice(&self.mac.0);
+        frame[38..42].copy_from_slice(&ip.octets());
+
+        self.sock.send(&frame)?;
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for an ARP packet from another host, returning
+    /// `None` if there was none
+    pub fn recv(&self, timeout: Duration) -> io::Result<Option<Packet>> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0u8; 1518];
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.sock.set_read_timeout(Some(remaining))?;
+
+            let len = match self.sock.recv(&mut buf) {
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e),
+            };
+
+            match Packet::parse(&buf[..len]) {
+                Some(packet) if packet.sender_mac != self.mac => return Ok(Some(packet)),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Probes every address given `attempts` times, waiting `wait` after the
+    /// last probe for stragglers, and returns the addresses which are in
+    /// use along with the hardware address using them
+    pub fn scan(
+        &self,
+        targets: &[Ipv4Addr],
+        attempts: u32,
+        wait: Duration,
+    ) -> io::Result<BTreeMap<Ipv4Addr, MacAddr>> {
+        let mut answered = BTreeMap::new();
+
+        for _ in 0..attempts {
+            for ip in targets {
+                if answered.contains_key(ip) {
+                    continue;
+                }
+                self.probe(*ip)?;
+                while let Some(packet) = self.recv(PACING)? {
+                    note(&mut answered, targets, packet);
+                }
+            }
+        }
+
+        let deadline = Instant::now() + wait;
+        while let Some(packet) = self.recv(deadline.saturating_duration_since(Instant::now()))? {
+            note(&mut answered, targets, packet);
+        }
+
+        Ok(answered)
+    }
+}
+
+/// Records the address a packet shows to be in use, if it was scanned for
+fn note(answered: &mut BTreeMap<Ipv4Addr, MacAddr>, targets: &[Ipv4Addr], packet: Packet) {
+    // Besides answers, a host asking about or announcing an address also
+    // shows that it is taken
+    let ip = if packet.is_probe() {
+        packet.target_ip
+    } else {
+        packet.sender_ip
+    };
+    if targets.contains(&ip) {
+        answered.entry(ip).or_insert(packet.sender_mac);
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Picking an unused address on the LAN of the egress interface to use as
+//! the source IP, for `-s auto`
+
+use std::{
+    net::Ipv4Addr,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use nl::route::MacAddr;
+
+use crate::{arp, backend::RouteEntry};
+
+/// The most addresses scanned for a free one. Larger subnets only have the
+/// block around the host's own address scanned
+const MAX_SCAN: u32 = 1024;
+
+/// How long to keep listening for answers once every address is probed
+pub const SCAN_WAIT: Duration = Duration::from_secs(1);
+
+/// How many probes the chosen address gets before it is used, and how long
+/// to listen for an answer afterwards, per RFC 5227
+pub const VERIFY_PROBES: u32 = 3;
+pub const VERIFY_WAIT: Duration = Duration::from_secs(2);
+
+/// How often the address is probed again while the session runs
+const REPROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An inclusive range of addresses, written as a single address, a CIDR
+/// block, or `first-last`
+#[derive(Debug, Clone, Copy)]
+pub struct AddrRange {
+    pub first: Ipv4Addr,
+    pub last: Ipv4Addr,
+}
+
+impl AddrRange {
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        (self.first..=self.last).contains(&ip)
+    }
+}
+
+impl FromStr for AddrRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((first, last)) = s.split_once('-') {
+            let (first, last) = (first.parse()?, last.parse()?);
+            if first > last {
+                anyhow::bail!("{first} comes after {last}");
+            }
+            return Ok(Self { first, last });
+        }
+
+        if let Some((ip, prefixlen)) = s.split_once('/') {
+            let (ip, prefixlen) = (ip.parse::<Ipv4Addr>()?, prefixlen.parse::<u8>()?);
+            if prefixlen > 32 {
+                anyhow::bail!("prefix length {prefixlen} is longer than 32");
+            }
+            let (first, last) = block(ip, prefixlen);
+            return Ok(Self { first, last });
+        }
+
+        let ip = s.parse()?;
+        Ok(Self {
+            first: ip,
+            last: ip,
+        })
+    }
+}
+
+/// The first and last address of the block of the size given containing
+/// the address
+fn block(ip: Ipv4Addr, prefixlen: u8) -> (Ipv4Addr, Ipv4Addr) {
+    let mask = u32::MAX.checked_shl(32 - prefixlen as u32).unwrap_or(0);
+    let ip = u32::from(ip);
+    (Ipv4Addr::from(ip & mask), Ipv4Addr::from(ip | !mask))
+}
+
+/// The network and broadcast address of the subnet attached to `ifname`,
+/// followed by the first and last address worth scanning in it
+pub fn scan_range(
+    ifname: &str,
+    routes: &[RouteEntry],
+    local_addrs: &[Ipv4Addr],
+) -> anyhow::Result<[Ipv4Addr; 4]> {
+    let subnet = routes
+        .iter()
+        .find(|r| {
+            r.dev.as_deref() == Some(ifname)
+                && r.gateway.is_none()
+                && (1..31).contains(&r.prefixlen)
+        })
+        .ok_or_else(|| {
+            anyhow::anyhow!("{ifname} is not attached to a subnet with room to spare")
+        })?;
+    let (network, broadcast) = block(subnet.dst, subnet.prefixlen);
+
+    let own = local_addrs
+        .iter()
+        .copied()
+        .find(|ip| (network..=broadcast).contains(ip))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "the host has no address in {}/{}",
+                subnet.dst,
+                subnet.prefixlen
+            )
+        })?;
+
+    let (first, last) = if u32::from(broadcast) - u32::from(network) < MAX_SCAN {
+        (network, broadcast)
+    } else {
+        block(own, 32 - MAX_SCAN.trailing_zeros() as u8)
+    };
+
+    Ok([network, broadcast, first, last])
+}
+
+/// Lists the addresses of the subnet attached to `ifname` that might be
+/// free: not the network or broadcast address, not a gateway, not the
+/// host's own, not already used by another session and not excluded
+pub fn candidates(
+    ifname: &str,
+    routes: &[RouteEntry],
+    local_addrs: &[Ipv4Addr],
+    excluded: &[AddrRange],
+) -> anyhow::Result<Vec<Ipv4Addr>> {
+    let [network, broadcast, first, last] = scan_range(ifname, routes, local_addrs)?;
+
+    // Other sessions route the address they use to their tunnel with a /32
+    let taken = |ip: Ipv4Addr| {
+        ip == network
+            || ip == broadcast
+            || local_addrs.contains(&ip)
+            || routes
+                .iter()
+                .any(|r| r.gateway == Some(ip) || (r.prefixlen == 32 && r.dst == ip))
+            || excluded.iter().any(|range| range.contains(ip))
+    };
+
+    Ok((u32::from(first)..=u32::from(last))
+        .map(Ipv4Addr::from)
+        .filter(|ip| !taken(*ip))
+        .collect())
+}
+
+/// Scans the candidates and picks one that nothing answers for, checking it
+/// once more before returning it
+pub fn choose(ifname: &str, candidates: &[Ipv4Addr]) -> anyhow::Result<Ipv4Addr> {
+    let prober =
+        arp::Prober::open(ifname).with_context(|| format!("could not open {ifname} to scan"))?;
+
+    let in_use = prober
+        .scan(candidates, 2, SCAN_WAIT)
+        .context("could not scan for a free address")?;
+
+    // Addresses at the top of the subnet are the least likely to be handed
+    // out by a DHCP server that isn't known about
+    for ip in candidates
+        .iter()
+        .rev()
+        .filter(|ip| !in_use.contains_key(ip))
+    {
+        let answer = prober
+            .scan(&[*ip], VERIFY_PROBES, VERIFY_WAIT)
+            .context("could not verify the free address")?;
+        if answer.is_empty() {
+            return Ok(*ip);
+        }
+    }
+
+    anyhow::bail!(
+        "every one of the {} candidate addresses on {ifname} is in use",
+        candidates.len()
+    )
+}
+
+/// Listens for up to `timeout` for another host using the address,
+/// returning its hardware address if one does
+fn listen(
+    prober: &arp::Prober,
+    ip: Ipv4Addr,
+    timeout: Duration,
+) -> std::io::Result<Option<MacAddr>> {
+    let deadline = Instant::now() + timeout;
+
+    while let Some(packet) = prober.recv(deadline.saturating_duration_since(Instant::now()))? {
+        if packet.sender_ip == ip || (packet.is_probe() && packet.target_ip == ip) {
+            return Ok(Some(packet.sender_mac));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Keeps probing the address chosen on a background thread, for the rest of
+/// the life of the process. If another host turns out to use it after all,
+/// the session is ended by sending SIGTERM to `session`, rather than
+/// fighting the host over the address
+pub fn watch(ifname: &str, ip: Ipv4Addr, session: libc::pid_t) -> std::io::Result<()> {
+    let prober = arp::Prober::open(ifname)?;
+
+    std::thread::spawn(move || {
+        let owner = loop {
+            match prober
+                .probe(ip)
+                .and_then(|_| listen(&prober, ip, REPROBE_INTERVAL))
+            {
+                Ok(Some(owner)) => break owner,
+                Ok(None) => {}
+                Err(e) => {
+                    log::warn!("stopped checking whether {ip} is still free: {e}");
+                    return;
+                }
+            }
+        };
+
+        log::warn!("{owner} is now using {ip}, ending the session");
+        unsafe {
+            libc::kill(session, libc::SIGTERM);
+        }
+    });
+
+    Ok(())
+}
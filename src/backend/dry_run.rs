@@ -0,0 +1,180 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! A backend for `--dry-run`, which looks at the host through another
+//! backend but leaves it untouched. What would have been changed is in the
+//! recording of the session instead
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use nl::route::MacAddr;
+
+use super::{Backend, Qdisc, RouteEntry};
+use crate::{fwmark::Fwmark, rate::Rate};
+
+pub struct DryRunBackend {
+    inner: Box<dyn Backend>,
+}
+
+impl DryRunBackend {
+    /// Reads the state of the host with `inner`
+    pub fn new(inner: Box<dyn Backend>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Backend for DryRunBackend {
+    fn routes(&self) -> anyhow::Result<Vec<RouteEntry>> {
+        self.inner.routes()
+    }
+
+    fn local_addrs(&self) -> anyhow::Result<Vec<Ipv4Addr>> {
+        self.inner.local_addrs()
+    }
+
+    fn routes6(&self) -> anyhow::Result<Vec<RouteEntry<Ipv6Addr>>> {
+        self.inner.routes6()
+    }
+
+    fn local_addrs6(&self) -> anyhow::Result<Vec<Ipv6Addr>> {
+        self.inner.local_addrs6()
+    }
+
+    fn addrs(&self) -> anyhow::Result<Vec<(Ipv4Addr, u8)>> {
+        self.inner.addrs()
+    }
+
+    fn link_names(&self) -> anyhow::Result<Vec<String>> {
+        self.inner.link_names()
+    }
+
+    fn link_master(&self, name: &str) -> anyhow::Result<Option<String>> {
+        self.inner.link_master(name)
+    }
+
+    fn link_indexes(&self, name: &str) -> anyhow::Result<(u32, Option<u32>)> {
+        self.inner.link_indexes(name)
+    }
+
+    fn add_veth(
+        &self,
+        _: &str,
+        _: &str,
+        _: Option<libc::pid_t>,
+        _: Option<u32>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_macvlan(&self, _: &str, _: &str, _: MacAddr) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn neighbour(&self, dev: &str, ip: Ipv4Addr) -> anyhow::Result<Option<MacAddr>> {
+        self.inner.neighbour(dev, ip)
+    }
+
+    fn set_link_up(&self, _: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn link_mtu(&self, name: &str) -> anyhow::Result<u32> {
+        self.inner.link_mtu(name)
+    }
+
+    fn set_link_mtu(&self, _: &str, _: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_link_txqlen(&self, _: &str, _: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_link_qdisc(&self, _: &str, _: Qdisc) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn limit_link_rate(&self, _: &str, _: Rate) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn mirror_link(&self, _: &str, _: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_link_netns(&self, _: &str, _: libc::pid_t) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn delete_link(&self, _: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_addr(
+        &self,
+        _: &str,
+        _: Ipv4Addr,
+        _: u8,
+        _: Option<Ipv4Addr>,
+        _: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_route(&self, _: Ipv4Addr, _: u8, _: &str, _: Option<Ipv4Addr>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_sit(&self, _: &str, _: Ipv4Addr, _: Ipv4Addr, _: u8) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_addr6(&self, _: &str, _: Ipv6Addr, _: u8) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_default_route6(&self, _: &str, _: Option<Ipv6Addr>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_neigh_proxy(&self, _: &str, _: Ipv6Addr) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn delete_neigh_proxy(&self, _: &str, _: Ipv6Addr) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_multipath_default(
+        &self,
+        _: u32,
+        _: &[(String, Ipv4Addr)],
+        _: Option<u32>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn delete_multipath_default(&self, _: u32, _: usize, _: Option<u32>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn add_fwmark_rule(&self, _: Fwmark, _: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn delete_fwmark_rule(&self, _: Fwmark, _: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
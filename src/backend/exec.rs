@@ -0,0 +1,542 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    process::Command,
+    str::FromStr,
+};
+
+use anyhow::Context;
+use nl::route::MacAddr;
+
+use super::{Backend, Qdisc, RULE_PRIORITY, RouteEntry};
+use crate::{fwmark::Fwmark, rate::Rate};
+
+/// Performs all operations by invoking the `ip` and `tc` utilities from
+/// iproute2. The basic setup only needs long-standing subcommands, but some
+/// options need a release as recent as the kernel feature they use: `ip
+/// nexthop` for uplink groups on kernels with nexthop objects (5.3),
+/// `clsact` and `matchall` for `--mirror` (4.8), and `numtxqueues` for
+/// `--queues`
+pub struct ExecBackend;
+
+impl ExecBackend {
+    pub fn new() -> anyhow::Result<Self> {
+        Command::new("ip")
+            .arg("-V")
+            .output()
+            .context("Could not run the ip utility")?;
+
+        Ok(Self)
+    }
+
+    /// Runs `ip` with the arguments provided, returning stdout and turning a
+    /// non-zero exit status into an error carrying stderr
+    fn ip(&self, args: &[&str]) -> anyhow::Result<String> {
+        Self::run("ip", args)
+    }
+
+    /// Runs `tc` the same way as [`ExecBackend::ip`]
+    fn tc(&self, args: &[&str]) -> anyhow::Result<String> {
+        Self::run("tc", args)
+    }
+
+    /// Adds or deletes the rule routing a firewall mark with a table
+    fn rule(&self, action: &str, fwmark: Fwmark, table: u32) -> anyhow::Result<()> {
+        self.ip(&[
+            "rule",
+            action,
+            "fwmark",
+            &fwmark.to_string(),
+            "table",
+            &format!("{table}"),
+            "priority",
+            &format!("{RULE_PRIORITY}"),
+        ])?;
+        Ok(())
+    }
+
+    fn run(program: &str, args: &[&str]) -> anyhow::Result<String> {
+        log::trace!("running {program} {}", args.join(" "));
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .with_context(|| format!("Could not run `{program} {}`", args.join(" ")))?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "`{program} {}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+/// Route types `ip` prints before the destination when they aren't unicast
+const ROUTE_TYPES: &[&str] = &[
+    "unicast",
+    "local",
+    "broadcast",
+    "multicast",
+    "anycast",
+    "unreachable",
+    "prohibit",
+    "blackhole",
+    "throw",
+    "nat",
+];
+
+/// Parses a single line of `ip -4 route show` output, e.g.
+/// `default via 192.168.1.1 dev eth0 proto dhcp metric 100`
+fn parse_route_line(line: &str) -> Option<RouteEntry> {
+    parse_family_route_line(line, Ipv4Addr::UNSPECIFIED, 32)
+}
+
+/// Parses a single line of `ip -6 route show` output, e.g.
+/// `default via fe80::1 dev eth0 proto ra metric 100 pref medium`
+fn parse_route6_line(line: &str) -> Option<RouteEntry<Ipv6Addr>> {
+    parse_family_route_line(line, Ipv6Addr::UNSPECIFIED, 128)
+}
+
+fn parse_family_route_line<A: FromStr>(
+    line: &str,
+    any: A,
+    host_prefixlen: u8,
+) -> Option<RouteEntry<A>> {
+    let mut words = line.split_ascii_whitespace().peekable();
+
+    words.next_if(|w| ROUTE_TYPES.contains(w));
+
+    let (dst, prefixlen) = match words.next()? {
+        "default" => (any, 0),
+        dst => match dst.split_once('/') {
+            Some((addr, len)) => (addr.parse().ok()?, len.parse().ok()?),
+            None => (dst.parse().ok()?, host_prefixlen),
+        },
+    };
+
+    let mut dev = None;
+    let mut gateway = None;
+
+    while let Some(word) = words.next() {
+        match word {
+            "dev" => dev = words.next().map(str::to_owned),
+            "via" => gateway = words.next().and_then(|g| g.parse().ok()),
+            _ => {}
+        }
+    }
+
+    Some(RouteEntry {
+        dst,
+        prefixlen,
+        dev,
+        gateway,
+    })
+}
+
+impl Backend for ExecBackend {
+    fn routes(&self) -> anyhow::Result<Vec<RouteEntry>> {
+        Ok(self
+            .ip(&["-4", "route", "show"])?
+            .lines()
+            .filter_map(parse_route_line)
+            .collect())
+    }
+
+    fn local_addrs(&self) -> anyhow::Result<Vec<Ipv4Addr>> {
+        Ok(self
+            .ip(&["-4", "route", "show", "table", "local"])?
+            .lines()
+            .filter(|l| l.starts_with("local "))
+            .filter_map(parse_route_line)
+            .map(|r| r.dst)
+            .collect())
+    }
+
+    fn routes6(&self) -> anyhow::Result<Vec<RouteEntry<Ipv6Addr>>> {
+        Ok(self
+            .ip(&["-6", "route", "show"])?
+            .lines()
+            .filter_map(parse_route6_line)
+            .collect())
+    }
+
+    fn local_addrs6(&self) -> anyhow::Result<Vec<Ipv6Addr>> {
+        Ok(self
+            .ip(&["-6", "route", "show", "table", "local"])?
+            .lines()
+            .filter(|l| l.starts_with("local "))
+            .filter_map(parse_route6_line)
+            .map(|r| r.dst)
+            .collect())
+    }
+
+    fn addrs(&self) -> anyhow::Result<Vec<(Ipv4Addr, u8)>> {
+        // e.g. `2: eth0    inet 192.168.1.5/24 brd 192.168.1.255 scope global eth0`
+        Ok(self
+            .ip(&["-4", "-o", "addr", "show"])?
+            .lines()
+            .filter_map(|l| {
+                let mut words = l.split_ascii_whitespace();
+                words.find(|w| *w == "inet")?;
+                let (addr, prefixlen) = words.next()?.split_once('/')?;
+                Some((addr.parse().ok()?, prefixlen.parse().ok()?))
+            })
+            .collect())
+    }
+
+    fn link_names(&self) -> anyhow::Result<Vec<String>> {
+        // e.g. `5: dlsh123.0@if4: <BROADCAST,...> mtu 1500 ...`
+        Ok(self
+            .ip(&["-o", "link", "show"])?
+            .lines()
+            .filter_map(|l| l.split(": ").nth(1))
+            .map(|name| name.split('@').next().unwrap_or(name).to_owned())
+            .collect())
+    }
+
+    fn link_master(&self, name: &str) -> anyhow::Result<Option<String>> {
+        // e.g. `3: eth0: <BROADCAST,...> mtu 1500 master br0 state UP ...`
+        let output = self.ip(&["-o", "link", "show", "dev", name])?;
+        let mut words = output.split_ascii_whitespace();
+
+        Ok(words
+            .find(|w| *w == "master")
+            .and_then(|_| words.next())
+            .map(str::to_owned))
+    }
+
+    fn link_indexes(&self, name: &str) -> anyhow::Result<(u32, Option<u32>)> {
+        // e.g. `5: dlsh123.0@if4: <BROADCAST,...> mtu 1500 ...`
+        let output = self.ip(&["-o", "link", "show", "dev", name])?;
+        let mut fields = output.split(": ");
+        let index = fields
+            .next()
+            .and_then(|i| i.trim().parse().ok())
+            .ok_or(anyhow::anyhow!("Could not find link {name}"))?;
+        let peer = fields
+            .next()
+            .and_then(|n| n.split_once("@if"))
+            .and_then(|(_, peer)| peer.parse().ok());
+
+        Ok((index, peer))
+    }
+
+    fn add_veth(
+        &self,
+        name: &str,
+        peer: &str,
+        peer_pid: Option<libc::pid_t>,
+        queues: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let pid = peer_pid.map(|p| format!("{p}"));
+        let queues = queues.map(|q| format!("{q}"));
+        let queue_args = match &queues {
+            Some(q) => vec!["numtxqueues", q, "numrxqueues", q],
+            None => vec![],
+        };
+
+        let mut args = vec!["link", "add", name];
+        args.extend(&queue_args);
+        args.extend(["type", "veth", "peer", "name", peer]);
+        args.extend(&queue_args);
+        if let Some(pid) = &pid {
+            args.extend(["netns", pid]);
+        }
+
+        self.ip(&args)?;
+        Ok(())
+    }
+
+    fn add_macvlan(&self, name: &str, parent: &str, mac: MacAddr) -> anyhow::Result<()> {
+        self.ip(&[
+            "link",
+            "add",
+            "link",
+            parent,
+            "name",
+            name,
+            "address",
+            &mac.to_string(),
+            "type",
+            "macvlan",
+        ])?;
+        Ok(())
+    }
+
+    fn neighbour(&self, dev: &str, ip: Ipv4Addr) -> anyhow::Result<Option<MacAddr>> {
+        // e.g. `192.168.1.20 lladdr 00:11:22:33:44:55 STALE`
+        let output = self.ip(&["-4", "neigh", "show", "to", &format!("{ip}"), "dev", dev])?;
+        let mut words = output.split_ascii_whitespace();
+
+        let Some(lladdr) = words.find(|w| *w == "lladdr").and_then(|_| words.next()) else {
+            return Ok(None);
+        };
+
+        let mac = lladdr
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Could not parse the hardware address {lladdr}"))?;
+
+        Ok(Some(mac))
+    }
+
+    fn set_link_up(&self, name: &str) -> anyhow::Result<()> {
+        self.ip(&["link", "set", name, "up"])?;
+        Ok(())
+    }
+
+    fn link_mtu(&self, name: &str) -> anyhow::Result<u32> {
+        // e.g. `3: eth0: <BROADCAST,...> mtu 1500 qdisc ...`
+        let output = self.ip(&["-o", "link", "show", "dev", name])?;
+        let mut words = output.split_ascii_whitespace();
+
+        words
+            .find(|w| *w == "mtu")
+            .and_then(|_| words.next())
+            .and_then(|mtu| mtu.parse().ok())
+            .ok_or(anyhow::anyhow!("Could not find the MTU of {name}"))
+    }
+
+    fn set_link_mtu(&self, name: &str, mtu: u32) -> anyhow::Result<()> {
+        self.ip(&["link", "set", "dev", name, "mtu", &format!("{mtu}")])?;
+        Ok(())
+    }
+
+    fn set_link_txqlen(&self, name: &str, txqlen: u32) -> anyhow::Result<()> {
+        self.ip(&[
+            "link",
+            "set",
+            "dev",
+            name,
+            "txqueuelen",
+            &format!("{txqlen}"),
+        ])?;
+        Ok(())
+    }
+
+    fn set_link_qdisc(&self, name: &str, qdisc: Qdisc) -> anyhow::Result<()> {
+        self.tc(&["qdisc", "replace", "dev", name, "root", qdisc.name()])?;
+        Ok(())
+    }
+
+    fn limit_link_rate(&self, name: &str, rate: Rate) -> anyhow::Result<()> {
+        let args = rate.tc_args(name);
+        self.tc(&args.iter().map(String::as_str).collect::<Vec<_>>())?;
+        Ok(())
+    }
+
+    fn mirror_link(&self, name: &str, target: &str) -> anyhow::Result<()> {
+        self.tc(&["qdisc", "add", "dev", name, "clsact"])?;
+        for direction in ["ingress", "egress"] {
+            self.tc(&[
+                "filter", "add", "dev", name, direction, "matchall", "action", "mirred", "egress",
+                "mirror", "dev", target,
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn set_link_netns(&self, name: &str, pid: libc::pid_t) -> anyhow::Result<()> {
+        self.ip(&["link", "set", name, "netns", &format!("{pid}")])?;
+        Ok(())
+    }
+
+    fn delete_link(&self, name: &str) -> anyhow::Result<()> {
+        self.ip(&["link", "del", "dev", name])?;
+        Ok(())
+    }
+
+    fn add_addr(
+        &self,
+        dev: &str,
+        local: Ipv4Addr,
+        prefixlen: u8,
+        broadcast: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        let local = match peer {
+            Some(_) => format!("{local}"),
+            None => format!("{local}/{prefixlen}"),
+        };
+        let broadcast = broadcast.map(|b| format!("{b}"));
+        let peer = peer.map(|p| format!("{p}/{prefixlen}"));
+
+        let mut args = vec!["addr", "add", &local];
+        if let Some(peer) = &peer {
+            args.extend(["peer", peer]);
+        }
+        if let Some(broadcast) = &broadcast {
+            args.extend(["broadcast", broadcast]);
+        }
+        args.extend(["dev", dev]);
+
+        self.ip(&args)?;
+        Ok(())
+    }
+
+    fn add_route(
+        &self,
+        dst: Ipv4Addr,
+        prefixlen: u8,
+        dev: &str,
+        gateway: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        let dst = format!("{dst}/{prefixlen}");
+        let gateway = gateway.map(|g| format!("{g}"));
+
+        let mut args = vec!["route", "add", &dst];
+        if let Some(gateway) = &gateway {
+            args.extend(["via", gateway]);
+        }
+        args.extend(["dev", dev]);
+
+        self.ip(&args)?;
+        Ok(())
+    }
+
+    fn add_sit(
+        &self,
+        name: &str,
+        local: Ipv4Addr,
+        remote: Ipv4Addr,
+        ttl: u8,
+    ) -> anyhow::Result<()> {
+        self.ip(&[
+            "link",
+            "add",
+            name,
+            "type",
+            "sit",
+            "local",
+            &format!("{local}"),
+            "remote",
+            &format!("{remote}"),
+            "ttl",
+            &format!("{ttl}"),
+        ])?;
+        Ok(())
+    }
+
+    fn add_addr6(&self, dev: &str, local: Ipv6Addr, prefixlen: u8) -> anyhow::Result<()> {
+        self.ip(&[
+            "-6",
+            "addr",
+            "add",
+            &format!("{local}/{prefixlen}"),
+            "dev",
+            dev,
+            "nodad",
+        ])?;
+        Ok(())
+    }
+
+    fn add_default_route6(&self, dev: &str, gateway: Option<Ipv6Addr>) -> anyhow::Result<()> {
+        match gateway {
+            Some(gateway) => self.ip(&[
+                "-6",
+                "route",
+                "add",
+                "default",
+                "via",
+                &format!("{gateway}"),
+                "dev",
+                dev,
+            ])?,
+            None => self.ip(&["-6", "route", "add", "default", "dev", dev])?,
+        };
+        Ok(())
+    }
+
+    fn add_neigh_proxy(&self, dev: &str, ip: Ipv6Addr) -> anyhow::Result<()> {
+        self.ip(&["-6", "neigh", "add", "proxy", &format!("{ip}"), "dev", dev])?;
+        Ok(())
+    }
+
+    fn delete_neigh_proxy(&self, dev: &str, ip: Ipv6Addr) -> anyhow::Result<()> {
+        self.ip(&["-6", "neigh", "del", "proxy", &format!("{ip}"), "dev", dev])?;
+        Ok(())
+    }
+
+    fn add_multipath_default(
+        &self,
+        table: u32,
+        hops: &[(String, Ipv4Addr)],
+        nexthop_ids: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let table = format!("{table}");
+
+        match nexthop_ids {
+            Some(group) => {
+                let ids = (group + 1..)
+                    .take(hops.len())
+                    .map(|id| format!("{id}"))
+                    .collect::<Vec<_>>();
+                for (id, (dev, gateway)) in ids.iter().zip(hops) {
+                    let gateway = format!("{gateway}");
+                    self.ip(&["nexthop", "add", "id", id, "via", &gateway, "dev", dev])?;
+                }
+
+                let group = format!("{group}");
+                self.ip(&["nexthop", "add", "id", &group, "group", &ids.join("/")])?;
+                self.ip(&["route", "add", "default", "nhid", &group, "table", &table])?;
+            }
+            None => {
+                let gateways = hops
+                    .iter()
+                    .map(|(_, gateway)| format!("{gateway}"))
+                    .collect::<Vec<_>>();
+                let mut args = vec!["route", "add", "default", "table", &table];
+                for (gateway, (dev, _)) in gateways.iter().zip(hops) {
+                    args.extend(["nexthop", "via", gateway, "dev", dev]);
+                }
+                self.ip(&args)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_multipath_default(
+        &self,
+        table: u32,
+        hops: usize,
+        nexthop_ids: Option<u32>,
+    ) -> anyhow::Result<()> {
+        match nexthop_ids {
+            Some(group) => {
+                for id in (group..).take(hops + 1) {
+                    self.ip(&["nexthop", "del", "id", &format!("{id}")])?;
+                }
+            }
+            None => {
+                self.ip(&["route", "del", "default", "table", &format!("{table}")])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_fwmark_rule(&self, fwmark: Fwmark, table: u32) -> anyhow::Result<()> {
+        self.rule("add", fwmark, table)
+    }
+
+    fn delete_fwmark_rule(&self, fwmark: Fwmark, table: u32) -> anyhow::Result<()> {
+        self.rule("del", fwmark, table)
+    }
+}
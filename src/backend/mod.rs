@@ -0,0 +1,295 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! The operations download-shell needs to perform on links, addresses and
+//! routes, abstracted so that they can be carried out either by talking
+//! netlink through libnl or by shelling out to the `ip` utility
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use nl::route::MacAddr;
+
+use crate::{fwmark::Fwmark, rate::Rate};
+
+mod dry_run;
+mod exec;
+mod netlink;
+mod traced;
+
+pub use dry_run::DryRunBackend;
+pub use exec::ExecBackend;
+pub use netlink::NetlinkBackend;
+pub use traced::TracedBackend;
+
+/// Selects which implementation of [`Backend`] is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Use libnl to talk to the kernel directly
+    Netlink,
+    /// Invoke iproute2 for every operation
+    Exec,
+}
+
+impl FromStr for Kind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "netlink" | "nl" => Ok(Kind::Netlink),
+            "exec" | "ip" => Ok(Kind::Exec),
+            _ => anyhow::bail!("unknown backend '{s}', expected one of: netlink, ip"),
+        }
+    }
+}
+
+/// A queueing discipline which can be attached with its default parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Qdisc {
+    PfifoFast,
+    Pfifo,
+    Bfifo,
+    Sfq,
+    Fq,
+    FqCodel,
+    Cake,
+    Noqueue,
+}
+
+impl Qdisc {
+    const ALL: [Qdisc; 8] = [
+        Qdisc::PfifoFast,
+        Qdisc::Pfifo,
+        Qdisc::Bfifo,
+        Qdisc::Sfq,
+        Qdisc::Fq,
+        Qdisc::FqCodel,
+        Qdisc::Cake,
+        Qdisc::Noqueue,
+    ];
+
+    /// The kind the kernel knows the qdisc by
+    pub fn name(self) -> &'static str {
+        match self {
+            Qdisc::PfifoFast => "pfifo_fast",
+            Qdisc::Pfifo => "pfifo",
+            Qdisc::Bfifo => "bfifo",
+            Qdisc::Sfq => "sfq",
+            Qdisc::Fq => "fq",
+            Qdisc::FqCodel => "fq_codel",
+            Qdisc::Cake => "cake",
+            Qdisc::Noqueue => "noqueue",
+        }
+    }
+}
+
+impl FromStr for Qdisc {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Qdisc::ALL
+            .into_iter()
+            .find(|q| q.name() == s)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown qdisc '{s}', expected one of: {}",
+                    Qdisc::ALL.map(Qdisc::name).join(", ")
+                )
+            })
+    }
+}
+
+/// A simplified view of a route, IPv4 unless stated otherwise, independent
+/// of the backend that produced it
+#[derive(Debug, Clone)]
+pub struct RouteEntry<A = Ipv4Addr> {
+    pub dst: A,
+    pub prefixlen: u8,
+    pub dev: Option<String>,
+    pub gateway: Option<A>,
+}
+
+/// The priority of the rules routing the traffic of sessions, ahead of the
+/// rule for the main table at 32766
+pub const RULE_PRIORITY: u32 = 1000;
+
+/// The set of network operations performed while setting up a session.
+/// Links are referred to by name, as that is the only handle the `ip`
+/// utility understands
+pub trait Backend {
+    /// Lists the IPv4 routes in the main routing table
+    fn routes(&self) -> anyhow::Result<Vec<RouteEntry>>;
+
+    /// Lists the IPv4 addresses owned by the host, taken from the local
+    /// routing table
+    fn local_addrs(&self) -> anyhow::Result<Vec<Ipv4Addr>>;
+
+    /// Lists the IPv6 routes in the main routing table
+    fn routes6(&self) -> anyhow::Result<Vec<RouteEntry<Ipv6Addr>>>;
+
+    /// Lists the IPv6 addresses owned by the host, taken from the local
+    /// routing table
+    fn local_addrs6(&self) -> anyhow::Result<Vec<Ipv6Addr>>;
+
+    /// Lists the IPv4 addresses assigned to links along with their prefix
+    /// lengths, including those on links which are down and so have no
+    /// routes
+    fn addrs(&self) -> anyhow::Result<Vec<(Ipv4Addr, u8)>>;
+
+    /// Lists the names of every link in the current namespace
+    fn link_names(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Returns the name of the bridge or bond the link is enslaved to, if any
+    fn link_master(&self, name: &str) -> anyhow::Result<Option<String>>;
+
+    /// Returns the index of the link, and that of the link it sits on top
+    /// of, which for a veth is its peer
+    fn link_indexes(&self, name: &str) -> anyhow::Result<(u32, Option<u32>)>;
+
+    /// Creates a veth pair with the names provided. If a process is given,
+    /// the peer is created directly inside of its network namespace. Both
+    /// ends get the number of transmit and receive queues given, or the
+    /// kernel's default of one
+    fn add_veth(
+        &self,
+        name: &str,
+        peer: &str,
+        peer_pid: Option<libc::pid_t>,
+        queues: Option<u32>,
+    ) -> anyhow::Result<()>;
+
+    /// Creates a macvlan on top of `parent` with the hardware address given
+    fn add_macvlan(&self, name: &str, parent: &str, mac: MacAddr) -> anyhow::Result<()>;
+
+    /// Looks up the hardware address the neighbour table has for an address
+    /// reached through the link, stale entries included
+    fn neighbour(&self, dev: &str, ip: Ipv4Addr) -> anyhow::Result<Option<MacAddr>>;
+
+    /// Sets the link with the specified name to be up
+    fn set_link_up(&self, name: &str) -> anyhow::Result<()>;
+
+    /// Returns the MTU of the link
+    fn link_mtu(&self, name: &str) -> anyhow::Result<u32>;
+
+    /// Sets the MTU of the link
+    fn set_link_mtu(&self, name: &str, mtu: u32) -> anyhow::Result<()>;
+
+    /// Sets the length of the link's transmit queue
+    fn set_link_txqlen(&self, name: &str, txqlen: u32) -> anyhow::Result<()>;
+
+    /// Replaces the root qdisc of the link with one of the kind given, using
+    /// its default parameters
+    fn set_link_qdisc(&self, name: &str, qdisc: Qdisc) -> anyhow::Result<()>;
+
+    /// Replaces the root qdisc of the link with a token bucket filter, which
+    /// holds the traffic leaving through it to the rate given
+    fn limit_link_rate(&self, name: &str, rate: Rate) -> anyhow::Result<()>;
+
+    /// Sends a copy of every packet entering or leaving the link out of
+    /// `target`, for an IDS or capture box to look at
+    fn mirror_link(&self, name: &str, target: &str) -> anyhow::Result<()>;
+
+    /// Moves a link into the network namespace of the process specified
+    fn set_link_netns(&self, name: &str, pid: libc::pid_t) -> anyhow::Result<()>;
+
+    /// Deletes the link, which for a veth takes its peer with it
+    fn delete_link(&self, name: &str) -> anyhow::Result<()>;
+
+    /// Assigns an address to a link, optionally with a broadcast address or
+    /// the address of the other end of a point to point link
+    fn add_addr(
+        &self,
+        dev: &str,
+        local: Ipv4Addr,
+        prefixlen: u8,
+        broadcast: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()>;
+
+    /// Adds a route through the link specified, optionally via a gateway
+    fn add_route(
+        &self,
+        dst: Ipv4Addr,
+        prefixlen: u8,
+        dev: &str,
+        gateway: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()>;
+
+    /// Creates a sit link carrying IPv6 in IPv4 between the addresses given
+    fn add_sit(&self, name: &str, local: Ipv4Addr, remote: Ipv4Addr, ttl: u8)
+    -> anyhow::Result<()>;
+
+    /// Assigns an IPv6 address to a link, skipping duplicate address
+    /// detection so that it can be used right away
+    fn add_addr6(&self, dev: &str, local: Ipv6Addr, prefixlen: u8) -> anyhow::Result<()>;
+
+    /// Adds a default IPv6 route through the link, via a gateway unless it
+    /// is a point to point link
+    fn add_default_route6(&self, dev: &str, gateway: Option<Ipv6Addr>) -> anyhow::Result<()>;
+
+    /// Makes the host answer neighbour solicitations for an address on the
+    /// link, so that traffic for it reaches the host to be forwarded
+    fn add_neigh_proxy(&self, dev: &str, ip: Ipv6Addr) -> anyhow::Result<()>;
+
+    /// Removes an entry added by [`Backend::add_neigh_proxy`]
+    fn delete_neigh_proxy(&self, dev: &str, ip: Ipv6Addr) -> anyhow::Result<()>;
+
+    /// Adds a default route to a routing table which spreads traffic evenly
+    /// over the gateways given, each reached through its link. With
+    /// `nexthop_ids` the route uses nexthop objects numbered from that id
+    /// onwards, the group first; otherwise it is a multipath route
+    fn add_multipath_default(
+        &self,
+        table: u32,
+        hops: &[(String, Ipv4Addr)],
+        nexthop_ids: Option<u32>,
+    ) -> anyhow::Result<()>;
+
+    /// Removes what [`Backend::add_multipath_default`] added
+    fn delete_multipath_default(
+        &self,
+        table: u32,
+        hops: usize,
+        nexthop_ids: Option<u32>,
+    ) -> anyhow::Result<()>;
+
+    /// Routes the traffic carrying the firewall mark with a routing table
+    fn add_fwmark_rule(&self, fwmark: Fwmark, table: u32) -> anyhow::Result<()>;
+
+    /// Removes a rule added by [`Backend::add_fwmark_rule`]
+    fn delete_fwmark_rule(&self, fwmark: Fwmark, table: u32) -> anyhow::Result<()>;
+}
+
+/// Opens the requested backend, logging the calls made to it. If no backend
+/// was explicitly requested, netlink is preferred and the `ip` utility is
+/// used as a fallback when libnl cannot be used. `debug_netlink` traces the
+/// netlink backend's messages
+pub fn open(kind: Option<Kind>, debug_netlink: bool) -> anyhow::Result<Box<dyn Backend>> {
+    let backend: Box<dyn Backend> = match kind {
+        Some(Kind::Netlink) => Box::new(NetlinkBackend::new(debug_netlink)?),
+        Some(Kind::Exec) => Box::new(ExecBackend::new()?),
+        None => match NetlinkBackend::new(debug_netlink) {
+            Ok(backend) => Box::new(backend),
+            Err(e) => {
+                log::warn!("netlink is unavailable ({e:#}), falling back to the ip utility");
+                Box::new(ExecBackend::new()?)
+            }
+        },
+    };
+
+    Ok(Box::new(TracedBackend::new(backend)))
+}
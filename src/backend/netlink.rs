@@ -0,0 +1,569 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use anyhow::Context;
+use nl::route::MacAddr;
+
+use super::{Backend, Qdisc, RULE_PRIORITY, RouteEntry};
+use crate::{fwmark::Fwmark, rate::Rate};
+
+/// How long to wait for the kernel to answer a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Performs all operations over a libnl netlink socket
+pub struct NetlinkBackend {
+    sock: nl::netlink::Socket,
+}
+
+impl NetlinkBackend {
+    /// Opens a netlink socket. With `debug`, every message exchanged with
+    /// the kernel is dumped to stderr
+    pub fn new(debug: bool) -> anyhow::Result<Self> {
+        #[cfg(feature = "runtime-libnl")]
+        nl::runtime::load()?;
+
+        let mut sock = nl::netlink::Socket::new().context("Could not allocate Netlink socket")?;
+
+        // Route and neighbor dumps on busy routers easily overrun the default
+        // buffers, and a dropped message means an incomplete cache
+        sock.set_buffer_size(1 << 20, 0)
+            .context("Could not resize the Netlink socket buffers")?;
+        sock.set_msg_peek(true);
+        sock.set_debug(debug)
+            .context("Could not enable Netlink message tracing")?;
+        // The kernel answers in milliseconds; rather report an error than
+        // leave a root process hanging on it forever
+        sock.set_timeout(Some(REQUEST_TIMEOUT))
+            .context("Could not set a timeout on the Netlink socket")?;
+
+        Ok(Self { sock })
+    }
+
+    fn find_link(&self, name: &str) -> anyhow::Result<nl::route::Link> {
+        let links = self
+            .sock
+            .get_links()
+            .context("Could not acquire link list")?;
+
+        let ifindex = links
+            .iter()
+            .find(|l| l.name() == name)
+            .ok_or(anyhow::anyhow!("Could not find link {name}"))?
+            .ifindex();
+
+        nl::netlink::get_link_by_index(&links, ifindex)
+            .ok_or(anyhow::anyhow!("Could not find link {name}"))
+    }
+}
+
+impl Backend for NetlinkBackend {
+    fn routes(&self) -> anyhow::Result<Vec<RouteEntry>> {
+        let routes = self
+            .sock
+            .get_routes(nl::route::Route::RT_TABLE_MAIN)
+            .context("Could not load routes")?;
+        let links = self.sock.get_links().context("Could not load links")?;
+
+        Ok(routes
+            .iter()
+            .filter_map(|route| {
+                let dst = route.dst()?;
+                let hop = route.hop_iter().next();
+
+                Some(RouteEntry {
+                    dst: (&dst).try_into().ok()?,
+                    prefixlen: dst.cidrlen() as u8,
+                    dev: hop
+                        .as_ref()
+                        .and_then(|h| nl::netlink::get_link_by_index(&links, h.ifindex()))
+                        .map(|l| l.name()),
+                    gateway: hop
+                        .and_then(|h| h.gateway())
+                        .and_then(|g| (&g).try_into().ok()),
+                })
+            })
+            .collect())
+    }
+
+    fn local_addrs(&self) -> anyhow::Result<Vec<Ipv4Addr>> {
+        let routes = self
+            .sock
+            .get_routes(nl::route::Route::RT_TABLE_LOCAL)
+            .context("Could not load the local routing table")?;
+
+        Ok(routes
+            .iter()
+            .filter(|r| r.rtype() == nl::route::Route::RTN_LOCAL)
+            .filter_map(|r| r.dst())
+            .filter_map(|dst| (&dst).try_into().ok())
+            .collect())
+    }
+
+    fn routes6(&self) -> anyhow::Result<Vec<RouteEntry<Ipv6Addr>>> {
+        let routes = self
+            .sock
+            .get_routes6(nl::route::Route::RT_TABLE_MAIN)
+            .context("Could not load IPv6 routes")?;
+        let links = self.sock.get_links().context("Could not load links")?;
+
+        Ok(routes
+            .iter()
+            .filter_map(|route| {
+                let dst = route.dst()?;
+                let hop = route.hop_iter().next();
+
+                Some(RouteEntry {
+                    dst: (&dst).try_into().ok()?,
+                    prefixlen: dst.cidrlen() as u8,
+                    dev: hop
+                        .as_ref()
+                        .and_then(|h| nl::netlink::get_link_by_index(&links, h.ifindex()))
+                        .map(|l| l.name()),
+                    gateway: hop
+                        .and_then(|h| h.gateway())
+                        .and_then(|g| (&g).try_into().ok()),
+                })
+            })
+            .collect())
+    }
+
+    fn local_addrs6(&self) -> anyhow::Result<Vec<Ipv6Addr>> {
+        let routes = self
+            .sock
+            .get_routes6(nl::route::Route::RT_TABLE_LOCAL)
+            .context("Could not load the local IPv6 routing table")?;
+
+        Ok(routes
+            .iter()
+            .filter(|r| r.rtype() == nl::route::Route::RTN_LOCAL)
+            .filter_map(|r| r.dst())
+            .filter_map(|dst| (&dst).try_into().ok())
+            .collect())
+    }
+
+    fn addrs(&self) -> anyhow::Result<Vec<(Ipv4Addr, u8)>> {
+        let addrs = self.sock.get_addrs().context("Could not load addresses")?;
+
+        Ok(addrs
+            .iter()
+            .filter(|a| a.family() == libc::AF_INET)
+            .filter_map(|a| Some(((&a.local()?).try_into().ok()?, a.prefixlen() as u8)))
+            .collect())
+    }
+
+    fn link_names(&self) -> anyhow::Result<Vec<String>> {
+        let links = self.sock.get_links().context("Could not load links")?;
+
+        Ok(links.iter().map(|l| l.name()).collect())
+    }
+
+    fn link_master(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let links = self.sock.get_links().context("Could not load links")?;
+
+        let Some(master) = links
+            .iter()
+            .find(|l| l.name() == name)
+            .and_then(|l| l.master())
+        else {
+            return Ok(None);
+        };
+
+        Ok(nl::netlink::get_link_by_index(&links, master).map(|l| l.name()))
+    }
+
+    fn link_indexes(&self, name: &str) -> anyhow::Result<(u32, Option<u32>)> {
+        let link = self.find_link(name)?;
+
+        Ok((
+            link.ifindex() as u32,
+            link.link_index().map(|index| index as u32),
+        ))
+    }
+
+    fn add_veth(
+        &self,
+        name: &str,
+        peer: &str,
+        peer_pid: Option<libc::pid_t>,
+        queues: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let link = nl::route::Link::new_veth();
+        let peer_link = link.get_peer().ok_or(anyhow::anyhow!(
+            "Could not get peer link for download tunnel"
+        ))?;
+
+        link.set_name(name);
+        peer_link.set_name(peer);
+        if let Some(pid) = peer_pid {
+            peer_link.set_ns_pid(pid);
+        }
+        if let Some(queues) = queues {
+            link.set_num_queues(queues, queues);
+            peer_link.set_num_queues(queues, queues);
+        }
+
+        link.add(
+            &self.sock,
+            0x200 | 0x400, /* NLM_F_CREATE | NLM_F_EXCL */
+        )?;
+
+        Ok(())
+    }
+
+    fn add_macvlan(&self, name: &str, parent: &str, mac: MacAddr) -> anyhow::Result<()> {
+        let parent = self.find_link(parent)?;
+
+        let link = nl::route::Link::new_macvlan();
+        link.set_name(name);
+        link.set_link(parent.ifindex());
+        link.set_addr(&mac.into());
+
+        link.add(
+            &self.sock,
+            0x200 | 0x400, /* NLM_F_CREATE | NLM_F_EXCL */
+        )?;
+
+        Ok(())
+    }
+
+    fn neighbour(&self, dev: &str, ip: Ipv4Addr) -> anyhow::Result<Option<MacAddr>> {
+        let neighs = self
+            .sock
+            .get_neigh()
+            .context("Could not load the neighbour table")?;
+
+        Ok(self
+            .find_link(dev)?
+            .get_neigh(&neighs, &nl::route::Addr::from(ip)))
+    }
+
+    fn set_link_up(&self, name: &str) -> anyhow::Result<()> {
+        let up = nl::route::Link::new();
+        up.set_flags(nl::route::Link::IFF_UP);
+
+        self.find_link(name)?.change(&self.sock, &up)?;
+
+        Ok(())
+    }
+
+    fn link_mtu(&self, name: &str) -> anyhow::Result<u32> {
+        Ok(self.find_link(name)?.mtu())
+    }
+
+    fn set_link_mtu(&self, name: &str, mtu: u32) -> anyhow::Result<()> {
+        let changes = nl::route::Link::new();
+        changes.set_mtu(mtu);
+
+        self.find_link(name)?.change(&self.sock, &changes)?;
+
+        Ok(())
+    }
+
+    fn set_link_txqlen(&self, name: &str, txqlen: u32) -> anyhow::Result<()> {
+        let changes = nl::route::Link::new();
+        changes.set_txqlen(txqlen);
+
+        self.find_link(name)?.change(&self.sock, &changes)?;
+
+        Ok(())
+    }
+
+    fn set_link_qdisc(&self, name: &str, qdisc: Qdisc) -> anyhow::Result<()> {
+        let link = self.find_link(name)?;
+        let kind = qdisc.name();
+
+        nl::route::Qdisc::new_root(link.ifindex(), kind)
+            .context("Could not allocate the qdisc")?
+            .replace(&self.sock)
+            .with_context(|| format!("Could not attach {kind} to {name}"))?;
+
+        Ok(())
+    }
+
+    fn limit_link_rate(&self, name: &str, rate: Rate) -> anyhow::Result<()> {
+        let link = self.find_link(name)?;
+
+        nl::route::Qdisc::new_tbf(link.ifindex(), rate.bytes(), rate.bucket(), Rate::LATENCY)
+            .context("Could not allocate the token bucket filter")?
+            .replace(&self.sock)
+            .with_context(|| format!("Could not limit {name} to {rate}"))?;
+
+        Ok(())
+    }
+
+    fn mirror_link(&self, name: &str, target: &str) -> anyhow::Result<()> {
+        let link = self.find_link(name)?;
+        let target = self.find_link(target)?;
+
+        nl::route::Qdisc::new_clsact(link.ifindex())
+            .context("Could not allocate the qdisc")?
+            .replace(&self.sock)
+            .with_context(|| format!("Could not attach clsact to {name}"))?;
+
+        for direction in [nl::route::Direction::Ingress, nl::route::Direction::Egress] {
+            nl::route::Filter::new_mirror(link.ifindex(), direction, target.ifindex())
+                .context("Could not allocate the mirroring filter")?
+                .add(&self.sock)
+                .with_context(|| format!("Could not mirror the traffic of {name}"))?;
+        }
+
+        Ok(())
+    }
+
+    fn set_link_netns(&self, name: &str, pid: libc::pid_t) -> anyhow::Result<()> {
+        let changes = nl::route::Link::new();
+        changes.set_ns_pid(pid);
+
+        self.find_link(name)?.change(&self.sock, &changes)?;
+
+        Ok(())
+    }
+
+    fn delete_link(&self, name: &str) -> anyhow::Result<()> {
+        self.find_link(name)?
+            .delete(&self.sock)
+            .with_context(|| format!("Could not delete {name}"))?;
+
+        Ok(())
+    }
+
+    fn add_addr(
+        &self,
+        dev: &str,
+        local: Ipv4Addr,
+        prefixlen: u8,
+        broadcast: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        let link = self.find_link(dev)?;
+
+        let rt_addr =
+            nl::route::RtAddr::new().ok_or(anyhow::anyhow!("Could not allocate new IP address"))?;
+
+        rt_addr
+            .set_local(nl::route::Addr::from(local))
+            .context("Could not set the local address")?;
+        rt_addr.set_ifindex(link.ifindex());
+        if let Some(broadcast) = broadcast {
+            rt_addr
+                .set_broadcast(nl::route::Addr::from(broadcast))
+                .context("Could not set the broadcast address")?;
+        }
+        if let Some(peer) = peer {
+            rt_addr
+                .set_peer(nl::route::Addr::from(peer))
+                .context("Could not set the peer address")?;
+        }
+        rt_addr.set_prefixlen(prefixlen as i32);
+
+        rt_addr.add(&self.sock, 0x200 /* NLM_F_CREATE */)?;
+
+        Ok(())
+    }
+
+    fn add_route(
+        &self,
+        dst: Ipv4Addr,
+        prefixlen: u8,
+        dev: &str,
+        gateway: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        let link = self.find_link(dev)?;
+
+        let hop = nl::route::Nexthop::new()
+            .ok_or(anyhow::anyhow!("Could not allocate a new nexthop object"))?;
+
+        hop.set_ifindex(link.ifindex());
+        if let Some(gateway) = gateway {
+            hop.set_gateway(nl::route::Addr::from(gateway));
+        }
+
+        let route = nl::route::Route::new()
+            .ok_or(anyhow::anyhow!("Could not allocate a new route object"))?;
+
+        route.add_nexthop(&hop);
+        route.set_dst(nl::route::Addr::from(dst).with_cidrlen(prefixlen as i32));
+
+        route.add(&self.sock, 0x400 /* NLM_F_EXCL */)?;
+
+        Ok(())
+    }
+
+    fn add_sit(
+        &self,
+        name: &str,
+        local: Ipv4Addr,
+        remote: Ipv4Addr,
+        ttl: u8,
+    ) -> anyhow::Result<()> {
+        let link = nl::route::Link::new_sit();
+        link.set_name(name);
+        link.set_sit_endpoints(local, remote, ttl)
+            .context("Could not set the endpoints of the tunnel")?;
+
+        link.add(
+            &self.sock,
+            0x200 | 0x400, /* NLM_F_CREATE | NLM_F_EXCL */
+        )?;
+
+        Ok(())
+    }
+
+    fn add_addr6(&self, dev: &str, local: Ipv6Addr, prefixlen: u8) -> anyhow::Result<()> {
+        let link = self.find_link(dev)?;
+
+        let rt_addr =
+            nl::route::RtAddr::new().ok_or(anyhow::anyhow!("Could not allocate new IP address"))?;
+
+        rt_addr
+            .set_local(nl::route::Addr::from(local))
+            .context("Could not set the local address")?;
+        rt_addr.set_ifindex(link.ifindex());
+        rt_addr.set_prefixlen(prefixlen as i32);
+        rt_addr.set_nodad();
+
+        rt_addr.add(&self.sock, 0x200 /* NLM_F_CREATE */)?;
+
+        Ok(())
+    }
+
+    fn add_default_route6(&self, dev: &str, gateway: Option<Ipv6Addr>) -> anyhow::Result<()> {
+        let link = self.find_link(dev)?;
+
+        let hop = nl::route::Nexthop::new()
+            .ok_or(anyhow::anyhow!("Could not allocate a new nexthop object"))?;
+        hop.set_ifindex(link.ifindex());
+        if let Some(gateway) = gateway {
+            hop.set_gateway(nl::route::Addr::from(gateway));
+        }
+
+        let route = nl::route::Route::new()
+            .ok_or(anyhow::anyhow!("Could not allocate a new route object"))?;
+
+        route.add_nexthop(&hop);
+        route.set_dst(nl::route::Addr::from(Ipv6Addr::UNSPECIFIED).with_cidrlen(0));
+
+        route.add(&self.sock, 0x400 /* NLM_F_EXCL */)?;
+
+        Ok(())
+    }
+
+    fn add_neigh_proxy(&self, dev: &str, ip: Ipv6Addr) -> anyhow::Result<()> {
+        let link = self.find_link(dev)?;
+
+        nl::route::NeighProxy::new(link.ifindex(), &nl::route::Addr::from(ip))?.add(&self.sock)?;
+
+        Ok(())
+    }
+
+    fn delete_neigh_proxy(&self, dev: &str, ip: Ipv6Addr) -> anyhow::Result<()> {
+        let link = self.find_link(dev)?;
+
+        nl::route::NeighProxy::new(link.ifindex(), &nl::route::Addr::from(ip))?
+            .delete(&self.sock)?;
+
+        Ok(())
+    }
+
+    fn add_multipath_default(
+        &self,
+        table: u32,
+        hops: &[(String, Ipv4Addr)],
+        nexthop_ids: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let Some(group) = nexthop_ids else {
+            let route = nl::route::Route::new()
+                .ok_or(anyhow::anyhow!("Could not allocate a new route object"))?;
+
+            for (dev, gateway) in hops {
+                let hop = nl::route::Nexthop::new()
+                    .ok_or(anyhow::anyhow!("Could not allocate a new nexthop object"))?;
+                hop.set_ifindex(self.find_link(dev)?.ifindex());
+                hop.set_gateway(nl::route::Addr::from(*gateway));
+                route.add_nexthop(&hop);
+            }
+            route.set_table(table);
+            route.set_dst(nl::route::Addr::from(Ipv4Addr::UNSPECIFIED).with_cidrlen(0));
+
+            route.add(&self.sock, 0x400 /* NLM_F_EXCL */)?;
+            return Ok(());
+        };
+
+        let mut members = Vec::new();
+        for (id, (dev, gateway)) in (group + 1..).zip(hops) {
+            self.sock
+                .add_nexthop(&nl::route::NexthopObject::Hop {
+                    id,
+                    ifindex: self.find_link(dev)?.ifindex(),
+                    gateway: Some(*gateway),
+                    onlink: false,
+                })
+                .with_context(|| format!("Could not add the nexthop via {gateway}"))?;
+            members.push((id, 1));
+        }
+
+        self.sock
+            .add_nexthop(&nl::route::NexthopObject::Group { id: group, members })
+            .context("Could not add the nexthop group")?;
+        self.sock
+            .add_route_via_nexthop(Ipv4Addr::UNSPECIFIED, 0, table, group)
+            .context("Could not add the route through the nexthop group")?;
+
+        Ok(())
+    }
+
+    fn delete_multipath_default(
+        &self,
+        table: u32,
+        hops: usize,
+        nexthop_ids: Option<u32>,
+    ) -> anyhow::Result<()> {
+        match nexthop_ids {
+            // The route goes away along with the group
+            Some(group) => {
+                for id in (group..).take(hops + 1) {
+                    self.sock.delete_nexthop(id)?;
+                }
+            }
+            None => self.sock.delete_route(Ipv4Addr::UNSPECIFIED, 0, table)?,
+        }
+
+        Ok(())
+    }
+
+    fn add_fwmark_rule(&self, fwmark: Fwmark, table: u32) -> anyhow::Result<()> {
+        self.sock.add_fwmark_rule(&fwmark_rule(fwmark, table))?;
+        Ok(())
+    }
+
+    fn delete_fwmark_rule(&self, fwmark: Fwmark, table: u32) -> anyhow::Result<()> {
+        self.sock.delete_fwmark_rule(&fwmark_rule(fwmark, table))?;
+        Ok(())
+    }
+}
+
+fn fwmark_rule(fwmark: Fwmark, table: u32) -> nl::route::FwmarkRule {
+    nl::route::FwmarkRule {
+        fwmark: fwmark.value,
+        mask: fwmark.mask,
+        table,
+        priority: RULE_PRIORITY,
+    }
+}
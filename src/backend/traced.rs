@@ -0,0 +1,255 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! A backend which logs each change before another backend makes it, and
+//! with -vv each lookup as well, whether it goes over netlink or through
+//! the `ip` utility
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use nl::route::MacAddr;
+
+use super::{Backend, Qdisc, RouteEntry};
+use crate::{fwmark::Fwmark, rate::Rate};
+
+pub struct TracedBackend {
+    inner: Box<dyn Backend>,
+}
+
+impl TracedBackend {
+    /// Logs the calls made to `inner`
+    pub fn new(inner: Box<dyn Backend>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Backend for TracedBackend {
+    fn routes(&self) -> anyhow::Result<Vec<RouteEntry>> {
+        log::trace!("listing routes");
+        self.inner.routes()
+    }
+
+    fn local_addrs(&self) -> anyhow::Result<Vec<Ipv4Addr>> {
+        log::trace!("listing local addresses");
+        self.inner.local_addrs()
+    }
+
+    fn routes6(&self) -> anyhow::Result<Vec<RouteEntry<Ipv6Addr>>> {
+        log::trace!("listing IPv6 routes");
+        self.inner.routes6()
+    }
+
+    fn local_addrs6(&self) -> anyhow::Result<Vec<Ipv6Addr>> {
+        log::trace!("listing local IPv6 addresses");
+        self.inner.local_addrs6()
+    }
+
+    fn addrs(&self) -> anyhow::Result<Vec<(Ipv4Addr, u8)>> {
+        log::trace!("listing addresses");
+        self.inner.addrs()
+    }
+
+    fn link_names(&self) -> anyhow::Result<Vec<String>> {
+        log::trace!("listing links");
+        self.inner.link_names()
+    }
+
+    fn link_master(&self, name: &str) -> anyhow::Result<Option<String>> {
+        log::trace!("looking up the master of {name}");
+        self.inner.link_master(name)
+    }
+
+    fn link_indexes(&self, name: &str) -> anyhow::Result<(u32, Option<u32>)> {
+        log::trace!("looking up the index of {name}");
+        self.inner.link_indexes(name)
+    }
+
+    fn add_veth(
+        &self,
+        name: &str,
+        peer: &str,
+        peer_pid: Option<libc::pid_t>,
+        queues: Option<u32>,
+    ) -> anyhow::Result<()> {
+        log::debug!(
+            "adding veth pair {name} <-> {peer}{}{}",
+            peer_pid
+                .map(|pid| format!(", peer in the namespace of {pid}"))
+                .unwrap_or_default(),
+            queues
+                .map(|queues| format!(", {queues} queues"))
+                .unwrap_or_default()
+        );
+        self.inner.add_veth(name, peer, peer_pid, queues)
+    }
+
+    fn add_macvlan(&self, name: &str, parent: &str, mac: MacAddr) -> anyhow::Result<()> {
+        log::debug!("adding macvlan {name} on {parent} with the address {mac}");
+        self.inner.add_macvlan(name, parent, mac)
+    }
+
+    fn neighbour(&self, dev: &str, ip: Ipv4Addr) -> anyhow::Result<Option<MacAddr>> {
+        log::trace!("looking up the neighbour {ip} on {dev}");
+        self.inner.neighbour(dev, ip)
+    }
+
+    fn set_link_up(&self, name: &str) -> anyhow::Result<()> {
+        log::debug!("setting {name} up");
+        self.inner.set_link_up(name)
+    }
+
+    fn link_mtu(&self, name: &str) -> anyhow::Result<u32> {
+        log::trace!("looking up the mtu of {name}");
+        self.inner.link_mtu(name)
+    }
+
+    fn set_link_mtu(&self, name: &str, mtu: u32) -> anyhow::Result<()> {
+        log::debug!("setting the mtu of {name} to {mtu}");
+        self.inner.set_link_mtu(name, mtu)
+    }
+
+    fn set_link_txqlen(&self, name: &str, txqlen: u32) -> anyhow::Result<()> {
+        log::debug!("setting the transmit queue length of {name} to {txqlen}");
+        self.inner.set_link_txqlen(name, txqlen)
+    }
+
+    fn set_link_qdisc(&self, name: &str, qdisc: Qdisc) -> anyhow::Result<()> {
+        log::debug!("setting the root qdisc of {name} to {}", qdisc.name());
+        self.inner.set_link_qdisc(name, qdisc)
+    }
+
+    fn limit_link_rate(&self, name: &str, rate: Rate) -> anyhow::Result<()> {
+        log::debug!("limiting the traffic leaving {name} to {rate}");
+        self.inner.limit_link_rate(name, rate)
+    }
+
+    fn mirror_link(&self, name: &str, target: &str) -> anyhow::Result<()> {
+        log::debug!("mirroring the traffic of {name} to {target}");
+        self.inner.mirror_link(name, target)
+    }
+
+    fn set_link_netns(&self, name: &str, pid: libc::pid_t) -> anyhow::Result<()> {
+        log::debug!("moving {name} into the namespace of {pid}");
+        self.inner.set_link_netns(name, pid)
+    }
+
+    fn delete_link(&self, name: &str) -> anyhow::Result<()> {
+        log::debug!("deleting {name}");
+        self.inner.delete_link(name)
+    }
+
+    fn add_addr(
+        &self,
+        dev: &str,
+        local: Ipv4Addr,
+        prefixlen: u8,
+        broadcast: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        log::debug!(
+            "adding {local}/{prefixlen} to {dev}{}{}",
+            broadcast
+                .map(|b| format!(", broadcast {b}"))
+                .unwrap_or_default(),
+            peer.map(|p| format!(", peer {p}")).unwrap_or_default()
+        );
+        self.inner.add_addr(dev, local, prefixlen, broadcast, peer)
+    }
+
+    fn add_route(
+        &self,
+        dst: Ipv4Addr,
+        prefixlen: u8,
+        dev: &str,
+        gateway: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        log::debug!(
+            "adding a route to {dst}/{prefixlen} over {dev}{}",
+            gateway.map(|g| format!(" via {g}")).unwrap_or_default()
+        );
+        self.inner.add_route(dst, prefixlen, dev, gateway)
+    }
+
+    fn add_sit(
+        &self,
+        name: &str,
+        local: Ipv4Addr,
+        remote: Ipv4Addr,
+        ttl: u8,
+    ) -> anyhow::Result<()> {
+        log::debug!("adding sit tunnel {name} from {local} to {remote}, ttl {ttl}");
+        self.inner.add_sit(name, local, remote, ttl)
+    }
+
+    fn add_addr6(&self, dev: &str, local: Ipv6Addr, prefixlen: u8) -> anyhow::Result<()> {
+        log::debug!("adding {local}/{prefixlen} to {dev}");
+        self.inner.add_addr6(dev, local, prefixlen)
+    }
+
+    fn add_default_route6(&self, dev: &str, gateway: Option<Ipv6Addr>) -> anyhow::Result<()> {
+        log::debug!(
+            "adding an IPv6 default route over {dev}{}",
+            gateway.map(|g| format!(" via {g}")).unwrap_or_default()
+        );
+        self.inner.add_default_route6(dev, gateway)
+    }
+
+    fn add_neigh_proxy(&self, dev: &str, ip: Ipv6Addr) -> anyhow::Result<()> {
+        log::debug!("proxying neighbour discovery for {ip} on {dev}");
+        self.inner.add_neigh_proxy(dev, ip)
+    }
+
+    fn delete_neigh_proxy(&self, dev: &str, ip: Ipv6Addr) -> anyhow::Result<()> {
+        log::debug!("no longer proxying neighbour discovery for {ip} on {dev}");
+        self.inner.delete_neigh_proxy(dev, ip)
+    }
+
+    fn add_multipath_default(
+        &self,
+        table: u32,
+        hops: &[(String, Ipv4Addr)],
+        nexthop_ids: Option<u32>,
+    ) -> anyhow::Result<()> {
+        log::debug!(
+            "adding a default route to table {table} over {}",
+            hops.iter()
+                .map(|(dev, gateway)| format!("{dev} via {gateway}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        self.inner.add_multipath_default(table, hops, nexthop_ids)
+    }
+
+    fn delete_multipath_default(
+        &self,
+        table: u32,
+        hops: usize,
+        nexthop_ids: Option<u32>,
+    ) -> anyhow::Result<()> {
+        log::debug!("deleting the default route of table {table}");
+        self.inner
+            .delete_multipath_default(table, hops, nexthop_ids)
+    }
+
+    fn add_fwmark_rule(&self, fwmark: Fwmark, table: u32) -> anyhow::Result<()> {
+        log::debug!("routing fwmark {fwmark} with table {table}");
+        self.inner.add_fwmark_rule(fwmark, table)
+    }
+
+    fn delete_fwmark_rule(&self, fwmark: Fwmark, table: u32) -> anyhow::Result<()> {
+        log::debug!("no longer routing fwmark {fwmark} with table {table}");
+        self.inner.delete_fwmark_rule(fwmark, table)
+    }
+}
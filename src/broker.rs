@@ -0,0 +1,73 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Gives sessions IPv6 on networks which only have IPv4, through a 6in4
+//! tunnel to a tunnel broker set up inside of the namespace. The tunnel
+//! leaves through the host like the rest of the session's traffic and is
+//! masqueraded with it, so the broker has to be configured with the public
+//! address of the host
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+/// The name of the sit link inside of the namespace
+pub const LINK_NAME: &str = "6in4";
+
+/// The hop limit of the tunnel packets
+pub const TTL: u8 = 64;
+
+/// The overhead the IPv4 header adds to every packet in the tunnel
+pub const OVERHEAD: u32 = 20;
+
+/// The end of a tunnel offered by a broker, given as `SERVER,ADDRESS/LEN`
+/// with the IPv4 address of the broker's server and the IPv6 address
+/// assigned to this end of the tunnel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Broker {
+    pub server: Ipv4Addr,
+    pub client: Ipv6Addr,
+    pub prefixlen: u8,
+}
+
+impl FromStr for Broker {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (server, client) = s
+            .split_once(',')
+            .ok_or_else(|| anyhow::anyhow!("expected SERVER,ADDRESS/LEN"))?;
+        let (client, prefixlen) = client.split_once('/').unwrap_or((client, "64"));
+
+        let server = server
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid server address {server}: {e}"))?;
+        let client = client
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid tunnel address {client}: {e}"))?;
+        let prefixlen = prefixlen
+            .parse()
+            .ok()
+            .filter(|len| *len <= 128)
+            .ok_or_else(|| anyhow::anyhow!("invalid prefix length {prefixlen}"))?;
+
+        Ok(Self {
+            server,
+            client,
+            prefixlen,
+        })
+    }
+}
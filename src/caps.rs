@@ -0,0 +1,192 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Probes the running kernel for the features download-shell relies on,
+//! so that a missing feature is reported up front instead of surfacing
+//! as a raw netlink error halfway through setting up the session
+
+use std::{path::Path, process::Command};
+
+use crate::{firewall::Mechanism, kernel::Features, sysctl};
+
+/// The result of probing for a single kernel feature
+#[derive(Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    /// Suggestion printed when the check fails
+    pub hint: &'static str,
+}
+
+/// Returns true if the module is loaded or built into the kernel
+fn module_present(module: &str) -> bool {
+    if Path::new(&format!("/sys/module/{module}")).exists() {
+        return true;
+    }
+
+    // Built in modules only show up in /sys/module if they have parameters
+    let mut uname: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uname) } != 0 {
+        return false;
+    }
+    let release = unsafe { std::ffi::CStr::from_ptr(uname.release.as_ptr()) }.to_string_lossy();
+
+    std::fs::read_to_string(format!("/lib/modules/{release}/modules.builtin"))
+        .map(|builtin| {
+            builtin
+                .lines()
+                .filter_map(|l| l.rsplit('/').next())
+                .any(|m| m.trim_end_matches(".ko").replace('-', "_") == module)
+        })
+        .unwrap_or(false)
+}
+
+/// Checks for a module, trying to load it with modprobe if it is missing
+fn probe_module(module: &str) -> bool {
+    if module_present(module) {
+        return true;
+    }
+
+    let loaded = Command::new("modprobe")
+        .arg(module)
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+
+    loaded && module_present(module)
+}
+
+/// Checks whether a namespace can be created by trying to unshare it in a
+/// throwaway child process. Used on kernels too old to expose namespaces
+/// under /proc/[pid]/ns
+fn unshare_works(flags: libc::c_int) -> bool {
+    unsafe {
+        match libc::fork() {
+            ..0 => false,
+            0 => libc::_exit(if libc::unshare(flags) == 0 { 0 } else { 1 }),
+            child => {
+                let mut status = 0;
+                libc::waitpid(child, &mut status, 0);
+                libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+            }
+        }
+    }
+}
+
+/// Runs all of the kernel feature checks, looking for the matches and
+/// targets of the firewall mechanism that will be used
+pub fn probe(features: &Features, mechanism: Mechanism) -> Vec<Check> {
+    let firewall = match mechanism {
+        Mechanism::Iptables => [
+            Check {
+                name: "xt_conntrack module",
+                ok: probe_module("xt_conntrack"),
+                hint: "load it with `modprobe xt_conntrack` or build the kernel with CONFIG_NETFILTER_XT_MATCH_CONNTRACK",
+            },
+            Check {
+                name: "xt_mark module",
+                ok: probe_module("xt_mark"),
+                hint: "load it with `modprobe xt_mark` or build the kernel with CONFIG_NETFILTER_XT_MARK",
+            },
+        ],
+        Mechanism::Nft => [
+            Check {
+                name: "nft_ct module",
+                ok: probe_module("nft_ct"),
+                hint: "load it with `modprobe nft_ct` or build the kernel with CONFIG_NFT_CT",
+            },
+            Check {
+                name: "nft_nat module",
+                ok: probe_module("nft_nat"),
+                hint: "load it with `modprobe nft_nat` or build the kernel with CONFIG_NFT_NAT",
+            },
+        ],
+    };
+
+    let mut checks = vec![
+        Check {
+            name: "network namespaces",
+            ok: if features.netns_file {
+                Path::new("/proc/self/ns/net").exists()
+            } else {
+                unshare_works(libc::CLONE_NEWNET)
+            },
+            hint: "the kernel needs to be built with CONFIG_NET_NS",
+        },
+        Check {
+            name: "mount namespaces",
+            ok: if features.mntns_file {
+                Path::new("/proc/self/ns/mnt").exists()
+            } else {
+                unshare_works(libc::CLONE_NEWNS)
+            },
+            hint: "the kernel needs to be built with CONFIG_NAMESPACES",
+        },
+        Check {
+            name: "veth module",
+            ok: probe_module("veth"),
+            hint: "load it with `modprobe veth` or build the kernel with CONFIG_VETH",
+        },
+        Check {
+            name: "nf_conntrack module",
+            ok: probe_module("nf_conntrack"),
+            hint: "load it with `modprobe nf_conntrack` or build the kernel with CONFIG_NF_CONNTRACK",
+        },
+        Check {
+            name: "nf_nat module",
+            ok: probe_module("nf_nat"),
+            hint: "load it with `modprobe nf_nat` or build the kernel with CONFIG_NF_NAT",
+        },
+        Check {
+            name: "net.ipv4.ip_forward sysctl",
+            ok: sysctl::exists("net/ipv4/ip_forward"),
+            hint: "/proc/sys needs to be mounted and writable",
+        },
+        Check {
+            name: "net.ipv4.conf.all.proxy_arp sysctl",
+            ok: sysctl::exists("net/ipv4/conf/all/proxy_arp"),
+            hint: "/proc/sys needs to be mounted and writable",
+        },
+    ];
+    checks.extend(firewall);
+    checks
+}
+
+/// Probes the kernel and prints a report of everything that is missing,
+/// failing if anything required to start a session is unavailable
+pub fn require(features: &Features, mechanism: Mechanism) -> anyhow::Result<()> {
+    let missing = probe(features, mechanism)
+        .into_iter()
+        .filter(|c| !c.ok)
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let kernel = match features.version {
+        Some(version) => format!("The running kernel ({version})"),
+        None => "The running kernel".to_owned(),
+    };
+    log::error!(
+        "{kernel} is missing features download-shell needs:{}",
+        missing
+            .iter()
+            .map(|check| format!("\n  - {}: {}", check.name, check.hint))
+            .collect::<String>()
+    );
+
+    anyhow::bail!("{} kernel feature(s) unavailable", missing.len());
+}
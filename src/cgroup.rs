@@ -0,0 +1,106 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! A cgroup for each session, which the program is moved into before it
+//! starts. Everything it starts stays in the cgroup, so the processes of a
+//! session can be found and killed even after the program itself exits
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Where the cgroup v2 hierarchy is mounted
+const ROOT: &str = "/sys/fs/cgroup";
+
+/// The cgroup holding the cgroups of every session
+const PARENT: &str = "download-shell";
+
+/// Prefixed to the pid of a session to name its cgroup
+const PREFIX: &str = "dlsh";
+
+/// The pid of the download-shell process whose session this process is
+/// running in, if any
+pub fn enclosing_session() -> Option<libc::pid_t> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+
+    path.strip_prefix('/')?
+        .strip_prefix(PARENT)?
+        .strip_prefix('/')?
+        .strip_prefix(PREFIX)?
+        .parse()
+        .ok()
+}
+
+/// Moves this process into the cgroup of a session that is already running
+pub fn join(session: libc::pid_t) -> io::Result<()> {
+    let procs = Path::new(ROOT)
+        .join(PARENT)
+        .join(format!("{PREFIX}{session}"))
+        .join("cgroup.procs");
+    std::fs::write(procs, std::process::id().to_string())
+}
+
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Creates the cgroup of the session run by `session`. Fails on hosts
+    /// which only have the legacy cgroup v1 hierarchy
+    pub fn create(session: libc::pid_t) -> io::Result<Self> {
+        if !Path::new(ROOT).join("cgroup.controllers").exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cgroup v2 is not mounted on /sys/fs/cgroup",
+            ));
+        }
+
+        let path = Path::new(ROOT)
+            .join(PARENT)
+            .join(format!("{PREFIX}{session}"));
+        std::fs::create_dir_all(&path)?;
+
+        Ok(Self { path })
+    }
+
+    /// Moves a process into the cgroup
+    pub fn add(&self, pid: libc::pid_t) -> io::Result<()> {
+        std::fs::write(self.path.join("cgroup.procs"), pid.to_string())
+    }
+
+    /// The processes in the cgroup
+    pub fn pids(&self) -> io::Result<Vec<libc::pid_t>> {
+        Ok(std::fs::read_to_string(self.path.join("cgroup.procs"))?
+            .lines()
+            .filter_map(|pid| pid.parse().ok())
+            .collect())
+    }
+
+    /// Kills every process in the cgroup at once, which needs Linux 5.14.
+    /// Older kernels fail with `NotFound`
+    pub fn kill(&self) -> io::Result<()> {
+        std::fs::write(self.path.join("cgroup.kill"), "1")
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // Only succeeds once the cgroup is empty; one which still has
+        // processes is left for them
+        let _ = std::fs::remove_dir(&self.path);
+    }
+}
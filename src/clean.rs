@@ -0,0 +1,100 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+//! `download-shell clean`, which removes what sessions that were killed
+//! before they could tear down left behind: their links, their firewall
+//! rules and their descriptions in the state directory
+
+use anyhow::Context;
+
+use crate::{
+    backend, cli,
+    firewall::{self, Firewall},
+    lock, naming, report,
+};
+
+/// Runs `download-shell clean`
+pub fn run(args: cli::CleanArgs) -> anyhow::Result<()> {
+    let cli::CleanArgs {
+        dry_run,
+        link_prefix,
+    } = args;
+
+    // Rules are deleted by their position, which a session starting now
+    // would shift
+    let _host_lock = lock::HostLock::acquire()?;
+
+    let backend = backend::open(None, false)?;
+    let links = backend.link_names().context("Could not list links")?;
+    let stale_links = links
+        .iter()
+        .filter(|name| {
+            naming::session_pid(&link_prefix, name).is_some_and(|pid| !lock::process_alive(pid))
+        })
+        .collect::<Vec<_>>();
+
+    let stale_rules = Firewall::leftovers()
+        .into_iter()
+        .filter(|f| firewall::session_pid(f.comment()).is_some_and(|pid| !lock::process_alive(pid)))
+        .collect::<Vec<_>>();
+
+    let mut failed = false;
+
+    for name in &stale_links {
+        // Deleting one end of a veth pair takes the other with it
+        let remaining = backend.link_names().context("Could not list links")?;
+        if !remaining.contains(name) {
+            continue;
+        }
+
+        log::info!("Deleting {name}");
+        if !dry_run && let Err(e) = backend.delete_link(name) {
+            log::warn!("could not delete {name}: {e:#}");
+            failed = true;
+        }
+    }
+
+    for firewall in &stale_rules {
+        log::info!("Removing the firewall rules of {}", firewall.comment());
+        if !dry_run && let Err(e) = firewall.clean() {
+            log::warn!(
+                "could not remove the firewall rules of {}: {e:#}",
+                firewall.comment()
+            );
+            failed = true;
+        }
+    }
+
+    let stale_reports = report::saved()
+        .into_iter()
+        .filter(|r| !lock::process_alive(r.pid))
+        .collect::<Vec<_>>();
+    for report in &stale_reports {
+        log::info!("Forgetting the session {}", report.pid);
+        if !dry_run && let Err(e) = report::remove(report.pid) {
+            log::warn!("could not forget the session {}: {e}", report.pid);
+            failed = true;
+        }
+    }
+
+    if stale_links.is_empty() && stale_rules.is_empty() && stale_reports.is_empty() {
+        log::info!("Nothing left behind by sessions which are no longer running");
+    }
+
+    if failed {
+        anyhow::bail!("Some of what was left behind could not be removed");
+    }
+
+    Ok(())
+}
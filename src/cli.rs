@@ -0,0 +1,862 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! The command line of download-shell and of its sessions
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+    time::Duration,
+};
+
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand, error::ErrorKind};
+use nl::route::MacAddr;
+
+use crate::{
+    autoip, backend, broker, config, environment, firewall, fwmark, hooks, mounts, naming, nat64,
+    offload, processes, program, publish, rate, report, session, tunnel,
+};
+
+/// Runs a program in a network namespace of its own, with its traffic
+/// leaving the host from an address of its choosing
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Source IP address for the traffic of the session, or `auto` to pick
+    /// an unused IPv4 address on the network of the egress interface. More
+    /// IPv4 addresses, given again or as a CIDR or FIRST-LAST range, are
+    /// taken in turn by each new connection. One IPv6 address may be given
+    /// as well
+    #[arg(short = 's', long = "source-ip", value_name = "IP|CIDR|RANGE|auto", value_parser = parse_source_ip)]
+    source: Vec<SourceIp>,
+    #[arg(skip)]
+    pub source_ip: Option<Ipv4Addr>,
+    /// Every IPv4 source IP, starting with `source_ip`
+    #[arg(skip)]
+    pub source_ips: Vec<Ipv4Addr>,
+    #[arg(skip)]
+    pub source_ip6: Option<Ipv6Addr>,
+    #[arg(skip)]
+    pub auto_source: bool,
+
+    /// Reach these IPv4 destinations, such as the LAN or a corporate
+    /// network, from the address of the host rather than the source IP; may
+    /// be given more than once
+    #[arg(long, value_name = "CIDR")]
+    pub exclude: Vec<tunnel::Net>,
+
+    /// Randomize the source ports the source IP translates connections to,
+    /// from a hash or, with `fully`, for every connection
+    #[arg(
+        long,
+        value_name = "hash|fully",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "hash",
+        requires = "source"
+    )]
+    pub snat_random: Option<firewall::PortRandomization>,
+
+    /// Keep translating the session to the same source IP and port mapping
+    /// across connections
+    #[arg(long, requires = "source")]
+    pub snat_persistent: bool,
+
+    /// Give the session IPv6 connectivity over the tunnel, leaving from the
+    /// address of the host unless an IPv6 source IP is given
+    #[arg(long)]
+    pub ipv6: bool,
+
+    /// Address ranges `-s auto` must not pick from, as an address, a CIDR
+    /// block or FIRST-LAST
+    #[arg(long, visible_alias = "dhcp-range", value_name = "RANGE")]
+    pub auto_exclude: Vec<autoip::AddrRange>,
+
+    /// Take over the address of another host on the network, sending and
+    /// receiving its traffic
+    #[arg(long, value_name = "IP")]
+    pub impersonate: Option<Ipv4Addr>,
+
+    /// Send the traffic of the session from this hardware address, putting
+    /// the source IP on the LAN through a macvlan instead of NATing it.
+    /// Needs -s or --impersonate, and replaces the hardware address of the
+    /// impersonated host
+    #[arg(long, value_name = "MAC", value_parser = parse_mac)]
+    pub mac: Option<MacAddr>,
+
+    /// Leave through a NAT64 gateway instead of over IPv4
+    #[arg(long)]
+    pub nat64: bool,
+
+    /// The NAT64 prefix to translate to, instead of discovering it; implies
+    /// --nat64
+    #[arg(long, value_name = "PREFIX/96", value_parser = nat64::parse_prefix)]
+    pub nat64_prefix: Option<Ipv6Addr>,
+
+    /// Give the session IPv6 connectivity through a 6in4 tunnel broker
+    #[arg(long = "6in4", value_name = "SERVER,ADDR/LEN")]
+    pub broker: Option<broker::Broker>,
+
+    /// Send the traffic of the session out of this interface instead of the
+    /// one of the default route
+    #[arg(short, long, value_name = "IFACE", conflicts_with = "uplinks")]
+    pub interface: Option<String>,
+
+    /// Send the traffic of the session out of this interface; may be given
+    /// more than once
+    #[arg(long = "uplink", value_name = "IFACE")]
+    pub uplinks: Vec<String>,
+
+    /// How the host is configured: `netlink` talks to the kernel directly,
+    /// `ip` runs iproute2
+    #[arg(short, long, value_name = "netlink|ip")]
+    pub backend: Option<backend::Kind>,
+
+    /// Use the oldest code paths, whatever the running kernel supports
+    #[arg(long)]
+    pub legacy_kernel: bool,
+
+    /// Run the program as this user
+    #[arg(short, long)]
+    pub user: Option<String>,
+
+    /// Run the program with this group
+    #[arg(short, long)]
+    pub group: Option<String>,
+
+    /// Keep the environment of root instead of the one of the user who ran
+    /// sudo
+    #[arg(long = "keep-root-env", action = ArgAction::SetFalse)]
+    pub sudo_env: bool,
+
+    /// Set a variable in the environment of the program, or with only a
+    /// name pass it on from this one; may be given more than once
+    #[arg(short, long, value_name = "KEY[=VALUE]")]
+    pub env: Vec<environment::Var>,
+
+    /// Set the variables in a file of KEY=VALUE lines in the environment of
+    /// the program; may be given more than once
+    #[arg(long, value_name = "FILE")]
+    pub env_file: Vec<PathBuf>,
+
+    /// Start the program with only PATH, TERM and the variables of the user
+    /// it runs as, besides those given with --env and --env-file
+    #[arg(long)]
+    pub clear_env: bool,
+
+    /// Make home directories read only for the program
+    #[arg(long = "protect-home", conflicts_with = "hide_home")]
+    protect_home_dirs: bool,
+
+    /// Replace home directories with empty ones for the program
+    #[arg(long)]
+    hide_home: bool,
+    #[arg(skip)]
+    pub protect_home: Option<mounts::HomeProtection>,
+
+    /// Make the directories of the operating system read only for the
+    /// program
+    #[arg(long)]
+    pub protect_system: bool,
+
+    /// Give the program an empty /tmp of its own
+    #[arg(long)]
+    pub private_tmp: bool,
+
+    /// Make everything read only for the program except for this directory
+    #[arg(short, long, value_name = "DIR")]
+    pub download_dir: Option<PathBuf>,
+
+    /// Start the program in the download directory
+    #[arg(long, requires = "download_dir")]
+    pub download_dir_cwd: bool,
+
+    /// Start the program in this directory instead of the current one
+    #[arg(long, value_name = "DIR", conflicts_with = "download_dir_cwd")]
+    pub workdir: Option<PathBuf>,
+
+    /// Give the files downloaded to the user who ran sudo
+    #[arg(long)]
+    pub chown_downloads: bool,
+
+    /// Prefix of the names of the links created for the session
+    #[arg(long, value_name = "PREFIX", default_value = naming::DEFAULT_PREFIX, value_parser = parse_link_prefix)]
+    pub link_prefix: String,
+
+    /// How the tunnel is addressed: a /30, a /31 or a peer address
+    #[arg(
+        long = "tunnel-addressing",
+        value_name = "30|31|peer",
+        default_value = "30"
+    )]
+    pub tunnel_addressing: tunnel::Addressing,
+
+    /// The block of addresses the tunnel is numbered from
+    #[arg(
+        long,
+        value_name = "CIDR",
+        env = "DL_SHELL_TUNNEL_NET",
+        default_value_t = tunnel::Net::default()
+    )]
+    pub tunnel_net: tunnel::Net,
+
+    /// Name the session, which then stays up after the program exits until
+    /// it is ended with `download-shell destroy NAME`
+    #[arg(long, value_name = "NAME", value_parser = session::parse_name)]
+    pub name: Option<String>,
+
+    /// End the session after this long, e.g. `30m` or `1h30m`: the program
+    /// is sent SIGTERM, and SIGKILL if it is still running 10s later
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// Give the session a hostname of its own
+    #[arg(long, value_name = "NAME", value_parser = parse_hostname)]
+    pub hostname: Option<String>,
+
+    /// Resolve names with this server instead of the ones in
+    /// /etc/resolv.conf; may be given more than once
+    #[arg(long, value_name = "IP")]
+    pub dns: Vec<IpAddr>,
+
+    /// Forward a port of the host, or of the source IP, to a port inside of
+    /// the namespace; may be given more than once
+    #[arg(long, value_name = "HOSTPORT:NSPORT[/tcp|udp]")]
+    pub publish: Vec<publish::Port>,
+
+    /// Relay mDNS and LLMNR traffic, so that `.local` names resolve inside
+    /// of the namespace
+    #[arg(long)]
+    pub mdns: bool,
+
+    /// Mark the traffic of the session with this firewall mark
+    #[arg(long, value_name = "VALUE[/MASK]")]
+    pub fwmark: Option<fwmark::Fwmark>,
+
+    /// Offloads to turn on or off for the tunnel, as a comma separated list
+    #[arg(long = "offload", value_name = "FEATURE=on|off,...")]
+    offload: Option<offload::Offloads>,
+    #[arg(skip)]
+    pub offloads: offload::Offloads,
+
+    /// MTU of the tunnel, instead of the one of the egress interface
+    #[arg(long)]
+    pub mtu: Option<u32>,
+
+    /// Size the tunnel for the path MTU to this address
+    #[arg(long, value_name = "IP")]
+    pub mtu_probe: Option<Ipv4Addr>,
+
+    /// Number of queues of the tunnel, or `auto` for one per CPU
+    #[arg(long, value_name = "N|auto", value_parser = parse_queues)]
+    pub queues: Option<u32>,
+
+    /// Transmit queue length of the tunnel
+    #[arg(long)]
+    pub txqueuelen: Option<u32>,
+
+    /// Queueing discipline of the tunnel
+    #[arg(long, value_name = "KIND")]
+    pub qdisc: Option<backend::Qdisc>,
+
+    /// Limit the bandwidth of the session in each direction, e.g. `5mbit`,
+    /// with a token bucket filter on either end of the tunnel
+    #[arg(long, value_name = "RATE", conflicts_with = "qdisc")]
+    pub rate: Option<rate::Rate>,
+
+    /// Turn on forwarding for every interface instead of only the ones the
+    /// session uses
+    #[arg(long)]
+    pub global_forwarding: bool,
+
+    /// Copy the traffic of the tunnel to another interface
+    #[arg(long, value_name = "IFACE")]
+    pub mirror: Option<String>,
+
+    /// Close the connections of the session once the program exits
+    #[arg(long)]
+    pub close_connections: bool,
+
+    /// Work out the tunnel, routes, kernel parameters and firewall rules of
+    /// the session and print them as commands, without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Also describe the session as JSON once it is up, on a line of its own
+    #[arg(long, value_name = "text|json", default_value = "text")]
+    pub output: report::Output,
+
+    /// What to do with processes still running once the program exits
+    #[arg(long, value_name = "warn|kill|wait", default_value = "warn")]
+    pub leftovers: processes::Leftovers,
+
+    /// Command to run before anything is changed
+    #[arg(long, value_name = "[host:]COMMAND", value_parser = |s: &str| hooks::Hook::parse(hooks::Stage::PreUp, s))]
+    pre_up: Vec<hooks::Hook>,
+
+    /// Command to run once the tunnel is up, before the program starts
+    #[arg(long, value_name = "[host:|ns:]COMMAND", value_parser = |s: &str| hooks::Hook::parse(hooks::Stage::PostUp, s))]
+    post_up: Vec<hooks::Hook>,
+
+    /// Command to run after the program exits, while the tunnel still
+    /// exists
+    #[arg(long, value_name = "[host:|ns:]COMMAND", value_parser = |s: &str| hooks::Hook::parse(hooks::Stage::PreDown, s))]
+    pre_down: Vec<hooks::Hook>,
+
+    /// Command to run once everything is cleaned up
+    #[arg(long, value_name = "[host:]COMMAND", value_parser = |s: &str| hooks::Hook::parse(hooks::Stage::PostDown, s))]
+    post_down: Vec<hooks::Hook>,
+    #[arg(skip)]
+    pub hooks: Vec<hooks::Hook>,
+
+    /// Plugin compiled into this binary to enable
+    #[arg(long = "plugin", value_name = "NAME")]
+    pub plugins: Vec<String>,
+
+    /// Take the options of a profile in the configuration file, for those
+    /// not given on the command line
+    #[arg(long, value_name = "NAME", env = "DL_SHELL_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Log each change made to the system; twice to also log how it was
+    /// made
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Keep a timestamped log of the session, including every change made to
+    /// the system
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Print the netlink messages exchanged with the kernel
+    #[arg(long)]
+    pub debug_netlink: bool,
+
+    /// Write the changes made to the system to a shell script of
+    /// equivalent commands
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Record the session as a typescript that scriptreplay(1) can play
+    /// back
+    #[arg(long, value_name = "FILE")]
+    pub transcript: Option<PathBuf>,
+
+    /// Run a script with the interpreter; the arguments after it are passed
+    /// to the script
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<PathBuf>,
+
+    /// The interpreter of scripts, including those with a `#!` line naming
+    /// this program
+    #[arg(long, value_name = "PROGRAM", default_value = "/bin/sh")]
+    interpreter: String,
+
+    /// The program to run and its arguments, /bin/sh by default
+    #[arg(value_name = "PROGRAM", trailing_var_arg = true)]
+    command: Vec<String>,
+    #[arg(skip)]
+    pub program: String,
+    #[arg(skip)]
+    pub program_args: Vec<String>,
+}
+
+impl Args {
+    /// Whether the mount namespace of the child needs any changes
+    pub fn changes_mounts(&self) -> bool {
+        self.protect_system
+            || self.protect_home.is_some()
+            || self.private_tmp
+            || self.download_dir.is_some()
+            || !self.dns.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum SourceIp {
+    Auto,
+    Fixed(IpAddr),
+    Pool(Vec<Ipv4Addr>),
+}
+
+/// The most IPv4 source IPs a session rotates between, each of which takes a
+/// firewall rule and a route
+const MAX_SOURCE_IPS: usize = 256;
+
+fn parse_source_ip(s: &str) -> anyhow::Result<SourceIp> {
+    if s == "auto" {
+        return Ok(SourceIp::Auto);
+    }
+
+    let (first, last) = if let Some((addr, len)) = s.split_once('/') {
+        let addr = addr
+            .parse::<Ipv4Addr>()
+            .map_err(|_| anyhow::anyhow!("only IPv4 source IPs can be given as a CIDR"))?;
+        let len = len
+            .parse::<u8>()
+            .ok()
+            .filter(|len| *len <= 32)
+            .ok_or(anyhow::anyhow!("invalid prefix length '{len}'"))?;
+        let mask = u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+        let (network, broadcast) = (u32::from(addr) & mask, u32::from(addr) | !mask);
+
+        // The network and broadcast addresses aren't usable as hosts
+        if len >= 31 {
+            (network, broadcast)
+        } else {
+            (network + 1, broadcast - 1)
+        }
+    } else if let Some((first, last)) = s.split_once('-') {
+        let parse = |ip: &str| {
+            ip.parse::<Ipv4Addr>()
+                .map(u32::from)
+                .map_err(|_| anyhow::anyhow!("invalid IPv4 address '{ip}' in range"))
+        };
+        let (first, last) = (parse(first)?, parse(last)?);
+        if first > last {
+            anyhow::bail!("the range starts after it ends");
+        }
+        (first, last)
+    } else {
+        return Ok(SourceIp::Fixed(s.parse()?));
+    };
+
+    if (last - first) as usize >= MAX_SOURCE_IPS {
+        anyhow::bail!("at most {MAX_SOURCE_IPS} source IPs can be rotated between");
+    }
+    Ok(SourceIp::Pool((first..=last).map(Ipv4Addr::from).collect()))
+}
+
+/// Parses a duration such as `90s`, `30m` or `1h30m`, in seconds when no
+/// unit is given
+fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let (mut total, mut rest) = match s.parse::<u64>() {
+        Ok(seconds) => (seconds, ""),
+        Err(_) => (0, s),
+    };
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (number, after) = rest.split_at(digits);
+        let unit = after
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit);
+
+        let seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => anyhow::bail!("expected a duration such as 90s, 30m or 1h30m"),
+        };
+        let number = number
+            .parse::<u64>()
+            .map_err(|_| anyhow::anyhow!("expected a duration such as 90s, 30m or 1h30m"))?;
+        total = number
+            .checked_mul(seconds)
+            .and_then(|n| total.checked_add(n))
+            .ok_or(anyhow::anyhow!("the duration is too long"))?;
+        rest = after;
+    }
+
+    if total == 0 {
+        anyhow::bail!("the duration has to be longer than 0s");
+    }
+    Ok(Duration::from_secs(total))
+}
+
+fn parse_hostname(s: &str) -> anyhow::Result<String> {
+    // HOST_NAME_MAX, which libc doesn't export
+    if s.is_empty() || s.len() > 64 {
+        anyhow::bail!("a hostname is between 1 and 64 bytes long");
+    }
+    Ok(s.to_owned())
+}
+
+// The error of MacAddr asks libnl for its message, which may not be loaded yet
+fn parse_mac(s: &str) -> anyhow::Result<MacAddr> {
+    s.parse()
+        .map_err(|_| anyhow::anyhow!("expected six pairs of hex digits, such as 02:00:00:00:00:01"))
+}
+
+fn parse_link_prefix(s: &str) -> anyhow::Result<String> {
+    naming::validate_prefix(s)?;
+    Ok(s.to_owned())
+}
+
+fn parse_queues(s: &str) -> anyhow::Result<u32> {
+    let queues = match s {
+        // One queue per CPU, so that each can have its own softirq
+        "auto" => std::thread::available_parallelism()?.get() as u32,
+        s => s.parse()?,
+    };
+
+    if queues == 0 {
+        anyhow::bail!("the number of queues must be at least 1");
+    }
+
+    Ok(queues)
+}
+
+/// Runs programs in network namespaces of their own, with their traffic
+/// leaving the host from an address of their choosing
+#[derive(Debug, Parser)]
+#[command(
+    name = "download-shell",
+    version,
+    about,
+    after_help = "Without a command, the arguments are those of `run`"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// What download-shell was asked to do, named by the first argument
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a program in a new session
+    Run(Box<Args>),
+    /// Run a program inside of a named session
+    Attach(AttachArgs),
+    /// Remove the links and rules of killed sessions
+    Clean(CleanArgs),
+    /// List the connections of a session
+    Connections(ConnectionsArgs),
+    /// Tear down a named session
+    Destroy(DestroyArgs),
+    /// Check whether this host can run sessions
+    Doctor(DoctorArgs),
+    /// Show the sessions on this host
+    List(ListArgs),
+    /// Look for unused addresses on the local network
+    Scan(ScanArgs),
+}
+
+impl Command {
+    /// Whether the subcommand can only be used by root. `doctor` reports
+    /// on a missing root instead
+    pub fn needs_root(&self) -> bool {
+        !matches!(self, Command::Doctor(_))
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AttachArgs {
+    /// The name the session was given with --name
+    pub name: String,
+
+    /// The program to run and its arguments, $SHELL by default
+    #[arg(value_name = "PROGRAM", trailing_var_arg = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CleanArgs {
+    /// Only list what would be removed
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+
+    /// Prefix of the names of the links created for sessions
+    #[arg(long, value_name = "PREFIX", default_value = naming::DEFAULT_PREFIX, value_parser = parse_link_prefix)]
+    pub link_prefix: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ConnectionsArgs {
+    /// A process of the session, such as the one download-shell runs as
+    pub pid: libc::pid_t,
+
+    /// Also list listening sockets and unconnected UDP sockets
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Print the connections as a JSON array
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DestroyArgs {
+    /// The name the session was given with --name
+    pub name: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DoctorArgs {
+    /// Print the findings as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Check the oldest code paths, as sessions use them with
+    /// --legacy-kernel
+    #[arg(long)]
+    pub legacy_kernel: bool,
+
+    /// Prefix of the names of the links created for sessions
+    #[arg(long, value_name = "PREFIX", default_value = naming::DEFAULT_PREFIX, value_parser = parse_link_prefix)]
+    pub link_prefix: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ListArgs {
+    /// Print the sessions as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Prefix of the names of the links created for sessions
+    #[arg(long, value_name = "PREFIX", default_value = naming::DEFAULT_PREFIX, value_parser = parse_link_prefix)]
+    pub link_prefix: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ScanArgs {
+    /// Scan the network of this interface instead of the one of the
+    /// default route
+    #[arg(long, value_name = "IFACE")]
+    pub interface: Option<String>,
+
+    /// Address ranges not to suggest as free, as an address, a CIDR block
+    /// or FIRST-LAST
+    #[arg(long, visible_alias = "dhcp-range", value_name = "RANGE")]
+    pub auto_exclude: Vec<autoip::AddrRange>,
+
+    /// Listen for this many seconds instead of sending ARP requests
+    #[arg(long, value_name = "SECS")]
+    pub passive: Option<u64>,
+
+    /// With --passive, count hosts not heard from for this many seconds as
+    /// silent, half of the listening period by default
+    #[arg(long, value_name = "SECS", requires = "passive")]
+    pub silent: Option<u64>,
+}
+
+/// The arguments without a subcommand are those of `run`, the way
+/// download-shell was used before it had subcommands
+fn with_subcommand(mut argv: Vec<String>) -> Vec<String> {
+    // The kernel passes everything after the interpreter of a `#!` line as
+    // a single argument, so `#!/usr/bin/download-shell -s 10.0.0.50`
+    // arrives as "-s 10.0.0.50" followed by the path of the script
+    if let Some(first) = argv.get(1)
+        && (first.starts_with('-') || first.starts_with("run "))
+        && first.contains(char::is_whitespace)
+    {
+        let options = first
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        argv.splice(1..2, options);
+    }
+
+    let cli = Cli::command();
+    let names_command = argv.get(1).is_some_and(|first| {
+        cli.find_subcommand(first).is_some()
+            || ["help", "-h", "--help", "-V", "--version"].contains(&first.as_str())
+    });
+    if !names_command {
+        argv.insert(1, "run".to_owned());
+    }
+
+    argv
+}
+
+fn run_command() -> clap::Command {
+    Args::command().bin_name("download-shell run")
+}
+
+/// Parses the command line, exiting with usage information if it is wrong
+pub fn parse() -> Command {
+    let mut argv = with_subcommand(std::env::args().collect());
+
+    let matches = Cli::command().get_matches_from(&argv);
+    if let Some(("run", run)) = matches.subcommand()
+        && let Some(name) = run.get_one::<String>("profile")
+    {
+        let profile = config::profile(name)
+            .and_then(|options| config::arguments(&Args::command(), run, &options))
+            .unwrap_or_else(|e| {
+                run_command()
+                    .error(ErrorKind::InvalidValue, format!("{e:#}"))
+                    .exit()
+            });
+        argv.splice(2..2, profile);
+    }
+
+    match Cli::from_arg_matches(&Cli::command().get_matches_from(argv))
+        .unwrap_or_else(|e| e.exit())
+        .command
+    {
+        Command::Run(args) => Command::Run(Box::new(finish_run(*args))),
+        command => command,
+    }
+}
+
+/// Works out what the arguments of `run` add up to, exiting with usage
+/// information if they conflict
+fn finish_run(mut args: Args) -> Args {
+    for source in std::mem::take(&mut args.source) {
+        let ipv4_given = !args.source_ips.is_empty() || args.auto_source;
+        let conflicts_with_auto = match &source {
+            SourceIp::Auto => ipv4_given,
+            SourceIp::Fixed(IpAddr::V4(_)) | SourceIp::Pool(_) => args.auto_source,
+            SourceIp::Fixed(IpAddr::V6(_)) => false,
+        };
+        match source {
+            _ if conflicts_with_auto => {
+                run_command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "auto picks a single IPv4 source IP, and cannot be used with others",
+                    )
+                    .exit();
+            }
+            SourceIp::Fixed(IpAddr::V6(_)) if args.source_ip6.is_some() => {
+                run_command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "only one IPv6 source IP can be given",
+                    )
+                    .exit();
+            }
+            SourceIp::Auto => args.auto_source = true,
+            SourceIp::Fixed(IpAddr::V4(ip)) => args.source_ips.push(ip),
+            SourceIp::Pool(ips) => args.source_ips.extend(ips),
+            SourceIp::Fixed(IpAddr::V6(ip)) => {
+                args.source_ip6 = Some(ip);
+                args.ipv6 = true;
+            }
+        }
+    }
+
+    let mut seen = Vec::new();
+    args.source_ips.retain(|ip| {
+        let new = !seen.contains(ip);
+        seen.push(*ip);
+        new
+    });
+    if args.source_ips.len() > MAX_SOURCE_IPS {
+        run_command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!("at most {MAX_SOURCE_IPS} source IPs can be rotated between"),
+            )
+            .exit();
+    }
+    args.source_ip = args.source_ips.first().copied();
+
+    if args.protect_home_dirs {
+        args.protect_home = Some(mounts::HomeProtection::ReadOnly);
+    } else if args.hide_home {
+        args.protect_home = Some(mounts::HomeProtection::Hidden);
+    }
+
+    args.nat64 |= args.nat64_prefix.is_some();
+    args.offloads = args.offload.take().unwrap_or_default();
+
+    args.hooks = [
+        std::mem::take(&mut args.pre_up),
+        std::mem::take(&mut args.post_up),
+        std::mem::take(&mut args.pre_down),
+        std::mem::take(&mut args.post_down),
+    ]
+    .concat();
+
+    let mut command = std::mem::take(&mut args.command).into_iter();
+    let mut program = command.next();
+
+    // Executing a script whose `#!` line names this program would only
+    // start another session, so it is handed to the interpreter instead
+    if args.script.is_none()
+        && let Some(path) = &program
+        && program::resolve(path).is_ok_and(|path| program::names_us_as_interpreter(&path))
+    {
+        args.script = program.take().map(PathBuf::from);
+    }
+
+    let mut program_args = program.into_iter().chain(command).collect::<Vec<_>>();
+    if let Some(path) = &mut args.script {
+        // The program may start in another directory, such as the download
+        // directory
+        if let Ok(absolute) = std::path::absolute(&*path) {
+            *path = absolute;
+        }
+        program_args.insert(0, path.to_string_lossy().into_owned());
+        program_args.insert(0, args.interpreter.clone());
+    } else if program_args.is_empty() {
+        program_args.push("/bin/sh".to_owned());
+    }
+
+    args.program = program_args[0].clone();
+    args.program_args = program_args;
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(s: &str) -> Vec<Ipv4Addr> {
+        match parse_source_ip(s).unwrap() {
+            SourceIp::Pool(ips) => ips,
+            other => panic!("{s} parsed as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn cidr_skips_network_and_broadcast() {
+        let ips = pool("192.168.1.5/29");
+        assert_eq!(ips.first(), Some(&Ipv4Addr::new(192, 168, 1, 1)));
+        assert_eq!(ips.last(), Some(&Ipv4Addr::new(192, 168, 1, 6)));
+        assert_eq!(ips.len(), 6);
+    }
+
+    #[test]
+    fn small_cidrs_use_every_address() {
+        assert_eq!(pool("10.0.0.7/32"), [Ipv4Addr::new(10, 0, 0, 7)]);
+        assert_eq!(
+            pool("10.0.0.7/31"),
+            [Ipv4Addr::new(10, 0, 0, 6), Ipv4Addr::new(10, 0, 0, 7)]
+        );
+    }
+
+    #[test]
+    fn range_is_inclusive() {
+        assert_eq!(
+            pool("10.0.0.254-10.0.1.1"),
+            [
+                Ipv4Addr::new(10, 0, 0, 254),
+                Ipv4Addr::new(10, 0, 0, 255),
+                Ipv4Addr::new(10, 0, 1, 0),
+                Ipv4Addr::new(10, 0, 1, 1),
+            ]
+        );
+        assert_eq!(pool("10.0.0.1-10.0.0.1"), [Ipv4Addr::new(10, 0, 0, 1)]);
+    }
+
+    #[test]
+    fn reversed_range_is_rejected() {
+        assert!(parse_source_ip("10.0.0.9-10.0.0.1").is_err());
+    }
+
+    #[test]
+    fn oversized_pools_are_rejected() {
+        assert_eq!(pool("10.0.0.0-10.0.0.255").len(), MAX_SOURCE_IPS);
+        assert!(parse_source_ip("10.0.0.0-10.0.1.0").is_err());
+        assert!(parse_source_ip("10.0.0.0/23").is_err());
+    }
+}
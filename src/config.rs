@@ -0,0 +1,290 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Profiles of command line options, kept in
+//! /etc/download-shell/config.toml and in the configuration directory of
+//! the user running download-shell:
+//!
+//! ```toml
+//! [profile.printer]
+//! source_ip = "192.168.1.50"
+//! mac = "00:11:22:33:44:55"
+//! dns = ["192.168.1.1", "1.1.1.1"]
+//! hostname = "printer"
+//! ```
+//!
+//! The keys are the long options, with `-` or `_` between words, and the
+//! options given on the command line take precedence over them. Only the
+//! parts of TOML profiles need are understood: tables, strings, integers,
+//! booleans and arrays on a single line
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::{ArgMatches, Command, parser::ValueSource};
+
+use crate::sudo;
+
+const SYSTEM_CONFIG: &str = "/etc/download-shell/config.toml";
+
+/// A value of an option in a profile
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+/// The options of each profile in a configuration file, by profile name
+type Profiles = BTreeMap<String, BTreeMap<String, Value>>;
+
+/// The configuration files read, from the least to the most specific
+fn paths() -> Vec<PathBuf> {
+    // Under sudo, the configuration of the user who ran it is wanted rather
+    // than root's
+    let user_config = match sudo::invoker() {
+        Some(invoker) => Some(Path::new(&invoker.home).join(".config")),
+        None => std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config"))),
+    };
+
+    std::iter::once(PathBuf::from(SYSTEM_CONFIG))
+        .chain(user_config.map(|dir| dir.join("download-shell/config.toml")))
+        .collect()
+}
+
+/// The options of the profile with the name given, where those in the
+/// configuration of the user replace the ones set for the whole system
+pub fn profile(name: &str) -> anyhow::Result<BTreeMap<String, Value>> {
+    let mut found = None::<BTreeMap<String, Value>>;
+
+    for path in paths() {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("could not read {}", path.display())),
+        };
+        let mut profiles = parse(&text).with_context(|| format!("in {}", path.display()))?;
+
+        if let Some(options) = profiles.remove(name) {
+            found.get_or_insert_default().extend(options);
+        }
+    }
+
+    found.ok_or_else(|| {
+        anyhow::anyhow!(
+            "no profile named '{name}' in {}",
+            paths()
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" or ")
+        )
+    })
+}
+
+/// The profile as command line arguments, leaving out the options already
+/// given on the command line or through the environment
+pub fn arguments(
+    command: &Command,
+    matches: &ArgMatches,
+    options: &BTreeMap<String, Value>,
+) -> anyhow::Result<Vec<String>> {
+    let mut argv = Vec::new();
+
+    for (key, value) in options {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(&long) && long != "profile")
+            .ok_or_else(|| anyhow::anyhow!("unknown option '{key}' in profile"))?;
+
+        if matches!(
+            matches.value_source(arg.get_id().as_str()),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+
+        let values = match value {
+            Value::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        for value in values {
+            match (value, arg.get_action().takes_values()) {
+                (Value::Boolean(true), false) => argv.push(format!("--{long}")),
+                (Value::Boolean(false), false) => {}
+                (_, false) => anyhow::bail!("'{key}' in profile has to be true or false"),
+                (Value::String(s), true) => argv.push(format!("--{long}={s}")),
+                (Value::Integer(n), true) => argv.push(format!("--{long}={n}")),
+                (Value::Boolean(b), true) => argv.push(format!("--{long}={b}")),
+                (Value::Array(_), true) => anyhow::bail!("'{key}' in profile has nested arrays"),
+            }
+        }
+    }
+
+    Ok(argv)
+}
+
+/// Reads the profiles out of a configuration file
+fn parse(text: &str) -> anyhow::Result<Profiles> {
+    let mut profiles = Profiles::new();
+    let mut current = None::<String>;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fail = |message: &str| anyhow::anyhow!("line {}: {message}", number + 1);
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = strip_comment(header)
+                .strip_suffix(']')
+                .ok_or_else(|| fail("expected ] at the end of the table header"))?
+                .trim();
+            let name = header
+                .strip_prefix("profile.")
+                .ok_or_else(|| fail("only [profile.NAME] tables are supported"))?
+                .trim();
+            let name = match name.strip_prefix('"') {
+                Some(quoted) => {
+                    let (name, rest) = string(quoted).map_err(fail)?;
+                    if !rest.trim().is_empty() {
+                        return Err(fail("unexpected characters after the profile name"));
+                    }
+                    name
+                }
+                None if !name.is_empty() && name.bytes().all(is_bare_key_byte) => name.to_owned(),
+                None => return Err(fail("invalid profile name")),
+            };
+
+            if profiles.insert(name.clone(), BTreeMap::new()).is_some() {
+                return Err(fail(&format!("profile '{name}' is defined twice")));
+            }
+            current = Some(name);
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| fail("expected key = value"))?;
+        let key = key.trim();
+        if key.is_empty() || !key.bytes().all(is_bare_key_byte) {
+            return Err(fail(&format!("invalid key '{key}'")));
+        }
+        let profile = current
+            .as_ref()
+            .and_then(|name| profiles.get_mut(name))
+            .ok_or_else(|| fail("options have to be inside of a [profile.NAME] table"))?;
+
+        let (value, rest) = self::value(value.trim_start()).map_err(fail)?;
+        if !strip_comment(rest).trim().is_empty() {
+            return Err(fail("unexpected characters after the value"));
+        }
+        if profile.insert(key.to_owned(), value).is_some() {
+            return Err(fail(&format!("'{key}' is set twice")));
+        }
+    }
+
+    Ok(profiles)
+}
+
+fn is_bare_key_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+/// What is left of a line before a comment, outside of any string
+fn strip_comment(rest: &str) -> &str {
+    let rest = rest.trim_start();
+    if rest.starts_with('#') { "" } else { rest }
+}
+
+/// Reads a value from the start of `s`, returning it along with the rest
+fn value(s: &str) -> Result<(Value, &str), &'static str> {
+    if let Some(rest) = s.strip_prefix('"') {
+        let (string, rest) = string(rest)?;
+        return Ok((Value::String(string), rest));
+    }
+
+    if let Some(rest) = s.strip_prefix('\'') {
+        let (literal, rest) = rest.split_once('\'').ok_or("unterminated literal string")?;
+        return Ok((Value::String(literal.to_owned()), rest));
+    }
+
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+            let (value, after) = value(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => {}
+                None => return Err("expected , or ] in array"),
+            }
+        }
+    }
+
+    let end = s
+        .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+        .unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+    let value = match word {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => Value::Integer(
+            word.replace('_', "")
+                .parse()
+                .map_err(|_| "expected a string, integer, boolean or array")?,
+        ),
+    };
+    Ok((value, rest))
+}
+
+/// Reads a basic string up to its closing quote, handling the escapes
+/// TOML allows in them
+fn string(s: &str) -> Result<(String, &str), &'static str> {
+    let mut string = String::new();
+    let mut chars = s.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, &s[i + 1..])),
+            '\\' => string.push(match chars.next().map(|(_, c)| c) {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('u') | Some('U') => return Err("unicode escapes are not supported"),
+                _ => return Err("invalid escape in string"),
+            }),
+            c => string.push(c),
+        }
+    }
+
+    Err("unterminated string")
+}
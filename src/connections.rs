@@ -0,0 +1,205 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! The `connections` subcommand, which lists the TCP and UDP sockets of a
+//! running session from the host, like `ss` run inside of it would, along
+//! with closing whatever connections are left when the session ends
+
+use std::{fs::File, net::Ipv4Addr, os::fd::AsRawFd, process::Command};
+
+use anyhow::Context;
+use nl::diag::{InetSocket, Protocol};
+
+use crate::{cli, processes};
+
+/// Finds the network namespace of the session with the pid given, which is
+/// either that of download-shell or of a process inside of the session
+fn session_netns(pid: libc::pid_t) -> anyhow::Result<File> {
+    let host = processes::netns_inode("self")
+        .context("Could not inspect the current network namespace")?;
+    let own = processes::netns_inode(&pid.to_string())
+        .with_context(|| format!("Could not inspect process {pid}"))?;
+
+    if own != host {
+        return File::open(format!("/proc/{pid}/ns/net"))
+            .with_context(|| format!("Could not open the network namespace of {pid}"));
+    }
+
+    // download-shell itself stays on the host, but its child doesn't
+    let child = processes::all()
+        .context("Could not list processes")?
+        .filter(|p| p.ppid == pid)
+        .find(|p| processes::netns_inode(&p.pid.to_string()).is_ok_and(|i| i != host));
+    if let Some(child) = child {
+        return File::open(format!("/proc/{}/ns/net", child.pid))
+            .with_context(|| format!("Could not open the network namespace of {}", child.pid));
+    }
+
+    anyhow::bail!("Process {pid} is not part of a download-shell session")
+}
+
+fn format_counter(counter: Option<u64>) -> String {
+    counter.map_or_else(|| "-".to_owned(), |c| c.to_string())
+}
+
+fn json_counter(counter: Option<u64>) -> String {
+    counter.map_or_else(|| "null".to_owned(), |c| c.to_string())
+}
+
+/// Runs `download-shell connections PID`
+pub fn run(args: cli::ConnectionsArgs) -> anyhow::Result<()> {
+    let cli::ConnectionsArgs { pid, all, json } = args;
+
+    let netns = session_netns(pid)?;
+    if unsafe { libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .context("Could not enter the network namespace of the session");
+    }
+
+    #[cfg(feature = "runtime-libnl")]
+    nl::runtime::load()?;
+
+    let sock = nl::netlink::Socket::new_sock_diag().context("Could not open a sock_diag socket")?;
+    let mut sockets = Vec::new();
+    for protocol in [Protocol::Tcp, Protocol::Udp] {
+        sockets.extend(
+            sock.get_inet_sockets(protocol)
+                .with_context(|| format!("Could not list the {} sockets", protocol.name()))?,
+        );
+    }
+
+    // Like ss, only show what is talking to something unless asked
+    sockets.retain(|s| {
+        all || !(s.state == InetSocket::TCP_LISTEN
+            || (s.protocol == Protocol::Udp && s.state == InetSocket::TCP_CLOSE))
+    });
+
+    if json {
+        let sockets = sockets
+            .iter()
+            .map(|s| {
+                format!(
+                    "{{\"protocol\":\"{}\",\"state\":\"{}\",\"local\":\"{}\",\"remote\":\"{}\",\"uid\":{},\"recv_queue\":{},\"send_queue\":{},\"bytes_acked\":{},\"bytes_received\":{}}}",
+                    s.protocol.name(),
+                    s.state_name(),
+                    s.local,
+                    s.remote,
+                    s.uid,
+                    s.recv_queue,
+                    s.send_queue,
+                    json_counter(s.bytes_acked),
+                    json_counter(s.bytes_received)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        println!("{{\"pid\":{pid},\"connections\":[{sockets}]}}");
+    } else {
+        println!(
+            "{:<5} {:<12} {:<47} {:<47} {:>12} {:>12}",
+            "Proto", "State", "Local", "Remote", "Sent", "Received"
+        );
+        for s in &sockets {
+            println!(
+                "{:<5} {:<12} {:<47} {:<47} {:>12} {:>12}",
+                s.protocol.name(),
+                s.state_name(),
+                s.local.to_string(),
+                s.remote.to_string(),
+                format_counter(s.bytes_acked),
+                format_counter(s.bytes_received)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Closes every connection still open in the network namespace given,
+/// from a thread of its own so the rest of the process stays where it is.
+/// Returns how many were closed
+pub fn close_all(netns: &File) -> anyhow::Result<usize> {
+    let netns = netns
+        .try_clone()
+        .context("Could not duplicate the namespace handle")?;
+
+    std::thread::spawn(move || {
+        if unsafe { libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .context("Could not enter the network namespace of the session");
+        }
+
+        #[cfg(feature = "runtime-libnl")]
+        nl::runtime::load()?;
+
+        let sock =
+            nl::netlink::Socket::new_sock_diag().context("Could not open a sock_diag socket")?;
+
+        let mut closed = 0;
+        for protocol in [Protocol::Tcp, Protocol::Udp] {
+            let sockets = sock
+                .get_inet_sockets(protocol)
+                .with_context(|| format!("Could not list the {} sockets", protocol.name()))?;
+
+            // Listening and unconnected sockets have no peer to keep alive,
+            // and TIME-WAIT ones are already closed
+            let open = sockets.into_iter().filter(|s| {
+                ![
+                    InetSocket::TCP_LISTEN,
+                    InetSocket::TCP_CLOSE,
+                    InetSocket::TCP_TIME_WAIT,
+                ]
+                .contains(&s.state)
+            });
+
+            for socket in open {
+                match sock.destroy_socket(protocol, socket.id()) {
+                    Ok(()) => closed += 1,
+                    // Closed on its own in the meantime
+                    Err(e) if e.code() == nl::error::Error::NLE_OBJ_NOTFOUND => {}
+                    Err(e) if e.code() == nl::error::Error::NLE_OPNOTSUPP => {
+                        anyhow::bail!("the kernel was built without CONFIG_INET_DIAG_DESTROY")
+                    }
+                    Err(e) => {
+                        return Err(e).with_context(|| {
+                            format!("Could not close {} {}", protocol.name(), socket.local)
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(closed)
+    })
+    .join()
+    .map_err(|_| anyhow::anyhow!("closing connections panicked"))?
+}
+
+/// Deletes the connection tracking entries of traffic from the address
+/// given, so NAT state for the session's connections doesn't outlive it
+pub fn forget_conntrack(source: Ipv4Addr) -> anyhow::Result<()> {
+    let output = Command::new("conntrack")
+        .args(["-D", "-s", &source.to_string()])
+        .output()
+        .context("could not run conntrack")?;
+
+    // Finding nothing to delete is reported as a failure
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && !stderr.contains("0 flow entries") {
+        anyhow::bail!("`conntrack -D -s {source}` failed: {}", stderr.trim());
+    }
+
+    Ok(())
+}
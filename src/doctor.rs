@@ -0,0 +1,341 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! The `doctor` subcommand, which checks everything a session depends on
+//! and prints a report suitable for attaching to bug reports
+
+use std::{fmt::Write, process::Command};
+
+use crate::{backend, caps, cgroup, cli, firewall, kernel, lock, naming, sysctl, tunnel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => "error",
+        }
+    }
+}
+
+/// The outcome of a single diagnostic
+#[derive(Debug)]
+struct Finding {
+    name: String,
+    status: Status,
+    detail: String,
+}
+
+impl Finding {
+    fn new(name: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Escapes a string for use inside of a JSON string literal
+pub fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn check_root() -> Finding {
+    if unsafe { libc::geteuid() } == 0 {
+        Finding::new("root", Status::Ok, "running as root")
+    } else {
+        Finding::new(
+            "root",
+            Status::Warning,
+            "not running as root; sessions need root and some checks below may be inaccurate",
+        )
+    }
+}
+
+/// Reports which iptables is installed, as the nf_tables and legacy
+/// variants keep separate rule sets that do not see each other
+fn check_iptables() -> Finding {
+    match Command::new("iptables").arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+            Finding::new("iptables", Status::Ok, version)
+        }
+        _ if firewall::Mechanism::detect().is_ok() => Finding::new(
+            "iptables",
+            Status::Warning,
+            "iptables could not be run, nft will be used instead",
+        ),
+        Ok(output) => Finding::new(
+            "iptables",
+            Status::Error,
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ),
+        Err(e) => Finding::new(
+            "iptables",
+            Status::Error,
+            format!("could not run iptables or nft: {e}"),
+        ),
+    }
+}
+
+/// Strict reverse path filtering on the outgoing interface can drop the
+/// replies to a spoofed source IP
+fn check_rp_filter(default_if: Option<&str>) -> Finding {
+    let read = |iface: &str| sysctl::read::<u8>(&format!("net/ipv4/conf/{iface}/rp_filter")).ok();
+
+    // The kernel uses the larger of the two values
+    let value = read("all")
+        .into_iter()
+        .chain(default_if.and_then(read))
+        .max();
+
+    match value {
+        None => Finding::new("rp_filter", Status::Warning, "could not read rp_filter"),
+        Some(1) => Finding::new(
+            "rp_filter",
+            Status::Warning,
+            "strict reverse path filtering is enabled, which may drop traffic when using --source-ip",
+        ),
+        Some(v) => Finding::new("rp_filter", Status::Ok, format!("rp_filter is {v}")),
+    }
+}
+
+/// Firewall rules left behind by sessions which are no longer running
+fn check_leftover_rules() -> Finding {
+    let mut stale = Vec::new();
+
+    for table in ["filter", "nat", "mangle"] {
+        let Ok(output) = Command::new("iptables").args(["-t", table, "-S"]).output() else {
+            return Finding::new(
+                "leftover firewall rules",
+                Status::Warning,
+                "could not list firewall rules",
+            );
+        };
+
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut words = line.split_ascii_whitespace();
+            let pid = words
+                .find(|w| *w == "--comment")
+                .and_then(|_| words.next())
+                .and_then(|c| firewall::session_pid(c.trim_matches('"')));
+
+            if pid.is_some_and(|pid| !lock::process_alive(pid)) {
+                stale.push(format!("{table}: {line}"));
+            }
+        }
+    }
+
+    if stale.is_empty() {
+        Finding::new("leftover firewall rules", Status::Ok, "none found")
+    } else {
+        Finding::new("leftover firewall rules", Status::Warning, stale.join("\n"))
+    }
+}
+
+/// Links named like ours whose session is no longer running
+fn check_leftover_links(backend: &dyn backend::Backend, prefix: &str) -> Finding {
+    let links = match backend.link_names() {
+        Ok(links) => links,
+        Err(e) => {
+            return Finding::new(
+                "leftover links",
+                Status::Warning,
+                format!("could not list links: {e:#}"),
+            );
+        }
+    };
+
+    let stale = links
+        .into_iter()
+        .filter(|name| {
+            naming::session_pid(prefix, name).is_some_and(|pid| !lock::process_alive(pid))
+        })
+        .collect::<Vec<_>>();
+
+    if stale.is_empty() {
+        Finding::new("leftover links", Status::Ok, "none found")
+    } else {
+        Finding::new("leftover links", Status::Warning, stale.join(", "))
+    }
+}
+
+/// Whether a tunnel subnet can still be found that collides with nothing
+fn check_subnets(backend: &dyn backend::Backend) -> Finding {
+    let taken = (|| {
+        let routes = backend.routes()?;
+        let local_addrs = backend.local_addrs()?;
+        let iface_addrs = backend.addrs()?;
+        anyhow::Ok(tunnel::taken_networks(&routes, &local_addrs, &iface_addrs))
+    })();
+
+    let addressing = tunnel::Addressing::Net30;
+    match taken.and_then(|taken| tunnel::find_range(&taken, addressing, tunnel::Net::default())) {
+        Ok(range) => Finding::new(
+            "tunnel subnet",
+            Status::Ok,
+            format!(
+                "{range}/{} is free for the tunnel",
+                addressing.block_prefixlen()
+            ),
+        ),
+        Err(e) => Finding::new("tunnel subnet", Status::Error, format!("{e:#}")),
+    }
+}
+
+/// Runs `download-shell doctor`, printing a report of every check
+pub fn run(args: cli::DoctorArgs) -> anyhow::Result<()> {
+    let cli::DoctorArgs {
+        json,
+        legacy_kernel,
+        link_prefix,
+    } = args;
+
+    let features = kernel::Features::detect(legacy_kernel);
+
+    let mut findings = vec![check_root()];
+
+    if let Some(outer) = cgroup::enclosing_session() {
+        findings.push(Finding::new(
+            "nesting",
+            Status::Ok,
+            format!("inside of the session of download-shell {outer}, whose namespace is checked"),
+        ));
+    }
+
+    findings.push(Finding::new(
+        "kernel",
+        Status::Ok,
+        match features.version {
+            Some(version) => format!("{version}"),
+            None => "could not determine the kernel version".to_owned(),
+        },
+    ));
+
+    // Without either firewall tool, the iptables modules are checked, and
+    // the missing tool itself is reported below
+    let mechanism = firewall::Mechanism::detect().unwrap_or(firewall::Mechanism::Iptables);
+    findings.extend(caps::probe(&features, mechanism).into_iter().map(|check| {
+        if check.ok {
+            Finding::new(check.name, Status::Ok, "available")
+        } else {
+            Finding::new(check.name, Status::Error, check.hint)
+        }
+    }));
+
+    findings.push(check_iptables());
+
+    findings.push(match backend::NetlinkBackend::new(false) {
+        Ok(_) => Finding::new("libnl", Status::Ok, "netlink socket opened"),
+        Err(e) => Finding::new(
+            "libnl",
+            Status::Warning,
+            format!("{e:#}; the ip utility will be used instead"),
+        ),
+    });
+
+    let backend = match backend::open(None, false) {
+        Ok(backend) => Some(backend),
+        Err(e) => {
+            findings.push(Finding::new(
+                "network backend",
+                Status::Error,
+                format!("{e:#}"),
+            ));
+            None
+        }
+    };
+
+    let default_if = backend
+        .as_ref()
+        .and_then(|b| b.routes().ok())
+        .and_then(|routes| routes.into_iter().find(|r| r.prefixlen == 0))
+        .and_then(|r| r.dev);
+
+    findings.push(match &default_if {
+        Some(dev) => Finding::new("default route", Status::Ok, format!("via {dev}")),
+        None => Finding::new("default route", Status::Error, "no default route found"),
+    });
+
+    findings.push(check_rp_filter(default_if.as_deref()));
+
+    if let Some(backend) = &backend {
+        findings.push(check_subnets(backend.as_ref()));
+        findings.push(check_leftover_links(backend.as_ref(), &link_prefix));
+    }
+
+    findings.push(check_leftover_rules());
+
+    let healthy = findings.iter().all(|f| f.status != Status::Error);
+
+    if json {
+        let checks = findings
+            .iter()
+            .map(|f| {
+                format!(
+                    "{{\"name\":\"{}\",\"status\":\"{}\",\"detail\":\"{}\"}}",
+                    json_escape(&f.name),
+                    f.status.as_str(),
+                    json_escape(&f.detail)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        println!(
+            "{{\"version\":\"{}\",\"healthy\":{healthy},\"checks\":[{checks}]}}",
+            env!("CARGO_PKG_VERSION")
+        );
+    } else {
+        println!("download-shell {}", env!("CARGO_PKG_VERSION"));
+        for finding in &findings {
+            let mut lines = finding.detail.lines();
+            println!(
+                "[{:^7}] {}: {}",
+                finding.status.as_str(),
+                finding.name,
+                lines.next().unwrap_or_default()
+            );
+            for line in lines {
+                println!("          {line}");
+            }
+        }
+    }
+
+    if !healthy {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
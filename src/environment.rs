@@ -0,0 +1,94 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! The variables given to the program with `--env` and `--env-file`, on top
+//! of or instead of the environment download-shell was started with
+
+use std::{ffi::OsString, path::Path, str::FromStr};
+
+use anyhow::Context;
+
+/// Variables passed on to the program even with `--clear-env`, without
+/// which a shell would be of little use
+pub const KEPT: [&str; 2] = ["PATH", "TERM"];
+
+/// A variable given with `--env`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Var {
+    /// `KEY=VALUE`
+    Set(String, String),
+    /// `KEY`, taking the value of the variable from the environment of
+    /// download-shell, for use with `--clear-env`
+    Inherit(String),
+}
+
+impl Var {
+    /// The name and value of the variable, if it has one
+    pub fn resolve(&self) -> Option<(OsString, OsString)> {
+        match self {
+            Var::Set(key, value) => Some((key.into(), value.into())),
+            Var::Inherit(key) => std::env::var_os(key).map(|value| (key.into(), value)),
+        }
+    }
+}
+
+fn check_name(key: &str) -> anyhow::Result<()> {
+    if key.is_empty() || key.contains(['=', '\0']) {
+        anyhow::bail!("invalid variable name '{key}'");
+    }
+    Ok(())
+}
+
+impl FromStr for Var {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('\0') {
+            anyhow::bail!("variables cannot contain NUL bytes");
+        }
+
+        match s.split_once('=') {
+            Some((key, value)) => {
+                check_name(key)?;
+                Ok(Var::Set(key.to_owned(), value.to_owned()))
+            }
+            None => {
+                check_name(s)?;
+                Ok(Var::Inherit(s.to_owned()))
+            }
+        }
+    }
+}
+
+/// Reads the `KEY=VALUE` lines of a file, skipping blank lines and those
+/// starting with `#`
+pub fn read_file(path: &Path) -> anyhow::Result<Vec<(OsString, OsString)>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read {}", path.display()))?;
+
+    text.lines()
+        .enumerate()
+        .map(|(number, line)| (number, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            match line.parse::<Var>() {
+                Ok(Var::Set(key, value)) => Ok((key.into(), value.into())),
+                Ok(Var::Inherit(_)) => Err(anyhow::anyhow!("expected KEY=VALUE")),
+                Err(e) => Err(e),
+            }
+            .with_context(|| format!("{}, line {}", path.display(), number + 1))
+        })
+        .collect()
+}
@@ -0,0 +1,615 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! The firewall rules of a session, written in iptables syntax and applied
+//! either with iptables or, on hosts which only have nftables, translated
+//! into a table of nft rules belonging to the session
+
+use std::{process::Command, str::FromStr};
+
+use anyhow::Context;
+
+use crate::record::Recorder;
+
+/// The tool used to change the firewall of the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mechanism {
+    /// iptables, either the legacy or the nf_tables variant
+    Iptables,
+    /// nft, with the rules of each session kept in a table of their own
+    Nft,
+}
+
+impl Mechanism {
+    /// Finds a way to change the firewall, preferring iptables. This is done
+    /// before anything is changed, so that a host without either tool isn't
+    /// left with a half configured session
+    pub fn detect() -> anyhow::Result<Self> {
+        let runs = |program: &str| {
+            Command::new(program)
+                .arg("--version")
+                .output()
+                .is_ok_and(|o| o.status.success())
+        };
+
+        if runs("iptables") {
+            Ok(Self::Iptables)
+        } else if runs("nft") {
+            Ok(Self::Nft)
+        } else {
+            anyhow::bail!(
+                "Neither iptables nor nft could be run, and one of them is needed to forward the traffic of the session; install iptables or nftables"
+            )
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Iptables => "iptables",
+            Self::Nft => "nft",
+        }
+    }
+}
+
+/// How source NAT picks the port a connection is translated to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortRandomization {
+    /// From a hash of the connection, with iptables --random
+    Hash,
+    /// From a random number for every connection, with --random-fully
+    Fully,
+}
+
+impl PortRandomization {
+    pub fn iptables(self) -> &'static str {
+        match self {
+            Self::Hash => "--random",
+            Self::Fully => "--random-fully",
+        }
+    }
+}
+
+impl FromStr for PortRandomization {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hash" => Ok(Self::Hash),
+            "fully" => Ok(Self::Fully),
+            _ => anyhow::bail!("unknown port randomization '{s}', expected one of: hash, fully"),
+        }
+    }
+}
+
+/// The address family of a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Family {
+    Ipv4,
+    Ipv6,
+}
+
+impl Family {
+    fn iptables(self) -> &'static str {
+        match self {
+            Family::Ipv4 => "iptables",
+            Family::Ipv6 => "ip6tables",
+        }
+    }
+
+    fn nft(self) -> &'static str {
+        match self {
+            Family::Ipv4 => "ip",
+            Family::Ipv6 => "ip6",
+        }
+    }
+}
+
+/// The rules added for a session, identified by a comment
+pub struct Firewall {
+    mechanism: Mechanism,
+    comment: String,
+    /// The family, table and chain of every rule added, in iptables terms
+    chains: Vec<(Family, String, String)>,
+    /// Only record the rules, for --dry-run
+    dry_run: bool,
+}
+
+impl Firewall {
+    /// With `dry_run`, rules are recorded but never applied
+    pub fn new(mechanism: Mechanism, comment: &str, dry_run: bool) -> Self {
+        Self {
+            mechanism,
+            comment: comment.to_owned(),
+            chains: Vec::new(),
+            dry_run,
+        }
+    }
+
+    /// Finds the rules sessions left in the firewall, in iptables and in nft
+    /// alike, so that [`Firewall::clean`] can remove them after the session
+    /// itself is gone. Tools which can't be run are skipped
+    pub fn leftovers() -> Vec<Self> {
+        let mut leftovers = Vec::<Self>::new();
+        let mut found = |mechanism, comment: &str, family, table: &str, chain: &str| {
+            let index = match leftovers
+                .iter()
+                .position(|f| f.mechanism == mechanism && f.comment == comment)
+            {
+                Some(index) => index,
+                None => {
+                    leftovers.push(Self::new(mechanism, comment, false));
+                    leftovers.len() - 1
+                }
+            };
+            leftovers[index]
+                .chains
+                .push((family, table.to_owned(), chain.to_owned()));
+        };
+
+        for family in [Family::Ipv4, Family::Ipv6] {
+            for table in LEFTOVER_TABLES {
+                let Ok(output) = Command::new(family.iptables())
+                    .args(["-t", table, "-S"])
+                    .output()
+                else {
+                    continue;
+                };
+
+                // e.g. `-A POSTROUTING -s 172.16.0.2/32 -m comment --comment dlsh1234 -j SNAT ...`
+                for line in String::from_utf8_lossy(&output.stdout).lines() {
+                    let mut words = line.split_ascii_whitespace();
+                    let Some(chain) = words.next().filter(|w| *w == "-A").and(words.next()) else {
+                        continue;
+                    };
+                    let comment = words
+                        .find(|w| *w == "--comment")
+                        .and_then(|_| words.next())
+                        .map(|c| c.trim_matches('"'))
+                        .filter(|c| session_pid(c).is_some());
+                    if let Some(comment) = comment {
+                        found(Mechanism::Iptables, comment, family, table, chain);
+                    }
+                }
+            }
+        }
+
+        // e.g. `table ip dlsh1234`
+        if let Ok(output) = Command::new("nft").args(["list", "tables"]).output() {
+            for line in String::from_utf8_lossy(&output.stdout).lines() {
+                let mut words = line.split_ascii_whitespace().skip(1);
+                let family = match words.next() {
+                    Some("ip") => Family::Ipv4,
+                    Some("ip6") => Family::Ipv6,
+                    _ => continue,
+                };
+                if let Some(comment) = words.next().filter(|c| session_pid(c).is_some()) {
+                    // The table is removed whole, whatever its chains
+                    found(Mechanism::Nft, comment, family, "", "");
+                }
+            }
+        }
+
+        leftovers
+    }
+
+    /// The comment the rules of the session carry
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    fn run(&self, program: &str, args: &[&str]) -> anyhow::Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        run(program, args)
+    }
+
+    /// Adds a rule, given as the arguments iptables would take to append or
+    /// insert it. The rule has to carry the comment of the session
+    pub fn add(&mut self, record: &mut Recorder, rule: &[&str]) -> anyhow::Result<()> {
+        self.add_rule(Family::Ipv4, record, rule)
+    }
+
+    /// Adds an IPv6 rule, given as the arguments ip6tables would take
+    pub fn add6(&mut self, record: &mut Recorder, rule: &[&str]) -> anyhow::Result<()> {
+        self.add_rule(Family::Ipv6, record, rule)
+    }
+
+    fn add_rule(
+        &mut self,
+        family: Family,
+        record: &mut Recorder,
+        rule: &[&str],
+    ) -> anyhow::Result<()> {
+        let table = option(rule, "-t").unwrap_or("filter");
+        let chain = option(rule, "-A")
+            .or(option(rule, "-I"))
+            .ok_or(anyhow::anyhow!("firewall rule without a chain"))?;
+
+        match self.mechanism {
+            Mechanism::Iptables => {
+                record.iptables(family.iptables(), rule);
+                self.run(family.iptables(), rule)?;
+            }
+            Mechanism::Nft => {
+                let nft_family = family.nft();
+                if !self.chains.iter().any(|(f, _, _)| *f == family) {
+                    let add_table = ["add", "table", nft_family, &self.comment];
+                    record.command(
+                        &[&["nft"], &add_table[..]].concat(),
+                        Some(&["nft", "delete", "table", nft_family, &self.comment]),
+                    );
+                    self.run("nft", &add_table)?;
+                }
+
+                let name = format!("{table}-{chain}").to_lowercase();
+                if !self
+                    .chains
+                    .iter()
+                    .any(|(f, t, c)| *f == family && t == table && c == chain)
+                {
+                    let hook = base_chain(table, chain)?;
+                    let add_chain = ["add", "chain", nft_family, &self.comment, &name, &hook];
+                    record.command(&[&["nft"], &add_chain[..]].concat(), None);
+                    self.run("nft", &add_chain)?;
+                }
+
+                let expr = translate(family, rule)?;
+                let mut add_rule = vec!["add", "rule", nft_family, &self.comment, &name];
+                add_rule.extend(expr.iter().map(String::as_str));
+                record.command(&[&["nft"], &add_rule[..]].concat(), None);
+                self.run("nft", &add_rule)?;
+            }
+        }
+
+        self.chains
+            .push((family, table.to_owned(), chain.to_owned()));
+        Ok(())
+    }
+
+    /// Removes every rule of the session
+    pub fn clean(&self) -> anyhow::Result<()> {
+        let mut chains = self.chains.clone();
+        chains.sort_unstable();
+        chains.dedup();
+
+        match self.mechanism {
+            Mechanism::Iptables => {
+                for (family, table, chain) in chains {
+                    clean_iptables(family.iptables(), &self.comment, &table, &chain)
+                        .with_context(|| format!("could not clear the {table} {chain} rules"))?;
+                }
+            }
+            Mechanism::Nft => {
+                chains.dedup_by_key(|(family, _, _)| *family);
+                for (family, _, _) in chains {
+                    run("nft", &["delete", "table", family.nft(), &self.comment])
+                        .context("could not delete the session's nft table")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The tables sessions add rules to
+const LEFTOVER_TABLES: [&str; 3] = ["filter", "nat", "mangle"];
+
+/// Recovers the pid of the session from the comment of its rules, which
+/// named sessions follow with their name
+pub fn session_pid(comment: &str) -> Option<libc::pid_t> {
+    let id = comment.strip_prefix("dlsh")?;
+    id.split_once('-').map_or(id, |(pid, _)| pid).parse().ok()
+}
+
+/// The value following an option in a rule
+fn option<'a>(rule: &[&'a str], name: &str) -> Option<&'a str> {
+    rule.iter()
+        .position(|a| *a == name)
+        .and_then(|i| rule.get(i + 1).copied())
+}
+
+fn run(program: &str, args: &[&str]) -> anyhow::Result<()> {
+    log::debug!("running {program} {}", args.join(" "));
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("could not run {program}"))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "{program} {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// The nft base chain standing in for an iptables chain, at the priority
+/// of the iptables table
+fn base_chain(table: &str, chain: &str) -> anyhow::Result<String> {
+    let (kind, priority) = match table {
+        "mangle" => ("filter", -150),
+        "nat" => ("nat", if chain == "PREROUTING" { -100 } else { 100 }),
+        "filter" => ("filter", 0),
+        _ => anyhow::bail!("no nft equivalent for the {table} table"),
+    };
+
+    Ok(format!(
+        "{{ type {kind} hook {} priority {priority} ; }}",
+        chain.to_lowercase()
+    ))
+}
+
+/// Translates the matches and target of an iptables rule into an nft rule.
+/// Only the options download-shell uses are understood
+fn translate(family: Family, rule: &[&str]) -> anyhow::Result<Vec<String>> {
+    let mut expr = Vec::new();
+    let mut comment = None;
+    let mut protocol = None;
+    let mut every = None;
+    let mut nat_flags = Vec::new();
+    let mut args = rule.iter().copied().peekable();
+
+    while let Some(arg) = args.next() {
+        match arg {
+            "-t" => {
+                value(&mut args, arg)?;
+            }
+            "-A" | "-I" => {
+                value(&mut args, arg)?;
+                args.next_if(|a| a.bytes().all(|b| b.is_ascii_digit()));
+            }
+            // Matches are loaded implicitly by nft
+            "-m" => {
+                value(&mut args, arg)?;
+            }
+            "-i" => expr.push(format!("iifname \"{}\"", value(&mut args, arg)?)),
+            "-o" => expr.push(format!("oifname \"{}\"", value(&mut args, arg)?)),
+            "-s" => expr.push(format!("{} saddr {}", family.nft(), value(&mut args, arg)?)),
+            "-d" => expr.push(format!("{} daddr {}", family.nft(), value(&mut args, arg)?)),
+            "-p" => protocol = Some(value(&mut args, arg)?),
+            "--dport" | "--sport" => {
+                let protocol = protocol
+                    .as_deref()
+                    .ok_or(anyhow::anyhow!("{arg} without a protocol"))?;
+                expr.push(format!(
+                    "{protocol} {} {}",
+                    &arg[2..],
+                    value(&mut args, arg)?
+                ));
+            }
+            "--mark" => {
+                let (mark, mask) = split_mark(&value(&mut args, arg)?);
+                expr.push(format!("meta mark and {mask} == {mark}"));
+            }
+            // Every Nth packet, counted by the rule
+            "--mode" => {
+                let mode = value(&mut args, arg)?;
+                if mode != "nth" {
+                    anyhow::bail!("no nft equivalent for the {mode} statistic mode");
+                }
+            }
+            "--every" => every = Some(value(&mut args, arg)?),
+            "--packet" => expr.push(format!(
+                "numgen inc mod {} == {}",
+                every
+                    .take()
+                    .ok_or(anyhow::anyhow!("--packet without --every"))?,
+                value(&mut args, arg)?
+            )),
+            "--ctstate" => expr.push(format!(
+                "ct state {}",
+                value(&mut args, arg)?.to_lowercase()
+            )),
+            "--comment" => comment = Some(value(&mut args, arg)?),
+            "-j" => match value(&mut args, arg)?.as_str() {
+                "ACCEPT" => expr.push("accept".to_owned()),
+                "MASQUERADE" => expr.push("masquerade".to_owned()),
+                "SNAT" | "DNAT" => {}
+                "MARK" => {}
+                target => anyhow::bail!("no nft equivalent for the {target} target"),
+            },
+            "--to-source" => expr.push(format!("snat to {}", value(&mut args, arg)?)),
+            "--to-destination" => expr.push(format!("dnat to {}", value(&mut args, arg)?)),
+            "--random" => nat_flags.push("random"),
+            "--random-fully" => nat_flags.push("fully-random"),
+            "--persistent" => nat_flags.push("persistent"),
+            "--set-xmark" => {
+                let (mark, mask) = split_mark(&value(&mut args, arg)?);
+                expr.push(format!(
+                    "meta mark set meta mark and {:#x} xor {mark}",
+                    !u32::from_str_radix(mask.trim_start_matches("0x"), 16)?
+                ));
+            }
+            arg => anyhow::bail!("no nft equivalent for the iptables option {arg}"),
+        }
+    }
+
+    // e.g. `snat to 10.0.0.5 fully-random,persistent`
+    if !nat_flags.is_empty() {
+        let statement = expr
+            .iter_mut()
+            .find(|e| e.starts_with("snat") || e.starts_with("dnat") || e == &"masquerade")
+            .ok_or(anyhow::anyhow!("NAT flags without a NAT target"))?;
+        *statement += &format!(" {}", nat_flags.join(","));
+    }
+
+    // Statements like snat have to follow every match
+    if let Some(protocol) = protocol.filter(|_| !expr.iter().any(|e| e.contains(" dport "))) {
+        expr.insert(0, format!("meta l4proto {protocol}"));
+    }
+    if let Some(comment) = comment {
+        expr.push(format!("comment \"{comment}\""));
+    }
+
+    Ok(expr)
+}
+
+/// The value of an option, taken from the arguments following it
+fn value<'a>(args: &mut impl Iterator<Item = &'a str>, name: &str) -> anyhow::Result<String> {
+    args.next()
+        .map(str::to_owned)
+        .ok_or(anyhow::anyhow!("{name} without a value"))
+}
+
+/// Splits `VALUE/MASK`, as written by [`crate::fwmark::Fwmark`]
+fn split_mark(mark: &str) -> (String, String) {
+    match mark.split_once('/') {
+        Some((mark, mask)) => (mark.to_owned(), mask.to_owned()),
+        None => (mark.to_owned(), "0xffffffff".to_owned()),
+    }
+}
+
+/// Deletes the rules carrying the comment from a chain
+fn clean_iptables(program: &str, comment: &str, table: &str, chain: &str) -> anyhow::Result<()> {
+    let current_rules = Command::new(program)
+        .args(["-t", table, "--line-numbers", "-vn", "-L", chain])
+        .output()
+        .context("could not list firewall rules")?
+        .stdout;
+
+    let output_utf8 = std::str::from_utf8(&current_rules)?;
+
+    let rule_nums = output_utf8
+        .lines()
+        .filter(|l| l.contains(&format!("/* {comment} */")))
+        .map(|l| {
+            l.split_ascii_whitespace()
+                .next()
+                .ok_or(anyhow::anyhow!("warning: could not clear out firewall rules from the {table} table: could not parse rule number"))?
+                .parse::<u16>()
+                .map_err(anyhow::Error::from)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if rule_nums.is_empty() {
+        log::warn!(
+            "could not clear out firewall rules from the {table} table: could not find rule"
+        );
+        return Ok(());
+    }
+
+    // Delete from the bottom up so the remaining numbers stay valid
+    for rule_num in rule_nums.into_iter().rev() {
+        log::debug!("running {program} -t {table} -D {chain} {rule_num}");
+        Command::new(program)
+            .args(["-t", table, "-D", chain, &format!("{rule_num}")])
+            .output()
+            .context("could not delete firewall rule")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_xmark_keeps_the_bits_outside_of_the_mask() {
+        let rule = [
+            "-t",
+            "mangle",
+            "-A",
+            "PREROUTING",
+            "-i",
+            "dlsh0",
+            "-j",
+            "MARK",
+            "--set-xmark",
+            "0x100/0xff00",
+        ];
+        assert_eq!(
+            translate(Family::Ipv4, &rule).unwrap(),
+            [
+                "iifname \"dlsh0\"",
+                "meta mark set meta mark and 0xffff00ff xor 0x100"
+            ]
+        );
+    }
+
+    #[test]
+    fn nat_flags_follow_the_nat_statement() {
+        let rule = [
+            "-t",
+            "nat",
+            "-A",
+            "POSTROUTING",
+            "-j",
+            "SNAT",
+            "--to-source",
+            "192.0.2.7",
+            "--random-fully",
+            "--persistent",
+            "-m",
+            "comment",
+            "--comment",
+            "dlsh1",
+        ];
+        assert_eq!(
+            translate(Family::Ipv4, &rule).unwrap(),
+            [
+                "snat to 192.0.2.7 fully-random,persistent",
+                "comment \"dlsh1\""
+            ]
+        );
+
+        let rule = ["-o", "eth0", "-j", "MASQUERADE", "--random"];
+        assert_eq!(
+            translate(Family::Ipv6, &rule).unwrap(),
+            ["oifname \"eth0\"", "masquerade random"]
+        );
+
+        assert!(translate(Family::Ipv4, &["-j", "ACCEPT", "--random"]).is_err());
+    }
+
+    #[test]
+    fn statistic_nth_becomes_numgen() {
+        let rule = [
+            "-m",
+            "mark",
+            "--mark",
+            "0x100/0xff00",
+            "-m",
+            "statistic",
+            "--mode",
+            "nth",
+            "--every",
+            "3",
+            "--packet",
+            "0",
+            "-j",
+            "SNAT",
+            "--to-source",
+            "192.0.2.7",
+        ];
+        assert_eq!(
+            translate(Family::Ipv4, &rule).unwrap(),
+            [
+                "meta mark and 0xff00 == 0x100",
+                "numgen inc mod 3 == 0",
+                "snat to 192.0.2.7"
+            ]
+        );
+
+        let rule = ["-m", "statistic", "--mode", "random", "-j", "ACCEPT"];
+        assert!(translate(Family::Ipv4, &rule).is_err());
+    }
+}
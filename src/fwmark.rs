@@ -0,0 +1,73 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Firewall marks used to tell the traffic of a session apart from
+//! everything else the host forwards
+
+use std::{fmt::Display, str::FromStr};
+
+/// A netfilter mark and the bits of the mark it occupies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fwmark {
+    pub value: u32,
+    pub mask: u32,
+}
+
+impl Fwmark {
+    /// The mark used for a session when none is specified. The pid is
+    /// included so that concurrent sessions get distinct marks; it fits in
+    /// the low 22 bits, as pid_max cannot exceed 2^22
+    pub fn for_session(pid: libc::pid_t) -> Self {
+        Self {
+            value: 0xd1000000 | (pid as u32 & 0x00ffffff),
+            mask: 0xffffffff,
+        }
+    }
+}
+
+fn parse_u32(s: &str) -> Result<u32, std::num::ParseIntError> {
+    match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+impl FromStr for Fwmark {
+    type Err = anyhow::Error;
+
+    /// Parses `VALUE` or `VALUE/MASK`, in decimal or hex with a 0x prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, mask) = match s.split_once('/') {
+            Some((value, mask)) => (parse_u32(value)?, parse_u32(mask)?),
+            None => (parse_u32(s)?, 0xffffffff),
+        };
+
+        if value == 0 {
+            anyhow::bail!("the mark cannot be 0, as that is what unmarked traffic carries");
+        }
+        if value & !mask != 0 {
+            anyhow::bail!("the mark 0x{value:x} has bits outside of the mask 0x{mask:x}");
+        }
+
+        Ok(Self { value, mask })
+    }
+}
+
+impl Display for Fwmark {
+    /// Formats the mark the way iptables expects it
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:x}/0x{:x}", self.value, self.mask)
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! User supplied commands run at fixed points of a session's life, so that
+//! sites can add steps of their own such as logging into a VPN or sending a
+//! notification
+
+use std::{
+    fs::File,
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    process::Command,
+};
+
+use anyhow::Context;
+
+/// The point in the session a hook runs at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Before anything on the host is changed
+    PreUp,
+    /// Once the tunnel is configured, before the program starts
+    PostUp,
+    /// After the program exits, while the tunnel still exists
+    PreDown,
+    /// After everything has been cleaned up
+    PostDown,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::PreUp => "pre-up",
+            Stage::PostUp => "post-up",
+            Stage::PreDown => "pre-down",
+            Stage::PostDown => "post-down",
+        }
+    }
+}
+
+/// Where a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Place {
+    Host,
+    /// In the network namespace of the session
+    Namespace,
+}
+
+/// A shell command to run at a stage
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub stage: Stage,
+    pub place: Place,
+    pub command: String,
+}
+
+impl Hook {
+    /// Parses the argument of a hook option. Commands prefixed with `ns:`
+    /// run inside of the namespace, and those optionally prefixed with
+    /// `host:` on the host
+    pub fn parse(stage: Stage, spec: &str) -> anyhow::Result<Self> {
+        let (place, command) = match spec.split_once(':') {
+            Some(("ns", command)) => (Place::Namespace, command),
+            Some(("host", command)) => (Place::Host, command),
+            _ => (Place::Host, spec),
+        };
+
+        if place == Place::Namespace && matches!(stage, Stage::PreUp | Stage::PostDown) {
+            anyhow::bail!(
+                "{} hooks cannot run in the namespace, as it does not exist yet or anymore",
+                stage.name()
+            );
+        }
+
+        Ok(Self {
+            stage,
+            place,
+            command: command.to_owned(),
+        })
+    }
+}
+
+/// Describes the session to hooks through `DLSH_*` environment variables
+#[derive(Debug, Clone, Default)]
+pub struct Env(Vec<(String, String)>);
+
+impl Env {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a variable, named without the `DLSH_` prefix
+    pub fn set(&mut self, name: &str, value: impl ToString) -> &mut Self {
+        self.0.push((format!("DLSH_{name}"), value.to_string()));
+        self
+    }
+}
+
+/// Runs the hooks of the stage which are meant for the place given, in the
+/// order they were specified. `netns` is a handle on the session's network
+/// namespace, which namespace hooks are run in; when it is `None` they are
+/// run in the namespace of the caller
+pub fn run(
+    hooks: &[Hook],
+    stage: Stage,
+    place: Place,
+    env: &Env,
+    netns: Option<&File>,
+) -> anyhow::Result<()> {
+    for hook in hooks
+        .iter()
+        .filter(|h| h.stage == stage && h.place == place)
+    {
+        log::debug!("running the {} hook: {}", stage.name(), hook.command);
+        let mut command = Command::new("/bin/sh");
+        command
+            .args(["-c", &hook.command])
+            .envs(env.0.iter().map(|(k, v)| (k, v)))
+            .env("DLSH_STAGE", stage.name());
+
+        if let Some(netns) = netns.filter(|_| place == Place::Namespace) {
+            let fd = netns.as_raw_fd();
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::setns(fd, libc::CLONE_NEWNET) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        let status = command
+            .status()
+            .with_context(|| format!("could not run {} hook `{}`", stage.name(), hook.command))?;
+
+        if !status.success() {
+            anyhow::bail!(
+                "{} hook `{}` failed with {status}",
+                stage.name(),
+                hook.command
+            );
+        }
+    }
+
+    Ok(())
+}
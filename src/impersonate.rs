@@ -0,0 +1,100 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Taking over both the address and the hardware address of a host on the
+//! LAN which is offline, for `--impersonate`
+//!
+//! Rather than being NATed on the host, the session gets a macvlan on the
+//! egress interface carrying the identity, and its traffic leaves through
+//! that directly. `--mac` puts a source IP on the LAN the same way, with a
+//! hardware address of its own
+
+use std::net::Ipv4Addr;
+
+use anyhow::Context;
+use nl::route::MacAddr;
+
+use crate::{
+    arp, autoip,
+    backend::{Backend, RouteEntry},
+};
+
+/// Everything needed to configure the macvlan inside of the namespace
+#[derive(Debug, Clone, Copy)]
+pub struct Identity {
+    pub ip: Ipv4Addr,
+    pub mac: MacAddr,
+    pub prefixlen: u8,
+    pub broadcast: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>,
+}
+
+/// Finds the hardware address the host was last seen with, unless one is
+/// given, and makes sure that it doesn't answer for its address any more
+pub fn prepare(
+    ip: Ipv4Addr,
+    mac: Option<MacAddr>,
+    ifname: &str,
+    routes: &[RouteEntry],
+    local_addrs: &[Ipv4Addr],
+    backend: &dyn Backend,
+) -> anyhow::Result<Identity> {
+    if local_addrs.contains(&ip) {
+        anyhow::bail!("{ip} is assigned to this host");
+    }
+
+    let subnet = routes
+        .iter()
+        .filter(|r| r.dev.as_deref() == Some(ifname) && r.gateway.is_none() && r.prefixlen > 0)
+        .find(|r| {
+            let mask = u32::MAX.checked_shl(32 - r.prefixlen as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(r.dst) & mask
+        })
+        .ok_or_else(|| anyhow::anyhow!("{ip} is not on a subnet attached to {ifname}"))?;
+    let mask = u32::MAX
+        .checked_shl(32 - subnet.prefixlen as u32)
+        .unwrap_or(0);
+
+    let mac = match mac {
+        Some(mac) => mac,
+        None => backend.neighbour(ifname, ip)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "the host has no record of the hardware address of {ip}; it has to have been seen on the LAN recently"
+            )
+        })?,
+    };
+
+    let prober =
+        arp::Prober::open(ifname).with_context(|| format!("could not open {ifname} to probe"))?;
+    let answers = prober
+        .scan(&[ip], autoip::VERIFY_PROBES, autoip::VERIFY_WAIT)
+        .with_context(|| format!("could not probe {ip}"))?;
+    if let Some(owner) = answers.get(&ip) {
+        anyhow::bail!("{ip} is still online at {owner}");
+    }
+
+    Ok(Identity {
+        ip,
+        mac,
+        prefixlen: subnet.prefixlen,
+        broadcast: Ipv4Addr::from(u32::from(ip) | !mask),
+        gateway: routes
+            .iter()
+            .filter(|r| r.dev.as_deref() == Some(ifname))
+            .filter_map(|r| Some((r.prefixlen, r.gateway?)))
+            .min()
+            .map(|(_, gateway)| gateway),
+    })
+}
@@ -0,0 +1,105 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Detects the version of the running kernel and decides which of the
+//! newer kernel interfaces can be relied upon
+
+use std::{ffi::CStr, fmt::Display};
+
+/// A kernel release, e.g. 3.10.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses a release string as reported by uname, e.g. `3.10.0-1160.el7.x86_64`
+    pub fn parse(release: &str) -> Option<Self> {
+        let mut parts = release.split(|c: char| !c.is_ascii_digit());
+
+        Some(Self {
+            major: parts.next()?.parse().ok()?,
+            minor: parts.next()?.parse().ok()?,
+            patch: parts.next().and_then(|p| p.parse().ok()).unwrap_or(0),
+        })
+    }
+
+    /// Returns the version of the running kernel
+    pub fn current() -> Option<Self> {
+        let mut uname: libc::utsname = unsafe { std::mem::zeroed() };
+        if unsafe { libc::uname(&mut uname) } != 0 {
+            return None;
+        }
+
+        let release = unsafe { CStr::from_ptr(uname.release.as_ptr()) };
+        Self::parse(&release.to_string_lossy())
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The kernel interfaces which are not available on every kernel
+/// download-shell supports, and so need an older fallback
+#[derive(Debug, Clone, Copy)]
+pub struct Features {
+    pub version: Option<Version>,
+    /// /proc/[pid]/ns/net exists (3.0)
+    pub netns_file: bool,
+    /// /proc/[pid]/ns/mnt exists (3.8)
+    pub mntns_file: bool,
+    /// The peer of a veth can be placed in another namespace as the pair is
+    /// created, instead of being moved afterwards (2.6.33)
+    pub veth_peer_netns: bool,
+    /// Routes can use standalone nexthop objects and groups (5.3)
+    pub nexthop_objects: bool,
+}
+
+impl Features {
+    /// Determines the available features from the running kernel. In legacy
+    /// mode every feature is treated as unavailable, forcing the oldest code
+    /// paths regardless of the kernel version
+    pub fn detect(legacy: bool) -> Self {
+        let version = Version::current();
+
+        let at_least = |major, minor, patch| {
+            !legacy
+                && version
+                    .map(|v| v >= Version::new(major, minor, patch))
+                    .unwrap_or(false)
+        };
+
+        Self {
+            version,
+            netns_file: at_least(3, 0, 0),
+            mntns_file: at_least(3, 8, 0),
+            veth_peer_netns: at_least(2, 6, 33),
+            nexthop_objects: at_least(5, 3, 0),
+        }
+    }
+}
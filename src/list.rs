@@ -0,0 +1,150 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+//! `download-shell list`, which shows the sessions on this host: those which
+//! described themselves in the state directory, along with any others whose
+//! links or firewall rules are around
+
+use anyhow::Context;
+
+use crate::{
+    backend, cli,
+    doctor::json_escape,
+    firewall::{self, Firewall},
+    lock, naming,
+    report::{self, Report},
+};
+
+/// A session found on the host
+struct Session {
+    pid: libc::pid_t,
+    /// What the session described itself as, once it was up
+    report: Option<Report>,
+    alive: bool,
+}
+
+impl Session {
+    fn state(&self) -> &'static str {
+        match (self.alive, &self.report) {
+            (true, Some(_)) => "running",
+            (true, None) => "starting",
+            (false, _) => "killed",
+        }
+    }
+}
+
+/// How long a session has been up, e.g. `2h05m`
+fn uptime(secs: u64) -> String {
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{mins}m{:02}s", secs % 60),
+        3600..86400 => format!("{hours}h{mins:02}m"),
+        _ => format!("{days}d{hours:02}h"),
+    }
+}
+
+fn json_string(value: Option<String>) -> String {
+    value.map_or_else(|| "null".to_owned(), |v| format!("\"{}\"", json_escape(&v)))
+}
+
+/// Runs `download-shell list`
+pub fn run(args: cli::ListArgs) -> anyhow::Result<()> {
+    let cli::ListArgs { json, link_prefix } = args;
+
+    let mut sessions = report::saved()
+        .into_iter()
+        .map(|report| Session {
+            pid: report.pid,
+            alive: lock::process_alive(report.pid),
+            report: Some(report),
+        })
+        .collect::<Vec<_>>();
+
+    // Sessions which are still setting up, or were killed before they could
+    // describe themselves, only show in what they changed on the host
+    let backend = backend::open(None, false)?;
+    let link_pids = backend
+        .link_names()
+        .context("Could not list links")?
+        .iter()
+        .filter_map(|name| naming::session_pid(&link_prefix, name))
+        .collect::<Vec<_>>();
+    let rule_pids = Firewall::leftovers()
+        .iter()
+        .filter_map(|f| firewall::session_pid(f.comment()))
+        .collect::<Vec<_>>();
+    for pid in link_pids.into_iter().chain(rule_pids) {
+        if !sessions.iter().any(|s| s.pid == pid) {
+            sessions.push(Session {
+                pid,
+                report: None,
+                alive: lock::process_alive(pid),
+            });
+        }
+    }
+    sessions.sort_by_key(|s| s.pid);
+
+    let now = report::now();
+
+    if json {
+        let sessions = sessions
+            .iter()
+            .map(|s| {
+                let report = s.report.as_ref();
+                format!(
+                    "{{\"pid\":{},\"state\":\"{}\",\"name\":{},\"program\":{},\"source_ip\":{},\"egress_interface\":{},\"started\":{}}}",
+                    s.pid,
+                    s.state(),
+                    json_string(report.and_then(|r| r.name.clone())),
+                    json_string(report.map(|r| r.program.clone())),
+                    json_string(report.and_then(|r| r.source_ip).map(|ip| ip.to_string())),
+                    json_string(report.map(|r| r.egress_if.clone())),
+                    report.map_or_else(|| "null".to_owned(), |r| r.started.to_string())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        println!("[{sessions}]");
+        return Ok(());
+    }
+
+    println!(
+        "{:<8} {:<9} {:<16} {:<15} {:<12} {:>8}  Program",
+        "PID", "State", "Name", "Source IP", "Egress", "Uptime"
+    );
+    for s in &sessions {
+        let report = s.report.as_ref();
+        println!(
+            "{:<8} {:<9} {:<16} {:<15} {:<12} {:>8}  {}",
+            s.pid,
+            s.state(),
+            report.and_then(|r| r.name.as_deref()).unwrap_or("-"),
+            report
+                .and_then(|r| r.source_ip)
+                .map_or_else(|| "-".to_owned(), |ip| ip.to_string()),
+            report.map_or("-", |r| &r.egress_if),
+            report
+                .filter(|_| s.alive)
+                .map_or_else(|| "-".to_owned(), |r| uptime(now.saturating_sub(r.started))),
+            report.map_or("-", |r| &r.program)
+        );
+    }
+
+    if sessions.iter().any(|s| !s.alive) {
+        log::info!("\nRun `download-shell clean` to remove what killed sessions left behind");
+    }
+
+    Ok(())
+}
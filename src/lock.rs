@@ -0,0 +1,96 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Serializing changes to host wide state (kernel parameters and firewall
+//! rules) between concurrent download-shell processes
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::{fd::AsRawFd, unix::fs::DirBuilderExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::cgroup;
+
+/// Where state shared between sessions is kept
+const STATE_DIR: &str = "/run/download-shell";
+
+/// Where the state of sessions changing the current network namespace is
+/// kept. Sessions started inside of another session change that session's
+/// namespace rather than the host's, so they get a directory of their own
+/// instead of sharing the host's lock and saved kernel parameters
+pub fn state_dir() -> PathBuf {
+    match cgroup::enclosing_session() {
+        Some(session) => Path::new(STATE_DIR).join(format!("nested-{session}")),
+        None => PathBuf::from(STATE_DIR),
+    }
+}
+
+/// Removes the state of sessions that were nested inside of `session`,
+/// which has no use once its namespace is gone
+pub fn forget_nested(session: libc::pid_t) {
+    let _ = std::fs::remove_dir_all(Path::new(STATE_DIR).join(format!("nested-{session}")));
+}
+
+/// Returns the path of a file in [`state_dir`]
+pub fn state_path(name: &str) -> PathBuf {
+    state_dir().join(name)
+}
+
+/// Whether the process is still running
+pub fn process_alive(pid: libc::pid_t) -> bool {
+    Path::new(&format!("/proc/{pid}")).exists()
+}
+
+/// An exclusive lock over host wide state, held until dropped
+pub struct HostLock {
+    _file: File,
+}
+
+impl HostLock {
+    /// Blocks until no other download-shell process holds the lock
+    pub fn acquire() -> anyhow::Result<Self> {
+        let dir = state_dir();
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+
+        let path = state_path("lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("could not open {}", path.display()))?;
+
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                break;
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err).context("could not lock the host state");
+            }
+        }
+
+        Ok(Self { _file: file })
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! The log of a session, written to by both the parent and the child.
+//! Progress and warnings go to the terminal, with -v each change made to
+//! the system does as well, and -vv adds the details of how it was made.
+//! `--log-file` keeps all but the details, with timestamps, and everything
+//! with -vv
+//!
+//! Output of the subcommands and the JSON description of sessions stays on
+//! standard output, so the log goes to standard error
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::{Instant, SystemTime},
+};
+
+use anyhow::Context;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+struct Logger {
+    /// The most detailed level shown on the terminal, as a [`Level`]
+    terminal: AtomicU8,
+    file: OnceLock<(File, Level)>,
+}
+
+static LOGGER: Logger = Logger {
+    terminal: AtomicU8::new(Level::Info as u8),
+    file: OnceLock::new(),
+};
+
+static START: OnceLock<Instant> = OnceLock::new();
+
+fn level(n: u8) -> Level {
+    match n {
+        0 | 1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+/// Seconds since download-shell started, as dmesg shows them
+fn elapsed() -> f64 {
+    START.get_or_init(Instant::now).elapsed().as_secs_f64()
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= level(self.terminal.load(Ordering::Relaxed))
+            || self
+                .file
+                .get()
+                .is_some_and(|(_, level)| metadata.level() <= *level)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= level(self.terminal.load(Ordering::Relaxed)) {
+            match record.level() {
+                Level::Error => eprintln!("error: {}", record.args()),
+                Level::Warn => eprintln!("warning: {}", record.args()),
+                Level::Info => eprintln!("{}", record.args()),
+                Level::Debug | Level::Trace => {
+                    eprintln!("[{:>10.3}] {}", elapsed(), record.args())
+                }
+            }
+        }
+
+        // One write per line, which O_APPEND keeps whole when the parent and
+        // the child log at the same time
+        if let Some((mut file, level)) = self.file.get().map(|(f, l)| (f, *l))
+            && record.level() <= level
+        {
+            let _ = file.write_all(
+                format!(
+                    "[{:>10.3}] {} {:<5} {}\n",
+                    elapsed(),
+                    std::process::id(),
+                    record.level(),
+                    record.args()
+                )
+                .as_bytes(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, which shows progress and warnings until it is
+/// configured otherwise
+pub fn init() {
+    START.get_or_init(Instant::now);
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+/// Shows more on the terminal with each level of verbosity, and keeps a
+/// copy of the log in `file`
+pub fn configure(verbosity: u8, file: Option<&Path>) -> anyhow::Result<()> {
+    let terminal = match verbosity {
+        0 => Level::Info,
+        1 => Level::Debug,
+        _ => Level::Trace,
+    };
+    LOGGER.terminal.store(terminal as u8, Ordering::Relaxed);
+    let mut max = terminal;
+
+    if let Some(path) = file {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("could not open {}", path.display()))?;
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        writeln!(
+            file,
+            "download-shell {} started by {} at {started} seconds since the epoch",
+            env!("CARGO_PKG_VERSION"),
+            std::process::id()
+        )
+        .with_context(|| format!("could not write {}", path.display()))?;
+
+        let level = terminal.max(Level::Debug);
+        max = max.max(level);
+        let _ = LOGGER.file.set((file, level));
+    }
+
+    log::set_max_level(max.to_level_filter());
+    Ok(())
+}
@@ -14,111 +14,58 @@
 // You should have received a copy of the GNU General Public License
 // along with this program; if not, see <https://www.gnu.org/licenses/>.
 
-use std::net::Ipv4Addr;
+use std::{
+    ffi::{CString, OsString},
+    net::{IpAddr, Ipv4Addr},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 
-mod nl;
-
-#[derive(Debug)]
-struct Args {
-    program: String,
-    program_args: Vec<String>,
-    source_ip: Option<Ipv4Addr>,
-}
-
-fn parse_args() -> Args {
-    let mut program = "/bin/sh".to_owned();
-    let mut source_ip = None::<Ipv4Addr>;
-
-    let mut args = std::env::args();
-    args.next();
-    while let Some(arg) = args.next().take() {
-        match &*arg {
-            "-s" | "--source-ip" => match args.next().take().map(|s| s.parse()) {
-                Some(Ok(ip)) => source_ip = Some(ip),
-                Some(Err(e)) => {
-                    eprintln!("Error parsing source IP address: {e}");
-                }
-                None => {
-                    eprintln!("Error: source IP address not provided");
-                }
-            },
-            _ => {
-                program = arg;
-                break;
-            }
-        }
-    }
-
-    let mut program_args = args.collect::<Vec<_>>();
-    program_args.insert(0, program.clone());
-
-    Args {
-        program,
-        program_args,
-        source_ip,
-    }
-}
-
-/// Find an available IP range that can be used to tunnel traffic
-/// between the new namespace and the host system
-fn find_tunnel_ip_range(routes: &nl::netlink::Cache<nl::route::Route>) -> anyhow::Result<Ipv4Addr> {
-    let mut result_ip = Ipv4Addr::new(172, 16, 0, 0);
-
-    let mut routes = routes.iter().collect::<Vec<_>>();
-
-    routes.sort_by(|r1, r2| {
-        r1.dst()
-            .and_then(|a| {
-                let a: Option<Ipv4Addr> = (&a).try_into().ok();
-                a.map(|ip| -> u32 { ip.into() })
-            })
-            .partial_cmp(
-                &r2.dst()
-                    .and_then(|a| (&a).try_into().ok().map(|ip: Ipv4Addr| ip.into())),
-            )
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
-
-    for route in routes {
-        let Some(dst) = route.dst() else {
-            continue;
-        };
-
-        if dst.cidrlen() == 0 {
-            continue;
-        }
-
-        let Ok(dst_addr): Result<Ipv4Addr, _> = (&dst).try_into() else {
-            continue;
-        };
-        let dst_addr: u32 = dst_addr.into();
-
-        if dst_addr & 0xFFF00000 != 0xAC100000 {
-            continue;
-        }
-
-        let mask = (0xFFFFFFFFu32.overflowing_shr(32 - dst.cidrlen()))
-            .0
-            .overflowing_shl(32 - dst.cidrlen())
-            .0;
-
-        let res_ip_u32: u32 = result_ip.into();
-        if (dst_addr & mask) == (res_ip_u32 & mask) {
-            let next_net = 0xFFFFFFFFu32.overflowing_shr(dst.cidrlen()).0 + 1;
-            let res_ip_u32 = dst_addr + next_net;
-            result_ip = res_ip_u32.into();
-        }
-    }
-
-    let res_ip_u32: u32 = result_ip.into();
-    if res_ip_u32 & 0xFFF00000 != 0xAC100000 {
-        anyhow::bail!("Unable to find a tunnel IP address in the 172.16.0.0/16 range!");
-    }
-
-    Ok(result_ip)
-}
+mod arp;
+mod autoip;
+mod backend;
+mod broker;
+mod caps;
+mod cgroup;
+mod clean;
+mod cli;
+mod config;
+mod connections;
+mod doctor;
+mod environment;
+mod firewall;
+mod fwmark;
+mod hooks;
+mod impersonate;
+mod kernel;
+mod list;
+mod lock;
+mod logging;
+mod mdns;
+mod mounts;
+mod mtu;
+mod naming;
+mod nat64;
+mod offload;
+mod packet;
+mod plugins;
+mod processes;
+mod program;
+mod publish;
+mod rate;
+mod record;
+mod report;
+mod scan;
+mod session;
+mod signals;
+mod sudo;
+mod sysctl;
+mod teardown;
+mod transcript;
+mod tunnel;
+mod user;
 
 fn main() -> anyhow::Result<()> {
     // This Rust program is based on a bash script, found in the root
@@ -139,227 +86,1068 @@ fn main() -> anyhow::Result<()> {
     // namespace create and delete commands. However, they will appear
     // in a different order
 
+    logging::init();
+
+    let command = cli::parse();
+
     // 3-6: Root check
-    if unsafe { libc::geteuid() } != 0 {
-        eprintln!("This program needs to be run as root");
+    if command.needs_root() && unsafe { libc::geteuid() } != 0 {
+        log::error!("This program needs to be run as root");
         std::process::exit(1);
     }
 
-    let args = parse_args();
+    let mut args = match command {
+        cli::Command::Run(args) => *args,
+        cli::Command::Attach(args) => return session::attach(args),
+        cli::Command::Clean(args) => return clean::run(args),
+        cli::Command::Connections(args) => return connections::run(args),
+        cli::Command::Destroy(args) => return session::destroy(args),
+        cli::Command::Doctor(args) => return doctor::run(args),
+        cli::Command::List(args) => return list::run(args),
+        cli::Command::Scan(args) => return scan::run(args),
+    };
+    logging::configure(args.verbose, args.log_file.as_deref())?;
+
+    if args.impersonate.is_some() && (args.source_ip.is_some() || args.auto_source) {
+        anyhow::bail!("--impersonate already picks the source IP, and cannot be used with -s");
+    }
+    if args.nat64 && (args.source_ip.is_some() || args.auto_source || args.impersonate.is_some()) {
+        anyhow::bail!("--nat64 leaves through the NAT64 gateway, and cannot pick a source IP");
+    }
+    if !args.uplinks.is_empty()
+        && (args.source_ip.is_some()
+            || args.auto_source
+            || args.impersonate.is_some()
+            || args.nat64)
+    {
+        anyhow::bail!("--uplink cannot be combined with -s, --impersonate or --nat64");
+    }
+    if args.interface.is_some() && args.nat64 {
+        anyhow::bail!("--nat64 leaves through the NAT64 gateway, and cannot pick an interface");
+    }
+    if args.mac.is_some() && args.source_ips.len() > 1 {
+        anyhow::bail!("--mac gives the session a single identity on the LAN, and one source IP");
+    }
+    if args.snat_persistent && args.source_ips.len() > 1 {
+        anyhow::bail!(
+            "--snat-persistent would keep every connection on one source IP; give a single one"
+        );
+    }
+    if args.mac.is_some()
+        && args.source_ip.is_none()
+        && !args.auto_source
+        && args.impersonate.is_none()
+    {
+        anyhow::bail!("--mac needs a source IP from -s or --impersonate to go with it");
+    }
+    if args.mirror.is_some() && (args.impersonate.is_some() || args.mac.is_some()) {
+        anyhow::bail!(
+            "--mirror copies the traffic of the tunnel, which --impersonate and --mac bypass"
+        );
+    }
+    if !args.publish.is_empty() && (args.impersonate.is_some() || args.mac.is_some()) {
+        anyhow::bail!(
+            "--publish forwards through the host, which --impersonate and --mac bypass; the session can be reached on its own address instead"
+        );
+    }
+    if args.broker.is_some() && args.nat64 {
+        anyhow::bail!("--6in4 and --nat64 both give the session its IPv6 connectivity");
+    }
+    if args.ipv6 && (args.broker.is_some() || args.nat64) {
+        anyhow::bail!(
+            "--6in4 and --nat64 give the session its IPv6 connectivity, and cannot be used with --ipv6 or an IPv6 source IP"
+        );
+    }
+
+    if let Some(script) = &args.script {
+        std::fs::File::open(script)
+            .with_context(|| format!("Could not read {}", script.display()))?;
+    }
+    let program_path = program::resolve(&args.program)
+        .with_context(|| format!("Could not run {}", args.program))?;
+
+    // Counted from the start, so that setting up doesn't add to it. One too
+    // far off to be represented is never reached
+    let deadline = args
+        .timeout
+        .and_then(|timeout| std::time::Instant::now().checked_add(timeout));
+
+    // Resolved now, as mounts of the session could hide where it leads
+    let workdir = args
+        .workdir
+        .as_deref()
+        .map(|dir| {
+            dir.canonicalize()
+                .ok()
+                .filter(|dir| dir.is_dir())
+                .ok_or_else(|| anyhow::anyhow!("--workdir {} is not a directory", dir.display()))
+        })
+        .transpose()?;
+
+    // Files come first so that --env can override what they set
+    let mut program_env = Vec::<(OsString, OsString)>::new();
+    let mut given = Vec::new();
+    for path in &args.env_file {
+        given.extend(environment::read_file(path)?);
+    }
+    given.extend(args.env.iter().filter_map(environment::Var::resolve));
+    for (key, value) in given {
+        program_env.retain(|(k, _)| *k != key);
+        program_env.push((key, value));
+    }
+
+    let account = args
+        .user
+        .as_deref()
+        .map(user::Account::lookup)
+        .transpose()
+        .context("Could not find the user to run the program as")?;
+    let group = args
+        .group
+        .as_deref()
+        .map(user::lookup_group)
+        .transpose()
+        .context("Could not find the group to run the program as")?;
+
+    let invoker = sudo::invoker();
+
+    let download_owner = match &invoker {
+        _ if !args.chown_downloads => None,
+        _ if args.download_dir.is_none() => {
+            anyhow::bail!("--chown-downloads needs a download directory, see --download-dir")
+        }
+        Some(invoker) => Some(invoker),
+        None => anyhow::bail!(
+            "--chown-downloads hands files to the user who ran sudo, but download-shell was not run through sudo"
+        ),
+    };
+    let download_dir_created = args.download_dir.as_ref().is_some_and(|dir| !dir.exists());
+
+    // The download directory has to be resolved on the host, as symlinks in
+    // its path could point somewhere else once the filesystem is read only
+    let download_dir = args
+        .download_dir
+        .as_ref()
+        .map(|dir| {
+            // A dry run leaves creating it to the real one
+            if args.dry_run && !dir.exists() {
+                return Ok(dir.clone());
+            }
+            std::fs::create_dir_all(dir)?;
+            dir.canonicalize()
+        })
+        .transpose()
+        .context("Could not prepare the download directory")?;
+
+    // The invoking user's home may live outside of /home, so look it up
+    // while the host's user database is still at hand
+    let protected_homes = {
+        let mut homes = vec![PathBuf::from("/home"), PathBuf::from("/root")];
+        let invoking_home = invoker
+            .as_ref()
+            .map(|a| a.home.clone())
+            .or(std::env::var("HOME").ok());
+        homes.extend(
+            invoking_home
+                .map(PathBuf::from)
+                .filter(|home| !homes.iter().any(|h| home.starts_with(h))),
+        );
+        homes
+    };
+
+    // Like the download directory, the transcript has to be opened while
+    // the host filesystem is still writable
+    let transcript = args
+        .transcript
+        .as_deref()
+        .filter(|_| !args.dry_run)
+        .map(transcript::Transcript::create)
+        .transpose()
+        .context("Could not create the transcript")?;
+    if let (Some(path), Some(transcript)) = (&args.transcript, &transcript) {
+        log::info!(
+            "Recording the session to {} (replay with scriptreplay --timing {} {0})",
+            path.display(),
+            transcript.timing_path().display()
+        );
+    }
+
+    let mut plugins = plugins::enable(&args.plugins)?;
+
+    let firewall_mechanism = firewall::Mechanism::detect()?;
+    log::debug!("Changing the firewall with {}", firewall_mechanism.name());
+    if firewall_mechanism == firewall::Mechanism::Nft {
+        log::warn!(
+            "iptables not found, using nft; a DROP policy in another nftables forward chain still applies to the session"
+        );
+    }
+
+    // Probing loads missing modules, which a dry run must not do
+    let features = kernel::Features::detect(args.legacy_kernel);
+    if !args.dry_run {
+        caps::require(&features, firewall_mechanism)?;
+    }
 
     // 13: Debug statement
     match &args.source_ip {
-        Some(ip) => println!("Sending traffic out as {ip:?}..."),
-        None => println!("Sending traffic using the host IP address"),
+        None if args.auto_source => log::info!("Looking for a free address on the LAN..."),
+        None if args.impersonate.is_some() => log::info!("Looking up the host to impersonate..."),
+        Some(ip) if args.source_ips.len() == 1 => log::info!("Sending traffic out as {ip:?}..."),
+        Some(_) => log::info!(
+            "Sending traffic out as {}, taking turns with each connection...",
+            args.source_ips
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        None => log::info!("Sending traffic using the host IP address"),
+    }
+    if let Some(ip) = &args.source_ip6 {
+        log::info!("Sending IPv6 traffic out as {ip}...");
     }
 
-    let nl_sock = nl::netlink::Socket::new().context("Could not allocate Netlink socket")?;
-    let routes = nl_sock
-        .get_routes()
-        .context("Could not initially load routes")?;
-
-    let tunnel_net_id: u32 = find_tunnel_ip_range(&routes)?.into();
+    let session_claim = args
+        .name
+        .as_deref()
+        .filter(|_| !args.dry_run)
+        .map(session::Claim::take)
+        .transpose()?;
 
-    let host_link_name = format!("dlsh{}.0", unsafe { libc::getpid() });
-    let container_link_name = format!("dlsh{}.1", unsafe { libc::getpid() });
+    let mut backend = backend::open(args.backend, args.debug_netlink)?;
+    if args.dry_run {
+        backend = Box::new(backend::DryRunBackend::new(backend));
+    }
+    let routes = backend
+        .routes()
+        .context("Could not initially load routes")?;
+    let local_addrs = backend
+        .local_addrs()
+        .context("Could not load the addresses owned by the host")?;
 
-    // 15: ip link add downloader.0 type veth peer name downloader.1
-    let (links, host_link, container_link) = {
-        let link = nl::route::Link::new_veth();
-        let peer = link.get_peer().ok_or(anyhow::anyhow!(
-            "Could not get peer link for download tunnel"
-        ))?;
+    if let Some(ip) = args.source_ips.iter().find(|ip| local_addrs.contains(ip)) {
+        anyhow::bail!("{ip} is already assigned to this host, and cannot be used as a source IP");
+    }
 
-        link.set_name(&host_link_name);
-        peer.set_name(&container_link_name);
+    let iface_addrs = backend
+        .addrs()
+        .context("Could not load the addresses assigned to links")?;
+
+    let taken_networks = tunnel::taken_networks(&routes, &local_addrs, &iface_addrs);
+
+    let tunnel = tunnel::Addresses::new(
+        args.tunnel_addressing,
+        tunnel::find_range(&taken_networks, args.tunnel_addressing, args.tunnel_net)?,
+    );
+
+    let existing_links = backend
+        .link_names()
+        .context("Could not list existing links")?;
+    let (host_link_name, container_link_name) = naming::link_names(
+        &args.link_prefix,
+        unsafe { libc::getpid() },
+        &existing_links,
+    )?;
+    if let Some(mirror) = args.mirror.as_ref().filter(|m| !existing_links.contains(m)) {
+        anyhow::bail!("Cannot mirror traffic to {mirror}, which does not exist");
+    }
+    if let Some(interface) = args
+        .interface
+        .as_ref()
+        .filter(|i| !existing_links.contains(i))
+    {
+        anyhow::bail!("Cannot send traffic out of {interface}, which does not exist");
+    }
 
-        link.add(&nl_sock, 0x200 | 0x400 /* NLM_F_CREATE | NLM_F_EXCL */)?;
+    let host_tunnel_ip = tunnel.host;
+    let container_tunnel_ip = tunnel.container;
 
-        let links = nl_sock
-            .get_links()
-            .context("Could not acquire link list for adding veth device")?;
+    // Lines 15-25 and 38 need to be done after forking and unshare, so that
+    // the veth peer can be created directly in the new namespace
 
-        let link = links
-            .iter()
-            .find(|l| l.name() == host_link_name)
-            .ok_or(anyhow::anyhow!(
-                "Could not get host link for download tunnel"
-            ))?;
-        let peer = links
-            .iter()
-            .find(|l| l.name() == container_link_name)
+    // 27: DEFAULT_IF="$(ip r | grep default | sed -nE 's/^.*dev ([^ ]*) ?.*/\1/p')""
+    // An IPv6 only uplink has no IPv4 default route to go by
+    let default_if = match (
+        args.uplinks.first().or(args.interface.as_ref()),
+        routes.iter().find(|r| r.prefixlen == 0),
+    ) {
+        (Some(uplink), _) => uplink.clone(),
+        (None, None) if args.nat64 => nat64::default_interface()?,
+        (None, route) => route
+            .ok_or(anyhow::anyhow!("Could not find the default route"))?
+            .dev
+            .clone()
             .ok_or(anyhow::anyhow!(
-                "Could not get peer link for download tunnel"
-            ))?;
-
-        (links, link, peer)
+                "Could not find the interface associated with the default route"
+            ))?,
     };
 
-    // 16: ip netns add downloader
+    // Traffic leaves through the bridge or bond rather than its ports, so NAT
+    // rules and proxy ARP on a port would never see any of it
+    if let Some(master) = backend
+        .link_master(&default_if)
+        .context("Could not inspect the default interface")?
     {
-        // Block left empty, to acknowledge the line of bash that
-        // doesn't get to be reimplemented
+        anyhow::bail!(
+            "Traffic would leave through {default_if}, which is a port of {master}; route through {master} instead"
+        );
     }
 
-    // 17: ip link set downloader.0 up
-    {
-        let up = nl::route::Link::new();
-        up.set_flags(nl::route::Link::IFF_UP);
-        host_link
-            .change(&nl_sock, &up)
-            .context("Could not set downloader interface to be up")?;
+    // With uplinks given, the session gets a routing table of its own which
+    // spreads its connections over their gateways. A chosen interface is
+    // routed the same way, as a single uplink
+    let uplink_gateways = args
+        .uplinks
+        .iter()
+        .chain(&args.interface)
+        .map(|uplink| {
+            routes
+                .iter()
+                .filter(|r| r.dev.as_ref() == Some(uplink))
+                .filter_map(|r| Some((r.prefixlen, r.gateway?)))
+                .min()
+                .map(|(_, gateway)| (uplink.clone(), gateway))
+                .ok_or(anyhow::anyhow!(
+                    "Could not find a gateway reached through {uplink}"
+                ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let egress_ifs = if args.uplinks.is_empty() {
+        vec![default_if.clone()]
+    } else {
+        args.uplinks.clone()
+    };
+
+    if args.auto_source {
+        let candidates = autoip::candidates(&default_if, &routes, &local_addrs, &args.auto_exclude)
+            .context("Could not pick a source IP")?;
+        let ip = autoip::choose(&default_if, &candidates).context("Could not pick a source IP")?;
+        log::info!("Sending traffic out as {ip:?}...");
+        args.source_ip = Some(ip);
+        args.source_ips = vec![ip];
     }
 
-    let host_tunnel_ip: Ipv4Addr = (tunnel_net_id + 1).into();
-    let container_tunnel_ip: Ipv4Addr = (tunnel_net_id + 2).into();
-    let tunnel_broadcast_ip: Ipv4Addr = (tunnel_net_id + 3).into();
-    // 20: ip addr add 172.31.254.253/30 dev downloader.0
+    // With a hardware address of its own, the source IP goes on the LAN
+    // through a macvlan just like an impersonated one
+    let mut impersonated = args.impersonate;
+    if args.mac.is_some()
+        && let Some(ip) = args.source_ip.take()
     {
-        let local_ip = nl::route::Addr::from(host_tunnel_ip);
-        let broadcast_ip = nl::route::Addr::from(tunnel_broadcast_ip);
-        let rt_local_ip = nl::route::RtAddr::new()
-            .ok_or(anyhow::anyhow!("Could not allocate new tunnel IP address"))?;
+        args.source_ips.clear();
+        impersonated = Some(ip);
+    }
+
+    let impersonation = impersonated
+        .map(|ip| {
+            impersonate::prepare(
+                ip,
+                args.mac,
+                &default_if,
+                &routes,
+                &local_addrs,
+                backend.as_ref(),
+            )
+            .with_context(|| format!("Could not put {ip} on the LAN"))
+        })
+        .transpose()?;
+    if let Some(identity) = &impersonation {
+        log::info!(
+            "Sending traffic out as {:?} with the hardware address {}...",
+            identity.ip,
+            identity.mac
+        );
+    }
+    let macvlan_name = naming::macvlan_name(&host_link_name);
+
+    // Size the tunnel for the egress interface, jumbo frames included,
+    // rather than leaving it at the veth default of 1500
+    let link_mtu = match args.mtu {
+        Some(mtu) => mtu,
+        None => {
+            let egress_mtu = backend
+                .link_mtu(&default_if)
+                .context("Could not find the MTU of the default interface")?;
 
-        rt_local_ip
-            .set_local(local_ip)
-            .context("Could not set the address of the host interface")?;
-        rt_local_ip.set_ifindex(host_link.ifindex());
-        rt_local_ip
-            .set_broadcast(broadcast_ip)
-            .context("Could not set the broadcast IP of the host interface")?;
-        rt_local_ip.set_prefixlen(30);
+            match args.mtu_probe.map(mtu::path_mtu) {
+                Some(Ok(path_mtu)) => path_mtu.min(egress_mtu),
+                Some(Err(e)) => {
+                    log::warn!("could not find the path MTU, using {egress_mtu}: {e}");
+                    egress_mtu
+                }
+                None => egress_mtu,
+            }
+        }
+    };
+    if link_mtu < mtu::MIN_MTU {
+        anyhow::bail!("An MTU of {link_mtu} is too small for IPv4");
+    }
+    if args.broker.is_some() && link_mtu < broker::OVERHEAD + 1280 {
+        anyhow::bail!("An MTU of {link_mtu} leaves too little room for IPv6 in the 6in4 tunnel");
+    }
+
+    // IPv6 leaves through whichever interface has the IPv6 default route,
+    // which need not be the one IPv4 uses
+    let ipv6 = args
+        .ipv6
+        .then(|| {
+            let routes6 = backend
+                .routes6()
+                .context("Could not load the IPv6 routes")?;
+            let egress_if = routes6
+                .iter()
+                .find(|r| r.prefixlen == 0)
+                .and_then(|r| r.dev.clone())
+                .ok_or(anyhow::anyhow!("Could not find the IPv6 default route"))?;
+
+            if let Some(ip) = args.source_ip6
+                && backend
+                    .local_addrs6()
+                    .context("Could not load the IPv6 addresses owned by the host")?
+                    .contains(&ip)
+            {
+                anyhow::bail!(
+                    "{ip} is already assigned to this host, and cannot be used as a source IP"
+                );
+            }
 
-        rt_local_ip
-            .add(&nl_sock, 0x200)
-            .context("Could not add the IP address to the host tunnel interface")?;
+            let tunnel =
+                tunnel::Addresses6::new(
+                    tunnel::find_range6(&routes6, unsafe { libc::getpid() } as u16)?
+                );
+            anyhow::Ok((egress_if, tunnel))
+        })
+        .transpose()?;
+
+    let mut hook_env = hooks::Env::new();
+    hook_env
+        .set("PID", unsafe { libc::getpid() })
+        .set("PROGRAM", &args.program)
+        .set("DEFAULT_IF", &default_if)
+        .set("HOST_LINK", &host_link_name)
+        .set("CONTAINER_LINK", &container_link_name)
+        .set("HOST_IP", host_tunnel_ip)
+        .set("CONTAINER_IP", container_tunnel_ip);
+    if let Some(ip) = args.source_ip.or(impersonated) {
+        hook_env.set("SOURCE_IP", ip);
+    }
+    if !args.source_ips.is_empty() {
+        hook_env.set(
+            "SOURCE_IPS",
+            args.source_ips
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    }
+    if let Some((_, tunnel6)) = &ipv6 {
+        hook_env
+            .set("HOST_IP6", tunnel6.host)
+            .set("CONTAINER_IP6", tunnel6.container);
+    }
+    if let Some(ip) = args.source_ip6 {
+        hook_env.set("SOURCE_IP6", ip);
+    }
+    if let Some(name) = &args.name {
+        hook_env.set("SESSION_NAME", name);
     }
 
-    // Lines 18 and 22-25 need to be done after forking and unshare
+    if !args.dry_run {
+        hooks::run(
+            &args.hooks,
+            hooks::Stage::PreUp,
+            hooks::Place::Host,
+            &hook_env,
+            None,
+        )?;
+    }
 
-    // 27: DEFAULT_IF="$(ip r | grep default | sed -nE 's/^.*dev ([^ ]*) ?.*/\1/p')""
-    let default_if = {
-        let default_route = routes
-            .iter()
-            .find(|r| r.dst().map(|a| a.cidrlen() == 0).unwrap_or(false))
-            .ok_or(anyhow::anyhow!("Could not find the default route"))?;
-
-        let local_hop = default_route
-            .hop_iter()
-            .next()
-            .ok_or(anyhow::anyhow!(
-                "Could not get the local interface for the default route gateway"
-            ))?
-            .ifindex();
+    // Inside of another session, that session's namespace stands in for
+    // the host: discovery, kernel parameters and firewall rules all apply to
+    // it, and the tunnel subnet is picked around its own
+    if let Some(outer) = cgroup::enclosing_session() {
+        log::info!("Running inside of the session of download-shell {outer}...");
+    }
 
-        links
-            .iter()
-            .find(|l| l.ifindex() == local_hop)
-            .ok_or(anyhow::anyhow!(
-                "Could not find the interface associated with the default route"
-            ))?
-    };
+    // Everything done to the system is kept track of for --record
+    let mut record = record::Recorder::new();
+
+    // The kernel parameters the session needs, which are put back once it
+    // is over
+    let mut host_sysctls = sysctl::Batch::new();
+
+    let nat64 = args.nat64.then(|| {
+        let prefix = args
+            .nat64_prefix
+            .or_else(nat64::discover_prefix)
+            .unwrap_or(nat64::WELL_KNOWN_PREFIX);
+        log::info!("Translating IPv4 traffic to the NAT64 prefix {prefix}/96...");
+        nat64::Nat64::new(prefix, unsafe { libc::getpid() }, container_tunnel_ip)
+    });
+    if nat64.is_some() {
+        // Forwarding turns off router advertisements unless accept_ra is 2,
+        // and the uplink may well be configured by them
+        host_sysctls.set("net/ipv6/conf/all/forwarding", 1);
+        host_sysctls.set(format!("net/ipv6/conf/{default_if}/accept_ra"), 2);
+    }
+
+    if let Some((egress6_if, _)) = &ipv6 {
+        host_sysctls.set("net/ipv6/conf/all/forwarding", 1);
+        host_sysctls.set(format!("net/ipv6/conf/{egress6_if}/accept_ra"), 2);
+        if args.source_ip6.is_some() {
+            host_sysctls.set(format!("net/ipv6/conf/{egress6_if}/proxy_ndp"), 1);
+        }
+    }
 
     // 29: echo 1 > /proc/sys/net/ipv4/ip_forward
-    std::fs::write("/proc/sys/net/ipv4/ip_forward", b"1")
-        .context("could not enable IP forwarding")?;
+    // IPv4 forwarding is decided by the interface a packet arrives on, so
+    // unless asked otherwise only the egress interface and the tunnel are
+    // allowed to forward, leaving the rest of the host as it was
+    let per_interface_forwarding =
+        !args.global_forwarding && sysctl::read::<u8>("net/ipv4/ip_forward").ok() != Some(1);
+    if per_interface_forwarding {
+        for egress_if in &egress_ifs {
+            host_sysctls.set(format!("net/ipv4/conf/{egress_if}/forwarding"), 1);
+        }
+    } else {
+        host_sysctls.set("net/ipv4/ip_forward", 1);
+    }
 
     // Having a consistent comment makes the cleanup that comes later a lot easier
-    let firewall_comment = format!("dlsh{}", unsafe { libc::getpid() });
+    // Named sessions carry their name as well, to tell them apart when they
+    // are left running
+    let firewall_comment = match &args.name {
+        Some(name) => format!("dlsh{}-{name}", unsafe { libc::getpid() }),
+        None => format!("dlsh{}", unsafe { libc::getpid() }),
+    };
+    // Whatever is changed on the host from here on is undone when the
+    // session ends, or right away if setting it up fails
+    let mut teardown = teardown::Teardown::new(
+        firewall::Firewall::new(firewall_mechanism, &firewall_comment, args.dry_run),
+        args.backend,
+        args.dry_run,
+    );
+
+    // Other sessions starting at the same time must not interleave their
+    // firewall and kernel parameter changes with ours. A dry run changes
+    // nothing, so it leaves the state directory alone
+    let host_lock = (!args.dry_run).then(lock::HostLock::acquire).transpose()?;
+
+    // Traffic coming out of the tunnel is marked, and the NAT and filter
+    // rules match on the mark rather than on the tunnel addresses, so that
+    // they only ever apply to this session even if the subnet is reused
+    let session_fwmark = args
+        .fwmark
+        .unwrap_or_else(|| fwmark::Fwmark::for_session(unsafe { libc::getpid() }));
+    let fwmark = session_fwmark.to_string();
+    let rule = [
+        "-t",
+        "mangle",
+        "-A",
+        "PREROUTING",
+        "-i",
+        &host_link_name,
+        "-j",
+        "MARK",
+        "--set-xmark",
+        &fwmark,
+        "-m",
+        "comment",
+        "--comment",
+        &firewall_comment,
+    ];
+    teardown
+        .firewall
+        .add(&mut record, &rule)
+        .context("Could not create the rule marking session traffic")?;
+    if ipv6.is_some() {
+        teardown
+            .firewall
+            .add6(&mut record, &rule)
+            .context("Could not create the rule marking session IPv6 traffic")?;
+    }
+
+    // Excluded destinations are masqueraded behind the host whatever the
+    // source IP, and on whichever interface their route takes them out of.
+    // NAT stops at the first rule that matches, so these come first
+    for net in &args.exclude {
+        let net = net.to_string();
+        let rule = [
+            "-t",
+            "nat",
+            "-A",
+            "POSTROUTING",
+            "-m",
+            "mark",
+            "--mark",
+            &fwmark,
+            "-d",
+            &net,
+            "-j",
+            "MASQUERADE",
+            "-m",
+            "comment",
+            "--comment",
+            &firewall_comment,
+        ];
+        teardown
+            .firewall
+            .add(&mut record, &rule)
+            .with_context(|| format!("Could not exclude {net} from source NAT"))?;
+    }
+
+    let snat_flags = args
+        .snat_random
+        .map(firewall::PortRandomization::iptables)
+        .into_iter()
+        .chain(args.snat_persistent.then_some("--persistent"))
+        .collect::<Vec<_>>();
 
     // 31: If a source IP is specified
     match &args.source_ip {
         None => {
             // 32: iptables -t nat -A POSTROUTING -o "$DEFAULT_IF" -j MASQUERADE
-            std::process::Command::new("iptables")
-                .args([
+            for egress_if in &egress_ifs {
+                let rule = [
                     "-t",
                     "nat",
                     "-A",
                     "POSTROUTING",
                     "-o",
-                    &default_if.name(),
+                    egress_if,
+                    "-m",
+                    "mark",
+                    "--mark",
+                    &fwmark,
                     "-j",
                     "MASQUERADE",
                     "-m",
                     "comment",
                     "--comment",
                     &firewall_comment,
-                ])
-                .output()
-                .context("Could not create the MASQUERADE rule")?;
+                ];
+                teardown
+                    .firewall
+                    .add(&mut record, &rule)
+                    .context("Could not create the MASQUERADE rule")?;
+            }
         }
-        Some(ip) => {
+        Some(_) => {
             // 34: iptables -t nat -A POSTROUTING -s 172.31.254.254 -j SNAT --to-source $1
-            std::process::Command::new("iptables")
-                .args([
+            // With more than one source IP, each rule takes every Nth of the
+            // connections the rules before it left over, so that they take
+            // turns; the last one takes whatever is left
+            let count = args.source_ips.len();
+            for (i, ip) in args.source_ips.iter().enumerate() {
+                let every = format!("{}", count - i);
+                let ip = format!("{ip}");
+                let rotation = if i + 1 < count {
+                    vec![
+                        "-m",
+                        "statistic",
+                        "--mode",
+                        "nth",
+                        "--every",
+                        &every,
+                        "--packet",
+                        "0",
+                    ]
+                } else {
+                    vec![]
+                };
+                let rule = [
+                    &[
+                        "-t",
+                        "nat",
+                        "-A",
+                        "POSTROUTING",
+                        "-m",
+                        "mark",
+                        "--mark",
+                        &fwmark,
+                    ][..],
+                    &rotation,
+                    &["-j", "SNAT", "--to-source", &ip],
+                    &snat_flags,
+                    &["-m", "comment", "--comment", &firewall_comment],
+                ]
+                .concat();
+                teardown
+                    .firewall
+                    .add(&mut record, &rule)
+                    .context("Could not create source NAT rule")?;
+            }
+
+            // 36: echo 1 > /proc/sys/net/ipv4/conf/all/proxy_arp
+            host_sysctls.set("net/ipv4/conf/all/proxy_arp", 1);
+            // 37: echo 1 > /proc/sys/net/ipv4/conf/$DEFAULT_IF/proxy_arp
+            host_sysctls.set(format!("net/ipv4/conf/{default_if}/proxy_arp"), 1);
+        }
+    }
+
+    match &host_lock {
+        Some(host_lock) => {
+            teardown.sysctls = host_sysctls
+                .apply(&mut record, host_lock)
+                .context("could not configure the kernel for forwarding")?;
+        }
+        None => host_sysctls.plan(&mut record),
+    }
+
+    // iptables -t filter -A FORWARD -s 172.31.254.254 -j ACCEPT
+    // The rules are inserted at the top of the chain rather than appended,
+    // as Docker and firewalld leave a REJECT or DROP rule at the end of it.
+    // Replies are accepted explicitly too, in case the policy is DROP
+    for direction in [
+        &["-m", "mark", "--mark", &fwmark][..],
+        &[
+            "-o",
+            &host_link_name,
+            "-m",
+            "conntrack",
+            "--ctstate",
+            "RELATED,ESTABLISHED",
+        ][..],
+    ] {
+        let rule = [
+            &["-t", "filter", "-I", "FORWARD", "1"][..],
+            direction,
+            &[
+                "-j",
+                "ACCEPT",
+                "-m",
+                "comment",
+                "--comment",
+                &firewall_comment,
+            ],
+        ]
+        .concat();
+        teardown
+            .firewall
+            .add(&mut record, &rule)
+            .context("could not add firewall rule to allow traffic forwarding")?;
+        if ipv6.is_some() {
+            teardown
+                .firewall
+                .add6(&mut record, &rule)
+                .context("could not add firewall rule to allow IPv6 forwarding")?;
+        }
+    }
+
+    // IPv6 is translated the same way as IPv4, with neighbour discovery
+    // proxied instead of ARP
+    if let Some((egress6_if, _)) = &ipv6 {
+        let source_ip6 = args.source_ip6.map(|ip| ip.to_string());
+        let target = match &source_ip6 {
+            None => vec!["-o", egress6_if.as_str(), "-j", "MASQUERADE"],
+            Some(ip) => [&["-j", "SNAT", "--to-source", ip][..], &snat_flags].concat(),
+        };
+        let rule = [
+            &[
+                "-t",
+                "nat",
+                "-A",
+                "POSTROUTING",
+                "-m",
+                "mark",
+                "--mark",
+                &fwmark,
+            ][..],
+            &target,
+            &["-m", "comment", "--comment", &firewall_comment],
+        ]
+        .concat();
+        teardown
+            .firewall
+            .add6(&mut record, &rule)
+            .context("Could not create the IPv6 source NAT rule")?;
+    }
+
+    // Let the multicast name resolution traffic reach the reflector from
+    // both sides, whatever the INPUT policy of the host is
+    if args.mdns {
+        for protocol in mdns::PROTOCOLS {
+            for iface in [&default_if, &host_link_name] {
+                let rule = [
                     "-t",
-                    "nat",
-                    "-A",
-                    "POSTROUTING",
-                    "-s",
-                    &format!("{container_tunnel_ip}"),
+                    "filter",
+                    "-I",
+                    "INPUT",
+                    "-i",
+                    iface,
+                    "-d",
+                    &format!("{}", protocol.group),
+                    "-p",
+                    "udp",
+                    "--dport",
+                    &format!("{}", protocol.port),
                     "-j",
-                    "SNAT",
-                    "--to-source",
-                    &format!("{ip}"),
+                    "ACCEPT",
                     "-m",
                     "comment",
                     "--comment",
                     &firewall_comment,
-                ])
-                .output()
-                .context("Could not create source NAT rule")?;
-
-            // 36: echo 1 > /proc/sys/net/ipv4/conf/all/proxy_arp
-            std::fs::write("/proc/sys/net/ipv4/conf/all/proxy_arp", b"1")
-                .context("could not enable proxy_arp")?;
-            // 37: echo 1 > /proc/sys/net/ipv4/conf/$DEFAULT_IF/proxy_arp
-            std::fs::write(
-                &format!("/proc/sys/net/ipv4/conf/{}/proxy_arp", &default_if.name()),
-                b"1",
-            )
-            .context("could not enable proxy arp for interface")?;
+                ];
+                teardown.firewall.add(&mut record, &rule).with_context(|| {
+                    format!("could not add firewall rule to accept {}", protocol.name)
+                })?;
+            }
+        }
+    }
 
-            // 38: ip route add $1/32 dev downloader.0
-            {
-                let hop = nl::route::Nexthop::new()
-                    .ok_or(anyhow::anyhow!("Could not allocate a new nexthop object"))?;
+    // Connections to the published ports are forwarded to the namespace,
+    // either at the source IP of the session or as they arrive on the
+    // egress interfaces. Replies come back out of the tunnel, and are
+    // accepted and translated back like the rest of its traffic
+    for port in &args.publish {
+        let host_port = format!("{}", port.host);
+        let namespace_port = format!("{}", port.namespace);
+        let protocol = port.protocol.name();
+
+        let mut families = vec![(
+            args.source_ips
+                .iter()
+                .map(|ip| IpAddr::V4(*ip))
+                .collect::<Vec<_>>(),
+            IpAddr::V4(container_tunnel_ip),
+            egress_ifs.clone(),
+        )];
+        if let Some((egress6_if, addresses6)) = &ipv6 {
+            families.push((
+                args.source_ip6.map(IpAddr::V6).into_iter().collect(),
+                IpAddr::V6(addresses6.container),
+                vec![egress6_if.clone()],
+            ));
+        }
 
-                hop.set_ifindex(host_link.ifindex());
+        for (sources, container, egress_ifs) in families {
+            let add = |firewall: &mut firewall::Firewall,
+                       record: &mut record::Recorder,
+                       rule: &[&str]| match container {
+                IpAddr::V4(_) => firewall.add(record, rule),
+                IpAddr::V6(_) => firewall.add6(record, rule),
+            };
+            let destination = match container {
+                IpAddr::V4(ip) => format!("{ip}:{namespace_port}"),
+                IpAddr::V6(ip) => format!("[{ip}]:{namespace_port}"),
+            };
 
-                let new_route = nl::route::Route::new().ok_or(anyhow::anyhow!(
-                    "Could not allocate a new route object for ARP proxy"
-                ))?;
+            let sources = sources.iter().map(IpAddr::to_string).collect::<Vec<_>>();
+            let arrivals = if sources.is_empty() {
+                egress_ifs.iter().map(|i| ["-i", i.as_str()]).collect()
+            } else {
+                sources
+                    .iter()
+                    .map(|ip| ["-d", ip.as_str()])
+                    .collect::<Vec<_>>()
+            };
+            for arrival in arrivals {
+                let rule = [
+                    &["-t", "nat", "-A", "PREROUTING"][..],
+                    &arrival,
+                    &[
+                        "-p",
+                        protocol,
+                        "--dport",
+                        &host_port,
+                        "-j",
+                        "DNAT",
+                        "--to-destination",
+                        &destination,
+                        "-m",
+                        "comment",
+                        "--comment",
+                        &firewall_comment,
+                    ],
+                ]
+                .concat();
+                add(&mut teardown.firewall, &mut record, &rule)
+                    .with_context(|| format!("Could not publish port {port}"))?;
+            }
 
-                let target_addr = nl::route::Addr::from(*ip);
-                target_addr.set_cidrlen(32);
+            let container = container.to_string();
+            let rule = [
+                "-t",
+                "filter",
+                "-I",
+                "FORWARD",
+                "1",
+                "-o",
+                &host_link_name,
+                "-d",
+                &container,
+                "-p",
+                protocol,
+                "--dport",
+                &namespace_port,
+                "-j",
+                "ACCEPT",
+                "-m",
+                "comment",
+                "--comment",
+                &firewall_comment,
+            ];
+            add(&mut teardown.firewall, &mut record, &rule)
+                .with_context(|| format!("Could not allow connections to port {port}"))?;
+        }
+    }
 
-                new_route.add_nexthop(&hop);
-                new_route.set_dst(target_addr);
+    // The translated traffic of the namespace is masqueraded behind the
+    // IPv6 address of the host
+    if let Some(nat64) = &nat64 {
+        let clat_ip = format!("{}/128", nat64.clat_ip);
+        teardown
+            .firewall
+            .add6(
+                &mut record,
+                &[
+                    "-t",
+                    "nat",
+                    "-A",
+                    "POSTROUTING",
+                    "-s",
+                    &clat_ip,
+                    "-o",
+                    &default_if,
+                    "-j",
+                    "MASQUERADE",
+                    "-m",
+                    "comment",
+                    "--comment",
+                    &firewall_comment,
+                ],
+            )
+            .context("Could not create the IPv6 MASQUERADE rule")?;
+        for direction in [
+            &["-s", &clat_ip][..],
+            &[
+                "-d",
+                &clat_ip,
+                "-m",
+                "conntrack",
+                "--ctstate",
+                "RELATED,ESTABLISHED",
+            ][..],
+        ] {
+            let rule = [
+                &["-t", "filter", "-I", "FORWARD", "1"][..],
+                direction,
+                &[
+                    "-j",
+                    "ACCEPT",
+                    "-m",
+                    "comment",
+                    "--comment",
+                    &firewall_comment,
+                ],
+            ]
+            .concat();
+            teardown
+                .firewall
+                .add6(&mut record, &rule)
+                .context("could not add firewall rule to allow IPv6 forwarding")?;
+        }
+    }
 
-                new_route.add(&nl_sock, 0x400)?;
+    // Marked traffic is routed with the session's table, numbered like its
+    // default mark. Kernels with nexthop objects get a group of them, with
+    // ids which are unique to the session as well
+    let session_table = 0xd1000000 | (unsafe { libc::getpid() } as u32 & 0x00ffffff);
+    let nexthop_ids = Some(unsafe { libc::getpid() } as u32 * 256)
+        .filter(|_| features.nexthop_objects && !uplink_gateways.is_empty());
+    if !uplink_gateways.is_empty() {
+        let table = format!("{session_table}");
+        match nexthop_ids {
+            Some(group) => {
+                let ids = (group + 1..)
+                    .take(uplink_gateways.len())
+                    .map(|id| format!("{id}"))
+                    .collect::<Vec<_>>();
+                for (id, (dev, gateway)) in ids.iter().zip(&uplink_gateways) {
+                    record.ip(
+                        None,
+                        &[
+                            "nexthop",
+                            "add",
+                            "id",
+                            id,
+                            "via",
+                            &format!("{gateway}"),
+                            "dev",
+                            dev,
+                        ],
+                        Some(&["nexthop", "del", "id", id]),
+                    );
+                }
+                let group = format!("{group}");
+                record.ip(
+                    None,
+                    &["nexthop", "add", "id", &group, "group", &ids.join("/")],
+                    Some(&["nexthop", "del", "id", &group]),
+                );
+                record.ip(
+                    None,
+                    &["route", "add", "default", "nhid", &group, "table", &table],
+                    None,
+                );
+            }
+            None => {
+                let gateways = uplink_gateways
+                    .iter()
+                    .map(|(_, gateway)| format!("{gateway}"))
+                    .collect::<Vec<_>>();
+                let mut route = vec!["route", "add", "default", "table", &table];
+                for (gateway, (dev, _)) in gateways.iter().zip(&uplink_gateways) {
+                    route.extend(["nexthop", "via", gateway, "dev", dev]);
+                }
+                record.ip(
+                    None,
+                    &route,
+                    Some(&["route", "del", "default", "table", &table]),
+                );
             }
         }
+        backend
+            .add_multipath_default(session_table, &uplink_gateways, nexthop_ids)
+            .context("Could not create the route over the uplinks")?;
+        teardown.uplinks = Some(teardown::UplinkRoute {
+            table: session_table,
+            hops: uplink_gateways.len(),
+            nexthop_ids,
+            fwmark: None,
+        });
+
+        let priority = format!("{}", backend::RULE_PRIORITY);
+        let rule = ["fwmark", &fwmark, "table", &table, "priority", &priority];
+        record.ip(
+            None,
+            &[&["rule", "add"][..], &rule].concat(),
+            Some(&[&["rule", "del"][..], &rule].concat()),
+        );
+        backend
+            .add_fwmark_rule(session_fwmark, session_table)
+            .context("Could not route the session's traffic over the uplinks")?;
+        if let Some(uplinks) = &mut teardown.uplinks {
+            uplinks.fwmark = Some(session_fwmark);
+        }
     }
 
-    // iptables -t filter -A FORWARD -s 172.31.254.254 -j ACCEPT
-    std::process::Command::new("iptables")
-        .args([
-            "-t",
-            "filter",
-            "-A",
-            "FORWARD",
-            "-s",
-            &format!("{container_tunnel_ip}"),
-            "-j",
-            "ACCEPT",
-            "-m",
-            "comment",
-            "--comment",
-            &firewall_comment,
-        ])
-        .output()
-        .context("could not add firewall rule to allow traffic forwarding")?;
+    drop(host_lock);
+
+    // Everything past here needs the namespace, so a dry run ends with what
+    // it has worked out so far
+    if args.dry_run {
+        println!(
+            "Would connect the session through {host_link_name} ({host_tunnel_ip}/{len}) and {container_link_name} ({container_tunnel_ip}/{len}), leaving through {egress}",
+            len = tunnel.prefixlen,
+            egress = egress_ifs.join(", "),
+        );
+        println!("Would run:");
+        for step in record.steps() {
+            println!("  {step}");
+        }
+        return Ok(());
+    }
 
     let (unshare_semaphore, movelink_semaphore) = unsafe {
         let unshare_semaphore = libc::mmap(
@@ -384,7 +1172,7 @@ fn main() -> anyhow::Result<()> {
             0,
             0,
         ) as *mut libc::sem_t;
-        let ret = libc::sem_init(movelink_semaphore, 1, 1);
+        let ret = libc::sem_init(movelink_semaphore, 1, 0);
         if ret != 0 {
             Err(std::io::Error::from_raw_os_error(ret))
                 .context("could not initialize the semaphore for moving links")?;
@@ -399,20 +1187,24 @@ fn main() -> anyhow::Result<()> {
         // Error
         ..0 => {
             // Error out
-            println!("Error forking!");
+            log::error!("could not fork: {}", std::io::Error::last_os_error());
+            drop(teardown);
             std::process::exit(3);
         }
         // Child
         0 => {
-            drop(nl_sock);
+            drop(backend);
 
             // 16: ip netns add downloader
             {
-                let unshare_result =
-                    unsafe { libc::unshare(libc::CLONE_NEWNS | libc::CLONE_NEWNET) };
+                let mut flags = libc::CLONE_NEWNS | libc::CLONE_NEWNET;
+                if args.hostname.is_some() {
+                    flags |= libc::CLONE_NEWUTS;
+                }
+                let unshare_result = unsafe { libc::unshare(flags) };
 
                 if unshare_result < 0 {
-                    eprintln!("Failed to unshare! {:?}", std::io::Error::last_os_error());
+                    log::error!("could not unshare: {}", std::io::Error::last_os_error());
                     std::process::exit(2);
                 }
 
@@ -425,6 +1217,13 @@ fn main() -> anyhow::Result<()> {
                 }
             }
 
+            if let Some(hostname) = &args.hostname
+                && unsafe { libc::sethostname(hostname.as_ptr().cast(), hostname.len()) } != 0
+            {
+                Err(std::io::Error::last_os_error())
+                    .context("child: could not set the hostname")?;
+            }
+
             // 18: ip link set downloader.1 netns downloader
             unsafe {
                 let ret = libc::sem_wait(movelink_semaphore);
@@ -434,114 +1233,264 @@ fn main() -> anyhow::Result<()> {
                 }
             }
 
-            let nl_sock =
-                nl::netlink::Socket::new().context("child: could not get new netlink socket")?;
-            let links = nl_sock
-                .get_links()
-                .context("child: could not get new links object")?;
-
-            let set_interface_up = nl::route::Link::new();
-            set_interface_up.set_flags(nl::route::Link::IFF_UP);
+            let backend = backend::open(args.backend, args.debug_netlink)
+                .context("child: could not open network backend")?;
 
             // 22: ip -n downloader link set lo up
-            {
-                let lo = links
-                    .iter()
-                    .find(|l| l.name() == "lo")
-                    .ok_or(anyhow::anyhow!("Could not find lo loopback interface!"))?;
-                lo.change(&nl_sock, &set_interface_up)
-                    .context("child: could not set loopback up")?;
+            backend
+                .set_link_up("lo")
+                .context("child: could not set loopback up")?;
+
+            backend
+                .set_link_mtu(&container_link_name, link_mtu)
+                .context("child: could not set the MTU of the container interface")?;
+            if let Some(txqlen) = args.txqueuelen {
+                backend
+                    .set_link_txqlen(&container_link_name, txqlen)
+                    .context("child: could not set the transmit queue length")?;
+            }
+            if let Some(qdisc) = args.qdisc {
+                backend
+                    .set_link_qdisc(&container_link_name, qdisc)
+                    .context("child: could not set the qdisc of the container interface")?;
+            }
+            if let Some(rate) = args.rate {
+                backend
+                    .limit_link_rate(&container_link_name, rate)
+                    .context("child: could not limit the rate of uploads")?;
             }
 
             // 23: ip -n downloader link set downloader.1 up
-            container_link
-                .change(&nl_sock, &set_interface_up)
+            backend
+                .set_link_up(&container_link_name)
                 .context("child: could not set container interface up")?;
+            args.offloads
+                .apply(&container_link_name)
+                .context("child: could not configure offloads")?;
 
             // 24: ip -n downloader addr add 172.31.254.254/30 dev downloader.1
-            {
-                let local_ip = nl::route::Addr::from(container_tunnel_ip);
-                let broadcast_ip = nl::route::Addr::from(tunnel_broadcast_ip);
-                let rt_local_ip = nl::route::RtAddr::new()
-                    .ok_or(anyhow::anyhow!("Could not allocate new tunnel IP address"))?;
-
-                rt_local_ip
-                    .set_local(local_ip)
-                    .context("child: could not set host IP for tunnel route")?;
-                rt_local_ip.set_ifindex(container_link.ifindex());
-                rt_local_ip
-                    .set_broadcast(broadcast_ip)
-                    .context("child: could not set broadcast for tunnel route")?;
-                rt_local_ip.set_prefixlen(30);
-
-                rt_local_ip
-                    .add(&nl_sock, 0x200)
-                    .context("child: could not create tunnel route")?;
-            }
+            backend
+                .add_addr(
+                    &container_link_name,
+                    container_tunnel_ip,
+                    tunnel.prefixlen,
+                    tunnel.broadcast,
+                    Some(host_tunnel_ip).filter(|_| tunnel.peer),
+                )
+                .context("child: could not create tunnel route")?;
 
             // 25: ip -n downloader route add default via 172.31.254.253
-            {
-                let hop = nl::route::Nexthop::new()
-                    .ok_or(anyhow::anyhow!("Could not allocate a new nexthop object"))?;
-
-                let gateway = nl::route::Addr::from(host_tunnel_ip);
-
-                hop.set_ifindex(container_link.ifindex());
-                hop.set_gateway(gateway);
+            match (&impersonation, &nat64) {
+                // IPv4 goes to the translator instead, and IPv6 to the host
+                (None, Some(nat64)) => {
+                    for command in nat64.namespace_commands(&container_link_name) {
+                        nat64::ip(&command).context("child: could not route through NAT64")?;
+                    }
+                }
+                (None, None) => backend
+                    .add_route(
+                        Ipv4Addr::UNSPECIFIED,
+                        0,
+                        &container_link_name,
+                        Some(host_tunnel_ip),
+                    )
+                    .context("child: could not create default route")?,
+                // The tunnel is only left to reach the host; everything else
+                // goes out of the macvlan with the borrowed identity
+                (Some(identity), _) => {
+                    backend
+                        .set_link_up(&macvlan_name)
+                        .context("child: could not set the macvlan up")?;
+                    backend
+                        .add_addr(
+                            &macvlan_name,
+                            identity.ip,
+                            identity.prefixlen,
+                            Some(identity.broadcast),
+                            None,
+                        )
+                        .context("child: could not add the impersonated address")?;
+                    backend
+                        .add_route(Ipv4Addr::UNSPECIFIED, 0, &macvlan_name, identity.gateway)
+                        .context("child: could not create default route")?;
+                }
+            }
 
-                let new_route = nl::route::Route::new().ok_or(anyhow::anyhow!(
-                    "Could not allocate a new default route object for the namespace"
-                ))?;
+            if let Some((_, tunnel6)) = &ipv6 {
+                backend
+                    .add_addr6(
+                        &container_link_name,
+                        tunnel6.container,
+                        tunnel::Addresses6::PREFIXLEN,
+                    )
+                    .context("child: could not add the IPv6 tunnel address")?;
+                backend
+                    .add_default_route6(&container_link_name, Some(tunnel6.host))
+                    .context("child: could not create the IPv6 default route")?;
+            }
 
-                let default_route = nl::route::Addr::from(Ipv4Addr::new(0, 0, 0, 0));
-                default_route.set_cidrlen(0);
+            // IPv6 goes through the broker, inside of the IPv4 the session
+            // already has
+            if let Some(broker) = &args.broker {
+                backend
+                    .add_sit(
+                        broker::LINK_NAME,
+                        container_tunnel_ip,
+                        broker.server,
+                        broker::TTL,
+                    )
+                    .context("child: could not create the 6in4 tunnel")?;
+                backend
+                    .set_link_mtu(broker::LINK_NAME, link_mtu - broker::OVERHEAD)
+                    .context("child: could not set the MTU of the 6in4 tunnel")?;
+                backend
+                    .set_link_up(broker::LINK_NAME)
+                    .context("child: could not set the 6in4 tunnel up")?;
+                backend
+                    .add_addr6(broker::LINK_NAME, broker.client, broker.prefixlen)
+                    .context("child: could not add the 6in4 tunnel address")?;
+                backend
+                    .add_default_route6(broker::LINK_NAME, None)
+                    .context("child: could not create the IPv6 default route")?;
+            }
 
-                new_route.add_nexthop(&hop);
-                new_route.set_dst(default_route);
+            hook_env.set("CHILD_PID", unsafe { libc::getpid() });
+            hooks::run(
+                &args.hooks,
+                hooks::Stage::PostUp,
+                hooks::Place::Namespace,
+                &hook_env,
+                None,
+            )
+            .context("child: could not run post-up hooks")?;
 
-                new_route
-                    .add(&nl_sock, 0x400)
-                    .context("child: could not create default route")?;
+            // Limit what the program can tamper with on the host
+            if args.changes_mounts() {
+                mounts::make_private().context("child: could not make mounts private")?;
+            }
+            // First, while the state directory can still be written to, and
+            // so that making /etc read only covers the new file too
+            if !args.dns.is_empty() {
+                mounts::resolv_conf(&args.dns)
+                    .context("child: could not replace /etc/resolv.conf")?;
+            }
+            if args.protect_system {
+                for dir in mounts::SYSTEM_DIRS {
+                    mounts::bind_read_only(Path::new(dir))
+                        .with_context(|| format!("child: could not protect {dir}"))?;
+                }
+            }
+            if let Some(protection) = args.protect_home {
+                mounts::protect_homes(protection, &protected_homes, download_dir.as_deref())
+                    .context("child: could not protect home directories")?;
+            }
+            if let Some(dir) = &download_dir {
+                mounts::read_only_root_with_download_dir(dir)
+                    .context("child: could not make the filesystem read only")?;
+            }
+            if args.private_tmp {
+                mounts::private_tmp().context("child: could not create a private /tmp")?;
+            }
+            if let Some(dir) = download_dir.as_ref().filter(|_| args.download_dir_cwd) {
+                std::env::set_current_dir(dir)
+                    .context("child: could not change to the download directory")?;
+            }
+            if let Some(dir) = &workdir {
+                std::env::set_current_dir(dir)
+                    .with_context(|| format!("child: could not change to {}", dir.display()))?;
             }
 
             // 41: ip netns exec downloader bash
             {
                 // TODO: remount /sys
 
-                let argv: Vec<*const std::ffi::c_char> = args
+                let argv_c = args
                     .program_args
                     .iter()
-                    .map(|s| s.as_ptr() as *const i8)
+                    .map(|s| CString::new(s.as_bytes()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .context("child: program arguments cannot contain NUL bytes")?;
+                let argv: Vec<*const std::ffi::c_char> = argv_c
+                    .iter()
+                    .map(|s| s.as_ptr())
                     .chain(Some(std::ptr::null()))
                     .collect();
 
-                let env: Vec<String> = std::env::vars()
-                    .map(|(k, v)| {
+                // Without --user the program runs as root, but should still
+                // find the configuration of whoever ran sudo
+                let account_env = match (&account, &invoker) {
+                    (Some(account), _) => account.env(),
+                    (None, Some(invoker)) if args.sudo_env => sudo::env(invoker),
+                    (None, _) => vec![],
+                };
+
+                let env: Vec<CString> = std::env::vars_os()
+                    .filter(|(k, _)| {
+                        !args.clear_env || environment::KEPT.iter().any(|name| k == name)
+                    })
+                    .filter(|(k, _)| !account_env.iter().any(|(name, _)| k == name))
+                    .chain(account_env.iter().map(|(k, v)| (k.into(), v.into())))
+                    .filter(|(k, _)| !program_env.iter().any(|(name, _)| k == name))
+                    .chain(program_env.iter().cloned())
+                    .filter_map(|(k, v)| {
+                        let mut var = k.as_bytes().to_vec();
+                        var.push(b'=');
                         if k == "PS1" {
-                            format!("PS1=(download-shell) {v}")
-                        } else {
-                            format!("{k}={v}")
+                            var.extend_from_slice(b"(download-shell) ");
                         }
+                        var.extend_from_slice(v.as_bytes());
+                        CString::new(var).ok()
                     })
                     .collect();
 
                 let envp: Vec<*const std::ffi::c_char> = env
                     .iter()
-                    .map(|m| m.as_ptr() as *const _)
+                    .map(|m| m.as_ptr())
                     .chain(Some(std::ptr::null()))
                     .collect();
 
-                let program = args.program.clone();
+                let program = CString::new(program_path.as_os_str().as_bytes())
+                    .context("child: program path cannot contain NUL bytes")?;
+
+                let exec_program = || -> anyhow::Result<()> {
+                    match (&account, group) {
+                        (Some(account), group) => account
+                            .become_user(group.unwrap_or(account.gid))
+                            .with_context(|| {
+                                format!("child: could not switch to {}", account.name)
+                            })?,
+                        (None, Some(group)) => {
+                            user::become_group(group).context("child: could not switch group")?
+                        }
+                        (None, None) => {}
+                    }
+
+                    unsafe { libc::execve(program.as_ptr(), argv.as_ptr(), envp.as_ptr()) };
 
-                unsafe {
-                    libc::execve(program.as_ptr() as *const i8, argv.as_ptr(), envp.as_ptr())
+                    Err(std::io::Error::last_os_error().into())
                 };
 
-                Err(std::io::Error::last_os_error())?;
+                match transcript {
+                    Some(transcript) => {
+                        let code = transcript
+                            .run(&args.program_args.join(" "), exec_program)
+                            .context("child: could not record the session")?;
+                        std::process::exit(code);
+                    }
+                    None => exec_program()?,
+                }
             }
         }
         // Parent
         1.. => {
+            teardown.child = Some(child);
+            if let Err(e) = signals::forward_to(child) {
+                log::warn!("signals will not be passed on to the session: {e}");
+            }
+            if let Some(deadline) = deadline {
+                signals::end_at(child, deadline, std::time::Duration::from_secs(10));
+            }
+
             // 16: ip netns add downloader
             unsafe {
                 let ret = libc::sem_wait(unshare_semaphore);
@@ -550,14 +1499,322 @@ fn main() -> anyhow::Result<()> {
                         .context("parent: could not wait for unshare")?;
                 }
             };
+            hook_env.set("CHILD_PID", child);
+
+            // The program goes into a cgroup before it starts, so that what
+            // it leaves running can still be found once it exits
+            let members = match cgroup::Cgroup::create(unsafe { libc::getpid() }) {
+                Ok(cgroup) => match cgroup.add(child) {
+                    Ok(()) => Some(processes::Members::Cgroup(cgroup)),
+                    Err(e) => {
+                        log::warn!("could not move the session into its cgroup: {e}");
+                        None
+                    }
+                },
+                Err(_) => None,
+            }
+            .or_else(|| {
+                processes::netns_inode(&child.to_string())
+                    .ok()
+                    .map(processes::Members::Netns)
+            });
+
+            // Keep the namespace alive past the end of the program, so that
+            // pre-down hooks can still run in it, connections left behind
+            // can be closed, and named sessions can be entered again
+            let netns_handle = if args.close_connections
+                || args.name.is_some()
+                || args
+                    .hooks
+                    .iter()
+                    .any(|h| h.stage == hooks::Stage::PreDown && h.place == hooks::Place::Namespace)
+            {
+                Some(
+                    std::fs::File::open(format!("/proc/{child}/ns/net"))
+                        .context("parent: could not open the session's network namespace")?,
+                )
+            } else {
+                None
+            };
 
+            // The namespace is anonymous, so recordings name it after the
+            // session instead
+            let netns = firewall_comment.as_str();
+            record.netns(netns);
+
+            // Runs until the program exits
+            let mut translator = None;
+            // Kept in the state directory while the session is up
+            let mut report_file = None;
+
+            // 15: ip link add downloader.0 type veth peer name downloader.1
             // 18: ip link set downloader.1 netns downloader
             {
-                let changes = nl::route::Link::new();
-                changes.set_ns_pid(child);
-                container_link
-                    .change(&nl_sock, &changes)
-                    .context("parent: could not move device to namespace")?;
+                let queues = args.queues.map(|q| format!("{q}"));
+                let queue_args = match &queues {
+                    Some(q) => vec!["numtxqueues", q, "numrxqueues", q],
+                    None => vec![],
+                };
+                record.ip(
+                    None,
+                    &[
+                        &["link", "add", &host_link_name][..],
+                        &queue_args,
+                        &["type", "veth", "peer", "name", &container_link_name],
+                        &queue_args,
+                        &["netns", netns],
+                    ]
+                    .concat(),
+                    Some(&["link", "delete", &host_link_name]),
+                );
+
+                // Abandoning the session only deletes the link if it exists,
+                // which covers failing to move the peer as well
+                teardown.host_link = Some(host_link_name.clone());
+                if features.veth_peer_netns {
+                    backend
+                        .add_veth(
+                            &host_link_name,
+                            &container_link_name,
+                            Some(child),
+                            args.queues,
+                        )
+                        .context("parent: could not create the download tunnel")?;
+                } else {
+                    backend
+                        .add_veth(&host_link_name, &container_link_name, None, args.queues)
+                        .context("parent: could not create the download tunnel")?;
+                    backend
+                        .set_link_netns(&container_link_name, child)
+                        .context("parent: could not move device to namespace")?;
+                }
+
+                let mtu = format!("{link_mtu}");
+                record.ip(
+                    None,
+                    &["link", "set", "dev", &host_link_name, "mtu", &mtu],
+                    None,
+                );
+                backend
+                    .set_link_mtu(&host_link_name, link_mtu)
+                    .context("parent: could not set the MTU of the downloader interface")?;
+
+                // Downloads queue up on the host end, so that is where the
+                // choice of qdisc matters most
+                record.queueing(
+                    None,
+                    &host_link_name,
+                    args.txqueuelen,
+                    args.qdisc.map(backend::Qdisc::name),
+                );
+                if let Some(txqlen) = args.txqueuelen {
+                    backend
+                        .set_link_txqlen(&host_link_name, txqlen)
+                        .context("parent: could not set the transmit queue length")?;
+                }
+                if let Some(qdisc) = args.qdisc {
+                    backend
+                        .set_link_qdisc(&host_link_name, qdisc)
+                        .context("parent: could not set the qdisc of the downloader interface")?;
+                }
+                if let Some(rate) = args.rate {
+                    record.rate(None, &host_link_name, rate);
+                    backend
+                        .limit_link_rate(&host_link_name, rate)
+                        .context("parent: could not limit the rate of downloads")?;
+                }
+
+                // The host end of the tunnel goes away with the session, so
+                // there is nothing to restore afterwards
+                if per_interface_forwarding {
+                    let key = format!("net/ipv4/conf/{host_link_name}/forwarding");
+                    record.sysctl(&key, "1", None);
+                    sysctl::write(&key, 1).context(
+                        "parent: could not enable forwarding on the downloader interface",
+                    )?;
+                }
+
+                // 17: ip link set downloader.0 up
+                record.link_up(None, &host_link_name);
+                backend
+                    .set_link_up(&host_link_name)
+                    .context("parent: could not set downloader interface to be up")?;
+                if let Some(mirror) = &args.mirror {
+                    record.mirror(&host_link_name, mirror);
+                    backend
+                        .mirror_link(&host_link_name, mirror)
+                        .with_context(|| format!("parent: could not mirror traffic to {mirror}"))?;
+                }
+                if !args.offloads.is_empty() {
+                    let ethtool = [
+                        &["ethtool", "-K", &host_link_name][..],
+                        &args.offloads.ethtool_args(),
+                    ]
+                    .concat();
+                    record.command(&ethtool, None);
+                }
+                args.offloads
+                    .apply(&host_link_name)
+                    .context("parent: could not configure offloads")?;
+
+                // 20: ip addr add 172.31.254.253/30 dev downloader.0
+                let host_peer = Some(container_tunnel_ip).filter(|_| tunnel.peer);
+                record.add_addr(
+                    None,
+                    &host_link_name,
+                    host_tunnel_ip,
+                    tunnel.prefixlen,
+                    tunnel.broadcast,
+                    host_peer,
+                );
+                backend
+                    .add_addr(
+                        &host_link_name,
+                        host_tunnel_ip,
+                        tunnel.prefixlen,
+                        tunnel.broadcast,
+                        host_peer,
+                    )
+                    .context("parent: could not add the IP address to the host tunnel interface")?;
+
+                // 38: ip route add $1/32 dev downloader.0
+                for ip in &args.source_ips {
+                    record.add_route(None, *ip, 32, &host_link_name, None);
+                    backend
+                        .add_route(*ip, 32, &host_link_name, None)
+                        .context("parent: could not add the route for ARP proxy")?;
+                }
+
+                if let Some((egress6_if, tunnel6)) = &ipv6 {
+                    record.add_addr6(
+                        None,
+                        &host_link_name,
+                        tunnel6.host,
+                        tunnel::Addresses6::PREFIXLEN,
+                    );
+                    backend
+                        .add_addr6(&host_link_name, tunnel6.host, tunnel::Addresses6::PREFIXLEN)
+                        .context(
+                            "parent: could not add the IPv6 address to the host tunnel interface",
+                        )?;
+
+                    if let Some(ip) = args.source_ip6 {
+                        record.neigh_proxy(egress6_if, ip);
+                        backend.add_neigh_proxy(egress6_if, ip).with_context(|| {
+                            format!("parent: could not proxy neighbour discovery for {ip}")
+                        })?;
+                        teardown.neigh_proxy = Some((egress6_if.clone(), ip));
+                    }
+                }
+
+                if let Some(nat64) = &nat64 {
+                    for command in nat64.host_commands(&host_link_name) {
+                        record.ip(
+                            None,
+                            &command.iter().map(String::as_str).collect::<Vec<_>>(),
+                            None,
+                        );
+                        nat64::ip(&command)
+                            .context("parent: could not configure IPv6 on the tunnel")?;
+                    }
+                }
+
+                // The macvlan joins the veth peer in the namespace before
+                // the child is told to configure its links
+                if let Some(identity) = &impersonation {
+                    record.ip(
+                        None,
+                        &[
+                            "link",
+                            "add",
+                            "link",
+                            &default_if,
+                            "name",
+                            &macvlan_name,
+                            "address",
+                            &identity.mac.to_string(),
+                            "type",
+                            "macvlan",
+                            "netns",
+                            netns,
+                        ],
+                        None,
+                    );
+                    backend
+                        .add_macvlan(&macvlan_name, &default_if, identity.mac)
+                        .context("parent: could not create the macvlan")?;
+                    backend
+                        .set_link_netns(&macvlan_name, child)
+                        .context("parent: could not move the macvlan to the namespace")?;
+                }
+
+                // The address was only free when it was picked; give it up
+                // if its owner comes back
+                if let Some(ip) = args.source_ip.or(impersonated).filter(|_| args.auto_source)
+                    && let Err(e) = autoip::watch(&default_if, ip, child)
+                {
+                    log::warn!("could not keep checking whether {ip} is free: {e}");
+                }
+
+                if args.mdns {
+                    for protocol in mdns::PROTOCOLS {
+                        if let Err(e) = mdns::reflect(protocol, &default_if, &host_link_name) {
+                            log::warn!("could not reflect {}: {e}", protocol.name);
+                        }
+                    }
+                }
+
+                // The TUN device has to exist before the child routes to it
+                if let Some(nat64) = &nat64 {
+                    translator = Some(
+                        nat64
+                            .start(child)
+                            .context("parent: could not start the NAT64 translator")?,
+                    );
+                }
+
+                // Described before the child goes on to start the program, so
+                // that the description comes before anything it prints
+                let (host_ifindex, container_ifindex) = match backend.link_indexes(&host_link_name)
+                {
+                    Ok((index, peer)) => (Some(index), peer),
+                    Err(e) => {
+                        log::warn!("could not look up the tunnel interfaces: {e:#}");
+                        (None, None)
+                    }
+                };
+                let report = report::Report {
+                    pid: unsafe { libc::getpid() },
+                    child_pid: child,
+                    name: args.name.clone(),
+                    program: args.program.clone(),
+                    started: report::now(),
+                    egress_if: default_if.clone(),
+                    host_link: report::Link {
+                        name: host_link_name.clone(),
+                        ifindex: host_ifindex,
+                    },
+                    container_link: report::Link {
+                        name: container_link_name.clone(),
+                        ifindex: container_ifindex,
+                    },
+                    host_ip: host_tunnel_ip,
+                    container_ip: container_tunnel_ip,
+                    prefixlen: tunnel.prefixlen,
+                    source_ip: args.source_ip.or(impersonated),
+                    host_ip6: ipv6.as_ref().map(|(_, tunnel6)| tunnel6.host),
+                    container_ip6: ipv6.as_ref().map(|(_, tunnel6)| tunnel6.container),
+                    source_ip6: args.source_ip6,
+                    firewall_comment: firewall_comment.clone(),
+                    fwmark: fwmark.clone(),
+                };
+                if args.output == report::Output::Json {
+                    println!("{}", report.to_json());
+                }
+                match report.write() {
+                    Ok(written) => report_file = Some(written),
+                    Err(e) => log::warn!("could not describe the session: {e:#}"),
+                }
 
                 unsafe {
                     let ret = libc::sem_post(movelink_semaphore);
@@ -568,6 +1825,212 @@ fn main() -> anyhow::Result<()> {
                 }
             }
 
+            if let Some(path) = &args.record {
+                // The child configures its side of the tunnel itself, so its
+                // part of the recording is filled in here on its behalf
+                record.link_up(Some(netns), "lo");
+                record.ip(
+                    Some(netns),
+                    &[
+                        "link",
+                        "set",
+                        "dev",
+                        &container_link_name,
+                        "mtu",
+                        &format!("{link_mtu}"),
+                    ],
+                    None,
+                );
+                record.queueing(
+                    Some(netns),
+                    &container_link_name,
+                    args.txqueuelen,
+                    args.qdisc.map(backend::Qdisc::name),
+                );
+                if let Some(rate) = args.rate {
+                    record.rate(Some(netns), &container_link_name, rate);
+                }
+                record.link_up(Some(netns), &container_link_name);
+                if !args.offloads.is_empty() {
+                    let ethtool = [
+                        &[
+                            "ip",
+                            "netns",
+                            "exec",
+                            netns,
+                            "ethtool",
+                            "-K",
+                            &container_link_name,
+                        ][..],
+                        &args.offloads.ethtool_args(),
+                    ]
+                    .concat();
+                    record.command(&ethtool, None);
+                }
+                record.add_addr(
+                    Some(netns),
+                    &container_link_name,
+                    container_tunnel_ip,
+                    tunnel.prefixlen,
+                    tunnel.broadcast,
+                    Some(host_tunnel_ip).filter(|_| tunnel.peer),
+                );
+                match (&impersonation, &nat64) {
+                    (None, Some(nat64)) => {
+                        for command in nat64.namespace_commands(&container_link_name) {
+                            record.ip(
+                                Some(netns),
+                                &command.iter().map(String::as_str).collect::<Vec<_>>(),
+                                None,
+                            );
+                        }
+                    }
+                    (None, None) => record.add_route(
+                        Some(netns),
+                        Ipv4Addr::UNSPECIFIED,
+                        0,
+                        &container_link_name,
+                        Some(host_tunnel_ip),
+                    ),
+                    (Some(identity), _) => {
+                        record.link_up(Some(netns), &macvlan_name);
+                        record.add_addr(
+                            Some(netns),
+                            &macvlan_name,
+                            identity.ip,
+                            identity.prefixlen,
+                            Some(identity.broadcast),
+                            None,
+                        );
+                        record.add_route(
+                            Some(netns),
+                            Ipv4Addr::UNSPECIFIED,
+                            0,
+                            &macvlan_name,
+                            identity.gateway,
+                        );
+                    }
+                }
+                if let Some((_, tunnel6)) = &ipv6 {
+                    record.add_addr6(
+                        Some(netns),
+                        &container_link_name,
+                        tunnel6.container,
+                        tunnel::Addresses6::PREFIXLEN,
+                    );
+                    record.add_default_route6(
+                        Some(netns),
+                        &container_link_name,
+                        Some(tunnel6.host),
+                    );
+                }
+                if let Some(broker) = &args.broker {
+                    record.ip(
+                        Some(netns),
+                        &[
+                            "link",
+                            "add",
+                            broker::LINK_NAME,
+                            "type",
+                            "sit",
+                            "local",
+                            &container_tunnel_ip.to_string(),
+                            "remote",
+                            &broker.server.to_string(),
+                            "ttl",
+                            &broker::TTL.to_string(),
+                        ],
+                        None,
+                    );
+                    record.ip(
+                        Some(netns),
+                        &[
+                            "link",
+                            "set",
+                            "dev",
+                            broker::LINK_NAME,
+                            "mtu",
+                            &(link_mtu - broker::OVERHEAD).to_string(),
+                        ],
+                        None,
+                    );
+                    record.link_up(Some(netns), broker::LINK_NAME);
+                    record.ip(
+                        Some(netns),
+                        &[
+                            "-6",
+                            "addr",
+                            "add",
+                            &format!("{}/{}", broker.client, broker.prefixlen),
+                            "dev",
+                            broker::LINK_NAME,
+                        ],
+                        None,
+                    );
+                    record.ip(
+                        Some(netns),
+                        &["-6", "route", "add", "default", "dev", broker::LINK_NAME],
+                        None,
+                    );
+                }
+                let program = program_path.to_string_lossy();
+                let exec = ["ip", "netns", "exec", netns, &program]
+                    .into_iter()
+                    .chain(args.program_args.iter().skip(1).map(String::as_str))
+                    .collect::<Vec<_>>();
+                record.command(&exec, None);
+
+                match record.write(path) {
+                    Ok(undo) => log::info!(
+                        "Recorded the session to {} (undo with {})",
+                        path.display(),
+                        undo.display()
+                    ),
+                    Err(e) => log::warn!("could not write {}: {e}", path.display()),
+                }
+            }
+
+            let session = plugins::Session {
+                pid: unsafe { libc::getpid() },
+                child_pid: child,
+                program: &args.program,
+                default_if: &default_if,
+                host_link: &host_link_name,
+                container_link: &container_link_name,
+                host_ip: host_tunnel_ip,
+                container_ip: container_tunnel_ip,
+                source_ip: args.source_ip.or(impersonated),
+                backend: backend.as_ref(),
+            };
+            for plugin in &mut plugins {
+                if let Err(e) = plugin.setup(&session) {
+                    log::warn!("{} plugin failed to set up: {e:#}", plugin.name());
+                }
+            }
+
+            if let Err(e) = hooks::run(
+                &args.hooks,
+                hooks::Stage::PostUp,
+                hooks::Place::Host,
+                &hook_env,
+                None,
+            ) {
+                log::warn!("{e:#}");
+            }
+
+            // The mount namespace is only needed to enter a named session
+            let mntns_handle = match (&session_claim, &netns_handle) {
+                (Some(claim), Some(netns)) => {
+                    let mntns = std::fs::File::open(format!("/proc/{child}/ns/mnt"))
+                        .context("parent: could not open the session's mount namespace")?;
+                    claim
+                        .publish(netns, &mntns)
+                        .context("parent: could not record where the session is")?;
+                    Some(mntns)
+                }
+                _ => None,
+            };
+
             // 41: ip netns exec downloader bash
             {
                 let mut status = 0;
@@ -575,47 +2038,142 @@ fn main() -> anyhow::Result<()> {
                     libc::waitpid(child, &mut status, 0);
                     libc::kill(child, libc::SIGKILL);
                 }
+                teardown.child = None;
             }
 
-            // 43: ip netns delete downloader
-            // Implicitly performed by the child process dying
-        }
-    }
+            if let Some(name) = &args.name {
+                log::info!(
+                    "The session {name} stays up until it is ended with ^C or `download-shell destroy {name}`"
+                );
+                match signals::wait_for_end(deadline) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => log::warn!("the session ran out of time, ending it"),
+                    Err(e) => log::warn!("could not wait to be told to end the session: {e}"),
+                }
+            }
+            drop(mntns_handle);
+            drop(report_file);
+
+            let helpers = translator
+                .as_ref()
+                .and_then(nat64::Translator::pid)
+                .into_iter()
+                .collect::<Vec<_>>();
+            if let Some(members) = &members {
+                match (args.leftovers, members.list(&helpers)) {
+                    (_, Ok(left)) if left.is_empty() => {}
+                    (processes::Leftovers::Kill, Ok(_)) => {
+                        match members.kill(&helpers, std::time::Duration::from_secs(5)) {
+                            Ok(n) => {
+                                log::info!("Stopped {n} processes left running in the session")
+                            }
+                            Err(e) => {
+                                log::warn!("could not stop the session's processes: {e}")
+                            }
+                        }
+                    }
+                    (processes::Leftovers::Wait, Ok(left)) => {
+                        log::warn!(
+                            "{} processes are still running in the session, which stays up until they exit:{}",
+                            left.len(),
+                            processes::listing(&left)
+                        );
+                        if let Err(e) =
+                            members.wait(&helpers, std::time::Duration::from_secs(1), None)
+                        {
+                            log::warn!("could not wait for the session's processes: {e}");
+                        }
+                    }
+                    (processes::Leftovers::Warn, Ok(_)) => {}
+                    (_, Err(e)) => {
+                        log::warn!("could not look for processes left in the session: {e}")
+                    }
+                }
+            }
 
-    // Find the firewall rules with the comment specified above and delete them
-    let clean_iptables = |table: &str, chain: &str| -> anyhow::Result<()> {
-        let current_rules = std::process::Command::new("iptables")
-            .args(["-t", table, "--line-numbers", "-vn", "-L", chain])
-            .output()
-            .context("could not list firewall rules")?
-            .stdout;
+            // Whatever the program left running could keep transfers going,
+            // and the NAT state for them would outlive the session
+            if args.close_connections {
+                if let Some(netns) = &netns_handle {
+                    match connections::close_all(netns) {
+                        Ok(0) => {}
+                        Ok(n) => log::info!("Closed {n} connections left open by the session"),
+                        Err(e) => log::warn!("could not close connections: {e:#}"),
+                    }
+                }
 
-        let output_utf8 = std::str::from_utf8(&current_rules)?;
+                let sources = std::iter::once(container_tunnel_ip)
+                    .chain(impersonation.as_ref().map(|identity| identity.ip));
+                for source in sources {
+                    if let Err(e) = connections::forget_conntrack(source) {
+                        log::warn!("could not delete the NAT state of {source}: {e:#}");
+                    }
+                }
+            }
+            drop(translator);
 
-        let Some(rule_line) = output_utf8
-            .lines()
-            .find(|l| l.contains(&format!("/* {firewall_comment} */")))
-        else {
-            eprintln!("warning: could not clear out firewall rules from the {table} table: could not find rule");
-            return Ok(());
-        };
+            // Background processes keep the namespace alive, but lose their
+            // way out once the tunnel is torn down
+            if args.leftovers == processes::Leftovers::Warn
+                && let Some(Ok(left)) = members.as_ref().map(|m| m.list(&[]))
+                && !left.is_empty()
+            {
+                log::warn!(
+                    "{} processes are still running in the session, and will lose network access:{}",
+                    left.len(),
+                    processes::listing(&left)
+                );
+            }
 
-        let rule_num: u16 = rule_line
-            .split_ascii_whitespace()
-            .next()
-            .ok_or(anyhow::anyhow!("warning: could not clear out firewall rules from the {table} table: could not parse rule number"))?
-            .parse()?;
+            if let (Some(dir), Some(owner)) = (&download_dir, download_owner) {
+                match sudo::chown_tree(dir, download_dir_created, owner) {
+                    Ok(0) => {}
+                    Ok(n) => log::info!("Gave {n} downloaded files to {}", owner.name),
+                    Err(e) => log::warn!(
+                        "could not give the files in {} to {}: {e}",
+                        dir.display(),
+                        owner.name
+                    ),
+                }
+            }
 
-        std::process::Command::new("iptables")
-            .args(["-t", table, "-D", chain, &format!("{rule_num}")])
-            .output()
-            .context("could not delete firewall rule")?;
+            for place in [hooks::Place::Namespace, hooks::Place::Host] {
+                if let Err(e) = hooks::run(
+                    &args.hooks,
+                    hooks::Stage::PreDown,
+                    place,
+                    &hook_env,
+                    netns_handle.as_ref(),
+                ) {
+                    log::warn!("{e:#}");
+                }
+            }
+            drop(netns_handle);
 
-        Ok(())
-    };
+            for plugin in plugins.iter_mut().rev() {
+                if let Err(e) = plugin.teardown(&session) {
+                    log::warn!("{} plugin failed to tear down: {e:#}", plugin.name());
+                }
+            }
+
+            teardown.finish(backend.as_ref())?;
+
+            // 43: ip netns delete downloader
+            // Implicitly performed by the child process dying
+        }
+    }
 
-    clean_iptables("filter", "FORWARD").context("could not clear filter rule")?;
-    clean_iptables("nat", "POSTROUTING").context("could not clear NAT rule")?;
+    drop(session_claim);
+
+    if let Err(e) = hooks::run(
+        &args.hooks,
+        hooks::Stage::PostDown,
+        hooks::Place::Host,
+        &hook_env,
+        None,
+    ) {
+        log::warn!("{e:#}");
+    }
 
     Ok(())
 }
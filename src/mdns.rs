@@ -0,0 +1,178 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! A reflector for mDNS and LLMNR traffic, so that `.local` names and
+//! services announced on the LAN can be resolved from inside the namespace
+//!
+//! Only multicast traffic is relayed; replies a responder unicasts back to
+//! the querier will reach the host instead of the namespace
+
+use std::{
+    ffi::CString,
+    io,
+    net::{Ipv4Addr, SocketAddrV4, UdpSocket},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+/// A multicast name resolution protocol to relay
+#[derive(Debug, Clone, Copy)]
+pub struct Protocol {
+    pub name: &'static str,
+    pub group: Ipv4Addr,
+    pub port: u16,
+    ttl: u32,
+}
+
+/// Multicast DNS, RFC 6762
+pub const MDNS: Protocol = Protocol {
+    name: "mDNS",
+    group: Ipv4Addr::new(224, 0, 0, 251),
+    port: 5353,
+    ttl: 255,
+};
+
+/// Link-Local Multicast Name Resolution, RFC 4795
+pub const LLMNR: Protocol = Protocol {
+    name: "LLMNR",
+    group: Ipv4Addr::new(224, 0, 0, 252),
+    port: 5355,
+    ttl: 1,
+};
+
+pub const PROTOCOLS: [Protocol; 2] = [MDNS, LLMNR];
+
+fn setsockopt<T>(fd: &OwnedFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Opens a socket which receives the multicast traffic of the protocol
+/// arriving on one interface, and sends to the group out of the same
+/// interface
+fn open(protocol: &Protocol, ifname: &str) -> io::Result<UdpSocket> {
+    let name = CString::new(ifname)?;
+    let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if ifindex == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let (on, off): (libc::c_int, libc::c_int) = (1, 0);
+
+    // Responders such as avahi are likely already bound to the port
+    setsockopt(&fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, &on)?;
+    setsockopt(&fd, libc::SOL_SOCKET, libc::SO_REUSEPORT, &on)?;
+
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            name.as_ptr() as *const libc::c_void,
+            name.as_bytes_with_nul().len() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let addr = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: protocol.port.to_be(),
+        sin_addr: libc::in_addr { s_addr: 0 },
+        sin_zero: [0; 8],
+    };
+    let ret = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_in as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mreq = libc::ip_mreqn {
+        imr_multiaddr: libc::in_addr {
+            s_addr: u32::from(protocol.group).to_be(),
+        },
+        imr_address: libc::in_addr { s_addr: 0 },
+        imr_ifindex: ifindex as libc::c_int,
+    };
+    setsockopt(&fd, libc::IPPROTO_IP, libc::IP_ADD_MEMBERSHIP, &mreq)?;
+    setsockopt(&fd, libc::IPPROTO_IP, libc::IP_MULTICAST_IF, &mreq)?;
+    // Only hear the group joined above, and never our own transmissions, or
+    // the two sides would echo packets back and forth
+    setsockopt(&fd, libc::IPPROTO_IP, libc::IP_MULTICAST_ALL, &off)?;
+    setsockopt(&fd, libc::IPPROTO_IP, libc::IP_MULTICAST_LOOP, &off)?;
+    setsockopt(&fd, libc::IPPROTO_IP, libc::IP_MULTICAST_TTL, &protocol.ttl)?;
+
+    Ok(UdpSocket::from(fd))
+}
+
+/// Copies every datagram received on `from` to the group on `to`
+fn relay(protocol: Protocol, from: UdpSocket, to: UdpSocket) {
+    let dst = SocketAddrV4::new(protocol.group, protocol.port);
+    let mut buf = [0u8; 9000];
+
+    loop {
+        let len = match from.recv_from(&mut buf) {
+            Ok((len, _)) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                log::warn!("{} reflector stopped: {e}", protocol.name);
+                return;
+            }
+        };
+
+        if let Err(e) = to.send_to(&buf[..len], dst) {
+            log::warn!("could not reflect {} packet: {e}", protocol.name);
+        }
+    }
+}
+
+/// Starts relaying the multicast traffic of `protocol` in both directions
+/// between the LAN interface and the host end of the tunnel. The relays run
+/// on background threads for the rest of the life of the process
+pub fn reflect(protocol: Protocol, lan: &str, tunnel: &str) -> io::Result<()> {
+    let lan_sock = open(&protocol, lan)?;
+    let tunnel_sock = open(&protocol, tunnel)?;
+
+    let (lan_rx, tunnel_rx) = (lan_sock.try_clone()?, tunnel_sock.try_clone()?);
+
+    std::thread::spawn(move || relay(protocol, lan_rx, tunnel_sock));
+    std::thread::spawn(move || relay(protocol, tunnel_rx, lan_sock));
+
+    Ok(())
+}
@@ -0,0 +1,306 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Changes to the filesystem as seen from inside of the session. All of these
+//! must be performed after the child has unshared its mount namespace, so
+//! that nothing here leaks out to the host
+
+use std::{
+    ffi::CString,
+    fs::File,
+    net::IpAddr,
+    os::{fd::AsRawFd, unix::ffi::OsStrExt},
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use anyhow::Context;
+
+use crate::lock;
+
+/// How the home directories should be protected from the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HomeProtection {
+    /// Home directories are visible but cannot be written to
+    ReadOnly,
+    /// Home directories are replaced by empty tmpfs mounts
+    Hidden,
+}
+
+/// Directories which make up the operating system
+pub const SYSTEM_DIRS: &[&str] = &["/usr", "/etc", "/boot", "/opt"];
+
+fn c_path(path: &Path) -> anyhow::Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+fn mount(
+    source: Option<&Path>,
+    target: &Path,
+    fstype: Option<&str>,
+    flags: libc::c_ulong,
+    data: Option<&str>,
+) -> anyhow::Result<()> {
+    let source = source.map(c_path).transpose()?;
+    let target_c = c_path(target)?;
+    let fstype = fstype.map(CString::new).transpose()?;
+    let data = data.map(CString::new).transpose()?;
+
+    let ret = unsafe {
+        libc::mount(
+            source.as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null()),
+            target_c.as_ptr(),
+            fstype.as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null()),
+            flags,
+            data.as_ref()
+                .map(|s| s.as_ptr() as *const libc::c_void)
+                .unwrap_or(ptr::null()),
+        )
+    };
+
+    if ret != 0 {
+        Err(std::io::Error::last_os_error())
+            .with_context(|| format!("could not mount {}", target.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Stops mount events from propagating back to the host. Distributions
+/// which mount / as shared would otherwise see every change made below
+pub fn make_private() -> anyhow::Result<()> {
+    mount(
+        None,
+        Path::new("/"),
+        None,
+        libc::MS_REC | libc::MS_PRIVATE,
+        None,
+    )
+}
+
+/// Undoes the octal escaping /proc/self/mountinfo applies to spaces and
+/// other special characters
+fn unescape_mountinfo(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escape = bytes
+            .get(i + 1..i + 4)
+            .filter(|e| bytes[i] == b'\\' && e.iter().all(|c| (b'0'..=b'7').contains(c)));
+
+        match escape {
+            Some(e) => {
+                out.push(e.iter().fold(0u8, |acc, c| (acc << 3) | (c - b'0')));
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    PathBuf::from(std::ffi::OsStr::from_bytes(&out))
+}
+
+/// Lists every mount point at or below the path provided
+fn submounts(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mountinfo =
+        std::fs::read_to_string("/proc/self/mountinfo").context("could not read mountinfo")?;
+
+    let mut mounts = mountinfo
+        .lines()
+        .filter_map(|l| l.split(' ').nth(4))
+        .map(unescape_mountinfo)
+        .filter(|m| m.starts_with(path))
+        .collect::<Vec<_>>();
+
+    mounts.sort();
+    mounts.dedup();
+
+    Ok(mounts)
+}
+
+/// Pseudo filesystems which are left writable when the entire filesystem
+/// is made read only, as programs expect to be able to write to them
+pub const PSEUDO_DIRS: &[&str] = &["/dev", "/proc", "/sys"];
+
+/// Makes the path, and everything mounted below it, read only
+pub fn bind_read_only(path: &Path) -> anyhow::Result<()> {
+    bind_read_only_except(path, &[])
+}
+
+/// Makes the path, and everything mounted below it, read only. Mounts at or
+/// below any of the paths in `except` keep their current flags
+pub fn bind_read_only_except(path: &Path, except: &[&Path]) -> anyhow::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    mount(Some(path), path, None, libc::MS_BIND | libc::MS_REC, None)?;
+
+    for mount_point in submounts(path)?
+        .into_iter()
+        .filter(|m| !except.iter().any(|e| m.starts_with(e)))
+    {
+        // A read only remount has to keep the restrictions the mount already
+        // had, or the kernel refuses to perform it
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        let mount_point_c = c_path(&mount_point)?;
+        if unsafe { libc::statvfs(mount_point_c.as_ptr(), &mut stat) } != 0 {
+            continue;
+        }
+
+        let mut flags = libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY;
+        for (st, ms) in [
+            (libc::ST_NOSUID, libc::MS_NOSUID),
+            (libc::ST_NODEV, libc::MS_NODEV),
+            (libc::ST_NOEXEC, libc::MS_NOEXEC),
+            (libc::ST_NOATIME, libc::MS_NOATIME),
+            (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+            (libc::ST_RELATIME, libc::MS_RELATIME),
+        ] {
+            if stat.f_flag & st != 0 {
+                flags |= ms;
+            }
+        }
+
+        mount(None, &mount_point, None, flags, None)?;
+    }
+
+    Ok(())
+}
+
+/// Makes the entire filesystem read only, apart from the pseudo filesystems
+/// and the download directory. The download directory is bind mounted onto
+/// itself first so that it stands as its own mount and can be skipped
+pub fn read_only_root_with_download_dir(download_dir: &Path) -> anyhow::Result<()> {
+    mount(
+        Some(download_dir),
+        download_dir,
+        None,
+        libc::MS_BIND | libc::MS_REC,
+        None,
+    )?;
+
+    let except = PSEUDO_DIRS
+        .iter()
+        .map(Path::new)
+        .chain(Some(download_dir))
+        .collect::<Vec<_>>();
+
+    bind_read_only_except(Path::new("/"), &except)
+}
+
+/// Covers the path with an empty tmpfs
+pub fn hide(path: &Path) -> anyhow::Result<()> {
+    if !path.is_dir() {
+        return Ok(());
+    }
+
+    mount(
+        Some(Path::new("tmpfs")),
+        path,
+        Some("tmpfs"),
+        libc::MS_NOSUID | libc::MS_NODEV,
+        Some("mode=0755"),
+    )
+}
+
+/// Gives the session its own temporary directories, which disappear along
+/// with the mount namespace once the session ends
+pub fn private_tmp() -> anyhow::Result<()> {
+    for dir in ["/tmp", "/var/tmp"] {
+        let dir = Path::new(dir);
+        if !dir.is_dir() {
+            continue;
+        }
+
+        mount(
+            Some(Path::new("tmpfs")),
+            dir,
+            Some("tmpfs"),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            Some("mode=1777"),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Covers /etc/resolv.conf with one listing only `servers`, as the resolver
+/// of the host is often on a loopback address the namespace cannot reach
+pub fn resolv_conf(servers: &[IpAddr]) -> anyhow::Result<()> {
+    let contents = servers
+        .iter()
+        .map(|server| format!("nameserver {server}\n"))
+        .collect::<String>();
+
+    // The mount keeps the file alive once it is unlinked, so nothing is
+    // left behind in the state directory
+    let path = lock::state_dir().join(format!("resolv.conf.{}", unsafe { libc::getpid() }));
+    std::fs::write(&path, contents)
+        .with_context(|| format!("could not write {}", path.display()))?;
+    let result = mount(
+        Some(&path),
+        Path::new("/etc/resolv.conf"),
+        None,
+        libc::MS_BIND,
+        None,
+    );
+    let _ = std::fs::remove_file(&path);
+
+    result
+}
+
+/// Applies the protection to each of the home directories listed. A
+/// download directory inside of a hidden home is put back where it was, on
+/// top of the empty one
+pub fn protect_homes(
+    protection: HomeProtection,
+    homes: &[PathBuf],
+    download_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    // Held open to reach the download directory once its home is covered
+    let download_dir = match download_dir {
+        Some(dir)
+            if protection == HomeProtection::Hidden
+                && homes.iter().any(|home| dir.starts_with(home)) =>
+        {
+            let kept =
+                File::open(dir).with_context(|| format!("could not open {}", dir.display()))?;
+            Some((dir, kept))
+        }
+        _ => None,
+    };
+
+    for home in homes {
+        match protection {
+            HomeProtection::ReadOnly => bind_read_only(home)?,
+            HomeProtection::Hidden => hide(home)?,
+        }
+    }
+
+    if let Some((dir, kept)) = download_dir {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("could not recreate {}", dir.display()))?;
+        let source = PathBuf::from(format!("/proc/self/fd/{}", kept.as_raw_fd()));
+        mount(Some(&source), dir, None, libc::MS_BIND | libc::MS_REC, None)?;
+    }
+
+    Ok(())
+}
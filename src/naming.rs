@@ -0,0 +1,96 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Names of the links created for a session
+
+/// The prefix used for link names when none is specified
+pub const DEFAULT_PREFIX: &str = "dlsh";
+
+/// Checks that the prefix only contains characters the kernel accepts in
+/// interface names
+pub fn validate_prefix(prefix: &str) -> anyhow::Result<()> {
+    if prefix.is_empty() {
+        anyhow::bail!("link name prefix cannot be empty");
+    }
+
+    if let Some(c) = prefix
+        .chars()
+        .find(|c| *c == '/' || *c == ':' || c.is_whitespace() || !c.is_ascii())
+    {
+        anyhow::bail!("link name prefix cannot contain {c:?}");
+    }
+
+    Ok(())
+}
+
+/// Size of the kernel's interface name buffer, including the trailing NUL
+pub const IFNAMSIZ: usize = 16;
+
+/// Returns the names of the host and container ends of the veth pair. The
+/// prefix is shortened as needed to keep the names within [`IFNAMSIZ`], and
+/// a counter is appended to the pid if either name is already taken
+pub fn link_names(
+    prefix: &str,
+    pid: libc::pid_t,
+    existing: &[String],
+) -> anyhow::Result<(String, String)> {
+    for attempt in 0..100 {
+        let id = match attempt {
+            0 => format!("{pid}"),
+            n => format!("{pid}-{n}"),
+        };
+
+        // Room for the id, the ".0" suffix and the trailing NUL
+        let max_prefix = IFNAMSIZ - 1 - id.len() - 2;
+        let prefix = &prefix[..prefix.len().min(max_prefix)];
+
+        let host = format!("{prefix}{id}.0");
+        let container = format!("{prefix}{id}.1");
+
+        if !existing.contains(&host) && !existing.contains(&container) {
+            return Ok((host, container));
+        }
+    }
+
+    anyhow::bail!("could not find an unused name for the links with prefix {prefix}")
+}
+
+/// The name of the macvlan used by `--impersonate`, which goes alongside
+/// the veth pair named by [`link_names`]
+pub fn macvlan_name(host: &str) -> String {
+    format!("{}.2", host.strip_suffix(".0").unwrap_or(host))
+}
+
+/// Recovers the pid of the session that created a link, if the name is one
+/// [`link_names`] could have produced for the prefix
+pub fn session_pid(prefix: &str, name: &str) -> Option<libc::pid_t> {
+    let id = name
+        .strip_suffix(".0")
+        .or(name.strip_suffix(".1"))
+        .or(name.strip_suffix(".2"))?;
+    let id = match id.rsplit_once('-') {
+        Some((id, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => id,
+        _ => id,
+    };
+
+    let digits = id.bytes().rev().take_while(|b| b.is_ascii_digit()).count();
+    let (link_prefix, pid) = id.split_at(id.len() - digits);
+
+    if link_prefix.is_empty() || !prefix.starts_with(link_prefix) {
+        return None;
+    }
+
+    pid.parse().ok()
+}
@@ -0,0 +1,275 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Keeps programs which only speak IPv4 working on hosts whose uplink only
+//! has IPv6, the way 464XLAT (RFC 6877) does. tayga runs in the namespace
+//! as the customer side translator, turning the IPv4 traffic of the session
+//! into IPv6 addressed to the NAT64 prefix of the network, whose NAT64
+//! gateway carries it the rest of the way. Names resolve as usual, over
+//! IPv6 and through the DNS64 resolver of the network
+
+use std::{
+    fs::File,
+    net::{Ipv4Addr, Ipv6Addr, ToSocketAddrs},
+    os::{fd::AsRawFd, unix::process::CommandExt},
+    path::PathBuf,
+    process::{Child, Command},
+};
+
+use anyhow::Context;
+
+/// The prefix of RFC 6052, used when the network doesn't advertise one
+pub const WELL_KNOWN_PREFIX: Ipv6Addr = Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0);
+
+/// The name of the translator's TUN device inside of the namespace
+pub const TUN_NAME: &str = "nat64";
+
+/// The address tayga sends ICMP errors from, out of the range RFC 7335
+/// reserves for translators
+const TAYGA_IPV4: Ipv4Addr = Ipv4Addr::new(192, 0, 0, 2);
+
+/// The addresses RFC 7050 has `ipv4only.arpa` resolve to
+const IPV4ONLY_ARPA: [Ipv4Addr; 2] = [Ipv4Addr::new(192, 0, 0, 170), Ipv4Addr::new(192, 0, 0, 171)];
+
+/// Parses a NAT64 prefix, which has to be a /96
+pub fn parse_prefix(prefix: &str) -> anyhow::Result<Ipv6Addr> {
+    let (addr, len) = prefix.split_once('/').unwrap_or((prefix, "96"));
+    if len != "96" {
+        anyhow::bail!("only /96 NAT64 prefixes are supported");
+    }
+
+    let addr = addr.parse::<Ipv6Addr>()?;
+    if addr.segments()[6..] != [0, 0] {
+        anyhow::bail!("{addr} has bits set past the /96 prefix length");
+    }
+
+    Ok(addr)
+}
+
+/// Finds the NAT64 prefix of the network by looking up the AAAA records of
+/// `ipv4only.arpa`, which a DNS64 resolver synthesizes from the prefix
+pub fn discover_prefix() -> Option<Ipv6Addr> {
+    ("ipv4only.arpa", 0)
+        .to_socket_addrs()
+        .ok()?
+        .filter_map(|addr| match addr.ip() {
+            std::net::IpAddr::V6(ip) => Some(ip),
+            _ => None,
+        })
+        .find(|ip| {
+            let octets = ip.octets();
+            IPV4ONLY_ARPA.contains(&Ipv4Addr::new(
+                octets[12], octets[13], octets[14], octets[15],
+            ))
+        })
+        .map(|ip| {
+            let mut octets = ip.octets();
+            octets[12..].fill(0);
+            Ipv6Addr::from(octets)
+        })
+}
+
+/// The interface of the default IPv6 route, for hosts with no IPv4 default
+/// route at all
+pub fn default_interface() -> anyhow::Result<String> {
+    let output = Command::new("ip")
+        .args(["-6", "-o", "route", "show", "default"])
+        .output()
+        .context("could not run ip")?;
+
+    let routes = String::from_utf8_lossy(&output.stdout);
+    let mut words = routes.split_ascii_whitespace();
+    words
+        .find(|w| *w == "dev")
+        .and_then(|_| words.next())
+        .map(str::to_owned)
+        .ok_or(anyhow::anyhow!(
+            "Could not find an IPv4 or IPv6 default route"
+        ))
+}
+
+/// The IPv6 addressing of a session. Each session gets a unique local /64
+/// on its veth pair, derived from the pid of download-shell
+pub struct Nat64 {
+    pub prefix: Ipv6Addr,
+    pub host_ip: Ipv6Addr,
+    pub container_ip: Ipv6Addr,
+    /// The address the IPv4 traffic of the namespace is translated to
+    pub clat_ip: Ipv6Addr,
+    tayga_ip: Ipv6Addr,
+    container_ipv4: Ipv4Addr,
+}
+
+impl Nat64 {
+    pub fn new(prefix: Ipv6Addr, pid: libc::pid_t, container_ipv4: Ipv4Addr) -> Self {
+        let addr = |host| {
+            Ipv6Addr::new(
+                0xfd64,
+                0x646c,
+                (pid >> 16) as u16,
+                pid as u16,
+                0,
+                0,
+                0,
+                host,
+            )
+        };
+
+        Self {
+            prefix,
+            host_ip: addr(1),
+            container_ip: addr(2),
+            clat_ip: addr(3),
+            tayga_ip: addr(4),
+            container_ipv4,
+        }
+    }
+
+    /// The `ip` commands configuring the host end of the veth pair, all of
+    /// which are undone by the link going away
+    pub fn host_commands(&self, host_link: &str) -> Vec<Vec<String>> {
+        vec![
+            words(&format!(
+                "-6 addr add {}/64 dev {host_link} nodad",
+                self.host_ip
+            )),
+            words(&format!(
+                "-6 route add {}/128 via {} dev {host_link}",
+                self.clat_ip, self.container_ip
+            )),
+        ]
+    }
+
+    /// The `ip` commands run inside of the namespace once the translator's
+    /// TUN device exists, in place of the IPv4 default route via the host
+    pub fn namespace_commands(&self, container_link: &str) -> Vec<Vec<String>> {
+        vec![
+            words(&format!("link set {TUN_NAME} up")),
+            words(&format!(
+                "-6 addr add {}/64 dev {container_link} nodad",
+                self.container_ip
+            )),
+            words(&format!(
+                "-6 route add default via {} dev {container_link}",
+                self.host_ip
+            )),
+            words(&format!("-6 route add {}/128 dev {TUN_NAME}", self.clat_ip)),
+            words(&format!(
+                "route add default dev {TUN_NAME} src {}",
+                self.container_ipv4
+            )),
+        ]
+    }
+
+    fn tayga_config(&self) -> String {
+        format!(
+            "tun-device {TUN_NAME}\nipv4-addr {TAYGA_IPV4}\nipv6-addr {}\nprefix {}/96\nmap {} {}\n",
+            self.tayga_ip, self.prefix, self.container_ipv4, self.clat_ip
+        )
+    }
+
+    /// Creates the TUN device in the namespace of `child` and starts tayga
+    /// on it. The TUN device disappears along with the namespace
+    pub fn start(&self, child: libc::pid_t) -> anyhow::Result<Translator> {
+        let config = std::env::temp_dir().join(format!("dlsh-nat64-{child}.conf"));
+        std::fs::write(&config, self.tayga_config())
+            .context("could not write the tayga configuration")?;
+        let mut translator = Translator {
+            config,
+            process: None,
+        };
+
+        let netns = File::open(format!("/proc/{child}/ns/net"))
+            .context("could not open the session's network namespace")?;
+        let tayga = |args: &[&str]| {
+            let mut command = Command::new("tayga");
+            command.arg("--config").arg(&translator.config).args(args);
+
+            let fd = netns.as_raw_fd();
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::setns(fd, libc::CLONE_NEWNET) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+            command
+        };
+
+        let status = tayga(&["--mktun"])
+            .status()
+            .context("could not run tayga; install it to use --nat64")?;
+        if !status.success() {
+            anyhow::bail!("tayga could not create the {TUN_NAME} device: {status}");
+        }
+
+        let process = tayga(&["--nodetach"])
+            .spawn()
+            .context("could not start tayga")?;
+        translator.process = Some(process);
+
+        Ok(translator)
+    }
+}
+
+/// A running tayga, which is stopped when dropped
+pub struct Translator {
+    config: PathBuf,
+    process: Option<Child>,
+}
+
+impl Translator {
+    /// The pid of tayga, which runs in the namespace alongside the program
+    pub fn pid(&self) -> Option<libc::pid_t> {
+        self.process.as_ref().map(|p| p.id() as libc::pid_t)
+    }
+}
+
+impl Drop for Translator {
+    fn drop(&mut self) {
+        if let Some(process) = &mut self.process {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+        let _ = std::fs::remove_file(&self.config);
+    }
+}
+
+fn words(command: &str) -> Vec<String> {
+    command
+        .split_ascii_whitespace()
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Runs `ip` with the arguments given
+pub fn ip(args: &[String]) -> anyhow::Result<()> {
+    log::debug!("running ip {}", args.join(" "));
+    let output = Command::new("ip")
+        .args(args)
+        .output()
+        .context("could not run ip")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "`ip {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,169 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Toggling the segmentation and checksum offloads of a link through the
+//! ethtool ioctl, which works on every kernel and with either backend
+
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    str::FromStr,
+};
+
+// from linux/ethtool.h
+const ETHTOOL_SRXCSUM: u32 = 0x15;
+const ETHTOOL_STXCSUM: u32 = 0x17;
+const ETHTOOL_STSO: u32 = 0x1f;
+const ETHTOOL_SGSO: u32 = 0x24;
+const ETHTOOL_SGRO: u32 = 0x2c;
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct ethtool_value {
+    cmd: u32,
+    data: u32,
+}
+
+/// An offload which can be switched on or off, named as `ethtool -K` does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Rx,
+    Tx,
+    Tso,
+    Gso,
+    Gro,
+}
+
+impl Feature {
+    const ALL: [Feature; 5] = [
+        Feature::Rx,
+        Feature::Tx,
+        Feature::Tso,
+        Feature::Gso,
+        Feature::Gro,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Feature::Rx => "rx",
+            Feature::Tx => "tx",
+            Feature::Tso => "tso",
+            Feature::Gso => "gso",
+            Feature::Gro => "gro",
+        }
+    }
+
+    fn command(self) -> u32 {
+        match self {
+            Feature::Rx => ETHTOOL_SRXCSUM,
+            Feature::Tx => ETHTOOL_STXCSUM,
+            Feature::Tso => ETHTOOL_STSO,
+            Feature::Gso => ETHTOOL_SGSO,
+            Feature::Gro => ETHTOOL_SGRO,
+        }
+    }
+}
+
+/// The offloads to change on the veth pair, in the order given
+#[derive(Debug, Clone, Default)]
+pub struct Offloads(Vec<(Feature, bool)>);
+
+impl FromStr for Offloads {
+    type Err = anyhow::Error;
+
+    /// Parses a comma separated list of `FEATURE=on|off`, where `all`
+    /// stands for every feature
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut offloads = Vec::new();
+
+        for setting in s.split(',') {
+            let Some((name, state)) = setting.split_once('=') else {
+                anyhow::bail!("expected FEATURE=on|off, got '{setting}'");
+            };
+
+            let enable = match state {
+                "on" => true,
+                "off" => false,
+                _ => anyhow::bail!("expected on or off for {name}, got '{state}'"),
+            };
+
+            match name {
+                "all" => offloads.extend(Feature::ALL.map(|f| (f, enable))),
+                name => match Feature::ALL.into_iter().find(|f| f.name() == name) {
+                    Some(feature) => offloads.push((feature, enable)),
+                    None => anyhow::bail!(
+                        "unknown offload '{name}', expected one of: all, rx, tx, tso, gso, gro"
+                    ),
+                },
+            }
+        }
+
+        Ok(Self(offloads))
+    }
+}
+
+impl Offloads {
+    /// Whether any offload is to be changed at all
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The arguments `ethtool -K` takes to make the same changes
+    pub fn ethtool_args(&self) -> Vec<&'static str> {
+        self.0
+            .iter()
+            .flat_map(|(f, enable)| [f.name(), if *enable { "on" } else { "off" }])
+            .collect()
+    }
+
+    /// Applies the settings to the link with the name given
+    pub fn apply(&self, ifname: &str) -> anyhow::Result<()> {
+        if ifname.len() >= libc::IFNAMSIZ {
+            anyhow::bail!("link name {ifname} is too long");
+        }
+
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let sock = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        for (feature, enable) in &self.0 {
+            let mut value = ethtool_value {
+                cmd: feature.command(),
+                data: (*enable).into(),
+            };
+
+            let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+            for (dst, src) in ifr.ifr_name.iter_mut().zip(ifname.bytes()) {
+                *dst = src as libc::c_char;
+            }
+            ifr.ifr_ifru.ifru_data = &mut value as *mut ethtool_value as *mut libc::c_char;
+
+            let ret = unsafe { libc::ioctl(sock.as_raw_fd(), libc::SIOCETHTOOL, &mut ifr) };
+            if ret < 0 {
+                return Err(
+                    anyhow::Error::from(io::Error::last_os_error()).context(format!(
+                        "could not turn {} {} on {ifname}",
+                        feature.name(),
+                        if *enable { "on" } else { "off" }
+                    )),
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
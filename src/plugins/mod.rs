@@ -0,0 +1,95 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Setup and teardown steps compiled into the binary behind cargo features,
+//! for site specific integrations which need more than a shell hook, such
+//! as registering the session's addresses with an IPAM system
+
+use std::net::Ipv4Addr;
+
+use crate::backend::Backend;
+
+#[cfg(feature = "plugin-session-log")]
+mod session_log;
+
+/// What a plugin gets to see of the session it is extending. Only plugins
+/// read it, and a build may have none of them compiled in
+#[allow(dead_code)]
+pub struct Session<'a> {
+    /// The pid of the download-shell process running the session
+    pub pid: libc::pid_t,
+    /// The pid of the process inside of the namespace
+    pub child_pid: libc::pid_t,
+    pub program: &'a str,
+    pub default_if: &'a str,
+    pub host_link: &'a str,
+    pub container_link: &'a str,
+    pub host_ip: Ipv4Addr,
+    pub container_ip: Ipv4Addr,
+    pub source_ip: Option<Ipv4Addr>,
+    /// The backend used to configure the host, for plugins that need to
+    /// make network changes of their own
+    pub backend: &'a dyn Backend,
+}
+
+/// An extension to the setup and teardown of every session
+pub trait Plugin {
+    /// The name the plugin is enabled with on the command line
+    fn name(&self) -> &'static str;
+
+    /// Called once the host side of the tunnel is configured, before the
+    /// program starts
+    fn setup(&mut self, session: &Session) -> anyhow::Result<()>;
+
+    /// Called after the program exits, while the tunnel still exists
+    fn teardown(&mut self, session: &Session) -> anyhow::Result<()>;
+}
+
+/// Every plugin compiled into this binary
+#[allow(clippy::vec_init_then_push)]
+fn available() -> Vec<Box<dyn Plugin>> {
+    #[allow(unused_mut)]
+    let mut plugins = Vec::<Box<dyn Plugin>>::new();
+
+    #[cfg(feature = "plugin-session-log")]
+    plugins.push(Box::new(session_log::SessionLog));
+
+    plugins
+}
+
+/// Looks up the plugins with the names given, in the order given
+pub fn enable(names: &[String]) -> anyhow::Result<Vec<Box<dyn Plugin>>> {
+    let mut compiled_in = available();
+
+    names
+        .iter()
+        .map(|name| {
+            let index = compiled_in
+                .iter()
+                .position(|p| p.name() == name)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "plugin '{name}' is not compiled into this binary; available: {}",
+                        available()
+                            .iter()
+                            .map(|p| p.name())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })?;
+            Ok(compiled_in.swap_remove(index))
+        })
+        .collect()
+}
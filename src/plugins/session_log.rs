@@ -0,0 +1,67 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! An example plugin which appends a line to a log file whenever a session
+//! starts or stops
+
+use std::{fs::OpenOptions, io::Write};
+
+use super::{Plugin, Session};
+
+const LOG_PATH: &str = "/var/log/download-shell.log";
+
+pub struct SessionLog;
+
+impl SessionLog {
+    fn log(&self, event: &str, session: &Session) -> anyhow::Result<()> {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(LOG_PATH)?;
+
+        let source = match session.source_ip {
+            Some(ip) => ip.to_string(),
+            None => session.default_if.to_owned(),
+        };
+
+        writeln!(
+            log,
+            "{event} pid={} child={} program={} source={source} host={}/{} tunnel={}/{}",
+            session.pid,
+            session.child_pid,
+            session.program,
+            session.host_link,
+            session.host_ip,
+            session.container_link,
+            session.container_ip
+        )?;
+
+        Ok(())
+    }
+}
+
+impl Plugin for SessionLog {
+    fn name(&self) -> &'static str {
+        "session-log"
+    }
+
+    fn setup(&mut self, session: &Session) -> anyhow::Result<()> {
+        self.log("start", session)
+    }
+
+    fn teardown(&mut self, session: &Session) -> anyhow::Result<()> {
+        self.log("stop", session)
+    }
+}
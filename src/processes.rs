@@ -0,0 +1,196 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Finding the processes of a session, which are all of those in its
+//! cgroup or network namespace. Anything the program started in the
+//! background, such as a download left running with nohup, is still there
+//! after it exits
+
+use std::{
+    io,
+    os::unix::fs::MetadataExt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::cgroup::Cgroup;
+
+/// What to do with processes still running in a session once the program
+/// exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leftovers {
+    /// Tear the session down anyway, warning that they lose network access
+    Warn,
+    /// Stop them, with SIGTERM and then SIGKILL
+    Kill,
+    /// Keep the session up until they exit
+    Wait,
+}
+
+impl FromStr for Leftovers {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Leftovers::Warn),
+            "kill" => Ok(Leftovers::Kill),
+            "wait" => Ok(Leftovers::Wait),
+            _ => {
+                anyhow::bail!("unknown leftover handling '{s}', expected one of: warn, kill, wait")
+            }
+        }
+    }
+}
+
+/// A process found inside of a session
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: libc::pid_t,
+    pub ppid: libc::pid_t,
+    /// The command name, as shown by `ps -o comm`
+    pub name: String,
+}
+
+impl Process {
+    /// Reads the process with the pid given out of /proc
+    fn read(pid: libc::pid_t) -> Option<Self> {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+
+        // The command name may contain spaces and parentheses, but is
+        // followed by the last closing parenthesis: `pid (comm) state ppid`
+        let (name, rest) = stat.split_once(" (")?.1.rsplit_once(')')?;
+        let ppid = rest.split_ascii_whitespace().nth(1)?.parse().ok()?;
+
+        Some(Self {
+            pid,
+            ppid,
+            name: name.to_owned(),
+        })
+    }
+}
+
+/// The processes given one per line, for the end of a warning
+pub fn listing(processes: &[Process]) -> String {
+    processes
+        .iter()
+        .map(|p| format!("\n  {:>7} {}", p.pid, p.name))
+        .collect()
+}
+
+/// The inode identifying the network namespace of a process, or of this
+/// one with `"self"`
+pub fn netns_inode(pid: &str) -> io::Result<u64> {
+    Ok(std::fs::metadata(format!("/proc/{pid}/ns/net"))?.ino())
+}
+
+/// Every process currently running
+pub fn all() -> io::Result<impl Iterator<Item = Process>> {
+    Ok(std::fs::read_dir("/proc")?
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .filter_map(Process::read))
+}
+
+/// The processes in the network namespace with the inode given, which
+/// exited processes and those of other users can't be checked against
+pub fn in_netns(inode: u64) -> io::Result<Vec<Process>> {
+    Ok(all()?
+        .filter(|p| netns_inode(&p.pid.to_string()).is_ok_and(|i| i == inode))
+        .collect())
+}
+
+/// The processes which make up a session
+pub enum Members {
+    /// Those in the session's cgroup, which they can't leave
+    Cgroup(Cgroup),
+    /// Those in the session's network namespace, on hosts without cgroup v2
+    Netns(u64),
+}
+
+impl Members {
+    /// The processes of the session, leaving out those given, such as
+    /// helpers download-shell started in the namespace itself
+    pub fn list(&self, except: &[libc::pid_t]) -> io::Result<Vec<Process>> {
+        let processes = match self {
+            Members::Cgroup(cgroup) => cgroup
+                .pids()?
+                .into_iter()
+                .filter_map(Process::read)
+                .collect(),
+            Members::Netns(inode) => in_netns(*inode)?,
+        };
+
+        Ok(processes
+            .into_iter()
+            .filter(|p| !except.contains(&p.pid))
+            .collect())
+    }
+
+    /// Waits for every process of the session to exit, checking every
+    /// `interval`, or until `timeout` has passed. Returns whether they did
+    pub fn wait(
+        &self,
+        except: &[libc::pid_t],
+        interval: Duration,
+        timeout: Option<Duration>,
+    ) -> io::Result<bool> {
+        let start = Instant::now();
+
+        loop {
+            if self.list(except)?.is_empty() {
+                return Ok(true);
+            }
+            if timeout.is_some_and(|t| start.elapsed() >= t) {
+                return Ok(false);
+            }
+            std::thread::sleep(interval);
+        }
+    }
+
+    /// Asks the processes of the session to exit with SIGTERM, and kills
+    /// those still there after the grace period. Returns how many there were
+    pub fn kill(&self, except: &[libc::pid_t], grace: Duration) -> io::Result<usize> {
+        let processes = self.list(except)?;
+        for process in &processes {
+            unsafe { libc::kill(process.pid, libc::SIGTERM) };
+        }
+
+        if self.wait(except, Duration::from_millis(100), Some(grace))? {
+            return Ok(processes.len());
+        }
+
+        if let Members::Cgroup(cgroup) = self
+            && cgroup.kill().is_ok()
+        {
+            self.wait(except, Duration::from_millis(10), Some(grace))?;
+            return Ok(processes.len());
+        }
+
+        // New processes may be started while the others are being killed
+        let deadline = Instant::now() + grace;
+        loop {
+            let left = self.list(except)?;
+            if left.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            for process in &left {
+                unsafe { libc::kill(process.pid, libc::SIGKILL) };
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(processes.len())
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Locates the program to run inside of the namespace before any changes
+//! are made to the host, so that a typo doesn't leave behind a half
+//! configured network
+
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+/// Checks that the path refers to a regular file that can be executed
+fn check_executable(path: &Path) -> anyhow::Result<()> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| anyhow::anyhow!("Could not access {}: {e}", path.display()))?;
+
+    if !metadata.is_file() {
+        anyhow::bail!("{} is not a regular file", path.display());
+    }
+
+    if metadata.permissions().mode() & 0o111 == 0 {
+        anyhow::bail!("{} is not executable", path.display());
+    }
+
+    let path_c = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::access(path_c.as_ptr(), libc::X_OK) } != 0 {
+        anyhow::bail!(
+            "{} is not executable: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        );
+    }
+
+    Ok(())
+}
+
+/// Resolves the program the same way a shell would: names containing a slash
+/// are used as is, otherwise each directory in PATH is searched in order.
+/// The path returned is absolute, as the program may start in another
+/// directory, such as the download directory
+pub fn resolve(program: &str) -> anyhow::Result<PathBuf> {
+    if program.is_empty() {
+        anyhow::bail!("No program specified");
+    }
+
+    if program.contains('/') {
+        let path = std::path::absolute(program)?;
+        check_executable(&path)?;
+        return Ok(path);
+    }
+
+    let search_path =
+        std::env::var_os("PATH").unwrap_or_else(|| "/usr/local/bin:/usr/bin:/bin".into());
+
+    // PATH may name directories relative to this one, such as `.`, which
+    // --workdir would leave behind
+    std::env::split_paths(&search_path)
+        .filter_map(|dir| std::path::absolute(dir.join(program)).ok())
+        .find(|candidate| check_executable(candidate).is_ok())
+        .ok_or(anyhow::anyhow!("Could not find {program} in PATH"))
+}
+
+/// Whether the file is a script naming this program as its interpreter, as
+/// in `#!/usr/bin/download-shell -s 10.0.0.50`
+pub fn names_us_as_interpreter(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+
+    let mut line = Vec::new();
+    if BufReader::new(file.take(256))
+        .read_until(b'\n', &mut line)
+        .is_err()
+    {
+        return false;
+    }
+
+    let Some(rest) = line.strip_prefix(b"#!") else {
+        return false;
+    };
+    let rest = String::from_utf8_lossy(rest);
+    let Some(interpreter) = rest.split_whitespace().next() else {
+        return false;
+    };
+
+    match (std::fs::canonicalize(interpreter), std::env::current_exe()) {
+        (Ok(interpreter), Ok(us)) => interpreter == us,
+        _ => false,
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Ports of the host forwarded into the namespace with `--publish`, so that
+//! a server started inside of the session can be reached from the LAN
+
+use std::{fmt, str::FromStr};
+
+/// The transport protocol of a published port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Udp => "udp",
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(Self::Tcp),
+            "udp" => Ok(Self::Udp),
+            _ => anyhow::bail!("unknown protocol '{s}', expected tcp or udp"),
+        }
+    }
+}
+
+/// A port of the host, or of the source IP of the session, forwarded to a
+/// port inside of the namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Port {
+    pub host: u16,
+    pub namespace: u16,
+    pub protocol: Protocol,
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}/{}",
+            self.host,
+            self.namespace,
+            self.protocol.name()
+        )
+    }
+}
+
+impl FromStr for Port {
+    type Err = anyhow::Error;
+
+    /// Parses `HOSTPORT:NSPORT[/tcp|udp]`, which is TCP unless stated
+    /// otherwise
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ports, protocol) = match s.split_once('/') {
+            Some((ports, protocol)) => (ports, protocol.parse()?),
+            None => (s, Protocol::Tcp),
+        };
+        let (host, namespace) = ports
+            .split_once(':')
+            .ok_or(anyhow::anyhow!("expected HOSTPORT:NSPORT[/PROTO]"))?;
+
+        let port = |port: &str| match port.parse::<u16>() {
+            Ok(0) | Err(_) => Err(anyhow::anyhow!("invalid port '{port}'")),
+            Ok(port) => Ok(port),
+        };
+
+        Ok(Self {
+            host: port(host)?,
+            namespace: port(namespace)?,
+            protocol,
+        })
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Limiting the bandwidth of a session with `--rate`, through a token
+//! bucket filter on each end of the tunnel: the one on the host holds back
+//! downloads, and the one in the namespace uploads
+
+use std::{fmt, str::FromStr};
+
+/// A bandwidth, in bits a second
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate(u64);
+
+/// Units of bandwidth as tc(8) writes them, in bits a second
+const UNITS: [(&str, u64); 8] = [
+    ("gbit", 1_000_000_000),
+    ("mbit", 1_000_000),
+    ("kbit", 1_000),
+    ("bit", 1),
+    ("gbps", 8_000_000_000),
+    ("mbps", 8_000_000),
+    ("kbps", 8_000),
+    ("bps", 8),
+];
+
+impl Rate {
+    /// How long packets may wait for the bucket to fill before they are
+    /// dropped, in microseconds
+    pub const LATENCY: u32 = 50_000;
+
+    pub fn bytes(&self) -> u32 {
+        (self.0 / 8) as u32
+    }
+
+    /// The burst allowed above the rate: 10ms worth of traffic, but never
+    /// less than a segmentation offloaded packet, which veth passes whole
+    pub fn bucket(&self) -> u32 {
+        (self.bytes() / 100).max(65_536)
+    }
+
+    /// The arguments to tc(8) putting the filter on the link given
+    pub fn tc_args(&self, dev: &str) -> Vec<String> {
+        [
+            "qdisc",
+            "replace",
+            "dev",
+            dev,
+            "root",
+            "tbf",
+            "rate",
+            &format!("{}bit", self.0),
+            "burst",
+            &format!("{}", self.bucket()),
+            "latency",
+            &format!("{}ms", Self::LATENCY / 1000),
+        ]
+        .map(str::to_owned)
+        .to_vec()
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unit, bits) = UNITS[..4]
+            .iter()
+            .find(|(_, bits)| self.0.is_multiple_of(*bits))
+            .unwrap_or(&UNITS[3]);
+        write!(f, "{}{unit}", self.0 / bits)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    /// Parses a number followed by a unit, e.g. `5mbit` or `1.5mbps`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let (number, bits) = UNITS
+            .iter()
+            .find_map(|(unit, bits)| Some((lower.strip_suffix(unit)?, bits)))
+            .ok_or(anyhow::anyhow!(
+                "expected a number followed by one of bit, kbit, mbit, gbit, bps, kbps, mbps or gbps"
+            ))?;
+        let number = number
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite() && *n > 0.0)
+            .ok_or(anyhow::anyhow!("invalid rate '{s}'"))?;
+
+        // The kernel takes the rate in bytes a second as a 32 bit number
+        let rate = (number * *bits as f64) as u64;
+        if rate < 8 {
+            anyhow::bail!("the rate has to be at least 8bit");
+        }
+        if rate / 8 > i32::MAX as u64 {
+            anyhow::bail!("the rate can be at most 17gbit");
+        }
+
+        Ok(Self(rate))
+    }
+}
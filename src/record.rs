@@ -0,0 +1,336 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Records the changes a session makes to the system as an equivalent
+//! shell script of ip, iptables and sysctl commands, along with a script
+//! that undoes them
+
+use std::{
+    io::{self, Write},
+    net::{Ipv4Addr, Ipv6Addr},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use crate::rate::Rate;
+
+/// Quotes a word for a POSIX shell, if it needs quoting at all
+fn quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_./:,=+@%".contains(&b));
+
+    if safe {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+fn command_line(argv: &[&str]) -> String {
+    argv.iter().map(|w| quote(w)).collect::<Vec<_>>().join(" ")
+}
+
+/// The commands run so far, and the commands which revert them
+#[derive(Debug, Default)]
+pub struct Recorder {
+    steps: Vec<String>,
+    undo: Vec<String>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a command, and optionally the command that reverts it
+    pub fn command(&mut self, argv: &[&str], undo: Option<&[&str]>) {
+        self.steps.push(command_line(argv));
+        if let Some(undo) = undo {
+            self.undo.push(command_line(undo));
+        }
+    }
+
+    /// Records an `ip` command, run inside of the named network namespace if
+    /// one is given
+    pub fn ip(&mut self, netns: Option<&str>, args: &[&str], undo: Option<&[&str]>) {
+        let prefix = match netns {
+            Some(netns) => vec!["ip", "-n", netns],
+            None => vec!["ip"],
+        };
+
+        let argv = [&prefix[..], args].concat();
+        let undo = undo.map(|undo| [&prefix[..], undo].concat());
+        self.command(&argv, undo.as_deref());
+    }
+
+    /// Records the creation of a named network namespace, which stands in
+    /// for the anonymous namespace download-shell uses
+    pub fn netns(&mut self, name: &str) {
+        self.command(
+            &["ip", "netns", "add", name],
+            Some(&["ip", "netns", "delete", name]),
+        );
+    }
+
+    /// Records a link being set up
+    pub fn link_up(&mut self, netns: Option<&str>, name: &str) {
+        self.ip(netns, &["link", "set", name, "up"], None);
+    }
+
+    /// Records the transmit queue length and root qdisc of a link being
+    /// changed
+    pub fn queueing(
+        &mut self,
+        netns: Option<&str>,
+        dev: &str,
+        txqlen: Option<u32>,
+        qdisc: Option<&str>,
+    ) {
+        if let Some(txqlen) = txqlen {
+            self.ip(
+                netns,
+                &[
+                    "link",
+                    "set",
+                    "dev",
+                    dev,
+                    "txqueuelen",
+                    &format!("{txqlen}"),
+                ],
+                None,
+            );
+        }
+        if let Some(qdisc) = qdisc {
+            let netns = netns.map(|n| ["-n", n]);
+            let argv = [
+                &["tc"][..],
+                netns.as_ref().map(|n| &n[..]).unwrap_or_default(),
+                &["qdisc", "replace", "dev", dev, "root", qdisc],
+            ]
+            .concat();
+            self.command(&argv, None);
+        }
+    }
+
+    /// Records a token bucket filter being put on a link, mirroring
+    /// [`crate::backend::Backend::limit_link_rate`]
+    pub fn rate(&mut self, netns: Option<&str>, dev: &str, rate: Rate) {
+        let args = rate.tc_args(dev);
+        let netns = netns.map(|n| ["-n", n]);
+        let argv = [
+            &["tc"][..],
+            netns.as_ref().map(|n| &n[..]).unwrap_or_default(),
+            &args.iter().map(String::as_str).collect::<Vec<_>>(),
+        ]
+        .concat();
+        self.command(&argv, None);
+    }
+
+    /// Records the traffic of a link being copied to another, mirroring
+    /// [`crate::backend::Backend::mirror_link`]
+    pub fn mirror(&mut self, dev: &str, target: &str) {
+        self.command(&["tc", "qdisc", "add", "dev", dev, "clsact"], None);
+        for direction in ["ingress", "egress"] {
+            self.command(
+                &[
+                    "tc", "filter", "add", "dev", dev, direction, "matchall", "action", "mirred",
+                    "egress", "mirror", "dev", target,
+                ],
+                None,
+            );
+        }
+    }
+
+    /// Records an address being assigned, mirroring
+    /// [`crate::backend::Backend::add_addr`]
+    pub fn add_addr(
+        &mut self,
+        netns: Option<&str>,
+        dev: &str,
+        local: Ipv4Addr,
+        prefixlen: u8,
+        broadcast: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) {
+        let (local, prefix) = match peer {
+            Some(peer) => (
+                format!("{local}"),
+                vec!["peer".to_owned(), format!("{peer}/{prefixlen}")],
+            ),
+            None => (format!("{local}/{prefixlen}"), vec![]),
+        };
+        let broadcast = broadcast
+            .map(|b| vec!["broadcast".to_owned(), format!("{b}")])
+            .unwrap_or_default();
+
+        let args = [
+            vec!["addr".to_owned(), "add".to_owned(), local],
+            prefix,
+            broadcast,
+        ]
+        .concat();
+        let mut args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        args.extend(["dev", dev]);
+        self.ip(netns, &args, None);
+    }
+
+    /// Records a route being added, mirroring
+    /// [`crate::backend::Backend::add_route`]
+    pub fn add_route(
+        &mut self,
+        netns: Option<&str>,
+        dst: Ipv4Addr,
+        prefixlen: u8,
+        dev: &str,
+        gateway: Option<Ipv4Addr>,
+    ) {
+        let dst = match prefixlen {
+            0 => "default".to_owned(),
+            _ => format!("{dst}/{prefixlen}"),
+        };
+        let gateway = gateway.map(|g| format!("{g}"));
+
+        let mut args = vec!["route", "add", &dst];
+        if let Some(gateway) = &gateway {
+            args.extend(["via", gateway]);
+        }
+        args.extend(["dev", dev]);
+        self.ip(netns, &args, None);
+    }
+
+    /// Records an IPv6 address being assigned, mirroring
+    /// [`crate::backend::Backend::add_addr6`]
+    pub fn add_addr6(&mut self, netns: Option<&str>, dev: &str, local: Ipv6Addr, prefixlen: u8) {
+        let local = format!("{local}/{prefixlen}");
+        self.ip(
+            netns,
+            &["-6", "addr", "add", &local, "dev", dev, "nodad"],
+            None,
+        );
+    }
+
+    /// Records a default IPv6 route being added, mirroring
+    /// [`crate::backend::Backend::add_default_route6`]
+    pub fn add_default_route6(
+        &mut self,
+        netns: Option<&str>,
+        dev: &str,
+        gateway: Option<Ipv6Addr>,
+    ) {
+        let gateway = gateway.map(|g| format!("{g}"));
+
+        let mut args = vec!["-6", "route", "add", "default"];
+        if let Some(gateway) = &gateway {
+            args.extend(["via", gateway]);
+        }
+        args.extend(["dev", dev]);
+        self.ip(netns, &args, None);
+    }
+
+    /// Records a neighbour proxy entry being added on the host, mirroring
+    /// [`crate::backend::Backend::add_neigh_proxy`]
+    pub fn neigh_proxy(&mut self, dev: &str, ip: Ipv6Addr) {
+        let ip = format!("{ip}");
+        self.ip(
+            None,
+            &["-6", "neigh", "add", "proxy", &ip, "dev", dev],
+            Some(&["-6", "neigh", "del", "proxy", &ip, "dev", dev]),
+        );
+    }
+
+    /// Records an iptables or ip6tables rule being appended or inserted. The
+    /// undo command deletes the rule by its specification
+    pub fn iptables(&mut self, program: &str, args: &[&str]) {
+        let mut undo = vec![program];
+        let mut args_iter = args.iter().peekable();
+        while let Some(arg) = args_iter.next() {
+            match *arg {
+                "-A" | "-I" => {
+                    undo.push("-D");
+                    undo.extend(args_iter.next());
+                    // Skip the rule number of an insert
+                    args_iter.next_if(|a| a.bytes().all(|b| b.is_ascii_digit()));
+                }
+                arg => undo.push(arg),
+            }
+        }
+
+        self.command(&[&[program], args].concat(), Some(&undo));
+    }
+
+    /// Records a kernel parameter being set, given as a /proc/sys path
+    /// relative key. `previous` is the value the undo script restores
+    pub fn sysctl(&mut self, key: &str, value: &str, previous: Option<&str>) {
+        let set = format!("{key}={value}");
+        match previous.filter(|p| *p != value) {
+            Some(previous) => {
+                let restore = format!("{key}={previous}");
+                self.command(&["sysctl", "-w", &set], Some(&["sysctl", "-w", &restore]))
+            }
+            None => self.command(&["sysctl", "-w", &set], None),
+        }
+    }
+
+    /// The commands recorded so far, in order
+    pub fn steps(&self) -> &[String] {
+        &self.steps
+    }
+
+    /// Writes the script to `path`, and the undo script next to it with
+    /// `.undo` added before the extension
+    pub fn write(&self, path: &Path) -> io::Result<PathBuf> {
+        let undo_path = undo_path(path);
+
+        let header = format!(
+            "#!/bin/sh\n# Generated by download-shell {}\n",
+            env!("CARGO_PKG_VERSION")
+        );
+
+        let mut script = open_script(path)?;
+        write!(script, "{header}set -e\n\n")?;
+        for step in &self.steps {
+            writeln!(script, "{step}")?;
+        }
+
+        // Every undo step is attempted, even if an earlier one failed
+        let mut undo = open_script(&undo_path)?;
+        writeln!(undo, "{header}")?;
+        for step in self.undo.iter().rev() {
+            writeln!(undo, "{step}")?;
+        }
+
+        Ok(undo_path)
+    }
+}
+
+fn open_script(path: &Path) -> io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(path)
+}
+
+fn undo_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.undo.{}", ext.to_string_lossy()),
+        None => format!("{stem}.undo"),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,219 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! A description of a running session for other tools, printed with
+//! `--output json` and kept in the state directory while the session runs
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+    str::FromStr,
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::lock;
+
+/// How the session is described when it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// Progress messages only
+    Text,
+    /// A JSON object on a line of its own as well, once the session is up
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => anyhow::bail!("unknown output format '{s}', expected one of: text, json"),
+        }
+    }
+}
+
+/// The directory the descriptions of running sessions are kept in
+pub fn dir() -> PathBuf {
+    lock::state_dir().join("running")
+}
+
+/// Where the description of the session with the pid is kept
+fn path(pid: libc::pid_t) -> PathBuf {
+    dir().join(format!("{pid}.json"))
+}
+
+/// Removes the description a session which was killed left behind
+pub fn remove(pid: libc::pid_t) -> std::io::Result<()> {
+    std::fs::remove_file(path(pid))
+}
+
+/// One end of the tunnel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    pub name: String,
+    pub ifindex: Option<u32>,
+}
+
+/// Everything a session has set up that other tools may need to find
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub pid: libc::pid_t,
+    pub child_pid: libc::pid_t,
+    pub name: Option<String>,
+    pub program: String,
+    /// When the session started, in seconds since the epoch
+    pub started: u64,
+    #[serde(rename = "egress_interface")]
+    pub egress_if: String,
+    pub host_link: Link,
+    pub container_link: Link,
+    pub host_ip: Ipv4Addr,
+    pub container_ip: Ipv4Addr,
+    pub prefixlen: u8,
+    pub source_ip: Option<Ipv4Addr>,
+    pub host_ip6: Option<Ipv6Addr>,
+    pub container_ip6: Option<Ipv6Addr>,
+    pub source_ip6: Option<Ipv6Addr>,
+    pub firewall_comment: String,
+    pub fwmark: String,
+}
+
+/// The current time, in seconds since the epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Report {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a report only holds plain values")
+    }
+
+    /// Reads back a description written by [`Report::to_json`]
+    pub fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+
+    /// Writes the description to [`dir`], where it stays until the returned
+    /// guard is dropped
+    pub fn write(&self) -> anyhow::Result<Written> {
+        let dir = dir();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+
+        let path = path(self.pid);
+        std::fs::write(&path, format!("{}\n", self.to_json()))
+            .with_context(|| format!("could not write {}", path.display()))?;
+
+        Ok(Written { path })
+    }
+}
+
+/// The descriptions in [`dir`], skipping any which can't be read. Those of
+/// sessions which were killed are left behind, and are returned as well
+pub fn saved() -> Vec<Report> {
+    let Ok(entries) = std::fs::read_dir(dir()) else {
+        return Vec::new();
+    };
+
+    let mut reports = entries
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+        .filter_map(|json| Report::from_json(&json))
+        .collect::<Vec<_>>();
+    reports.sort_by_key(|r| r.pid);
+    reports
+}
+
+/// A description in the state directory, removed again when dropped
+pub struct Written {
+    path: PathBuf,
+}
+
+impl Drop for Written {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        Report {
+            pid: 4242,
+            child_pid: 4243,
+            name: None,
+            program: "/bin/sh".to_owned(),
+            started: 1_700_000_000,
+            egress_if: "eth0".to_owned(),
+            host_link: Link {
+                name: "dlsh4242.0".to_owned(),
+                ifindex: Some(17),
+            },
+            container_link: Link {
+                name: "dlsh4242.1".to_owned(),
+                ifindex: None,
+            },
+            host_ip: Ipv4Addr::new(172, 31, 254, 253),
+            container_ip: Ipv4Addr::new(172, 31, 254, 254),
+            prefixlen: 30,
+            source_ip: Some(Ipv4Addr::new(10, 0, 0, 50)),
+            host_ip6: None,
+            container_ip6: None,
+            source_ip6: Some("2001:db8::50".parse().unwrap()),
+            firewall_comment: "dlsh4242".to_owned(),
+            fwmark: "0x1092".to_owned(),
+        }
+    }
+
+    #[test]
+    fn round_trips_null_fields() {
+        let report = report();
+        assert_eq!(Report::from_json(&report.to_json()), Some(report));
+    }
+
+    #[test]
+    fn round_trips_escaped_strings() {
+        let mut report = report();
+        report.name = Some("quote\"d".to_owned());
+        report.program = "C:\\path\\with \\\"backslashes\\\"".to_owned();
+        report.egress_if = "tab\there, newline\nthere\u{1}".to_owned();
+        report.firewall_comment = "\":{},[]".to_owned();
+        assert_eq!(Report::from_json(&report.to_json()), Some(report));
+    }
+
+    #[test]
+    fn keeps_the_field_names() {
+        let json = report().to_json();
+        assert!(json.contains("\"egress_interface\":\"eth0\""));
+        assert!(json.contains("\"container_link\":{\"name\":\"dlsh4242.1\",\"ifindex\":null}"));
+        assert!(json.contains("\"host_ip6\":null"));
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_report() {
+        assert_eq!(Report::from_json(""), None);
+        assert_eq!(Report::from_json("{\"pid\":1}"), None);
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! `download-shell scan`, which lists the hosts answering on the LAN of the
+//! egress interface and the addresses which appear to be free, to help with
+//! choosing a value for `-s`
+//!
+//! With `--passive`, nothing is sent; the hosts are found by listening to
+//! the traffic on the LAN instead
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, BufRead, BufReader},
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use nl::route::MacAddr;
+
+use crate::{arp, autoip, backend, cli, packet::PacketSocket};
+
+/// Copies of the IEEE OUI registry shipped by distributions, in the order
+/// they are looked for
+const OUI_FILES: [&str; 3] = [
+    "/usr/share/hwdata/oui.txt",
+    "/usr/share/ieee-data/oui.txt",
+    "/usr/share/misc/oui.txt",
+];
+
+/// Finds the organisation each of the hardware addresses was assigned to,
+/// for the ones listed in the first OUI registry found
+fn vendors(macs: impl Iterator<Item = MacAddr>) -> BTreeMap<[u8; 3], String> {
+    let wanted = macs
+        .map(|mac| [mac.0[0], mac.0[1], mac.0[2]])
+        .collect::<BTreeSet<_>>();
+    let mut found = BTreeMap::new();
+
+    let Some(file) = OUI_FILES
+        .iter()
+        .find_map(|path| std::fs::File::open(path).ok())
+    else {
+        return found;
+    };
+
+    // Entries look like `00-00-0C   (hex)\t\tCisco Systems, Inc`
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        let Some((prefix, vendor)) = line.split_once("(hex)") else {
+            continue;
+        };
+        let octets = prefix
+            .trim()
+            .split('-')
+            .map(|o| u8::from_str_radix(o, 16))
+            .collect::<Result<Vec<_>, _>>();
+        if let Ok(Ok(oui)) = octets.map(<[u8; 3]>::try_from)
+            && wanted.contains(&oui)
+        {
+            found.insert(oui, vendor.trim().to_owned());
+        }
+    }
+
+    found
+}
+
+/// Collapses a sorted list of addresses into `first-last` ranges
+fn ranges(addrs: &[Ipv4Addr]) -> Vec<String> {
+    let mut ranges = Vec::<(Ipv4Addr, Ipv4Addr)>::new();
+
+    for ip in addrs {
+        match ranges.last_mut() {
+            Some((_, last)) if u32::from(*last) + 1 == u32::from(*ip) => *last = *ip,
+            _ => ranges.push((*ip, *ip)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(first, last)| {
+            if first == last {
+                format!("{first}")
+            } else {
+                format!("{first}-{last}")
+            }
+        })
+        .collect()
+}
+
+/// Only lets ARP and IPv4 frames through to the socket while listening
+const ARP_AND_IPV4: [libc::sock_filter; 5] = [
+    // ldh [12]
+    libc::sock_filter {
+        code: 0x28,
+        jt: 0,
+        jf: 0,
+        k: 12,
+    },
+    // jeq #ETH_P_ARP, accept
+    libc::sock_filter {
+        code: 0x15,
+        jt: 1,
+        jf: 0,
+        k: libc::ETH_P_ARP as u32,
+    },
+    // jeq #ETH_P_IP, accept, drop
+    libc::sock_filter {
+        code: 0x15,
+        jt: 0,
+        jf: 1,
+        k: libc::ETH_P_IP as u32,
+    },
+    // accept: ret #65535
+    libc::sock_filter {
+        code: 0x06,
+        jt: 0,
+        jf: 0,
+        k: 0xffff,
+    },
+    // drop: ret #0
+    libc::sock_filter {
+        code: 0x06,
+        jt: 0,
+        jf: 0,
+        k: 0,
+    },
+];
+
+/// What was seen of one address and hardware address pair while listening
+struct Sighting {
+    packets: u64,
+    last_seen: Instant,
+}
+
+/// The sender of an ARP or IPv4 frame
+fn sender(frame: &[u8]) -> Option<(Ipv4Addr, MacAddr)> {
+    if let Some(packet) = arp::Packet::parse(frame) {
+        return Some((packet.sender_ip, packet.sender_mac));
+    }
+
+    if frame.len() < 34 || u16::from_be_bytes([frame[12], frame[13]]) != libc::ETH_P_IP as u16 {
+        return None;
+    }
+
+    Some((
+        Ipv4Addr::new(frame[26], frame[27], frame[28], frame[29]),
+        MacAddr(frame[6..12].try_into().unwrap()),
+    ))
+}
+
+/// Listens on the interface for `period` without sending anything,
+/// recording which hosts on the subnet send traffic
+fn listen(
+    interface: &str,
+    subnet: (Ipv4Addr, Ipv4Addr),
+    period: Duration,
+) -> io::Result<BTreeMap<(Ipv4Addr, MacAddr), Sighting>> {
+    let own = arp::hwaddr(interface)?;
+    let sock = PacketSocket::open(arp::ifindex(interface)?, libc::ETH_P_ALL as u16)?;
+    sock.attach_filter(&ARP_AND_IPV4)?;
+
+    let mut seen = BTreeMap::<_, Sighting>::new();
+    let mut buf = [0u8; 1518];
+    let deadline = Instant::now() + period;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(seen);
+        }
+        sock.set_read_timeout(Some(remaining))?;
+
+        let len = match sock.recv(&mut buf) {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(seen),
+            Err(e) => return Err(e),
+        };
+
+        // Traffic routed in from elsewhere carries the address of its origin
+        // with the hardware address of the router, so only the subnet counts
+        let Some((ip, mac)) = sender(&buf[..len])
+            .filter(|(ip, mac)| *mac != own && (subnet.0..=subnet.1).contains(ip))
+        else {
+            continue;
+        };
+
+        let sighting = seen.entry((ip, mac)).or_insert(Sighting {
+            packets: 0,
+            last_seen: Instant::now(),
+        });
+        sighting.packets += 1;
+        sighting.last_seen = Instant::now();
+    }
+}
+
+/// Runs `download-shell scan --passive`, printing every host heard from and
+/// marking the ones that have gone quiet
+fn run_passive(
+    interface: &str,
+    subnet: (Ipv4Addr, Ipv4Addr),
+    period: Duration,
+    silent_after: Duration,
+) -> anyhow::Result<()> {
+    eprintln!(
+        "Listening on {interface} for {}s, without sending anything...",
+        period.as_secs()
+    );
+
+    let seen = listen(interface, subnet, period)
+        .with_context(|| format!("Could not listen on {interface}"))?;
+    let vendors = vendors(seen.keys().map(|(_, mac)| *mac));
+
+    let mut silent = Vec::new();
+    for ((ip, mac), sighting) in &seen {
+        let quiet_for = sighting.last_seen.elapsed();
+        let vendor = vendors
+            .get(&[mac.0[0], mac.0[1], mac.0[2]])
+            .map(String::as_str)
+            .unwrap_or("");
+        let mark = if quiet_for >= silent_after {
+            silent.push(*ip);
+            "silent"
+        } else {
+            ""
+        };
+        println!(
+            "{ip:<15}  {mac}  {:>6} packets  last seen {:>4}s ago  {mark:<6}  {vendor}",
+            sighting.packets,
+            quiet_for.as_secs()
+        );
+    }
+
+    println!();
+    println!(
+        "{} hosts heard from, {} silent for at least {}s: {}",
+        seen.len(),
+        silent.len(),
+        silent_after.as_secs(),
+        ranges(&silent).join(", ")
+    );
+
+    Ok(())
+}
+
+/// Runs `download-shell scan`
+pub fn run(args: cli::ScanArgs) -> anyhow::Result<()> {
+    let cli::ScanArgs {
+        interface,
+        auto_exclude: excluded,
+        passive,
+        silent,
+    } = args;
+    let passive = passive.map(Duration::from_secs);
+    let silent_after = silent.map(Duration::from_secs);
+
+    let backend = backend::open(None, false)?;
+    let routes = backend.routes().context("Could not load routes")?;
+    let local_addrs = backend
+        .local_addrs()
+        .context("Could not load the addresses owned by the host")?;
+
+    let interface = match interface {
+        Some(name) => name,
+        None => routes
+            .iter()
+            .find(|r| r.prefixlen == 0)
+            .and_then(|r| r.dev.clone())
+            .ok_or(anyhow::anyhow!(
+                "Could not find the interface of the default route; use --interface"
+            ))?,
+    };
+
+    let [network, broadcast, first, last] = autoip::scan_range(&interface, &routes, &local_addrs)?;
+
+    if let Some(period) = passive {
+        // Without a threshold, anything not heard from in the second half
+        // of the period counts as silent
+        return run_passive(
+            &interface,
+            (network, broadcast),
+            period,
+            silent_after.unwrap_or(period / 2),
+        );
+    }
+
+    let targets = (u32::from(first)..=u32::from(last))
+        .map(Ipv4Addr::from)
+        .filter(|ip| *ip != network && *ip != broadcast && !local_addrs.contains(ip))
+        .collect::<Vec<_>>();
+
+    eprintln!(
+        "Scanning {} addresses from {first} to {last} on {interface}...",
+        targets.len()
+    );
+
+    let prober = arp::Prober::open(&interface)
+        .with_context(|| format!("Could not open {interface} to scan"))?;
+    let in_use = prober
+        .scan(&targets, 2, autoip::SCAN_WAIT)
+        .context("Could not scan for hosts")?;
+
+    let vendors = vendors(in_use.values().copied());
+    for (ip, mac) in &in_use {
+        let vendor = match vendors.get(&[mac.0[0], mac.0[1], mac.0[2]]) {
+            Some(vendor) => vendor.as_str(),
+            // Randomised addresses, as phones use on Wi-Fi, set this bit
+            None if mac.is_locally_administered() => "(locally administered)",
+            None => "",
+        };
+        println!("{ip:<15}  {mac}  {vendor}");
+    }
+
+    let free = autoip::candidates(&interface, &routes, &local_addrs, &excluded)?
+        .into_iter()
+        .filter(|ip| !in_use.contains_key(ip))
+        .collect::<Vec<_>>();
+
+    println!();
+    println!(
+        "{} answered, {} appear to be free: {}",
+        in_use.len(),
+        free.len(),
+        ranges(&free).join(", ")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Sessions given a name with `--name`, which stay up after their program
+//! exits until `download-shell destroy NAME` ends them. Other programs can
+//! be started in them with `download-shell attach NAME`
+//!
+//! The download-shell process of a named session keeps its namespaces open,
+//! and leaves a file in the state directory saying where to find them
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    os::{
+        fd::AsRawFd,
+        unix::{fs::FileExt, process::CommandExt},
+    },
+    path::PathBuf,
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{cgroup, cli, lock};
+
+/// The longest name a session can have, leaving room in the comment on its
+/// firewall rules
+const MAX_NAME_LEN: usize = 32;
+
+/// Checks that a name can be used for a session
+pub fn parse_name(s: &str) -> anyhow::Result<String> {
+    if s.is_empty() || s.len() > MAX_NAME_LEN {
+        anyhow::bail!("a session name is between 1 and {MAX_NAME_LEN} characters long");
+    }
+    if !s
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-'))
+    {
+        anyhow::bail!("a session name may only contain letters, digits, '_', '.' and '-'");
+    }
+
+    Ok(s.to_owned())
+}
+
+fn path(name: &str) -> PathBuf {
+    lock::state_dir().join("sessions").join(name)
+}
+
+/// Where a running session can be found
+#[derive(Debug, Clone, Copy)]
+pub struct Location {
+    /// The pid of the download-shell process holding the session
+    pub pid: libc::pid_t,
+    /// The descriptors of its network and mount namespaces in that process,
+    /// once they exist
+    pub namespaces: Option<(libc::c_int, libc::c_int)>,
+}
+
+impl Location {
+    fn parse(contents: &str) -> Option<Self> {
+        let mut fields = contents.split_ascii_whitespace().map(str::parse);
+        let pid = fields.next()?.ok()?;
+        let namespaces = match (fields.next(), fields.next()) {
+            (Some(Ok(net)), Some(Ok(mnt))) => Some((net, mnt)),
+            _ => None,
+        };
+
+        Some(Self { pid, namespaces })
+    }
+}
+
+/// Looks up the session with the name given, if it is still running
+pub fn find(name: &str) -> anyhow::Result<Location> {
+    let contents = std::fs::read_to_string(path(name))
+        .map_err(|_| anyhow::anyhow!("there is no session named {name}"))?;
+
+    match Location::parse(&contents) {
+        Some(location) if lock::process_alive(location.pid) => Ok(location),
+        _ => anyhow::bail!("there is no session named {name}"),
+    }
+}
+
+/// The name of a session, held from before it is set up until it is torn
+/// down
+pub struct Claim {
+    path: PathBuf,
+    file: File,
+    owner: libc::pid_t,
+}
+
+impl Claim {
+    /// Takes the name for this process, unless a running session has it
+    pub fn take(name: &str) -> anyhow::Result<Self> {
+        let path = path(name);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("could not create {}", dir.display()))?;
+        }
+
+        // A session which died without tearing down leaves its file behind
+        if find(name).is_err() {
+            let _ = std::fs::remove_file(&path);
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => {
+                    anyhow::anyhow!("a session named {name} is already running")
+                }
+                _ => anyhow::Error::new(e).context(format!("could not create {}", path.display())),
+            })?;
+        let owner = unsafe { libc::getpid() };
+        writeln!(file, "{owner}").with_context(|| format!("could not write {}", path.display()))?;
+
+        Ok(Self { path, file, owner })
+    }
+
+    /// Lets other processes find the namespaces of the session, which have
+    /// to stay open for as long as the claim is held
+    pub fn publish(&self, net: &File, mnt: &File) -> anyhow::Result<()> {
+        // Only ever adds to what is there, so readers never see the file
+        // half written
+        let contents = format!("{} {} {}\n", self.owner, net.as_raw_fd(), mnt.as_raw_fd());
+        self.file
+            .write_all_at(contents.as_bytes(), 0)
+            .with_context(|| format!("could not write {}", self.path.display()))
+    }
+}
+
+impl Drop for Claim {
+    /// Gives the name up, unless this is a child forked after it was taken
+    fn drop(&mut self) {
+        if unsafe { libc::getpid() } == self.owner {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Runs `download-shell destroy NAME`
+pub fn destroy(args: cli::DestroyArgs) -> anyhow::Result<()> {
+    let name = args.name;
+
+    let location = find(&name)?;
+    if unsafe { libc::kill(location.pid, libc::SIGTERM) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("Could not stop the session {name}"));
+    }
+
+    // Tearing down takes a moment, and the caller may well want to start a
+    // new session with the same name right away
+    log::info!("Tearing down {name}...");
+    while lock::process_alive(location.pid) {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    Ok(())
+}
+
+/// Opens a namespace the session holds open, making sure the descriptor
+/// still is one of the kind expected
+fn open_namespace(location: &Location, fd: libc::c_int, kind: &str) -> anyhow::Result<File> {
+    let path = format!("/proc/{}/fd/{fd}", location.pid);
+    let target = std::fs::read_link(&path).with_context(|| format!("Could not inspect {path}"))?;
+    if !target.to_string_lossy().starts_with(&format!("{kind}:[")) {
+        anyhow::bail!("{path} is not a {kind} namespace any more");
+    }
+
+    File::open(&path).with_context(|| format!("Could not open {path}"))
+}
+
+/// Runs `download-shell attach NAME [PROGRAM [ARGS...]]`, which starts a
+/// program, or a shell by default, inside of a named session
+pub fn attach(args: cli::AttachArgs) -> anyhow::Result<()> {
+    let cli::AttachArgs { name, mut command } = args;
+    if command.is_empty() {
+        command.push(std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned()));
+    }
+
+    let location = find(&name)?;
+    let (net, mnt) = location
+        .namespaces
+        .ok_or(anyhow::anyhow!("The session {name} is still starting"))?;
+    let net = open_namespace(&location, net, "net")?;
+    let mnt = open_namespace(&location, mnt, "mnt")?;
+
+    // Joining the cgroup keeps what runs here accounted to the session, and
+    // has sessions started from here treated as nested inside of it. There
+    // is none without cgroup v2
+    if let Err(e) = cgroup::join(location.pid)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        log::warn!("could not join the cgroup of the session: {e}");
+    }
+
+    for (ns, flag) in [(&net, libc::CLONE_NEWNET), (&mnt, libc::CLONE_NEWNS)] {
+        if unsafe { libc::setns(ns.as_raw_fd(), flag) } != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("Could not enter the session {name}"));
+        }
+    }
+    drop((net, mnt));
+
+    let mut program = Command::new(&command[0]);
+    program.args(&command[1..]);
+    if let Ok(ps1) = std::env::var("PS1") {
+        program.env("PS1", format!("(download-shell) {ps1}"));
+    }
+
+    Err(program.exec()).with_context(|| format!("Could not run {}", command[0]))
+}
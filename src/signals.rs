@@ -0,0 +1,164 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Passing signals on to the program running in the session, and noticing
+//! when the terminal is resized
+
+use std::{
+    io,
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Signals which are passed on to the session when they are sent to us
+pub const FORWARDED: [libc::c_int; 6] = [
+    libc::SIGTERM,
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+];
+
+static FORWARD_TO: AtomicI32 = AtomicI32::new(0);
+static WINCH: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn forward(signal: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    // Signals raised by the terminal (^C, ^\, hangups) are delivered to the
+    // whole foreground process group, so the session has already received
+    // them. Only pass on the ones a user or another process sent to us
+    let user_sent = unsafe { (*info).si_code <= 0 };
+
+    let pid = FORWARD_TO.load(Ordering::Relaxed);
+    if user_sent && pid > 0 {
+        unsafe { libc::kill(pid, signal) };
+    }
+}
+
+extern "C" fn winch(_: libc::c_int) {
+    WINCH.store(true, Ordering::Relaxed);
+}
+
+fn install(signal: libc::c_int, handler: libc::sighandler_t, flags: libc::c_int) -> io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = flags;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Passes the [`FORWARDED`] signals on to the process given for as long as
+/// this process runs
+pub fn forward_to(pid: libc::pid_t) -> io::Result<()> {
+    FORWARD_TO.store(pid, Ordering::Relaxed);
+
+    for signal in FORWARDED {
+        install(
+            signal,
+            forward as *const () as libc::sighandler_t,
+            libc::SA_SIGINFO | libc::SA_RESTART,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Ends the process given once `deadline` passes, with SIGTERM and then
+/// SIGKILL if it is still running `grace` later, for `--timeout`
+pub fn end_at(pid: libc::pid_t, deadline: Instant, grace: Duration) {
+    std::thread::spawn(move || {
+        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+
+        log::warn!("the session ran out of time, ending it");
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+        std::thread::sleep(grace);
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+    });
+}
+
+/// Blocks until asked to stop with SIGTERM, SIGINT or SIGHUP, and returns
+/// the signal received, or `None` once `deadline` passes. The signals stay
+/// blocked afterwards
+pub fn wait_for_end(deadline: Option<Instant>) -> io::Result<Option<libc::c_int>> {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+            libc::sigaddset(&mut set, signal);
+        }
+
+        let ret = libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        if ret != 0 {
+            return Err(io::Error::from_raw_os_error(ret));
+        }
+
+        let Some(deadline) = deadline else {
+            let mut signal = 0;
+            let ret = libc::sigwait(&set, &mut signal);
+            if ret != 0 {
+                return Err(io::Error::from_raw_os_error(ret));
+            }
+            return Ok(Some(signal));
+        };
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let timeout = libc::timespec {
+                tv_sec: left.as_secs() as libc::time_t,
+                tv_nsec: left.subsec_nanos() as libc::c_long,
+            };
+
+            let signal = libc::sigtimedwait(&set, std::ptr::null_mut(), &timeout);
+            if signal >= 0 {
+                return Ok(Some(signal));
+            }
+            match io::Error::last_os_error() {
+                e if e.raw_os_error() == Some(libc::EAGAIN) => return Ok(None),
+                e if e.kind() == io::ErrorKind::Interrupted => {}
+                e => return Err(e),
+            }
+        }
+    }
+}
+
+/// Starts watching for SIGWINCH. Blocking calls such as poll(2) are
+/// interrupted when the terminal is resized, after which
+/// [`take_resized`] reports it
+pub fn watch_resize() -> io::Result<()> {
+    install(libc::SIGWINCH, winch as *const () as libc::sighandler_t, 0)
+}
+
+/// Whether the terminal has been resized since this was last called
+pub fn take_resized() -> bool {
+    WINCH.swap(false, Ordering::Relaxed)
+}
+
+/// Copies the size of the terminal on `from` to the terminal on `to`. The
+/// kernel then signals the foreground process group of `to` with SIGWINCH
+pub fn copy_window_size(from: libc::c_int, to: libc::c_int) {
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(from, libc::TIOCGWINSZ, &mut size) == 0 {
+            libc::ioctl(to, libc::TIOCSWINSZ, &size);
+        }
+    }
+}
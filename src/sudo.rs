@@ -0,0 +1,125 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Finding out who ran download-shell through sudo, so that the program in
+//! the namespace can pick up their configuration instead of root's
+
+use std::{io, os::unix::fs::MetadataExt, path::Path};
+
+use crate::user::Account;
+
+/// XDG base directories, which default to places in the home directory
+const XDG_HOMES: [(&str, &str); 4] = [
+    ("XDG_CONFIG_HOME", ".config"),
+    ("XDG_CACHE_HOME", ".cache"),
+    ("XDG_DATA_HOME", ".local/share"),
+    ("XDG_STATE_HOME", ".local/state"),
+];
+
+/// The account of the user who invoked sudo, if download-shell is running
+/// as root because of it
+pub fn invoker() -> Option<Account> {
+    if unsafe { libc::geteuid() } != 0 {
+        return None;
+    }
+
+    // The uid is more reliable than the name, which may have been reused
+    let account = std::env::var("SUDO_UID")
+        .ok()
+        .and_then(|uid| Account::lookup(&uid).ok())
+        .or_else(|| {
+            std::env::var("SUDO_USER")
+                .ok()
+                .and_then(|name| Account::lookup(&name).ok())
+        })?;
+
+    (account.uid != 0).then_some(account)
+}
+
+/// The variables to change so that the program uses the invoker's home,
+/// XDG directories and personal bin directories while still running as root
+pub fn env(invoker: &Account) -> Vec<(&'static str, String)> {
+    let root_home = Account::lookup("0")
+        .map(|a| a.home)
+        .unwrap_or_else(|_| "/root".to_owned());
+    let home = Path::new(&invoker.home);
+
+    let mut env = vec![("HOME", invoker.home.clone())];
+
+    // Directories sudo passed through from root's environment would still
+    // point into root's home
+    for (var, default) in XDG_HOMES {
+        if std::env::var(var).is_ok_and(|dir| Path::new(&dir).starts_with(&root_home)) {
+            env.push((var, home.join(default).to_string_lossy().into_owned()));
+        }
+    }
+
+    let runtime_dir = format!("/run/user/{}", invoker.uid);
+    if Path::new(&runtime_dir).is_dir() {
+        env.push(("XDG_RUNTIME_DIR", runtime_dir));
+    }
+
+    // sudo replaces PATH with secure_path, which loses the directories the
+    // invoker installs their own tools into
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut dirs = std::env::split_paths(&path).collect::<Vec<_>>();
+    for dir in [".local/bin", "bin"].iter().rev().map(|d| home.join(d)) {
+        if dir.is_dir() && !dirs.contains(&dir) {
+            dirs.insert(0, dir);
+        }
+    }
+    if let Ok(path) = std::env::join_paths(dirs) {
+        env.push(("PATH", path.to_string_lossy().into_owned()));
+    }
+
+    env
+}
+
+/// Hands everything root owns under `dir` over to the invoker, so that they
+/// can clean up what was downloaded for them. `dir` itself is only changed
+/// when `include_dir` is set, as it may be shared, e.g. /tmp. Returns how
+/// many entries changed owner
+pub fn chown_tree(dir: &Path, include_dir: bool, owner: &Account) -> io::Result<usize> {
+    let mut changed = 0;
+
+    if include_dir {
+        changed += chown_entry(dir, &std::fs::symlink_metadata(dir)?, owner)?;
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = std::fs::symlink_metadata(&path)?;
+
+        // Symlinks are changed themselves, but never followed
+        if metadata.is_dir() {
+            changed += chown_tree(&path, true, owner)?;
+        } else {
+            changed += chown_entry(&path, &metadata, owner)?;
+        }
+    }
+
+    Ok(changed)
+}
+
+fn chown_entry(path: &Path, metadata: &std::fs::Metadata, owner: &Account) -> io::Result<usize> {
+    // A hard link to a file elsewhere, such as /etc/shadow, would hand that
+    // file over as well
+    if metadata.uid() != 0 || (!metadata.is_dir() && metadata.nlink() > 1) {
+        return Ok(0);
+    }
+
+    std::os::unix::fs::lchown(path, Some(owner.uid), Some(owner.gid))?;
+    Ok(1)
+}
@@ -0,0 +1,214 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Reading and writing kernel parameters through /proc/sys
+//!
+//! Keys are written with slashes, e.g. `net/ipv4/conf/eth0.100/rp_filter`,
+//! as interface names may contain dots. Network parameters are per
+//! namespace, so a process applies them to the namespace it is in
+
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+use anyhow::Context;
+
+use crate::{
+    lock::{self, HostLock},
+    record::Recorder,
+};
+
+fn path(key: &str) -> PathBuf {
+    PathBuf::from("/proc/sys").join(key)
+}
+
+/// Whether the kernel exposes the parameter at all
+pub fn exists(key: &str) -> bool {
+    path(key).exists()
+}
+
+/// Reads a parameter, parsing it as the type requested
+pub fn read<T>(key: &str) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value =
+        std::fs::read_to_string(path(key)).with_context(|| format!("could not read {key}"))?;
+
+    value
+        .trim()
+        .parse()
+        .with_context(|| format!("could not parse {key}"))
+}
+
+/// Writes a parameter
+pub fn write(key: &str, value: impl Display) -> anyhow::Result<()> {
+    log::debug!("setting {key} to {value}");
+    std::fs::write(path(key), value.to_string()).with_context(|| format!("could not set {key}"))
+}
+
+/// A parameter changed by one or more running sessions, as kept in the
+/// shared state file. The last session to stop using it puts the original
+/// value back
+struct Shared {
+    key: String,
+    original: String,
+    users: Vec<libc::pid_t>,
+}
+
+fn load_shared() -> Vec<Shared> {
+    let contents = std::fs::read_to_string(lock::state_path("sysctl")).unwrap_or_default();
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let key = fields.next()?.to_owned();
+            let original = fields.next()?.to_owned();
+            let users = fields
+                .next()?
+                .split(',')
+                .filter_map(|pid| pid.parse().ok())
+                // Sessions that died without cleaning up no longer count
+                .filter(|pid| lock::process_alive(*pid))
+                .collect();
+
+            Some(Shared {
+                key,
+                original,
+                users,
+            })
+        })
+        .collect()
+}
+
+fn store_shared(shared: &[Shared]) -> anyhow::Result<()> {
+    let contents = shared
+        .iter()
+        .map(|s| {
+            let users = s.users.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            format!("{}\t{}\t{}\n", s.key, s.original, users.join(","))
+        })
+        .collect::<String>();
+
+    let path = lock::state_path("sysctl");
+    std::fs::write(&path, contents).with_context(|| format!("could not write {}", path.display()))
+}
+
+/// A set of parameters to apply together
+#[derive(Debug, Default)]
+pub struct Batch {
+    settings: Vec<(String, String)>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a parameter to the batch. Later settings of the same key win
+    pub fn set(&mut self, key: impl Into<String>, value: impl Display) -> &mut Self {
+        self.settings.push((key.into(), value.to_string()));
+        self
+    }
+
+    /// Records every parameter the batch would change, without writing any
+    pub fn plan(&self, record: &mut Recorder) {
+        for (key, value) in &self.settings {
+            record.sysctl(key, value, read::<String>(key).ok().as_deref());
+        }
+    }
+
+    /// Writes every parameter in order, recording each change. The values
+    /// that were replaced are kept in the shared state so that they can be
+    /// put back once no session needs them. If a write fails, the parameters
+    /// already written are restored
+    pub fn apply(&self, record: &mut Recorder, lock: &HostLock) -> anyhow::Result<Saved> {
+        let pid = unsafe { libc::getpid() };
+        let mut shared = load_shared();
+        let mut saved = Saved::default();
+
+        for (key, value) in &self.settings {
+            let previous = read::<String>(key).ok();
+
+            record.sysctl(key, value, previous.as_deref());
+
+            let changes = previous.as_deref() != Some(value.as_str());
+            match shared.iter_mut().find(|s| s.key == *key) {
+                // Another session changed it first and knows the original
+                Some(entry) => entry.users.push(pid),
+                None if changes => shared.extend(previous.map(|original| Shared {
+                    key: key.clone(),
+                    original,
+                    users: vec![pid],
+                })),
+                None => continue,
+            }
+            saved.keys.push(key.clone());
+
+            if !changes {
+                continue;
+            }
+
+            if let Err(e) = write(key, value) {
+                store_shared(&shared)?;
+                saved.restore(lock);
+                return Err(e);
+            }
+        }
+
+        store_shared(&shared)?;
+
+        Ok(saved)
+    }
+}
+
+/// The parameters a [`Batch`] holds a claim on
+#[derive(Debug, Default)]
+#[must_use = "the saved values should be restored once they are no longer needed"]
+pub struct Saved {
+    keys: Vec<String>,
+}
+
+impl Saved {
+    /// Gives up this session's claim on its parameters, putting back the
+    /// original values of those no other running session still needs.
+    /// Failures are reported but do not stop the remaining values from
+    /// being restored
+    pub fn restore(self, _lock: &HostLock) {
+        let pid = unsafe { libc::getpid() };
+        let mut shared = load_shared();
+
+        for key in self.keys.iter().rev() {
+            let Some(index) = shared.iter().position(|s| s.key == *key) else {
+                continue;
+            };
+
+            let entry = &mut shared[index];
+            entry.users.retain(|user| *user != pid);
+            if !entry.users.is_empty() {
+                continue;
+            }
+
+            if let Err(e) = write(key, &entry.original) {
+                log::warn!("could not restore {key} to {}: {e:#}", entry.original);
+            }
+            shared.remove(index);
+        }
+
+        if let Err(e) = store_shared(&shared) {
+            log::warn!("{e:#}");
+        }
+    }
+}
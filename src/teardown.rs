@@ -0,0 +1,163 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Undoing what a session changed on the host: once it has ended, or as
+//! soon as setting it up fails part of the way through
+
+use std::net::Ipv6Addr;
+
+use anyhow::Context;
+
+use crate::{
+    backend::{self, Backend},
+    firewall::Firewall,
+    fwmark::Fwmark,
+    lock, sysctl,
+};
+
+/// The route over the uplinks, in a table of the session's own
+pub struct UplinkRoute {
+    pub table: u32,
+    pub hops: usize,
+    pub nexthop_ids: Option<u32>,
+    /// The mark routed with the table, once the rule for it exists
+    pub fwmark: Option<Fwmark>,
+}
+
+/// The changes made to the host for a session, which are undone when this
+/// is dropped unless [`Teardown::finish`] already did so
+pub struct Teardown {
+    /// The process making the changes; children forked from it leave them
+    /// to it
+    owner: libc::pid_t,
+    /// Nothing is changed with --dry-run, so there is nothing to undo
+    dry_run: bool,
+    /// Opened again to undo the routing if the session is abandoned
+    backend: Option<backend::Kind>,
+    pub firewall: Firewall,
+    pub sysctls: sysctl::Saved,
+    pub uplinks: Option<UplinkRoute>,
+    /// The host end of the tunnel, along with the mirroring, offloads and
+    /// NAT64 addresses set up on it
+    pub host_link: Option<String>,
+    /// The uplink and address neighbour discovery is proxied for
+    pub neigh_proxy: Option<(String, Ipv6Addr)>,
+    /// The child setting up the namespace, killed if the session is
+    /// abandoned before it is reaped
+    pub child: Option<libc::pid_t>,
+    done: bool,
+}
+
+impl Teardown {
+    pub fn new(firewall: Firewall, backend: Option<backend::Kind>, dry_run: bool) -> Self {
+        Self {
+            owner: unsafe { libc::getpid() },
+            dry_run,
+            backend,
+            firewall,
+            sysctls: sysctl::Saved::default(),
+            uplinks: None,
+            host_link: None,
+            neigh_proxy: None,
+            child: None,
+            done: false,
+        }
+    }
+
+    /// Undoes the changes once the session has ended. Everything is undone
+    /// that can be, and the first failure to clear the firewall is returned
+    pub fn finish(mut self, backend: &dyn Backend) -> anyhow::Result<()> {
+        self.done = true;
+        self.undo(Some(backend))
+    }
+
+    fn undo(&mut self, backend: Option<&dyn Backend>) -> anyhow::Result<()> {
+        // Unlike the tunnel, the route and rule over the uplinks don't go
+        // away with the namespace, nor does the proxy entry on the uplink
+        if let Some(backend) = backend {
+            if let Some(uplinks) = self.uplinks.take() {
+                if let Some(fwmark) = uplinks.fwmark
+                    && let Err(e) = backend.delete_fwmark_rule(fwmark, uplinks.table)
+                {
+                    log::warn!("could not delete the session's routing rule: {e:#}");
+                }
+                if let Err(e) = backend.delete_multipath_default(
+                    uplinks.table,
+                    uplinks.hops,
+                    uplinks.nexthop_ids,
+                ) {
+                    log::warn!("could not delete the route over the uplinks: {e:#}");
+                }
+            }
+
+            if let Some((dev, ip)) = self.neigh_proxy.take()
+                && let Err(e) = backend.delete_neigh_proxy(&dev, ip)
+            {
+                log::warn!("could not stop proxying neighbour discovery for {ip}: {e:#}");
+            }
+        }
+
+        let host_lock = lock::HostLock::acquire()?;
+        let cleared = self
+            .firewall
+            .clean()
+            .context("could not clear the session's firewall rules");
+        std::mem::take(&mut self.sysctls).restore(&host_lock);
+        drop(host_lock);
+
+        lock::forget_nested(self.owner);
+
+        cleared
+    }
+}
+
+impl Drop for Teardown {
+    /// Abandons a session whose setup failed, stopping the child before it
+    /// can start the program
+    fn drop(&mut self) {
+        if self.done || self.dry_run || unsafe { libc::getpid() } != self.owner {
+            return;
+        }
+
+        if let Some(child) = self.child.take() {
+            unsafe {
+                libc::kill(child, libc::SIGKILL);
+                libc::waitpid(child, std::ptr::null_mut(), 0);
+            }
+        }
+
+        let backend = match (&self.uplinks, &self.neigh_proxy, &self.host_link) {
+            (None, None, None) => None,
+            _ => backend::open(self.backend, false)
+                .inspect_err(|e| log::warn!("could not undo the session's routing: {e:#}"))
+                .ok(),
+        };
+
+        // The link goes with the namespace of the child too, unless something
+        // still holds that open
+        if let (Some(backend), Some(link)) = (&backend, self.host_link.take())
+            && backend
+                .link_names()
+                .is_ok_and(|names| names.contains(&link))
+            && let Err(e) = backend.delete_link(&link)
+        {
+            log::warn!("could not delete {link}: {e:#}");
+        }
+
+        if let Err(e) = self.undo(backend.as_deref()) {
+            log::warn!("{e:#}");
+        }
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Records a session as a typescript in the format of script(1), with a
+//! timing file next to it so that it can be played back by scriptreplay(1)
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::signals;
+
+/// The files a session is being recorded to
+pub struct Transcript {
+    log: File,
+    timing: File,
+    timing_path: PathBuf,
+}
+
+/// Formats the current local time the way script(1) does in its header
+fn now() -> String {
+    let mut buf = [0u8; 64];
+    let len = unsafe {
+        let t = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&t, &mut tm);
+        libc::strftime(
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+            c"%Y-%m-%d %H:%M:%S%z".as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+fn read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        let ret = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if ret >= 0 {
+            return Ok(ret as usize);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+fn write_all(fd: RawFd, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let ret = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        buf = &buf[ret as usize..];
+    }
+    Ok(())
+}
+
+/// Allocates a pseudo terminal, returning the master and the path of the
+/// slave
+fn open_pty() -> io::Result<(OwnedFd, PathBuf)> {
+    let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC) };
+    if master < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let master = unsafe { OwnedFd::from_raw_fd(master) };
+
+    let mut name = [0 as libc::c_char; 64];
+    unsafe {
+        if libc::grantpt(master.as_raw_fd()) != 0 || libc::unlockpt(master.as_raw_fd()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ret = libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len());
+        if ret != 0 {
+            return Err(io::Error::from_raw_os_error(ret));
+        }
+    }
+
+    let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+    Ok((master, PathBuf::from(name.to_string_lossy().into_owned())))
+}
+
+/// Puts the terminal into raw mode for as long as it is held, so that
+/// keystrokes are passed through to the session untouched
+struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable(fd: RawFd) -> Option<Self> {
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut original) != 0 {
+                return None;
+            }
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(fd, libc::TCSANOW, &raw);
+            Some(Self { fd, original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSAFLUSH, &self.original) };
+    }
+}
+
+impl Transcript {
+    /// Creates the typescript at `path` and its timing file at
+    /// `path.timing`. This has to happen on the host, before the filesystem
+    /// is made read only
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut timing_path = path.as_os_str().to_owned();
+        timing_path.push(".timing");
+        let timing_path = PathBuf::from(timing_path);
+
+        Ok(Self {
+            log: File::create(path)?,
+            timing: File::create(&timing_path)?,
+            timing_path,
+        })
+    }
+
+    /// Where the timing file is written
+    pub fn timing_path(&self) -> &Path {
+        &self.timing_path
+    }
+
+    /// Runs `exec` in a new process whose standard streams are a pseudo
+    /// terminal, relaying between it and our own terminal and recording
+    /// everything the session prints. `exec` should only return on failure.
+    /// Returns the exit status of the session
+    pub fn run(
+        mut self,
+        command: &str,
+        exec: impl FnOnce() -> anyhow::Result<()>,
+    ) -> anyhow::Result<i32> {
+        let (master, slave_path) = open_pty()?;
+
+        // Start the session at the size of the terminal it is shown on, and
+        // keep it that way as the terminal is resized
+        signals::copy_window_size(libc::STDIN_FILENO, master.as_raw_fd());
+        signals::watch_resize()?;
+
+        let child = unsafe { libc::fork() };
+        if child < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        if child == 0 {
+            let slave = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&slave_path);
+
+            let result = slave.map_err(anyhow::Error::from).and_then(|slave| {
+                unsafe {
+                    libc::setsid();
+                    libc::ioctl(slave.as_raw_fd(), libc::TIOCSCTTY, 0);
+                    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                        libc::dup2(slave.as_raw_fd(), fd);
+                    }
+                }
+                drop(slave);
+                drop(master);
+                exec()
+            });
+
+            if let Err(e) = result {
+                log::error!("{e:?}");
+            }
+            unsafe { libc::_exit(127) };
+        }
+
+        signals::forward_to(child)?;
+
+        writeln!(
+            self.log,
+            "Script started on {} [COMMAND=\"{command}\"]",
+            now()
+        )?;
+
+        let raw = RawMode::enable(libc::STDIN_FILENO);
+        let mut last = Instant::now();
+        let mut stdin_open = true;
+        let mut buf = [0u8; 8192];
+
+        loop {
+            let mut fds = [
+                libc::pollfd {
+                    fd: master.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: if stdin_open { libc::STDIN_FILENO } else { -1 },
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+
+            let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+
+            if signals::take_resized() {
+                signals::copy_window_size(libc::STDIN_FILENO, master.as_raw_fd());
+            }
+
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err.into());
+            }
+
+            if fds[0].revents != 0 {
+                // EIO once the last process holding the terminal exits
+                let len = read(master.as_raw_fd(), &mut buf).unwrap_or(0);
+                if len == 0 {
+                    break;
+                }
+
+                write_all(libc::STDOUT_FILENO, &buf[..len])?;
+                self.log.write_all(&buf[..len])?;
+
+                let now = Instant::now();
+                writeln!(
+                    self.timing,
+                    "{:.6} {len}",
+                    now.duration_since(last).as_secs_f64()
+                )?;
+                last = now;
+            }
+
+            if fds[1].revents != 0 {
+                match read(libc::STDIN_FILENO, &mut buf) {
+                    Ok(0) | Err(_) => stdin_open = false,
+                    Ok(len) => write_all(master.as_raw_fd(), &buf[..len])?,
+                }
+            }
+        }
+
+        drop(raw);
+
+        let mut status = 0;
+        unsafe { libc::waitpid(child, &mut status, 0) };
+        let code = if libc::WIFEXITED(status) {
+            libc::WEXITSTATUS(status)
+        } else {
+            128 + libc::WTERMSIG(status)
+        };
+
+        writeln!(
+            self.log,
+            "\nScript done on {} [COMMAND_EXIT_CODE=\"{code}\"]",
+            now()
+        )?;
+
+        Ok(code)
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Addressing of the veth pair connecting the namespace to the host
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use crate::backend::RouteEntry;
+
+/// How addresses are assigned to the two ends of the tunnel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Addressing {
+    /// A /30 with a network and broadcast address, as download-shell.sh does
+    Net30,
+    /// A point to point /31 as described in RFC 3021
+    Net31,
+    /// A /32 on each end, with the other end configured as its peer
+    Peer,
+}
+
+impl FromStr for Addressing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "30" | "/30" => Ok(Addressing::Net30),
+            "31" | "/31" => Ok(Addressing::Net31),
+            "peer" => Ok(Addressing::Peer),
+            _ => anyhow::bail!("unknown tunnel addressing '{s}', expected one of: 30, 31, peer"),
+        }
+    }
+}
+
+impl Addressing {
+    /// The size of the block of addresses taken up by a tunnel, as a prefix
+    pub fn block_prefixlen(self) -> u32 {
+        match self {
+            Addressing::Net30 => 30,
+            Addressing::Net31 | Addressing::Peer => 31,
+        }
+    }
+}
+
+/// The block of addresses tunnels are numbered from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Net {
+    pub addr: Ipv4Addr,
+    pub prefixlen: u8,
+}
+
+impl Net {
+    fn mask(self) -> u32 {
+        u32::MAX
+            .checked_shl(32 - self.prefixlen as u32)
+            .unwrap_or(0)
+    }
+
+    fn contains(self, ip: u32) -> bool {
+        ip & self.mask() == u32::from(self.addr)
+    }
+}
+
+impl Default for Net {
+    /// The private block 172.16.0.0/12
+    fn default() -> Self {
+        Self {
+            addr: Ipv4Addr::new(172, 16, 0, 0),
+            prefixlen: 12,
+        }
+    }
+}
+
+impl std::fmt::Display for Net {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefixlen)
+    }
+}
+
+impl FromStr for Net {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefixlen) = s.split_once('/').ok_or(anyhow::anyhow!(
+            "expected a CIDR block such as 10.99.0.0/16"
+        ))?;
+        let net = Self {
+            addr: addr.parse()?,
+            prefixlen: prefixlen.parse()?,
+        };
+
+        if net.prefixlen > 32 {
+            anyhow::bail!("a prefix length is at most 32");
+        }
+        if u32::from(net.addr) & !net.mask() != 0 {
+            anyhow::bail!("{net} has bits set past its prefix length");
+        }
+
+        Ok(net)
+    }
+}
+
+/// The addresses assigned to either end of the tunnel
+#[derive(Debug, Clone, Copy)]
+pub struct Addresses {
+    pub host: Ipv4Addr,
+    pub container: Ipv4Addr,
+    pub prefixlen: u8,
+    pub broadcast: Option<Ipv4Addr>,
+    /// Whether each end is configured with the other as its peer
+    pub peer: bool,
+}
+
+impl Addresses {
+    /// Lays out the addresses of a tunnel in the block starting at `net`
+    pub fn new(addressing: Addressing, net: Ipv4Addr) -> Self {
+        let net: u32 = net.into();
+
+        match addressing {
+            Addressing::Net30 => Self {
+                host: (net + 1).into(),
+                container: (net + 2).into(),
+                prefixlen: 30,
+                broadcast: Some((net + 3).into()),
+                peer: false,
+            },
+            Addressing::Net31 => Self {
+                host: net.into(),
+                container: (net + 1).into(),
+                prefixlen: 31,
+                broadcast: None,
+                peer: false,
+            },
+            Addressing::Peer => Self {
+                host: net.into(),
+                container: (net + 1).into(),
+                prefixlen: 32,
+                broadcast: None,
+                peer: true,
+            },
+        }
+    }
+}
+
+/// The unique local prefix the IPv6 side of tunnels is numbered from, a /64
+/// for each session. Its global ID spells out "dlsh"
+const ULA_PREFIX: u128 = 0xfd64_6c73_6800 << 80;
+
+/// The IPv6 addresses assigned to either end of the tunnel
+#[derive(Debug, Clone, Copy)]
+pub struct Addresses6 {
+    pub host: Ipv6Addr,
+    pub container: Ipv6Addr,
+}
+
+impl Addresses6 {
+    pub const PREFIXLEN: u8 = 64;
+
+    /// Lays out the addresses of a tunnel in the /64 starting at `net`
+    pub fn new(net: Ipv6Addr) -> Self {
+        let net = u128::from(net);
+
+        Self {
+            host: (net + 1).into(),
+            container: (net + 2).into(),
+        }
+    }
+}
+
+/// Finds a /64 for the IPv6 side of the tunnel which overlaps none of the
+/// routes given. The search starts at a subnet picked by `seed`, such as the
+/// pid of the session, so that sessions starting together rarely race for
+/// the same one
+pub fn find_range6(routes: &[RouteEntry<Ipv6Addr>], seed: u16) -> anyhow::Result<Ipv6Addr> {
+    (0..=u16::MAX)
+        .map(|i| ULA_PREFIX | (u128::from(seed.wrapping_add(i)) << 64))
+        .find(|net| {
+            !routes.iter().filter(|r| r.prefixlen > 0).any(|r| {
+                let mask = u128::MAX << (128 - r.prefixlen.min(64) as u32);
+                u128::from(r.dst) & mask == net & mask
+            })
+        })
+        .map(Ipv6Addr::from)
+        .ok_or(anyhow::anyhow!(
+            "Unable to find a free IPv6 tunnel prefix in fd64:6c73:6800::/48!"
+        ))
+}
+
+/// Collects every network the tunnel must not overlap with: anything
+/// routed, owned, or configured on a link (even one that is down and so has
+/// no routes)
+pub fn taken_networks(
+    routes: &[RouteEntry],
+    local_addrs: &[Ipv4Addr],
+    iface_addrs: &[(Ipv4Addr, u8)],
+) -> Vec<(Ipv4Addr, u8)> {
+    routes
+        .iter()
+        .map(|r| (r.dst, r.prefixlen))
+        .chain(local_addrs.iter().map(|a| (*a, 32)))
+        .chain(iface_addrs.iter().map(|(a, len)| {
+            let mask = u32::MAX.checked_shl(32 - *len as u32).unwrap_or(0);
+            (Ipv4Addr::from(u32::from(*a) & mask), *len)
+        }))
+        .collect()
+}
+
+/// Find an available IP range in `net` that can be used to tunnel traffic
+/// between the new namespace and the host system, avoiding every network
+/// listed in `taken`
+pub fn find_range(
+    taken: &[(Ipv4Addr, u8)],
+    addressing: Addressing,
+    net: Net,
+) -> anyhow::Result<Ipv4Addr> {
+    if u32::from(net.prefixlen) > addressing.block_prefixlen() {
+        anyhow::bail!(
+            "{net} is too small for a tunnel, which needs a /{}",
+            addressing.block_prefixlen()
+        );
+    }
+
+    let mut result_ip = net.addr;
+
+    let mut networks = taken.to_vec();
+    networks.sort_by_key(|(dst, _)| u32::from(*dst));
+
+    for (dst, prefixlen) in networks {
+        if prefixlen == 0 {
+            continue;
+        }
+
+        let dst_addr: u32 = dst.into();
+
+        if !net.contains(dst_addr) {
+            continue;
+        }
+
+        // Compare using the larger of the two networks, so that routes
+        // inside of the candidate block are caught as well
+        let cidrlen = (prefixlen as u32).min(addressing.block_prefixlen());
+        let mask = (0xFFFFFFFFu32.overflowing_shr(32 - cidrlen))
+            .0
+            .overflowing_shl(32 - cidrlen)
+            .0;
+
+        let res_ip_u32: u32 = result_ip.into();
+        if (dst_addr & mask) == (res_ip_u32 & mask) {
+            let next_net = 0xFFFFFFFFu32.overflowing_shr(cidrlen).0 + 1;
+            let res_ip_u32 = (dst_addr & mask).wrapping_add(next_net);
+            result_ip = res_ip_u32.into();
+        }
+    }
+
+    if !net.contains(result_ip.into()) {
+        anyhow::bail!("Unable to find a tunnel IP address in the {net} range!");
+    }
+
+    Ok(result_ip)
+}
@@ -0,0 +1,165 @@
+// Copyright (C) 2025 Andrew Rioux
+//
+// This program is free software; you can redistribute it and/or
+// modify it under the terms of the GNU General Public License
+// as published by the Free Software Foundation; either version 2
+// of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program; if not, see <https://www.gnu.org/licenses/>.
+
+//! Looks up accounts in the user and group databases so that the program
+//! in the namespace can be run as someone other than root
+
+use std::ffi::{CStr, CString};
+
+/// A user account, as found in the passwd database
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub name: String,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    pub home: String,
+    pub shell: String,
+}
+
+impl Account {
+    unsafe fn from_passwd(pw: &libc::passwd) -> Self {
+        let field = |p: *const libc::c_char| {
+            if p.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+            }
+        };
+
+        Self {
+            name: field(pw.pw_name),
+            uid: pw.pw_uid,
+            gid: pw.pw_gid,
+            home: field(pw.pw_dir),
+            shell: field(pw.pw_shell),
+        }
+    }
+
+    /// Looks up a user by name, or by uid if the specification is numeric
+    pub fn lookup(spec: &str) -> anyhow::Result<Self> {
+        let mut pw: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut::<libc::passwd>();
+        let mut buf = vec![0 as libc::c_char; 16384];
+
+        let ret = match spec.parse::<libc::uid_t>() {
+            Ok(uid) => unsafe {
+                libc::getpwuid_r(uid, &mut pw, buf.as_mut_ptr(), buf.len(), &mut result)
+            },
+            Err(_) => {
+                let name = CString::new(spec)?;
+                unsafe {
+                    libc::getpwnam_r(
+                        name.as_ptr(),
+                        &mut pw,
+                        buf.as_mut_ptr(),
+                        buf.len(),
+                        &mut result,
+                    )
+                }
+            }
+        };
+
+        if ret != 0 {
+            Err(std::io::Error::from_raw_os_error(ret))?;
+        }
+        if result.is_null() {
+            anyhow::bail!("No such user: {spec}");
+        }
+
+        Ok(unsafe { Self::from_passwd(&pw) })
+    }
+
+    /// Switches the current process over to this account, with the primary
+    /// group given and supplementary groups initialized from the group
+    /// database. This cannot be undone, which is checked before returning
+    pub fn become_user(&self, gid: libc::gid_t) -> anyhow::Result<()> {
+        let name = CString::new(self.name.as_str())?;
+
+        unsafe {
+            if libc::initgroups(name.as_ptr(), gid) != 0 {
+                Err(std::io::Error::last_os_error())?;
+            }
+            if libc::setgid(gid) != 0 {
+                Err(std::io::Error::last_os_error())?;
+            }
+            if libc::setuid(self.uid) != 0 {
+                Err(std::io::Error::last_os_error())?;
+            }
+
+            // The program is only unprivileged if there is no way back
+            if self.uid != 0 && (libc::setuid(0) == 0 || libc::geteuid() == 0) {
+                anyhow::bail!("could still regain root after switching to {}", self.name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The environment variables a login for this account would set
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("HOME", self.home.clone()),
+            ("LOGNAME", self.name.clone()),
+            ("USER", self.name.clone()),
+            ("SHELL", self.shell.clone()),
+        ]
+    }
+}
+
+/// Switches the current process over to the group given, leaving root's
+/// supplementary groups behind, for `--group` without `--user`
+pub fn become_group(gid: libc::gid_t) -> anyhow::Result<()> {
+    unsafe {
+        if libc::setgroups(1, &gid) != 0 {
+            Err(std::io::Error::last_os_error())?;
+        }
+        if libc::setgid(gid) != 0 {
+            Err(std::io::Error::last_os_error())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Looks up a group by name, or accepts a numeric gid
+pub fn lookup_group(spec: &str) -> anyhow::Result<libc::gid_t> {
+    if let Ok(gid) = spec.parse::<libc::gid_t>() {
+        return Ok(gid);
+    }
+
+    let mut gr: libc::group = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut::<libc::group>();
+    let mut buf = vec![0 as libc::c_char; 16384];
+    let name = CString::new(spec)?;
+
+    let ret = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut gr,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+
+    if ret != 0 {
+        Err(std::io::Error::from_raw_os_error(ret))?;
+    }
+    if result.is_null() {
+        anyhow::bail!("No such group: {spec}");
+    }
+
+    Ok(gr.gr_gid)
+}